@@ -76,6 +76,32 @@ fn main() {
             compiler.compile("liblz4.a");
         }
     }
+
+    #[cfg(all(feature = "ffi", not(feature = "docs-rs")))]
+    generate_c_header();
+}
+
+// generate C header for the C API to OUT_DIR, it is also written to the
+// directory in ZBOX_C_HEADER_DIR if that is set
+#[cfg(all(feature = "ffi", not(feature = "docs-rs")))]
+fn generate_c_header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=ZBOX_C_HEADER_DIR");
+
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config =
+        cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(crate_dir.join("src/ffi.rs"))
+        .generate()
+        .expect("failed to generate C header");
+    bindings.write_to_file(out_dir.join("zbox.h"));
+    if let Some(header_dir) = env::var_os("ZBOX_C_HEADER_DIR") {
+        bindings.write_to_file(PathBuf::from(header_dir).join("zbox.h"));
+    }
 }
 
 // This downloads function and builds the libsodium from source for linux and
@@ -0,0 +1,506 @@
+#ifndef ZBOX_H
+#define ZBOX_H
+
+/* This file is generated by cbindgen, do not edit it manually. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Success return code.
+ */
+#define ZBOX_OK 0
+
+/**
+ * Return code when a panic is caught at the API boundary.
+ */
+#define ZBOX_ERR_PANIC -1
+
+/**
+ * Error codes returned by the C API, same as the codes converted from
+ * [`Error`].
+ *
+ * [`Error`]: ../enum.Error.html
+ */
+typedef enum ZboxError {
+  ZBOX_ERROR_REF_OVERFLOW = -1000,
+  ZBOX_ERROR_REF_UNDERFLOW = -1001,
+  ZBOX_ERROR_INIT_CRYPTO = -1010,
+  ZBOX_ERROR_NO_AES_HARDWARE = -1011,
+  ZBOX_ERROR_HASHING = -1012,
+  ZBOX_ERROR_INVALID_COST = -1013,
+  ZBOX_ERROR_INVALID_CIPHER = -1014,
+  ZBOX_ERROR_ENCRYPT = -1015,
+  ZBOX_ERROR_DECRYPT = -1016,
+  ZBOX_ERROR_INVALID_URI = -1020,
+  ZBOX_ERROR_INVALID_SUPER_BLK = -1021,
+  ZBOX_ERROR_CORRUPTED = -1022,
+  ZBOX_ERROR_WRONG_VERSION = -1023,
+  ZBOX_ERROR_NO_ENTITY = -1024,
+  ZBOX_ERROR_NOT_IN_SYNC = -1025,
+  ZBOX_ERROR_REPO_OPENED = -1026,
+  ZBOX_ERROR_REPO_CLOSED = -1027,
+  ZBOX_ERROR_REPO_EXISTS = -1028,
+  ZBOX_ERROR_IN_TRANS = -1030,
+  ZBOX_ERROR_NOT_IN_TRANS = -1031,
+  ZBOX_ERROR_NO_TRANS = -1032,
+  ZBOX_ERROR_UNCOMPLETED = -1033,
+  ZBOX_ERROR_IN_USE = -1034,
+  ZBOX_ERROR_NO_CONTENT = -1040,
+  ZBOX_ERROR_INVALID_ARGUMENT = -1050,
+  ZBOX_ERROR_INVALID_PATH = -1051,
+  ZBOX_ERROR_NOT_FOUND = -1052,
+  ZBOX_ERROR_ALREADY_EXISTS = -1053,
+  ZBOX_ERROR_IS_ROOT = -1054,
+  ZBOX_ERROR_IS_DIR = -1055,
+  ZBOX_ERROR_IS_FILE = -1056,
+  ZBOX_ERROR_NOT_DIR = -1057,
+  ZBOX_ERROR_NOT_FILE = -1058,
+  ZBOX_ERROR_NOT_EMPTY = -1059,
+  ZBOX_ERROR_NO_VERSION = -1060,
+  ZBOX_ERROR_NOT_LINK = -1061,
+  ZBOX_ERROR_TOO_MANY_LINKS = -1062,
+  ZBOX_ERROR_QUOTA_EXCEEDED = -1063,
+  ZBOX_ERROR_READ_ONLY = -1070,
+  ZBOX_ERROR_CANNOT_READ = -1071,
+  ZBOX_ERROR_CANNOT_WRITE = -1072,
+  ZBOX_ERROR_NOT_WRITE = -1073,
+  ZBOX_ERROR_NOT_FINISH = -1074,
+  ZBOX_ERROR_CLOSED = -1075,
+  ZBOX_ERROR_ENCODE = -2000,
+  ZBOX_ERROR_DECODE = -2010,
+  ZBOX_ERROR_VAR = -2020,
+  ZBOX_ERROR_IO = -2030,
+  ZBOX_ERROR_SQLITE = -2040,
+  ZBOX_ERROR_REDIS = -2050,
+  ZBOX_ERROR_HTTP = -2060,
+  ZBOX_ERROR_HTTP_STATUS = -2061,
+  ZBOX_ERROR_JSON = -2062,
+  ZBOX_ERROR_REQWEST = -2063,
+  ZBOX_ERROR_JNI = -2064,
+  ZBOX_ERROR_REQUEST_ERROR = -2065,
+} ZboxError;
+
+/**
+ * File type.
+ */
+typedef enum ZboxFileType {
+  /**
+   * Regular file.
+   */
+  ZBOX_FILE_TYPE_FILE,
+  /**
+   * Directory.
+   */
+  ZBOX_FILE_TYPE_DIR,
+  /**
+   * Symbolic link.
+   */
+  ZBOX_FILE_TYPE_SYMLINK,
+} ZboxFileType;
+
+/**
+ * Seek origin used by `zbox_file_seek`.
+ */
+typedef enum ZboxSeekFrom {
+  /**
+   * Offset from start of the file.
+   */
+  ZBOX_SEEK_FROM_START,
+  /**
+   * Offset from current position.
+   */
+  ZBOX_SEEK_FROM_CURRENT,
+  /**
+   * Offset from end of the file.
+   */
+  ZBOX_SEEK_FROM_END,
+} ZboxSeekFrom;
+
+/**
+ * Opaque file handle.
+ */
+typedef struct ZboxFile ZboxFile;
+
+/**
+ * Opaque repository handle.
+ */
+typedef struct ZboxRepo ZboxRepo;
+
+/**
+ * Options for opening or creating a repository.
+ */
+typedef struct ZboxRepoOptions {
+  /**
+   * Create repository if it doesn't exist.
+   */
+  bool create;
+  /**
+   * Always create a new repository, fail if it already exists.
+   */
+  bool create_new;
+  /**
+   * Open repository in read-only mode.
+   */
+  bool read_only;
+  /**
+   * Enable compression, only used when creating repository.
+   */
+  bool compress;
+  /**
+   * Default maximum number of file versions, only used when creating
+   * repository. Zero means the default value.
+   */
+  uint32_t version_limit;
+} ZboxRepoOptions;
+
+/**
+ * Metadata of a file or directory. Times are seconds since unix epoch.
+ */
+typedef struct ZboxMetadata {
+  enum ZboxFileType file_type;
+  uint64_t content_len;
+  uint64_t curr_version;
+  uint64_t created_at;
+  uint64_t modified_at;
+  uint32_t mode;
+} ZboxMetadata;
+
+/**
+ * Directory entry. The strings are owned by the entry array.
+ */
+typedef struct ZboxDirEntry {
+  char *path;
+  char *name;
+  struct ZboxMetadata metadata;
+} ZboxDirEntry;
+
+/**
+ * File content version. Times are seconds since unix epoch.
+ */
+typedef struct ZboxVersion {
+  uint64_t num;
+  uint64_t content_len;
+  uint64_t created_at;
+} ZboxVersion;
+
+/**
+ * Options for opening a file.
+ */
+typedef struct ZboxOpenOptions {
+  /**
+   * Open for reading.
+   */
+  bool read;
+  /**
+   * Open for writing.
+   */
+  bool write;
+  /**
+   * Open in append mode.
+   */
+  bool append;
+  /**
+   * Truncate file to zero length.
+   */
+  bool truncate;
+  /**
+   * Create file if it doesn't exist.
+   */
+  bool create;
+  /**
+   * Always create a new file, fail if it already exists.
+   */
+  bool create_new;
+} ZboxOpenOptions;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Initialise zbox environment, must be called before any other function.
+ */
+int32_t zbox_init_env(void);
+
+/**
+ * Returns the error message of the last failed call on current thread, or
+ * NULL if there is no error. The returned string is valid until next
+ * failed call on the same thread.
+ */
+const char *zbox_last_error_message(void);
+
+/**
+ * Opens a repository.
+ *
+ * `opts` can be NULL, which opens an existing repository in read-write
+ * mode. The repository handle is stored to `repo` on success.
+ *
+ * # Safety
+ *
+ * `uri` and `pwd` must be valid C strings, `repo` must be a valid pointer.
+ */
+int32_t zbox_repo_open(struct ZboxRepo **repo,
+                       const char *uri,
+                       const char *pwd,
+                       const struct ZboxRepoOptions *opts);
+
+/**
+ * Closes a repository and releases its handle.
+ *
+ * # Safety
+ *
+ * `repo` must be a handle returned by `zbox_repo_open` or NULL, all files
+ * opened from it should be closed before.
+ */
+void zbox_repo_close(struct ZboxRepo *repo);
+
+/**
+ * Checks if a repository exists at `uri`.
+ *
+ * # Safety
+ *
+ * `uri` must be a valid C string, `result` must be a valid pointer.
+ */
+int32_t zbox_repo_exists(const char *uri, bool *result);
+
+/**
+ * Checks if `path` points at an existing entity in repository.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle, `path` must be a valid C string and
+ * `result` must be a valid pointer.
+ */
+int32_t zbox_repo_path_exists(struct ZboxRepo *repo, const char *path, bool *result);
+
+/**
+ * Creates a directory, parent directories are created as well if `all` is
+ * true.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle and `path` must be a valid C string.
+ */
+int32_t zbox_repo_create_dir(struct ZboxRepo *repo, const char *path, bool all);
+
+/**
+ * Lists entries of a directory.
+ *
+ * The entry array and its length are stored to `entries` and `len`, it
+ * must be released by `zbox_dir_entries_free`.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle, `path` must be a valid C string, `entries`
+ * and `len` must be valid pointers.
+ */
+int32_t zbox_repo_read_dir(struct ZboxRepo *repo,
+                           const char *path,
+                           struct ZboxDirEntry **entries,
+                           uintptr_t *len);
+
+/**
+ * Releases directory entry array returned by `zbox_repo_read_dir`.
+ *
+ * # Safety
+ *
+ * `entries` and `len` must be returned by `zbox_repo_read_dir`.
+ */
+void zbox_dir_entries_free(struct ZboxDirEntry *entries, uintptr_t len);
+
+/**
+ * Gets metadata of a file or directory.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle, `path` must be a valid C string and
+ * `metadata` must be a valid pointer.
+ */
+int32_t zbox_repo_metadata(struct ZboxRepo *repo, const char *path, struct ZboxMetadata *metadata);
+
+/**
+ * Gets history versions of a file.
+ *
+ * The version array and its length are stored to `versions` and `len`, it
+ * must be released by `zbox_history_free`.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle, `path` must be a valid C string,
+ * `versions` and `len` must be valid pointers.
+ */
+int32_t zbox_repo_history(struct ZboxRepo *repo,
+                          const char *path,
+                          struct ZboxVersion **versions,
+                          uintptr_t *len);
+
+/**
+ * Releases version array returned by `zbox_repo_history` or
+ * `zbox_file_history`.
+ *
+ * # Safety
+ *
+ * `versions` and `len` must be returned by `zbox_repo_history` or
+ * `zbox_file_history`.
+ */
+void zbox_history_free(struct ZboxVersion *versions, uintptr_t len);
+
+/**
+ * Copies a file.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle, `from` and `to` must be valid C strings.
+ */
+int32_t zbox_repo_copy(struct ZboxRepo *repo, const char *from, const char *to);
+
+/**
+ * Renames a file or directory.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle, `from` and `to` must be valid C strings.
+ */
+int32_t zbox_repo_rename(struct ZboxRepo *repo, const char *from, const char *to);
+
+/**
+ * Removes a regular file or symbolic link.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle and `path` must be a valid C string.
+ */
+int32_t zbox_repo_remove_file(struct ZboxRepo *repo, const char *path);
+
+/**
+ * Removes a directory, its children are removed as well if `all` is true,
+ * otherwise it must be empty.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle and `path` must be a valid C string.
+ */
+int32_t zbox_repo_remove_dir(struct ZboxRepo *repo, const char *path, bool all);
+
+/**
+ * Opens a file.
+ *
+ * `opts` can be NULL, which opens the file in read-only mode. The file
+ * handle is stored to `file` on success.
+ *
+ * # Safety
+ *
+ * `repo` must be a valid handle, `path` must be a valid C string and `file`
+ * must be a valid pointer.
+ */
+int32_t zbox_file_open(struct ZboxRepo *repo,
+                       const char *path,
+                       const struct ZboxOpenOptions *opts,
+                       struct ZboxFile **file);
+
+/**
+ * Closes a file and releases its handle.
+ *
+ * # Safety
+ *
+ * `file` must be a handle returned by `zbox_file_open` or NULL.
+ */
+void zbox_file_close(struct ZboxFile *file);
+
+/**
+ * Reads data from file at current position.
+ *
+ * Number of bytes read is stored to `read`, zero means end of file.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle, `buf` must be valid for writing `len`
+ * bytes and `read` must be a valid pointer.
+ */
+int32_t zbox_file_read(struct ZboxFile *file, uint8_t *buf, uintptr_t len, uintptr_t *read);
+
+/**
+ * Writes data to file at current position.
+ *
+ * This starts or continues a multi-part write, call `zbox_file_finish` to
+ * create a new version. Number of bytes written is stored to `written`.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle, `buf` must be valid for reading `len`
+ * bytes and `written` must be a valid pointer.
+ */
+int32_t zbox_file_write(struct ZboxFile *file,
+                        const uint8_t *buf,
+                        uintptr_t len,
+                        uintptr_t *written);
+
+/**
+ * Completes multi-part write and creates a new version.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle.
+ */
+int32_t zbox_file_finish(struct ZboxFile *file);
+
+/**
+ * Writes `buf` to file and creates a new version in one call.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle and `buf` must be valid for reading `len`
+ * bytes.
+ */
+int32_t zbox_file_write_once(struct ZboxFile *file, const uint8_t *buf, uintptr_t len);
+
+/**
+ * Seeks to an offset and stores the new position to `pos`.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle, `pos` must be a valid pointer or NULL.
+ */
+int32_t zbox_file_seek(struct ZboxFile *file,
+                       int64_t offset,
+                       enum ZboxSeekFrom whence,
+                       uint64_t *pos);
+
+/**
+ * Truncates or extends file to `len` and creates a new version.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle.
+ */
+int32_t zbox_file_set_len(struct ZboxFile *file, uintptr_t len);
+
+/**
+ * Gets file metadata.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle and `metadata` must be a valid pointer.
+ */
+int32_t zbox_file_metadata(struct ZboxFile *file, struct ZboxMetadata *metadata);
+
+/**
+ * Gets file history versions.
+ *
+ * The version array and its length are stored to `versions` and `len`, it
+ * must be released by `zbox_history_free`.
+ *
+ * # Safety
+ *
+ * `file` must be a valid handle, `versions` and `len` must be valid
+ * pointers.
+ */
+int32_t zbox_file_history(struct ZboxFile *file, struct ZboxVersion **versions, uintptr_t *len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* ZBOX_H */
@@ -0,0 +1,671 @@
+//! Async facade of repository and file, based on tokio.
+//!
+//! All the storage and crypto work is still done by the blocking API, it is
+//! offloaded to tokio's blocking thread pool. The number of blocking
+//! operations running at the same time is bounded by a semaphore, so a busy
+//! repository will not exhaust the blocking pool.
+
+use std::any::Any;
+use std::cmp::min;
+use std::fmt::{self, Debug};
+use std::future::{poll_fn, Future};
+use std::io::{self, Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use tokio::sync::Semaphore;
+use tokio::task;
+
+use crate::error::{Error, Result};
+use crate::file::File;
+use crate::fs::fnode::{DirEntry, Metadata, Version};
+use crate::repo::{OpenOptions, Repo, RepoInfo, RepoOpener, Transaction};
+
+// default number of blocking operations can run at the same time
+const DEFAULT_POOL_SIZE: usize = 8;
+
+// max number of bytes read or written by one blocking operation
+const MAX_BUF_SIZE: usize = 2 * 1024 * 1024;
+
+// run a blocking operation in the blocking thread pool, the pool permit is
+// moved into the blocking task so the bound is kept even if the caller's
+// future is dropped
+async fn run_blocking<T, F>(pool: &Arc<Semaphore>, f: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let permit = pool
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|_| Error::RepoClosed)?;
+    task::spawn_blocking(move || {
+        let _permit = permit;
+        f()
+    })
+    .await
+    .map_err(|err| Error::from(IoError::other(err)))
+}
+
+/// An async wrapper of [`Repo`].
+///
+/// `AsyncRepo` can be cheaply cloned and shared between tasks. Each
+/// operation is executed on tokio's blocking thread pool, at most
+/// `pool_size` operations can run at the same time. Operations which only
+/// read the repository can run in parallel, while the others are serialised.
+///
+/// This type is only available with the `async` feature.
+///
+/// # Examples
+///
+/// ```
+/// # use zbox::{init_env, AsyncRepo, Result, RepoOpener};
+/// use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+///
+/// # async fn foo() -> Result<()> {
+/// # init_env();
+/// let repo = AsyncRepo::open(
+///     RepoOpener::new().create(true),
+///     "mem://async",
+///     "pwd",
+/// )
+/// .await?;
+///
+/// let mut file = repo.create_file("/foo.txt").await?;
+/// file.write_all(b"Hello, world!").await?;
+/// file.finish().await?;
+///
+/// file.seek(std::io::SeekFrom::Start(0)).await?;
+/// let mut content = String::new();
+/// file.read_to_string(&mut content).await?;
+/// assert_eq!(content, "Hello, world!");
+/// # Ok(())
+/// # }
+/// # tokio::runtime::Builder::new_current_thread()
+/// #     .build()
+/// #     .unwrap()
+/// #     .block_on(foo())
+/// #     .unwrap();
+/// ```
+///
+/// [`Repo`]: struct.Repo.html
+#[derive(Clone)]
+pub struct AsyncRepo {
+    repo: Arc<RwLock<Repo>>,
+    pool: Arc<Semaphore>,
+}
+
+impl AsyncRepo {
+    /// Wraps a repository with the default blocking pool size.
+    pub fn new(repo: Repo) -> Self {
+        Self::with_pool_size(repo, DEFAULT_POOL_SIZE)
+    }
+
+    /// Wraps a repository, at most `pool_size` blocking operations can run
+    /// at the same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pool_size` is zero.
+    pub fn with_pool_size(repo: Repo, pool_size: usize) -> Self {
+        assert!(pool_size > 0, "pool size must be greater than 0");
+        AsyncRepo {
+            repo: Arc::new(RwLock::new(repo)),
+            pool: Arc::new(Semaphore::new(pool_size)),
+        }
+    }
+
+    /// Opens a repository using the specified opener.
+    ///
+    /// See [`RepoOpener::open`] for details.
+    ///
+    /// [`RepoOpener::open`]: struct.RepoOpener.html#method.open
+    pub async fn open(
+        opener: &RepoOpener,
+        uri: &str,
+        pwd: &str,
+    ) -> Result<Self> {
+        let opener = opener.clone();
+        let uri = uri.to_string();
+        let pwd = pwd.to_string();
+        let pool = Arc::new(Semaphore::new(DEFAULT_POOL_SIZE));
+        let repo =
+            run_blocking(&pool, move || opener.open(&uri, &pwd)).await??;
+        Ok(AsyncRepo {
+            repo: Arc::new(RwLock::new(repo)),
+            pool,
+        })
+    }
+
+    /// Runs a closure with mutable access to the wrapped repository on the
+    /// blocking thread pool.
+    ///
+    /// This can be used to call any [`Repo`] method which doesn't have an
+    /// async counterpart.
+    ///
+    /// [`Repo`]: struct.Repo.html
+    pub async fn run<T, F>(&self, oper: F) -> Result<T>
+    where
+        F: FnOnce(&mut Repo) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let repo = self.repo.clone();
+        run_blocking(&self.pool, move || {
+            let mut repo = repo.write().unwrap();
+            oper(&mut repo)
+        })
+        .await?
+    }
+
+    // run a closure with shared access to the wrapped repository
+    async fn run_ref<T, F>(&self, oper: F) -> Result<T>
+    where
+        F: FnOnce(&Repo) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let repo = self.repo.clone();
+        run_blocking(&self.pool, move || {
+            let repo = repo.read().unwrap();
+            oper(&repo)
+        })
+        .await?
+    }
+
+    // wrap a file opened on the blocking thread pool
+    fn wrap_file(&self, file: File) -> AsyncFile {
+        AsyncFile::new(file, self.pool.clone())
+    }
+
+    /// Get repository metadata information.
+    pub async fn info(&self) -> Result<RepoInfo> {
+        self.run_ref(|repo| repo.info()).await
+    }
+
+    /// Returns whether the path points at an existing entity in repository.
+    pub async fn path_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref().to_path_buf();
+        self.run_ref(move |repo| repo.path_exists(path)).await
+    }
+
+    /// Returns whether the path exists in repository and is pointing at
+    /// a regular file.
+    pub async fn is_file<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref().to_path_buf();
+        self.run_ref(move |repo| repo.is_file(path)).await
+    }
+
+    /// Returns whether the path exists in repository and is pointing at
+    /// a directory.
+    pub async fn is_dir<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        let path = path.as_ref().to_path_buf();
+        self.run_ref(move |repo| repo.is_dir(path)).await
+    }
+
+    /// Create a file in read-write mode.
+    ///
+    /// See [`Repo::create_file`] for details.
+    ///
+    /// [`Repo::create_file`]: struct.Repo.html#method.create_file
+    pub async fn create_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<AsyncFile> {
+        let path = path.as_ref().to_path_buf();
+        let file = self.run(move |repo| repo.create_file(path)).await?;
+        Ok(self.wrap_file(file))
+    }
+
+    /// Attempts to open a file in read-only mode.
+    ///
+    /// See [`Repo::open_file`] for details.
+    ///
+    /// [`Repo::open_file`]: struct.Repo.html#method.open_file
+    pub async fn open_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<AsyncFile> {
+        let path = path.as_ref().to_path_buf();
+        let file = self.run(move |repo| repo.open_file(path)).await?;
+        Ok(self.wrap_file(file))
+    }
+
+    /// Opens a file at path with the options specified by `options`.
+    ///
+    /// See [`OpenOptions::open`] for details.
+    ///
+    /// [`OpenOptions::open`]: struct.OpenOptions.html#method.open
+    pub async fn open_file_with<P: AsRef<Path>>(
+        &self,
+        options: &OpenOptions,
+        path: P,
+    ) -> Result<AsyncFile> {
+        let options = options.clone();
+        let path = path.as_ref().to_path_buf();
+        let file = self.run(move |repo| options.open(repo, path)).await?;
+        Ok(self.wrap_file(file))
+    }
+
+    /// Creates a new, empty directory at the specified path.
+    pub async fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        self.run(move |repo| repo.create_dir(path)).await
+    }
+
+    /// Recursively create a directory and all of its parent components if
+    /// they are missing.
+    pub async fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        self.run(move |repo| repo.create_dir_all(path)).await
+    }
+
+    /// Returns a vector of all the entries within a directory.
+    pub async fn read_dir<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Vec<DirEntry>> {
+        let path = path.as_ref().to_path_buf();
+        self.run_ref(move |repo| repo.read_dir(path)).await
+    }
+
+    /// Get the metadata about a file or directory at specified path.
+    pub async fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        let path = path.as_ref().to_path_buf();
+        self.run_ref(move |repo| repo.metadata(path)).await
+    }
+
+    /// Return a vector of history versions of a regular file.
+    pub async fn history<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Vec<Version>> {
+        let path = path.as_ref().to_path_buf();
+        self.run_ref(move |repo| repo.history(path)).await
+    }
+
+    /// Copies the content of one file to another.
+    pub async fn copy<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> Result<()> {
+        let from = from.as_ref().to_path_buf();
+        let to = to.as_ref().to_path_buf();
+        self.run(move |repo| repo.copy(from, to)).await
+    }
+
+    /// Removes a regular file from the repository.
+    pub async fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        self.run(move |repo| repo.remove_file(path)).await
+    }
+
+    /// Remove an existing empty directory.
+    pub async fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        self.run(move |repo| repo.remove_dir(path)).await
+    }
+
+    /// Removes a directory at this path, after removing all its children.
+    pub async fn remove_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        self.run(move |repo| repo.remove_dir_all(path)).await
+    }
+
+    /// Rename a file or directory to a new name.
+    pub async fn rename<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        from: P,
+        to: Q,
+    ) -> Result<()> {
+        let from = from.as_ref().to_path_buf();
+        let to = to.as_ref().to_path_buf();
+        self.run(move |repo| repo.rename(from, to)).await
+    }
+
+    /// Runs a group of operations in one transaction and commits it.
+    ///
+    /// See [`Repo::transaction`] for details.
+    ///
+    /// [`Repo::transaction`]: struct.Repo.html#method.transaction
+    pub async fn transaction<T, F>(&self, oper: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.run(move |repo| repo.transaction(oper)).await
+    }
+}
+
+impl Debug for AsyncRepo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncRepo")
+            .field("repo", &self.repo)
+            .field("available", &self.pool.available_permits())
+            .finish()
+    }
+}
+
+// result of a blocking file operation
+enum Op {
+    Read(io::Result<Vec<u8>>),
+    Write(io::Result<usize>),
+    Seek(io::Result<u64>),
+    Run(Box<dyn Any + Send>, Option<u64>),
+}
+
+type OpFuture = Pin<Box<dyn Future<Output = Result<(File, Op)>> + Send>>;
+
+// file operation state
+enum State {
+    Idle,
+    Busy(OpFuture),
+}
+
+/// An async wrapper of [`File`].
+///
+/// `AsyncFile` implements tokio's [`AsyncRead`], [`AsyncWrite`] and
+/// [`AsyncSeek`] traits. Only one operation can be in flight at a time, the
+/// wrapped file is moved to the blocking thread pool while the operation is
+/// running.
+///
+/// Like [`File`], data written through [`AsyncWrite`] is not committed until
+/// [`finish`] is called. Shutting down the writer, for example by
+/// `AsyncWriteExt::shutdown`, will also finish the multi-part write.
+///
+/// This type is only available with the `async` feature.
+///
+/// [`File`]: struct.File.html
+/// [`AsyncRead`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncRead.html
+/// [`AsyncWrite`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncWrite.html
+/// [`AsyncSeek`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncSeek.html
+/// [`finish`]: struct.AsyncFile.html#method.finish
+pub struct AsyncFile {
+    file: Option<File>,
+    state: State,
+    pos: u64,
+    pool: Arc<Semaphore>,
+}
+
+impl AsyncFile {
+    fn new(file: File, pool: Arc<Semaphore>) -> Self {
+        AsyncFile {
+            file: Some(file),
+            state: State::Idle,
+            pos: 0,
+            pool,
+        }
+    }
+
+    // start a blocking operation on the file, the file's pending
+    // transaction is moved to the blocking thread while running
+    fn start<F>(&mut self, oper: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut File) -> Op + Send + 'static,
+    {
+        let mut file = self
+            .file
+            .take()
+            .ok_or_else(|| IoError::other(Error::Closed.to_string()))?;
+        let pool = self.pool.clone();
+        let fut = async move {
+            run_blocking(&pool, move || {
+                let op = {
+                    let _guard = file.attach_thread();
+                    oper(&mut file)
+                };
+                (file, op)
+            })
+            .await
+        };
+        self.state = State::Busy(Box::pin(fut));
+        Ok(())
+    }
+
+    // poll the in-flight operation until it completes, returns None if
+    // there is no operation in flight
+    fn poll_op(&mut self, cx: &mut Context) -> Poll<io::Result<Option<Op>>> {
+        let fut = match self.state {
+            State::Idle => return Poll::Ready(Ok(None)),
+            State::Busy(ref mut fut) => fut,
+        };
+        let result = match fut.as_mut().poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.state = State::Idle;
+        let (file, op) = map_io_err!(result)?;
+        self.file = Some(file);
+
+        // keep track of file position
+        match op {
+            Op::Read(Ok(ref data)) => self.pos += data.len() as u64,
+            Op::Write(Ok(written)) => self.pos += written as u64,
+            Op::Seek(Ok(pos)) | Op::Run(_, Some(pos)) => self.pos = pos,
+            _ => {}
+        }
+
+        Poll::Ready(Ok(Some(op)))
+    }
+
+    // wait for the in-flight operation, its result is discarded
+    async fn wait_idle(&mut self) -> Result<()> {
+        poll_fn(|cx| self.poll_op(cx)).await?;
+        Ok(())
+    }
+
+    /// Runs a closure with mutable access to the wrapped file on the
+    /// blocking thread pool.
+    ///
+    /// This can be used to call any [`File`] method which doesn't have an
+    /// async counterpart.
+    ///
+    /// [`File`]: struct.File.html
+    pub async fn run<T, F>(&mut self, oper: F) -> Result<T>
+    where
+        F: FnOnce(&mut File) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.wait_idle().await?;
+        self.start(move |file| {
+            let ret = oper(file);
+            let pos = file.stream_position().ok();
+            Op::Run(Box::new(ret), pos)
+        })?;
+        match poll_fn(|cx| self.poll_op(cx)).await? {
+            Some(Op::Run(ret, _)) => *ret.downcast::<Result<T>>().unwrap(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Complete multi-part write to file and create a new version.
+    ///
+    /// See [`File::finish`] for details.
+    ///
+    /// [`File::finish`]: struct.File.html#method.finish
+    pub async fn finish(&mut self) -> Result<()> {
+        self.run(|file| file.finish()).await
+    }
+
+    /// Single-part write to file and create a new version.
+    ///
+    /// See [`File::write_once`] for details.
+    ///
+    /// [`File::write_once`]: struct.File.html#method.write_once
+    pub async fn write_once(&mut self, buf: &[u8]) -> Result<()> {
+        let buf = buf.to_vec();
+        self.run(move |file| file.write_once(&buf)).await
+    }
+
+    /// Truncates or extends the underlying file, create a new version of
+    /// content which size to become `size`.
+    ///
+    /// See [`File::set_len`] for details.
+    ///
+    /// [`File::set_len`]: struct.File.html#method.set_len
+    pub async fn set_len(&mut self, len: usize) -> Result<()> {
+        self.run(move |file| file.set_len(len)).await
+    }
+
+    /// Queries metadata about the file.
+    pub async fn metadata(&mut self) -> Result<Metadata> {
+        self.run(|file| file.metadata()).await
+    }
+
+    /// Returns a list of all the file content versions.
+    pub async fn history(&mut self) -> Result<Vec<Version>> {
+        self.run(|file| file.history()).await
+    }
+
+    /// Returns the current content version number.
+    pub async fn curr_version(&mut self) -> Result<usize> {
+        self.run(|file| file.curr_version()).await
+    }
+}
+
+impl Debug for AsyncFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncFile")
+            .field("file", &self.file)
+            .field("busy", &matches!(self.state, State::Busy(_)))
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+impl AsyncRead for AsyncFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if let State::Idle = this.state {
+                if buf.remaining() == 0 {
+                    return Poll::Ready(Ok(()));
+                }
+                let len = min(buf.remaining(), MAX_BUF_SIZE);
+                this.start(move |file| {
+                    let mut data = vec![0u8; len];
+                    Op::Read(file.read(&mut data).map(|read| {
+                        data.truncate(read);
+                        data
+                    }))
+                })?;
+            }
+
+            match this.poll_op(cx) {
+                Poll::Ready(Ok(Some(Op::Read(result)))) => {
+                    buf.put_slice(&result?);
+                    return Poll::Ready(Ok(()));
+                }
+                // result of other operation is discarded
+                Poll::Ready(Ok(_)) => continue,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for AsyncFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if let State::Idle = this.state {
+                if buf.is_empty() {
+                    return Poll::Ready(Ok(0));
+                }
+                let data = buf[..min(buf.len(), MAX_BUF_SIZE)].to_vec();
+                this.start(move |file| Op::Write(file.write(&data)))?;
+            }
+
+            match this.poll_op(cx) {
+                Poll::Ready(Ok(Some(Op::Write(result)))) => {
+                    return Poll::Ready(result);
+                }
+                // result of other operation is discarded
+                Poll::Ready(Ok(_)) => continue,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        // written data is already handed over to the file, so flush only
+        // needs to wait for the in-flight operation
+        match self.get_mut().poll_op(cx) {
+            Poll::Ready(Ok(Some(Op::Write(Err(err))))) => Poll::Ready(Err(err)),
+            Poll::Ready(result) => Poll::Ready(result.map(|_| ())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if let State::Idle = this.state {
+                this.start(|file| {
+                    // finish without any write is not an error here
+                    let ret = match file.finish() {
+                        Err(Error::NotWrite) => Ok(()),
+                        ret => ret,
+                    };
+                    let pos = file.stream_position().ok();
+                    Op::Run(Box::new(ret), pos)
+                })?;
+            }
+
+            match this.poll_op(cx) {
+                Poll::Ready(Ok(Some(Op::Run(ret, _)))) => {
+                    return match ret.downcast::<Result<()>>() {
+                        Ok(ret) => Poll::Ready(map_io_err!(*ret)),
+                        Err(_) => continue,
+                    };
+                }
+                // result of other operation is discarded
+                Poll::Ready(Ok(_)) => continue,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncSeek for AsyncFile {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        if let State::Busy(_) = this.state {
+            return Err(IoError::other("other file operation is in progress"));
+        }
+        this.start(move |file| Op::Seek(file.seek(pos)))
+    }
+
+    fn poll_complete(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        match this.poll_op(cx) {
+            Poll::Ready(Ok(Some(Op::Seek(result)))) => Poll::Ready(result),
+            Poll::Ready(Ok(_)) => Poll::Ready(Ok(this.pos)),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
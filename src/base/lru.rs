@@ -3,7 +3,7 @@ use std::fmt::{self, Debug};
 use std::hash::Hash;
 use std::marker::PhantomData;
 
-use linked_hash_map::{Entries, LinkedHashMap};
+use linked_hash_map::LinkedHashMap;
 
 pub trait Meter<T> {
     fn measure(&self, item: &T) -> isize;
@@ -84,11 +84,6 @@ where
         self.map.get_refresh(k)
     }
 
-    #[inline]
-    pub fn entries(&mut self) -> Entries<K, V> {
-        self.map.entries()
-    }
-
     pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<V>
     where
         K: Borrow<Q>,
@@ -100,6 +95,21 @@ where
         })
     }
 
+    // remove all the entries whose value matches the predicate
+    pub fn remove_if<F>(&mut self, mut pred: F)
+    where
+        F: FnMut(&V) -> bool,
+    {
+        let mut delta: isize = 0;
+        for ent in self.map.entries() {
+            if pred(ent.get()) {
+                delta += self.meter.measure(ent.get());
+                ent.remove();
+            }
+        }
+        self.used = (self.used as isize - delta) as usize;
+    }
+
     fn remove_lru(&mut self) -> Option<V> {
         let pin_ckr = self.pin_ckr.clone();
         let ret = self
@@ -30,4 +30,9 @@ impl RefCnt {
             r
         })
     }
+
+    #[inline]
+    pub fn set(&mut self, val: u32) {
+        self.0 = val;
+    }
 }
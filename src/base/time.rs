@@ -26,6 +26,18 @@ impl Time {
         Time(duration)
     }
 
+    /// Create time from system time, return None if it is earlier than
+    /// unix epoch
+    #[inline]
+    pub fn from_system_time(time: SystemTime) -> Option<Self> {
+        time.duration_since(UNIX_EPOCH).ok().map(Time)
+    }
+
+    #[inline]
+    pub fn as_secs(self) -> u64 {
+        self.0.as_secs()
+    }
+
     #[inline]
     pub fn to_system_time(self) -> SystemTime {
         UNIX_EPOCH + self.0
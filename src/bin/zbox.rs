@@ -0,0 +1,481 @@
+//! Command line tool to inspect and manage ZboxFS repositories.
+//!
+//! To build this tool, use the command below:
+//!
+//! $ cargo build --release --features cli
+//!
+//! The repository password is read from `ZBOX_PASSWORD` environment variable
+//! if it is set, otherwise it is prompted from terminal.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use clap::{Parser, Subcommand};
+use zbox::{
+    init_env, Error, ExportOptions, ImportOptions, OpenOptions, Repo,
+    RepoOpener, Result,
+};
+
+// environment variables for passwords
+const PWD_ENV: &str = "ZBOX_PASSWORD";
+const NEW_PWD_ENV: &str = "ZBOX_NEW_PASSWORD";
+
+/// Inspect and manage ZboxFS repositories.
+///
+/// Repository is specified by URI, for example "file:///path/to/repo".
+/// Password is read from ZBOX_PASSWORD environment variable if it is set,
+/// otherwise it is prompted from terminal.
+#[derive(Debug, Parser)]
+#[command(name = "zbox", version)]
+struct Cli {
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// Create a new repository
+    Init {
+        uri: String,
+        /// Enable compression
+        #[arg(long)]
+        compress: bool,
+        /// Default maximum number of file versions
+        #[arg(long)]
+        version_limit: Option<u32>,
+    },
+
+    /// Show repository information
+    Info { uri: String },
+
+    /// List directory entries
+    Ls {
+        uri: String,
+        #[arg(default_value = "/")]
+        path: PathBuf,
+        /// Show type, size and modification time
+        #[arg(short, long)]
+        long: bool,
+    },
+
+    /// Show directory tree
+    Tree {
+        uri: String,
+        #[arg(default_value = "/")]
+        path: PathBuf,
+    },
+
+    /// Copy a host file or directory into repository
+    Put {
+        uri: String,
+        local: PathBuf,
+        path: PathBuf,
+    },
+
+    /// Copy a file or directory from repository to host
+    Get {
+        uri: String,
+        path: PathBuf,
+        local: PathBuf,
+        /// Overwrite existing host files
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Print file content to standard output
+    Cat {
+        uri: String,
+        path: PathBuf,
+        /// Print the specified version instead of the current one
+        #[arg(long)]
+        version: Option<usize>,
+    },
+
+    /// Copy a file inside repository
+    Cp {
+        uri: String,
+        from: PathBuf,
+        to: PathBuf,
+        /// Copy directories recursively
+        #[arg(short, long)]
+        recursive: bool,
+    },
+
+    /// Move or rename a file or directory
+    Mv {
+        uri: String,
+        from: PathBuf,
+        to: PathBuf,
+    },
+
+    /// Remove a file or directory
+    Rm {
+        uri: String,
+        path: PathBuf,
+        /// Remove directories and their content recursively
+        #[arg(short, long)]
+        recursive: bool,
+    },
+
+    /// Create a directory
+    Mkdir {
+        uri: String,
+        path: PathBuf,
+        /// Create parent directories as needed
+        #[arg(short, long)]
+        parents: bool,
+    },
+
+    /// Show file version history
+    History { uri: String, path: PathBuf },
+
+    /// Restore a history version as the current file content
+    Restore {
+        uri: String,
+        path: PathBuf,
+        version: usize,
+    },
+
+    /// Change repository password
+    Passwd { uri: String },
+
+    /// Repair repository super block
+    RepairSb { uri: String },
+
+    /// Permanently destroy a repository
+    Destroy {
+        uri: String,
+        /// Do not ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+// read password from environment variable or terminal
+fn read_password(env_name: &str, prompt: &str) -> Result<String> {
+    if let Ok(pwd) = env::var(env_name) {
+        return Ok(pwd);
+    }
+    Ok(rpassword::prompt_password(prompt)?)
+}
+
+// open an existing repository
+fn open_repo(uri: &str, read_only: bool) -> Result<Repo> {
+    let pwd = read_password(PWD_ENV, "Password: ")?;
+    RepoOpener::new().read_only(read_only).open(uri, &pwd)
+}
+
+fn format_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+fn init(uri: &str, compress: bool, version_limit: Option<u32>) -> Result<()> {
+    let pwd = read_password(PWD_ENV, "New password: ")?;
+    if env::var(PWD_ENV).is_err() {
+        let confirm = read_password(PWD_ENV, "Confirm password: ")?;
+        if pwd != confirm {
+            return Err(Error::InvalidArgument);
+        }
+    }
+
+    let mut opener = RepoOpener::new();
+    opener.create_new(true).compress(compress);
+    if let Some(version_limit) = version_limit {
+        opener.version_limit(version_limit);
+    }
+    opener.open(uri, &pwd)?;
+    println!("created repository {}", uri);
+    Ok(())
+}
+
+fn info(uri: &str) -> Result<()> {
+    let repo = open_repo(uri, true)?;
+    let info = repo.info()?;
+    println!("uri:             {}", info.uri());
+    println!("volume id:       {:?}", info.volume_id());
+    println!("version:         {}", info.version());
+    println!("cipher:          {:?}", info.cipher());
+    println!("ops limit:       {:?}", info.ops_limit());
+    println!("mem limit:       {:?}", info.mem_limit());
+    println!("compress:        {}", info.compress());
+    println!("version limit:   {}", info.version_limit());
+    println!("dedup chunk:     {}", info.dedup_chunk());
+    println!("dedup file:      {}", info.dedup_file());
+    println!("chunking:        {:?}", info.chunking_algorithm());
+    println!("trash:           {}", info.trash());
+    println!("created at:      {}", format_time(info.created_at()));
+    Ok(())
+}
+
+fn ls(uri: &str, path: &Path, long: bool) -> Result<()> {
+    let repo = open_repo(uri, true)?;
+    let mut ents = repo.read_dir(path)?;
+    ents.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    for ent in ents {
+        if !long {
+            println!("{}", ent.file_name());
+            continue;
+        }
+        let md = ent.metadata();
+        let kind = if md.is_dir() {
+            'd'
+        } else if md.is_symlink() {
+            'l'
+        } else {
+            '-'
+        };
+        println!(
+            "{}{:04o} {:>12} {} {}",
+            kind,
+            md.mode(),
+            md.content_len(),
+            format_time(md.modified_at()),
+            ent.file_name()
+        );
+    }
+    Ok(())
+}
+
+fn print_tree(repo: &Repo, path: &Path, prefix: &str) -> Result<()> {
+    let mut ents = repo.read_dir(path)?;
+    ents.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    let cnt = ents.len();
+    for (idx, ent) in ents.into_iter().enumerate() {
+        let last = idx + 1 == cnt;
+        let md = ent.metadata();
+        let name = if md.is_symlink() {
+            let target = repo.read_link(ent.path())?;
+            format!("{} -> {}", ent.file_name(), target.display())
+        } else {
+            ent.file_name().to_string()
+        };
+        println!("{}{}{}", prefix, if last { "└── " } else { "├── " }, name);
+        if md.is_dir() {
+            let prefix =
+                format!("{}{}", prefix, if last { "    " } else { "│   " });
+            print_tree(repo, ent.path(), &prefix)?;
+        }
+    }
+    Ok(())
+}
+
+fn tree(uri: &str, path: &Path) -> Result<()> {
+    let repo = open_repo(uri, true)?;
+    println!("{}", path.display());
+    print_tree(&repo, path, "")
+}
+
+fn put(uri: &str, local: &Path, path: &Path) -> Result<()> {
+    let mut repo = open_repo(uri, false)?;
+    if fs::metadata(local)?.is_dir() {
+        let report = repo.import_dir(local, path, &mut ImportOptions::new())?;
+        println!(
+            "{} files, {} directories, {} skipped, {} bytes",
+            report.files(),
+            report.dirs(),
+            report.skipped(),
+            report.bytes()
+        );
+        return Ok(());
+    }
+
+    // only truncate when new content is shorter, to avoid an empty version
+    let mut src = fs::File::open(local)?;
+    let len = src.metadata()?.len();
+    let truncate = repo
+        .metadata(path)
+        .map(|md| md.content_len() as u64 > len)
+        .unwrap_or(false);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(truncate)
+        .open(&mut repo, path)?;
+    let written = io::copy(&mut src, &mut file)?;
+    if written > 0 {
+        file.finish()?;
+    }
+    Ok(())
+}
+
+fn get(uri: &str, path: &Path, local: &Path, force: bool) -> Result<()> {
+    let mut repo = open_repo(uri, true)?;
+    if repo.is_dir(path)? {
+        let report = repo.export_dir(
+            path,
+            local,
+            ExportOptions::new().overwrite(force),
+        )?;
+        println!(
+            "{} files, {} directories, {} bytes",
+            report.files(),
+            report.dirs(),
+            report.bytes()
+        );
+        return Ok(());
+    }
+
+    if !force && local.exists() {
+        return Err(Error::AlreadyExists);
+    }
+    let mut file = repo.open_file(path)?;
+    let mut dst = fs::File::create(local)?;
+    io::copy(&mut file, &mut dst)?;
+    Ok(())
+}
+
+fn cat(uri: &str, path: &Path, version: Option<usize>) -> Result<()> {
+    let mut repo = open_repo(uri, true)?;
+    let mut file = repo.open_file(path)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match version {
+        Some(ver) => io::copy(&mut file.version_reader(ver)?, &mut out)?,
+        None => io::copy(&mut file, &mut out)?,
+    };
+    out.flush()?;
+    Ok(())
+}
+
+fn history(uri: &str, path: &Path) -> Result<()> {
+    let repo = open_repo(uri, true)?;
+    for ver in repo.history(path)? {
+        println!(
+            "{:>6} {:>12} {} {}",
+            ver.num(),
+            ver.content_len(),
+            format_time(ver.created_at()),
+            ver.tag().unwrap_or("")
+        );
+    }
+    Ok(())
+}
+
+fn passwd(uri: &str) -> Result<()> {
+    let old_pwd = read_password(PWD_ENV, "Old password: ")?;
+    let new_pwd = read_password(NEW_PWD_ENV, "New password: ")?;
+    if env::var(NEW_PWD_ENV).is_err() {
+        let confirm = read_password(NEW_PWD_ENV, "Confirm new password: ")?;
+        if new_pwd != confirm {
+            return Err(Error::InvalidArgument);
+        }
+    }
+
+    let mut repo = RepoOpener::new().open(uri, &old_pwd)?;
+    let info = repo.info()?;
+    repo.reset_password(
+        &old_pwd,
+        &new_pwd,
+        info.ops_limit(),
+        info.mem_limit(),
+    )?;
+    println!("password changed");
+    Ok(())
+}
+
+fn repair_sb(uri: &str) -> Result<()> {
+    let pwd = read_password(PWD_ENV, "Password: ")?;
+    Repo::repair_super_block(uri, &pwd)?;
+    println!("super block repaired");
+    Ok(())
+}
+
+fn destroy(uri: &str, yes: bool) -> Result<()> {
+    if !yes {
+        print!("Destroy repository {}? This cannot be undone [y/N] ", uri);
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            return Ok(());
+        }
+    }
+
+    // make sure the password is correct before destroying
+    let pwd = read_password(PWD_ENV, "Password: ")?;
+    drop(RepoOpener::new().read_only(true).open(uri, &pwd)?);
+    Repo::destroy(uri)?;
+    println!("destroyed repository {}", uri);
+    Ok(())
+}
+
+fn run(cmd: Cmd) -> Result<()> {
+    match cmd {
+        Cmd::Init {
+            uri,
+            compress,
+            version_limit,
+        } => init(&uri, compress, version_limit),
+        Cmd::Info { uri } => info(&uri),
+        Cmd::Ls { uri, path, long } => ls(&uri, &path, long),
+        Cmd::Tree { uri, path } => tree(&uri, &path),
+        Cmd::Put { uri, local, path } => put(&uri, &local, &path),
+        Cmd::Get {
+            uri,
+            path,
+            local,
+            force,
+        } => get(&uri, &path, &local, force),
+        Cmd::Cat { uri, path, version } => cat(&uri, &path, version),
+        Cmd::Cp {
+            uri,
+            from,
+            to,
+            recursive,
+        } => {
+            let mut repo = open_repo(&uri, false)?;
+            if recursive {
+                repo.copy_dir_all(&from, &to)
+            } else {
+                repo.copy(&from, &to)
+            }
+        }
+        Cmd::Mv { uri, from, to } => open_repo(&uri, false)?.rename(&from, &to),
+        Cmd::Rm {
+            uri,
+            path,
+            recursive,
+        } => {
+            let mut repo = open_repo(&uri, false)?;
+            if repo.is_dir(&path)? {
+                if recursive {
+                    repo.remove_dir_all(&path)
+                } else {
+                    repo.remove_dir(&path)
+                }
+            } else {
+                repo.remove_file(&path)
+            }
+        }
+        Cmd::Mkdir { uri, path, parents } => {
+            let mut repo = open_repo(&uri, false)?;
+            if parents {
+                repo.create_dir_all(&path)
+            } else {
+                repo.create_dir(&path)
+            }
+        }
+        Cmd::History { uri, path } => history(&uri, &path),
+        Cmd::Restore { uri, path, version } => {
+            open_repo(&uri, false)?.restore_version(&path, version)
+        }
+        Cmd::Passwd { uri } => passwd(&uri),
+        Cmd::RepairSb { uri } => repair_sb(&uri),
+        Cmd::Destroy { uri, yes } => destroy(&uri, yes),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    init_env();
+
+    if let Err(err) = run(cli.cmd) {
+        eprintln!("zbox: {}", err);
+        process::exit(1);
+    }
+}
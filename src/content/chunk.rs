@@ -35,6 +35,16 @@ impl Chunk {
         self.refcnt.dec_ref()
     }
 
+    #[inline]
+    pub fn refcnt(&self) -> u32 {
+        self.refcnt.val()
+    }
+
+    #[inline]
+    pub fn set_refcnt(&mut self, refcnt: u32) {
+        self.refcnt.set(refcnt)
+    }
+
     #[inline]
     pub fn end_pos(&self) -> usize {
         self.pos + self.len
@@ -161,6 +171,11 @@ impl ChunkMap {
         );
     }
 
+    #[inline]
+    pub fn has_segment(&self, seg_id: &Eid) -> bool {
+        self.seg_ids.contains(seg_id)
+    }
+
     pub fn remove_segment(&mut self, seg_id: &Eid) {
         if !self.is_enabled {
             return;
@@ -1,16 +1,19 @@
-use std::cmp::min;
+use std::cmp::{max, min};
 use std::fmt::{self, Debug};
 use std::io::{
     Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom,
     Write,
 };
+use std::ops::Range;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 
 use super::chunk::ChunkMap;
-use super::entry::{CutableList, EntryList};
-use super::merkle_tree::{Leaves, MerkleTree, Writer as MerkleTreeWriter};
+use super::entry::EntryList;
+use super::merkle_tree::{
+    Leaves, MerkleProof, MerkleTree, Writer as MerkleTreeWriter,
+};
 use super::segment::Writer as SegWriter;
 use super::span::{Extent, Span};
 use super::{StoreRef, StoreWeakRef};
@@ -20,6 +23,18 @@ use crate::trans::cow::{CowCache, CowRef, Cowable, IntoCow};
 use crate::trans::{Eid, Finish, Id, TxMgrRef, TxMgrWeakRef, Txid};
 use crate::volume::VolumeWeakRef;
 
+// append a range to sorted ranges, merge it with the last range if they
+// are adjacent
+fn push_range(ranges: &mut Vec<Range<usize>>, range: Range<usize>) {
+    if range.start >= range.end {
+        return;
+    }
+    match ranges.last_mut() {
+        Some(last) if last.end == range.start => last.end = range.end,
+        _ => ranges.push(range),
+    }
+}
+
 /// Content
 #[derive(Default, Clone, Deserialize, Serialize)]
 pub struct Content {
@@ -50,6 +65,12 @@ impl Content {
         self.ents.end_offset()
     }
 
+    // get length of data which is actually stored, excluding holes
+    #[inline]
+    pub fn allocated_len(&self) -> usize {
+        self.ents.allocated_len()
+    }
+
     #[inline]
     pub fn hash(&self) -> &Hash {
         self.mtree.root_hash()
@@ -80,15 +101,33 @@ impl Content {
         Ok(())
     }
 
+    /// Write a hole into content, the hole can extend the content
+    pub fn write_hole(
+        &mut self,
+        offset: usize,
+        len: usize,
+        store: &StoreRef,
+    ) -> Result<()> {
+        assert!(offset <= self.len());
+
+        let mut mtree_wtr = MerkleTreeWriter::new();
+        mtree_wtr.seek(SeekFrom::Start(offset as u64))?;
+        mtree_wtr.write_zeros(len);
+
+        let hole = Content {
+            ents: EntryList::new_hole(offset, len),
+            mtree: MerkleTree::new(),
+            leaves: mtree_wtr.finish_with_leaves(),
+        };
+        self.merge_from(&hole, store)
+    }
+
     pub fn truncate(&mut self, at: usize, store: &StoreRef) -> Result<()> {
         // truncate content
         {
             let store = store.read().unwrap();
             assert!(at <= self.len());
-            let pos = self.ents.locate(at);
-            let seg_ref = store.get_seg(self.ents[pos].seg_id())?;
-            let seg = seg_ref.read().unwrap();
-            self.ents.split_off(at, &seg);
+            self.ents.split_off_with(at, &store)?;
         }
 
         // truncate merkle tree
@@ -98,6 +137,73 @@ impl Content {
         Ok(())
     }
 
+    /// Get byte ranges which are different from another content
+    ///
+    /// Data locations referenced by the two contents are compared first,
+    /// then the ranges referring to different locations are compared by
+    /// merkle tree leaves, so no data is read.
+    pub fn diff(
+        &self,
+        other: &Content,
+        store: &StoreRef,
+    ) -> Result<Vec<Range<usize>>> {
+        let (refs, other_refs) = {
+            let store = store.read().unwrap();
+            (self.ents.span_refs(&store)?, other.ents.span_refs(&store)?)
+        };
+
+        // compare data locations in the common part
+        let mut diffs = Vec::new();
+        let end = min(self.len(), other.len());
+        let (mut i, mut j, mut pos) = (0, 0, 0);
+        while pos < end {
+            let (ref rng, ref loc) = refs[i];
+            let (ref other_rng, ref other_loc) = other_refs[j];
+            let next = min(rng.end, other_rng.end);
+            let is_same = match (loc, other_loc) {
+                (None, None) => true,
+                (Some((seg_id, at)), Some((other_seg_id, other_at))) => {
+                    seg_id == other_seg_id
+                        && at + pos - rng.start
+                            == other_at + pos - other_rng.start
+                }
+                _ => false,
+            };
+            if !is_same {
+                push_range(&mut diffs, pos..next);
+            }
+            pos = next;
+            if rng.end == next {
+                i += 1;
+            }
+            if other_rng.end == next {
+                j += 1;
+            }
+        }
+        push_range(&mut diffs, end..max(self.len(), other.len()));
+
+        // then compare merkle tree leaves
+        let mut ret = Vec::new();
+        for range in diffs {
+            for rng in self.mtree.diff(&other.mtree, range) {
+                push_range(&mut ret, rng);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    // get chunk ranges referenced by this content in each segment
+    pub fn chunk_refs(&self) -> Vec<(Eid, Range<usize>)> {
+        let mut ret = Vec::new();
+        for ent in self.ents.iter().filter(|ent| !ent.is_hole()) {
+            for span in ent.iter() {
+                ret.push((ent.seg_id().clone(), span.begin..span.end));
+            }
+        }
+        ret
+    }
+
     // build reference between content and segment
     #[inline]
     pub fn link(&self, store: &StoreRef, txmgr: &TxMgrRef) -> Result<()> {
@@ -170,17 +276,38 @@ impl Reader {
             store: store.clone(),
         }
     }
-}
 
-impl Read for Reader {
-    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+    /// Verify content data in the range against its merkle tree
+    ///
+    /// Return byte ranges of the corrupted pieces, the reader position is
+    /// not changed.
+    pub fn verify(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        let mut rdr = Reader::new(self.content.clone(), &self.store);
+        self.content.mtree.verify(range, &mut rdr)
+    }
+
+    /// Create inclusion proof for the content piece at index
+    #[inline]
+    pub fn merkle_proof(&self, piece_idx: usize) -> Option<MerkleProof> {
+        self.content.mtree.proof(piece_idx)
+    }
+
+    /// Get content length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.content.len()
+    }
+
+    /// Read data at offset without changing reader position
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> IoResult<usize> {
         if buf.is_empty() {
             return Ok(0);
         }
 
         let store = map_io_err!(self.store.upgrade().ok_or(Error::RepoClosed))?;
         let store = store.read().unwrap();
-        let start = self.pos as usize;
+        let start = offset as usize;
+        let mut pos = start;
         let mut buf_read = 0;
 
         for ent in self
@@ -189,13 +316,31 @@ impl Read for Reader {
             .iter()
             .skip_while(|e| e.end_offset() <= start)
         {
+            // hole is read as zeros
+            if ent.is_hole() {
+                let over_ent = pos - ent.offset();
+                let dst = &mut buf[buf_read..];
+                let read_len = min(ent.len() - over_ent, dst.len());
+                for b in dst[..read_len].iter_mut() {
+                    *b = 0;
+                }
+                buf_read += read_len;
+                pos += read_len;
+
+                // if destination buffer is full, stop reading
+                if buf_read == buf.len() {
+                    return Ok(buf_read);
+                }
+                continue;
+            }
+
             let seg_ref = map_io_err!(store.get_seg(ent.seg_id()))?;
             let seg = seg_ref.read().unwrap();
             let segdata_ref = map_io_err!(store.get_segdata(seg.data_id()))?;
             let segdata = segdata_ref.read().unwrap();
 
             for span in ent.iter().skip_while(|s| s.end_offset() <= start) {
-                let over_span = self.pos as usize - span.offset;
+                let over_span = pos - span.offset;
                 let mut seg_offset = span.offset_in_seg(&seg) + over_span;
                 let mut span_left = span.len - over_span;
 
@@ -212,7 +357,7 @@ impl Read for Reader {
                     buf_read += read;
                     seg_offset += read;
                     span_left -= read;
-                    self.pos += read as u64;
+                    pos += read;
                 }
             }
         }
@@ -221,6 +366,14 @@ impl Read for Reader {
     }
 }
 
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let read = self.read_at(buf, self.pos)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
 impl Seek for Reader {
     fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
         match pos {
@@ -1,5 +1,5 @@
 use std::io::{Result as IoResult, Seek, SeekFrom};
-use std::ops::Index;
+use std::ops::{Index, Range};
 use std::slice::Iter;
 
 use serde::{Deserialize, Serialize};
@@ -26,7 +26,7 @@ pub(super) trait CutableList: Clone + Extent {
             .unwrap_or(0)
     }
 
-    fn split_off(&mut self, at: usize, seg: &Segment) -> Self {
+    fn split_off(&mut self, at: usize, seg: Option<&Segment>) -> Self {
         assert!(self.offset() <= at && at <= self.end_offset());
         let mut pos = self.locate(at);
         let split = {
@@ -58,7 +58,7 @@ pub(super) trait CutableList: Clone + Extent {
         ret
     }
 
-    fn split_to(&mut self, at: usize, seg: &Segment) -> Self {
+    fn split_to(&mut self, at: usize, seg: Option<&Segment>) -> Self {
         assert!(self.offset() <= at && at <= self.end_offset());
         let pos = self.locate(at);
         let split = {
@@ -91,7 +91,12 @@ pub(super) trait CutableList: Clone + Extent {
     }
 }
 
+// content range of a span and its data location
+pub type SpanRef = (Range<usize>, Option<(Eid, usize)>);
+
 /// An entry in content entry list, one entry per segment
+///
+/// An entry with empty segment id is a hole, it has only hole spans.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Entry {
     seg_id: Eid,
@@ -115,6 +120,11 @@ impl Entry {
         &self.seg_id
     }
 
+    #[inline]
+    pub fn is_hole(&self) -> bool {
+        self.seg_id.is_empty()
+    }
+
     #[inline]
     pub fn iter(&self) -> Iter<Span> {
         self.spans.iter()
@@ -159,12 +169,12 @@ impl Extent for Entry {
 
 impl Cutable for Entry {
     #[inline]
-    fn cut_off(&mut self, at: usize, seg: &Segment) -> Self {
+    fn cut_off(&mut self, at: usize, seg: Option<&Segment>) -> Self {
         self.split_off(at, seg)
     }
 
     #[inline]
-    fn cut_to(&mut self, at: usize, seg: &Segment) -> Self {
+    fn cut_to(&mut self, at: usize, seg: Option<&Segment>) -> Self {
         self.split_to(at, seg)
     }
 }
@@ -229,11 +239,30 @@ impl EntryList {
         EntryList::default()
     }
 
+    // create an entry list which has only one hole
+    pub fn new_hole(offset: usize, len: usize) -> Self {
+        let mut elst = EntryList {
+            len: 0,
+            offset,
+            ents: Vec::new(),
+        };
+        elst.append_hole(len);
+        elst
+    }
+
     #[inline]
     pub fn iter(&self) -> Iter<Entry> {
         self.ents.iter()
     }
 
+    // get total length of entries which are not hole
+    pub fn allocated_len(&self) -> usize {
+        self.ents
+            .iter()
+            .filter(|ent| !ent.is_hole())
+            .fold(0, |sum, ent| sum + ent.len)
+    }
+
     // append span
     pub fn append(&mut self, seg_id: &Eid, span: &Span) {
         // try to merge with the last entry
@@ -251,6 +280,36 @@ impl EntryList {
         self.len += span.len;
     }
 
+    // append hole
+    pub fn append_hole(&mut self, len: usize) {
+        if len > 0 {
+            let span = Span::new_hole(len, self.end_offset());
+            self.append(&Eid::new_empty(), &span);
+        }
+    }
+
+    // split off at position, the segment being cut is loaded from store
+    pub fn split_off_with(&mut self, at: usize, store: &Store) -> Result<Self> {
+        let pos = self.locate(at);
+        if self[pos].is_hole() {
+            return Ok(self.split_off(at, None));
+        }
+        let seg_ref = store.get_seg(&self[pos].seg_id)?;
+        let seg = seg_ref.read().unwrap();
+        Ok(self.split_off(at, Some(&seg)))
+    }
+
+    // split to position, the segment being cut is loaded from store
+    pub fn split_to_with(&mut self, at: usize, store: &Store) -> Result<Self> {
+        let pos = self.locate(at);
+        if self[pos].is_hole() {
+            return Ok(self.split_to(at, None));
+        }
+        let seg_ref = store.get_seg(&self[pos].seg_id)?;
+        let seg = seg_ref.read().unwrap();
+        Ok(self.split_to(at, Some(&seg)))
+    }
+
     fn join(&mut self, other: &EntryList) {
         assert_eq!(self.end_offset(), other.offset);
         self.len += other.len;
@@ -276,20 +335,14 @@ impl EntryList {
         };
 
         if at < self.end_offset() {
-            let pos = self.locate(at);
-            let seg_ref = store.get_seg(&self[pos].seg_id)?;
-            let seg = seg_ref.read().unwrap();
-            self.split_off(at, &seg);
+            self.split_off_with(at, store)?;
         }
 
         let head = self.clone();
         self.join(other);
 
         if end_at < tail.end_offset() {
-            let pos = tail.locate(end_at);
-            let seg_ref = store.get_seg(&tail[pos].seg_id)?;
-            let seg = seg_ref.read().unwrap();
-            tail.split_to(end_at, &seg);
+            tail.split_to_with(end_at, store)?;
             self.join(&tail);
         }
 
@@ -298,7 +351,7 @@ impl EntryList {
 
     // create reference relationship between content and segment
     pub fn link(&self, store: &Store, txmgr: &TxMgrRef) -> Result<()> {
-        for ent in self.ents.iter() {
+        for ent in self.ents.iter().filter(|ent| !ent.is_hole()) {
             let seg_ref = store.get_seg(&ent.seg_id)?;
             let mut seg_cow = seg_ref.write().unwrap();
             let seg = seg_cow.make_mut(txmgr)?;
@@ -317,7 +370,7 @@ impl EntryList {
         store: &Store,
         txmgr: &TxMgrRef,
     ) -> Result<()> {
-        for ent in self.ents.iter() {
+        for ent in self.ents.iter().filter(|ent| !ent.is_hole()) {
             let seg_ref = store.get_seg(&ent.seg_id)?;
             let mut seg_cow = seg_ref.write().unwrap();
 
@@ -352,7 +405,7 @@ impl EntryList {
         store: &mut Store,
         txmgr: &TxMgrRef,
     ) -> Result<()> {
-        for ent in self.ents.iter() {
+        for ent in self.ents.iter().filter(|ent| !ent.is_hole()) {
             let seg_ref = store.get_seg(&ent.seg_id)?;
             let mut seg_cow = seg_ref.write().unwrap();
 
@@ -366,6 +419,28 @@ impl EntryList {
         Ok(())
     }
 
+    // get data location of each span, which is the content range and its
+    // begin position in segment, hole span has no data location
+    pub fn span_refs(&self, store: &Store) -> Result<Vec<SpanRef>> {
+        let mut ret = Vec::new();
+        for ent in self.ents.iter() {
+            if ent.is_hole() {
+                for span in ent.spans.iter() {
+                    ret.push((span.offset..span.end_offset(), None));
+                }
+                continue;
+            }
+
+            let seg_ref = store.get_seg(&ent.seg_id)?;
+            let seg = seg_ref.read().unwrap();
+            for span in ent.spans.iter() {
+                let loc = (ent.seg_id.clone(), span.offset_in_seg(&seg));
+                ret.push((span.offset..span.end_offset(), Some(loc)));
+            }
+        }
+        Ok(ret)
+    }
+
     #[allow(dead_code)]
     pub fn check(&self) {
         let mut ents_len = 0;
@@ -519,4 +594,49 @@ mod tests {
 
         elst.check();
     }
+
+    #[test]
+    fn entry_list_hole() {
+        init_env();
+
+        let mut elst = EntryList::new();
+        let id = Eid::new();
+
+        // append data and continuous holes, holes should merge
+        elst.append(&id, &Span::new(0, 1, 0, 10, 0));
+        elst.append_hole(20);
+        elst.append_hole(30);
+        assert_eq!(elst.len, 60);
+        assert_eq!(elst.ents.len(), 2);
+        assert!(elst.ents[1].is_hole());
+        assert_eq!(elst.allocated_len(), 10);
+        elst.check();
+
+        // split inside hole
+        let mut dst = elst.clone();
+        let split = dst.split_off(40, None);
+        dst.check();
+        split.check();
+        assert_eq!(dst.len(), 40);
+        assert_eq!(split.len(), 20);
+        assert_eq!(split.offset(), 40);
+        assert!(split[0].is_hole());
+
+        let mut dst = elst.clone();
+        let split = dst.split_to(40, None);
+        dst.check();
+        split.check();
+        assert_eq!(split.len(), 40);
+        assert_eq!(split.allocated_len(), 10);
+        assert_eq!(dst.len(), 20);
+        assert_eq!(dst.offset(), 40);
+        assert_eq!(dst.allocated_len(), 0);
+
+        // hole only entry list
+        let elst = EntryList::new_hole(100, 50);
+        assert_eq!(elst.offset(), 100);
+        assert_eq!(elst.end_offset(), 150);
+        assert_eq!(elst.allocated_len(), 0);
+        elst.check();
+    }
 }
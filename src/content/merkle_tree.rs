@@ -1,6 +1,7 @@
 use std::cmp::{max, min};
 use std::fmt::{self, Debug};
 use std::io::{Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::ops::Range;
 
 use serde::{Deserialize, Serialize};
 
@@ -90,6 +91,83 @@ impl Leaves {
     }
 }
 
+/// An inclusion proof for a content piece in a merkle tree.
+///
+/// Content is split into pieces of 256 KiB, the piece `i` covers the bytes
+/// from `i * 256 KiB` up to the next piece or the end of content. A proof
+/// contains the sibling hashes along the path from a piece up to the merkle
+/// tree root, so a piece can be checked against a root hash, such as
+/// [`Version::content_hash`], without the rest of the content.
+///
+/// The proof can be serialized and sent to a remote party, which then uses
+/// [`verify_merkle_proof`] to check the piece data it received.
+///
+/// [`Version::content_hash`]: struct.Version.html#method.content_hash
+/// [`verify_merkle_proof`]: fn.verify_merkle_proof.html
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MerkleProof {
+    path: Vec<Hash>,
+}
+
+/// Verifies a content piece against a merkle tree root hash.
+///
+/// `root_hash` is the root hash published for the content, for example,
+/// from [`Version::content_hash`], and `content_len` is the total length of
+/// that content. Returns `true` only if `piece` is the piece at `piece_idx`
+/// of the content with that root hash.
+///
+/// The position of each sibling hash in `proof` is derived from `piece_idx`
+/// and `content_len`, so a proof for another piece never passes.
+///
+/// [`init_env`] must be called before using this function.
+///
+/// [`Version::content_hash`]: struct.Version.html#method.content_hash
+/// [`init_env`]: fn.init_env.html
+pub fn verify_merkle_proof(
+    root_hash: &[u8],
+    content_len: usize,
+    piece_idx: usize,
+    piece: &[u8],
+    proof: &MerkleProof,
+) -> bool {
+    let mut lvl_node_cnt = align_piece_ceil_chunk(content_len);
+    if piece_idx >= lvl_node_cnt {
+        return false;
+    }
+    let offset = piece_idx * PIECE_SIZE;
+    if piece.len() != min(PIECE_SIZE, content_len - offset) {
+        return false;
+    }
+
+    // hash up to root, the last node in a level which has no sibling is
+    // promoted to upper level as is
+    let mut hash = Crypto::hash(piece);
+    let mut path = proof.path.iter();
+    let mut pos = piece_idx;
+    while lvl_node_cnt > 1 {
+        let is_left = pos & 1 == 1;
+        if is_left || pos + 1 < lvl_node_cnt {
+            let sib = match path.next() {
+                Some(sib) => sib,
+                None => return false,
+            };
+            let mut state = Crypto::hash_init();
+            if is_left {
+                Crypto::hash_update(&mut state, sib);
+                Crypto::hash_update(&mut state, &hash);
+            } else {
+                Crypto::hash_update(&mut state, &hash);
+                Crypto::hash_update(&mut state, sib);
+            }
+            hash = Crypto::hash_final(&mut state);
+        }
+        pos /= 2;
+        lvl_node_cnt = lvl_node_cnt.div_ceil(2);
+    }
+
+    path.next().is_none() && *hash == *root_hash
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MerkleTree {
     len: usize,
@@ -321,6 +399,110 @@ impl MerkleTree {
 
         Ok(())
     }
+
+    // create inclusion proof for a piece, the last node in a level which
+    // has no sibling is promoted to upper level as is, so it is skipped
+    pub fn proof(&self, idx: usize) -> Option<MerkleProof> {
+        let mut lvl_node_cnt = self.leaf_cnt();
+        if idx >= lvl_node_cnt {
+            return None;
+        }
+
+        let mut proof = MerkleProof { path: Vec::new() };
+
+        let mut lvl_begin = self.inner_cnt();
+        let mut pos = idx;
+        while lvl_node_cnt > 1 {
+            if pos & 1 == 1 {
+                let sib = self.nodes[lvl_begin + pos - 1].clone();
+                proof.path.push(sib);
+            } else if pos + 1 < lvl_node_cnt {
+                let sib = self.nodes[lvl_begin + pos + 1].clone();
+                proof.path.push(sib);
+            }
+            pos /= 2;
+            lvl_node_cnt = lvl_node_cnt.div_ceil(2);
+            lvl_begin -= lvl_node_cnt;
+        }
+
+        Some(proof)
+    }
+
+    // compare leaf nodes with another tree for pieces overlapping the range,
+    // return the parts of range in mismatched pieces
+    pub fn diff(
+        &self,
+        other: &MerkleTree,
+        range: Range<usize>,
+    ) -> Vec<Range<usize>> {
+        let mut ret: Vec<Range<usize>> = Vec::new();
+        if range.start >= range.end {
+            return ret;
+        }
+
+        let begin_idx = align_piece_floor_chunk(range.start);
+        let end_idx = align_piece_ceil_chunk(range.end);
+
+        for idx in begin_idx..end_idx {
+            let offset = idx * PIECE_SIZE;
+            let piece_end = offset + PIECE_SIZE;
+
+            // piece is same only if it has same length in both trees
+            let is_same = idx < self.leaf_cnt()
+                && min(piece_end, self.len) == min(piece_end, other.len)
+                && self.nodes[self.inner_cnt() + idx]
+                    == other.nodes[other.inner_cnt() + idx];
+            if is_same {
+                continue;
+            }
+
+            let begin = max(offset, range.start);
+            let end = min(piece_end, range.end);
+            match ret.last_mut() {
+                Some(last) if last.end == begin => last.end = end,
+                _ => ret.push(begin..end),
+            }
+        }
+
+        ret
+    }
+
+    // re-hash pieces overlapping the range and compare them with leaf nodes,
+    // return the byte ranges of mismatched pieces
+    pub fn verify<R: Read + Seek>(
+        &self,
+        range: Range<usize>,
+        rdr: &mut R,
+    ) -> Vec<Range<usize>> {
+        let mut ret: Vec<Range<usize>> = Vec::new();
+        let end = min(range.end, self.len);
+        if range.start >= end {
+            return ret;
+        }
+
+        let leaves_begin = self.inner_cnt();
+        let begin_idx = align_piece_floor_chunk(range.start);
+        let end_idx = align_piece_ceil_chunk(end);
+
+        for idx in begin_idx..end_idx {
+            let offset = idx * PIECE_SIZE;
+            let is_good = piece_hash(offset, rdr)
+                .map(|hash| hash == self.nodes[leaves_begin + idx])
+                .unwrap_or(false);
+            if is_good {
+                continue;
+            }
+
+            // merge with the previous corrupted range if it is adjacent
+            let piece_end = min(offset + PIECE_SIZE, self.len);
+            match ret.last_mut() {
+                Some(last) if last.end == offset => last.end = piece_end,
+                _ => ret.push(offset..piece_end),
+            }
+        }
+
+        ret
+    }
 }
 
 impl Default for MerkleTree {
@@ -347,6 +529,30 @@ impl Writer {
         }
     }
 
+    // write zeros, the hash of a full zero piece is calculated only once
+    pub fn write_zeros(&mut self, len: usize) {
+        let zeros = vec![0u8; min(len, PIECE_SIZE)];
+        let mut piece_hash: Option<Hash> = None;
+        let mut left = len;
+
+        while left > 0 {
+            let pos = align_piece_offset(self.hash_offset);
+            let hash_len = min(PIECE_SIZE - pos, left);
+
+            if hash_len == PIECE_SIZE {
+                let hash =
+                    piece_hash.get_or_insert_with(|| Crypto::hash(&zeros));
+                self.leaves.nodes.push(hash.clone());
+                self.leaves.len += hash_len;
+                self.hash_offset += hash_len;
+            } else {
+                self.write_all(&zeros[..hash_len]).unwrap();
+            }
+
+            left -= hash_len;
+        }
+    }
+
     pub fn finish_with_leaves(mut self) -> Leaves {
         if self.leaves.len == 0 || align_piece_offset(self.hash_offset) != 0 {
             self.leaves.nodes.push(Crypto::hash_final(&mut self.state));
@@ -589,6 +795,104 @@ mod tests {
         test_truncate(PIECE_SIZE * 4, PIECE_SIZE * 4);
     }
 
+    #[test]
+    fn verify_merkle_tree() {
+        init_env();
+
+        let len = PIECE_SIZE * 4 + 3;
+        let mut buf = vec![0u8; len];
+        Crypto::random_buf_deterministic(&mut buf, &RandomSeed::default());
+        let mtree = build_mtree(&buf[..]);
+
+        // intact data has no corrupted ranges
+        let mut rdr = Cursor::new(&buf);
+        assert!(mtree.verify(0..len, &mut rdr).is_empty());
+        assert!(mtree.verify(0..0, &mut rdr).is_empty());
+
+        // corrupt the 2nd and 3rd pieces and the last piece
+        buf[PIECE_SIZE + 1] ^= 0xff;
+        buf[PIECE_SIZE * 3 - 1] ^= 0xff;
+        buf[len - 1] ^= 0xff;
+        let mut rdr = Cursor::new(&buf);
+        assert_eq!(
+            mtree.verify(0..len, &mut rdr),
+            vec![PIECE_SIZE..PIECE_SIZE * 3, PIECE_SIZE * 4..len]
+        );
+
+        // verify partial ranges
+        assert!(mtree.verify(0..PIECE_SIZE, &mut rdr).is_empty());
+        assert_eq!(
+            mtree.verify(PIECE_SIZE * 3 - 1..PIECE_SIZE * 3 + 1, &mut rdr),
+            vec![PIECE_SIZE * 2..PIECE_SIZE * 3]
+        );
+        assert_eq!(
+            mtree.verify(len - 1..len * 2, &mut rdr),
+            vec![PIECE_SIZE * 4..len]
+        );
+    }
+
+    fn test_proof(len: usize) {
+        let mut buf = vec![0u8; len];
+        Crypto::random_buf_deterministic(&mut buf, &RandomSeed::default());
+        let mtree = build_mtree(&buf[..]);
+        let root = mtree.root_hash();
+
+        for idx in 0..mtree.leaf_cnt() {
+            let proof = mtree.proof(idx).unwrap();
+            let begin = idx * PIECE_SIZE;
+            let end = min(begin + PIECE_SIZE, len);
+            let mut piece = buf[begin..end].to_vec();
+            assert!(verify_merkle_proof(root, len, idx, &piece, &proof));
+
+            // proof must not pass for other pieces
+            for other in 0..mtree.leaf_cnt() {
+                if other == idx {
+                    continue;
+                }
+                let begin = other * PIECE_SIZE;
+                let end = min(begin + PIECE_SIZE, len);
+                let other_piece = &buf[begin..end];
+                assert!(!verify_merkle_proof(
+                    root,
+                    len,
+                    idx,
+                    other_piece,
+                    &proof
+                ));
+                assert!(!verify_merkle_proof(
+                    root,
+                    len,
+                    other,
+                    other_piece,
+                    &proof
+                ));
+            }
+
+            // tampered piece must fail
+            piece.push(0);
+            assert!(!verify_merkle_proof(root, len, idx, &piece, &proof));
+            piece.pop();
+            if !piece.is_empty() {
+                piece[0] ^= 0xff;
+                assert!(!verify_merkle_proof(root, len, idx, &piece, &proof));
+            }
+        }
+        assert!(mtree.proof(mtree.leaf_cnt()).is_none());
+    }
+
+    #[test]
+    fn merkle_tree_proof() {
+        init_env();
+
+        test_proof(0);
+        test_proof(3);
+        for i in 1..12 {
+            test_proof(PIECE_SIZE * i);
+            test_proof(PIECE_SIZE * i - 1);
+            test_proof(PIECE_SIZE * i + 1);
+        }
+    }
+
     #[test]
     fn truncate_merkle_tree_fuzz() {
         init_env();
@@ -14,4 +14,6 @@ mod store;
 pub use self::chunk::ChunkMap;
 pub use self::chunker::{Chunking, ChunkingAlgorithm};
 pub use self::content::{Content, ContentRef, Reader as ContentReader};
+pub use self::merkle_tree::{verify_merkle_proof, MerkleProof};
+pub use self::segment::{SegData, SegRef, Segment};
 pub use self::store::{Store, StoreRef, StoreWeakRef, Writer};
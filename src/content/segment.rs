@@ -206,15 +206,10 @@ impl DataCache {
     // remove deleted segment data from cache
     pub fn remove_deleted(&self) {
         let mut lru = self.lru.write().unwrap();
-        lru.entries()
-            .filter(|ent| {
-                let cow_ref = ent.get();
-                let cow = cow_ref.read().unwrap();
-                cow.in_trans() && cow.action() == Action::Delete
-            })
-            .for_each(|ent| {
-                ent.remove();
-            });
+        lru.remove_if(|cow_ref| {
+            let cow = cow_ref.read().unwrap();
+            cow.in_trans() && cow.action() == Action::Delete
+        });
     }
 }
 
@@ -255,6 +250,11 @@ impl Segment {
         self.chunks.len() >= Self::MAX_CHUNKS
     }
 
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
     #[inline]
     pub fn is_orphan(&self) -> bool {
         self.used == 0
@@ -303,6 +303,18 @@ impl Segment {
         Ok(())
     }
 
+    // reset chunk reference counts and re-calculate used length
+    pub fn reset_refs(&mut self, refs: &[u32]) {
+        assert_eq!(refs.len(), self.chunks.len());
+        self.used = 0;
+        for (chunk, refcnt) in self.chunks.iter_mut().zip(refs.iter()) {
+            chunk.set_refcnt(*refcnt);
+            if *refcnt > 0 {
+                self.used += chunk.len;
+            }
+        }
+    }
+
     // remove segment and its associated segment data
     pub fn remove(seg_cow: &mut Cow<Segment>, txmgr: &TxMgrRef) -> Result<()> {
         // add segment data to transaction for deletion
@@ -558,7 +570,7 @@ mod tests {
     ) {
         // split at the beginning
         let mut dst = elst.clone();
-        let split = dst.split_off(0, seg_begin);
+        let split = dst.split_off(0, Some(seg_begin));
         dst.check();
         split.check();
         assert!(dst.is_empty());
@@ -569,7 +581,7 @@ mod tests {
 
         // split at the end
         let mut dst = elst.clone();
-        let split = dst.split_off(elst.len(), seg_end);
+        let split = dst.split_off(elst.len(), Some(seg_end));
         dst.check();
         split.check();
         assert_eq!(dst.len(), elst.len());
@@ -580,7 +592,7 @@ mod tests {
     fn test_split_to(elst: &EntryList, seg_begin: &Segment, seg_end: &Segment) {
         // split at the beginning
         let mut dst = elst.clone();
-        let split = dst.split_to(0, seg_begin);
+        let split = dst.split_to(0, Some(seg_begin));
         dst.check();
         split.check();
         assert!(split.is_empty());
@@ -588,7 +600,7 @@ mod tests {
 
         // split at the end
         let mut dst = elst.clone();
-        let split = dst.split_to(elst.len(), seg_end);
+        let split = dst.split_to(elst.len(), Some(seg_end));
         dst.check();
         split.check();
         assert_eq!(split.len(), elst.len());
@@ -608,7 +620,7 @@ mod tests {
         // split off in the middle
         let half = elst.len() / 2;
         let mut dst = elst.clone();
-        let split = dst.split_off(half, &seg);
+        let split = dst.split_off(half, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), half);
@@ -617,7 +629,7 @@ mod tests {
 
         // split off again
         let at = elst.len() / 3;
-        let split = dst.split_off(at, &seg);
+        let split = dst.split_off(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), at);
@@ -627,7 +639,7 @@ mod tests {
         // split to in the middle
         let half = elst.len() / 2;
         let mut dst = elst.clone();
-        let split = dst.split_to(half, &seg);
+        let split = dst.split_to(half, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), half);
@@ -637,7 +649,7 @@ mod tests {
 
         // split to again
         let at = half + elst.len() / 3;
-        let split = dst.split_to(at, &seg);
+        let split = dst.split_to(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.offset(), at);
@@ -661,7 +673,7 @@ mod tests {
         // split off in the middle
         let half = elst.len() / 2;
         let mut dst = elst.clone();
-        let split = dst.split_off(half, &seg);
+        let split = dst.split_off(half, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), half);
@@ -672,7 +684,7 @@ mod tests {
         // split off at 1/3
         let at = elst.len() / 3;
         let mut dst = elst.clone();
-        let split = dst.split_off(at, &seg);
+        let split = dst.split_off(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), at);
@@ -684,7 +696,7 @@ mod tests {
         // split off at 2/3
         let at = elst.len() * 2 / 3;
         let mut dst = elst.clone();
-        let split = dst.split_off(at, &seg);
+        let split = dst.split_off(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), at);
@@ -697,7 +709,7 @@ mod tests {
         // split to in the middle
         let half = elst.len() / 2;
         let mut dst = elst.clone();
-        let split = dst.split_to(half, &seg);
+        let split = dst.split_to(half, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), half);
@@ -708,7 +720,7 @@ mod tests {
         // split to at 1/3
         let at = elst.len() / 3;
         let mut dst = elst.clone();
-        let split = dst.split_to(at, &seg);
+        let split = dst.split_to(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), elst.len() - at);
@@ -721,7 +733,7 @@ mod tests {
         // split to at 2/3
         let at = elst.len() * 2 / 3;
         let mut dst = elst.clone();
-        let split = dst.split_to(at, &seg);
+        let split = dst.split_to(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), elst.len() - at);
@@ -755,7 +767,7 @@ mod tests {
         // split off in the middle
         let half = elst.len() / 2;
         let mut dst = elst.clone();
-        let split = dst.split_off(half, &seg);
+        let split = dst.split_off(half, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), half);
@@ -770,7 +782,7 @@ mod tests {
         // split off at 1/3
         let at = elst.len() / 3;
         let mut dst = elst.clone();
-        let split = dst.split_off(at, &seg);
+        let split = dst.split_off(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), at);
@@ -785,7 +797,7 @@ mod tests {
         // split off at 2/3
         let at = elst.len() * 2 / 3;
         let mut dst = elst.clone();
-        let split = dst.split_off(at, &seg2);
+        let split = dst.split_off(at, Some(&seg2));
         dst.check();
         split.check();
         assert_eq!(dst.len(), at);
@@ -801,7 +813,7 @@ mod tests {
         // split to in the middle
         let half = elst.len() / 2;
         let mut dst = elst.clone();
-        let split = dst.split_to(half, &seg);
+        let split = dst.split_to(half, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), half);
@@ -812,7 +824,7 @@ mod tests {
         // split to at 1/3
         let at = elst.len() / 3;
         let mut dst = elst.clone();
-        let split = dst.split_to(at, &seg);
+        let split = dst.split_to(at, Some(&seg));
         dst.check();
         split.check();
         assert_eq!(dst.len(), elst.len() - at);
@@ -827,7 +839,7 @@ mod tests {
         // split to at 2/3
         let at = elst.len() * 2 / 3;
         let mut dst = elst.clone();
-        let split = dst.split_to(at, &seg2);
+        let split = dst.split_to(at, Some(&seg2));
         dst.check();
         split.check();
         assert_eq!(dst.len(), elst.len() - at);
@@ -22,12 +22,16 @@ pub(super) trait Extent {
     }
 }
 
+// segment is None when cutting a hole
 pub(super) trait Cutable: Clone {
-    fn cut_off(&mut self, at: usize, seg: &Segment) -> Self;
-    fn cut_to(&mut self, at: usize, seg: &Segment) -> Self;
+    fn cut_off(&mut self, at: usize, seg: Option<&Segment>) -> Self;
+    fn cut_to(&mut self, at: usize, seg: Option<&Segment>) -> Self;
 }
 
 /// Span, continuous area in a segment
+///
+/// A span which refers no chunks (begin == end) is a hole, it has no data
+/// stored and reads as zeros.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Span {
     pub(super) begin: usize,      // begin chunk index
@@ -54,6 +58,11 @@ impl Span {
         }
     }
 
+    #[inline]
+    pub fn new_hole(len: usize, offset: usize) -> Self {
+        Span::new(0, 0, 0, len, offset)
+    }
+
     #[inline]
     pub fn offset_in_seg(&self, seg: &Segment) -> usize {
         seg[self.begin].pos + self.seg_offset
@@ -119,7 +128,19 @@ impl Extent for Span {
 }
 
 impl Cutable for Span {
-    fn cut_off(&mut self, at: usize, seg: &Segment) -> Self {
+    fn cut_off(&mut self, at: usize, seg: Option<&Segment>) -> Self {
+        let seg = match seg {
+            Some(seg) => seg,
+            None => {
+                // hole has no chunks to align with
+                let mut ret = self.clone();
+                ret.offset = at;
+                ret.len = self.end_offset() - at;
+                self.len = at - self.offset;
+                return ret;
+            }
+        };
+
         let (align_idx, align_at) = self.align_up(at, seg);
         let mut ret = self.clone();
         ret.begin = align_idx;
@@ -135,9 +156,21 @@ impl Cutable for Span {
         ret
     }
 
-    fn cut_to(&mut self, at: usize, seg: &Segment) -> Self {
-        let (align_idx, align_at) = self.align_down(at, seg);
+    fn cut_to(&mut self, at: usize, seg: Option<&Segment>) -> Self {
         let delta = at - self.offset;
+        let seg = match seg {
+            Some(seg) => seg,
+            None => {
+                // hole has no chunks to align with
+                let mut ret = self.clone();
+                ret.len = delta;
+                self.len -= delta;
+                self.offset = at;
+                return ret;
+            }
+        };
+
+        let (align_idx, align_at) = self.align_down(at, seg);
         let mut ret = self.clone();
         self.begin = align_idx;
         self.seg_offset = at - align_at;
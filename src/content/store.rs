@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Debug};
 use std::io::{Result as IoResult, Seek, SeekFrom, Write};
 use std::sync::Arc;
@@ -15,7 +15,7 @@ use super::segment::{
 };
 use super::{ChunkingAlgorithm, Content};
 use crate::base::crypto::Hash;
-use crate::base::RefCnt;
+use crate::base::{RefCnt, Time};
 use crate::error::{Error, Result};
 use crate::trans::cow::{Cow, CowRef, CowWeakRef, Cowable, IntoCow};
 use crate::trans::{Eid, Id, TxMgrRef, TxMgrWeakRef, Txid};
@@ -47,6 +47,76 @@ impl ContentMapEntry {
     }
 }
 
+/// Snapshot entry
+///
+/// A snapshot shares the fnode tree, it is identified by its sequence
+/// number. Fnodes changed after the snapshot keep frozen copies for it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Snapshot {
+    seq: u64,
+    ctime: Time,
+}
+
+impl Snapshot {
+    #[inline]
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    #[inline]
+    pub fn created_at(&self) -> Time {
+        self.ctime
+    }
+}
+
+/// Pinned fnode entry
+///
+/// An fnode changed after snapshots keeps frozen copies of its previous
+/// states as (snapshot sequence, copy id) in sequence order. A copy is seen
+/// by the snapshots whose sequence is greater than the previous copy's and
+/// not greater than its own, later snapshots see the fnode itself.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Pin {
+    // snapshot sequence when the fnode is created
+    born: u64,
+
+    frozen: Vec<(u64, Eid)>,
+
+    // snapshot sequence when the fnode is removed, it is only set if the
+    // fnode itself is kept for snapshots
+    removed: Option<u64>,
+}
+
+impl Pin {
+    fn new(born: u64) -> Self {
+        Pin {
+            born,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn born(&self) -> u64 {
+        self.born
+    }
+
+    #[inline]
+    pub fn frozen(&self) -> &[(u64, Eid)] {
+        &self.frozen
+    }
+
+    #[inline]
+    pub fn removed(&self) -> Option<u64> {
+        self.removed
+    }
+
+    // the last snapshot sequence which doesn't see the fnode itself
+    #[inline]
+    fn frozen_seq(&self) -> Option<u64> {
+        self.frozen.last().map(|(seq, _)| *seq)
+    }
+}
+
 /// Content Store
 #[derive(Default, Clone, Deserialize, Serialize)]
 pub struct Store {
@@ -67,6 +137,19 @@ pub struct Store {
 
     #[serde(skip_serializing, skip_deserializing, default)]
     vol: VolumeRef,
+
+    // snapshots which pin contents in the store, keyed by snapshot name
+    #[serde(default)]
+    snapshots: BTreeMap<String, Snapshot>,
+
+    // the last used snapshot sequence, sequences are never reused
+    #[serde(default)]
+    snapshot_seq: u64,
+
+    // fnodes which have frozen copies or are removed but still seen by
+    // snapshots, keyed by fnode id
+    #[serde(default)]
+    pinned: HashMap<Eid, Pin>,
 }
 
 impl Store {
@@ -88,6 +171,9 @@ impl Store {
             segdata_cache: SegDataCache::new(Self::SEG_DATA_CACHE_SIZE),
             txmgr: txmgr.clone(),
             vol: vol.clone(),
+            snapshots: BTreeMap::new(),
+            snapshot_seq: 0,
+            pinned: HashMap::new(),
         }
     }
 
@@ -109,6 +195,144 @@ impl Store {
         Ok(store)
     }
 
+    #[inline]
+    pub fn is_dedup_file(&self) -> bool {
+        self.dedup_file
+    }
+
+    // get all content map entries as (hash, content id, reference count)
+    pub fn content_refs(&self) -> Vec<(Hash, Eid, u32)> {
+        self.content_map
+            .iter()
+            .map(|(hash, ent)| {
+                (hash.clone(), ent.content_id.clone(), ent.refcnt.val())
+            })
+            .collect()
+    }
+
+    // reset reference count of a content map entry, zero reference count
+    // will remove the entry
+    pub fn reset_content_ref(
+        &mut self,
+        hash: &Hash,
+        content_id: &Eid,
+        refcnt: u32,
+    ) {
+        if refcnt == 0 {
+            self.content_map.remove(hash);
+            return;
+        }
+        let ent = self
+            .content_map
+            .entry(hash.clone())
+            .or_insert_with(ContentMapEntry::new);
+        ent.content_id = content_id.clone();
+        ent.refcnt.set(refcnt);
+    }
+
+    #[inline]
+    pub fn snapshots(&self) -> &BTreeMap<String, Snapshot> {
+        &self.snapshots
+    }
+
+    #[inline]
+    pub fn get_snapshot(&self, name: &str) -> Option<&Snapshot> {
+        self.snapshots.get(name)
+    }
+
+    #[inline]
+    pub fn snapshot_seq(&self) -> u64 {
+        self.snapshot_seq
+    }
+
+    // check if any snapshot sequence is in range (from, to]
+    pub fn has_snapshot_between(&self, from: u64, to: u64) -> bool {
+        self.snapshots
+            .values()
+            .any(|snapshot| snapshot.seq > from && snapshot.seq <= to)
+    }
+
+    // add a snapshot with a new sequence
+    pub fn add_snapshot(&mut self, name: &str) {
+        self.snapshot_seq += 1;
+        self.snapshots.insert(
+            name.to_string(),
+            Snapshot {
+                seq: self.snapshot_seq,
+                ctime: Time::now(),
+            },
+        );
+    }
+
+    #[inline]
+    pub fn remove_snapshot(&mut self, name: &str) -> Option<Snapshot> {
+        self.snapshots.remove(name)
+    }
+
+    #[inline]
+    pub fn pinned(&self) -> &HashMap<Eid, Pin> {
+        &self.pinned
+    }
+
+    // get the last snapshot sequence which doesn't see current state of
+    // the fnode, which is born at the specified sequence
+    pub fn frozen_seq(&self, fnode_id: &Eid, born: u64) -> u64 {
+        self.pinned
+            .get(fnode_id)
+            .and_then(Pin::frozen_seq)
+            .unwrap_or(born)
+    }
+
+    // get id of the frozen fnode copy seen by snapshot, None if the
+    // snapshot sees the fnode itself
+    pub fn frozen_for(&self, fnode_id: &Eid, seq: u64) -> Option<&Eid> {
+        self.pinned.get(fnode_id).and_then(|pin| {
+            pin.frozen
+                .iter()
+                .find(|(frozen_seq, _)| *frozen_seq >= seq)
+                .map(|(_, id)| id)
+        })
+    }
+
+    // add a frozen copy of fnode for the current snapshot sequence
+    pub fn add_frozen(&mut self, fnode_id: &Eid, born: u64, copy_id: &Eid) {
+        let seq = self.snapshot_seq;
+        self.pinned
+            .entry(fnode_id.clone())
+            .or_insert_with(|| Pin::new(born))
+            .frozen
+            .push((seq, copy_id.clone()));
+    }
+
+    // remove a frozen copy of fnode
+    pub fn remove_frozen(&mut self, fnode_id: &Eid, copy_id: &Eid) {
+        if let Some(pin) = self.pinned.get_mut(fnode_id) {
+            pin.frozen.retain(|(_, id)| id != copy_id);
+        }
+    }
+
+    // mark fnode as removed at the current snapshot sequence, it is kept
+    // for snapshots
+    pub fn set_removed(&mut self, fnode_id: &Eid, born: u64) {
+        let seq = self.snapshot_seq;
+        self.pinned
+            .entry(fnode_id.clone())
+            .or_insert_with(|| Pin::new(born))
+            .removed = Some(seq);
+    }
+
+    // clear the removed mark after the fnode is deleted
+    pub fn clear_removed(&mut self, fnode_id: &Eid) {
+        if let Some(pin) = self.pinned.get_mut(fnode_id) {
+            pin.removed = None;
+        }
+    }
+
+    #[inline]
+    pub fn unpin(&mut self, fnode_id: &Eid) {
+        self.pinned.remove(fnode_id);
+    }
+
     #[inline]
     pub fn get_vol_weak(&self) -> VolumeWeakRef {
         Arc::downgrade(&self.vol)
@@ -151,8 +375,9 @@ impl Store {
         let mut store = store.write().unwrap();
 
         if !store.dedup_file {
-            let ctn = content.clone().into_cow(&store.txmgr)?;
-            let ctn = ctn.read().unwrap();
+            let ctn_ref = content.clone().into_cow(&store.txmgr)?;
+            store.content_cache.insert(&ctn_ref);
+            let ctn = ctn_ref.read().unwrap();
             return Ok((true, ctn.id().clone()));
         }
 
@@ -166,9 +391,11 @@ impl Store {
             .or_insert_with(ContentMapEntry::new);
         ent.inc_ref()?;
         if ent.content_id.is_empty() {
-            // no duplication found
-            let ctn = content.clone().into_cow(&txmgr)?;
-            let ctn = ctn.read().unwrap();
+            // no duplication found, put the new content in cache so it
+            // can be found before transaction is committed
+            let ctn_ref = content.clone().into_cow(&txmgr)?;
+            store.content_cache.insert(&ctn_ref);
+            let ctn = ctn_ref.read().unwrap();
             ent.content_id = ctn.id().clone();
             no_dup = true;
         }
@@ -183,14 +410,15 @@ impl Store {
         content_id: &Eid,
     ) -> Result<Option<ContentRef>> {
         let mut store = store.write().unwrap();
+        let txmgr = store.txmgr.clone();
+        let store = store.make_mut(&txmgr)?;
 
+        // content is not shared by versions if file dedup is disabled, it is
+        // still removed in store so it can be evicted from content cache
         if !store.dedup_file {
-            return Ok(None);
+            return store.get_content(content_id).map(Some);
         }
 
-        let txmgr = store.txmgr.clone();
-        let store = store.make_mut(&txmgr)?;
-
         let ctn_ref = store.get_content(content_id)?;
         {
             let ctn = ctn_ref.read().unwrap();
@@ -213,6 +441,8 @@ impl Debug for Store {
         f.debug_struct("Store")
             .field("dedup_file", &self.dedup_file)
             .field("content_map", &self.content_map)
+            .field("snapshots", &self.snapshots)
+            .field("pinned", &self.pinned)
             .finish()
     }
 }
@@ -71,6 +71,9 @@ pub enum Error {
     NotFile,
     NotEmpty,
     NoVersion,
+    NotLink,
+    TooManyLinks,
+    QuotaExceeded,
 
     ReadOnly,
     CannotRead,
@@ -150,6 +153,11 @@ impl Display for Error {
             Error::NotFile => write!(f, "Path is not file"),
             Error::NotEmpty => write!(f, "Directory is not empty"),
             Error::NoVersion => write!(f, "File has no version"),
+            Error::NotLink => write!(f, "Path is not symbolic link"),
+            Error::TooManyLinks => {
+                write!(f, "Too many levels of symbolic links")
+            }
+            Error::QuotaExceeded => write!(f, "Storage quota exceeded"),
 
             Error::ReadOnly => write!(f, "Opened as read only"),
             Error::CannotRead => write!(f, "Cannot read file"),
@@ -240,6 +248,11 @@ impl From<VarError> for Error {
 
 impl From<IoError> for Error {
     fn from(err: IoError) -> Error {
+        // unwrap the error passed through IO traits, such as Write
+        if err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
         Error::Io(err)
     }
 }
@@ -329,6 +342,9 @@ impl From<Error> for i32 {
             Error::NotFile => -1058,
             Error::NotEmpty => -1059,
             Error::NoVersion => -1060,
+            Error::NotLink => -1061,
+            Error::TooManyLinks => -1062,
+            Error::QuotaExceeded => -1063,
 
             Error::ReadOnly => -1070,
             Error::CannotRead => -1071,
@@ -410,6 +426,9 @@ impl PartialEq for Error {
             (&Error::NotFile, &Error::NotFile) => true,
             (&Error::NotEmpty, &Error::NotEmpty) => true,
             (&Error::NoVersion, &Error::NoVersion) => true,
+            (&Error::NotLink, &Error::NotLink) => true,
+            (&Error::TooManyLinks, &Error::TooManyLinks) => true,
+            (&Error::QuotaExceeded, &Error::QuotaExceeded) => true,
 
             (&Error::ReadOnly, &Error::ReadOnly) => true,
             (&Error::CannotRead, &Error::CannotRead) => true,
@@ -0,0 +1,1016 @@
+//! C API.
+//!
+//! All functions return `ZBOX_OK` on success, or a negative error code
+//! converted from [`Error`] on failure. The error message of the last failed
+//! call on the current thread can be retrieved by
+//! `zbox_last_error_message`.
+//!
+//! Repositories and files are opaque handles, they must be released by
+//! `zbox_repo_close` and `zbox_file_close`. Arrays returned by this API
+//! must be released by the corresponding `free` function.
+//!
+//! The C header `include/zbox.h` is generated by cbindgen when building with
+//! `ffi` feature. It is written to the build script's `OUT_DIR`, and is only
+//! written to the directory in `ZBOX_C_HEADER_DIR` environment variable if
+//! that is set.
+//!
+//! [`Error`]: ../enum.Error.html
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+use crate::file::File;
+use crate::fs::fnode::{DirEntry, FileType, Metadata, Version};
+use crate::repo::{OpenOptions, Repo, RepoOpener};
+
+/// Success return code.
+pub const ZBOX_OK: i32 = 0;
+
+/// Return code when a panic is caught at the API boundary.
+pub const ZBOX_ERR_PANIC: i32 = -1;
+
+/// Error codes returned by the C API, same as the codes converted from
+/// [`Error`].
+///
+/// [`Error`]: ../enum.Error.html
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZboxError {
+    RefOverflow = -1000,
+    RefUnderflow = -1001,
+
+    InitCrypto = -1010,
+    NoAesHardware = -1011,
+    Hashing = -1012,
+    InvalidCost = -1013,
+    InvalidCipher = -1014,
+    Encrypt = -1015,
+    Decrypt = -1016,
+
+    InvalidUri = -1020,
+    InvalidSuperBlk = -1021,
+    Corrupted = -1022,
+    WrongVersion = -1023,
+    NoEntity = -1024,
+    NotInSync = -1025,
+    RepoOpened = -1026,
+    RepoClosed = -1027,
+    RepoExists = -1028,
+
+    InTrans = -1030,
+    NotInTrans = -1031,
+    NoTrans = -1032,
+    Uncompleted = -1033,
+    InUse = -1034,
+
+    NoContent = -1040,
+
+    InvalidArgument = -1050,
+    InvalidPath = -1051,
+    NotFound = -1052,
+    AlreadyExists = -1053,
+    IsRoot = -1054,
+    IsDir = -1055,
+    IsFile = -1056,
+    NotDir = -1057,
+    NotFile = -1058,
+    NotEmpty = -1059,
+    NoVersion = -1060,
+    NotLink = -1061,
+    TooManyLinks = -1062,
+    QuotaExceeded = -1063,
+
+    ReadOnly = -1070,
+    CannotRead = -1071,
+    CannotWrite = -1072,
+    NotWrite = -1073,
+    NotFinish = -1074,
+    Closed = -1075,
+
+    Encode = -2000,
+    Decode = -2010,
+    Var = -2020,
+    Io = -2030,
+    Sqlite = -2040,
+    Redis = -2050,
+    Http = -2060,
+    HttpStatus = -2061,
+    Json = -2062,
+    Reqwest = -2063,
+    Jni = -2064,
+    RequestError = -2065,
+}
+
+thread_local! {
+    // error message of the last failed call
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+// run an API call, convert its result to return code and catch panic
+fn wrap<F>(oper: F) -> i32
+where
+    F: FnOnce() -> Result<()>,
+{
+    let (code, msg) = match panic::catch_unwind(AssertUnwindSafe(oper)) {
+        Ok(Ok(_)) => return ZBOX_OK,
+        Ok(Err(err)) => {
+            let msg = err.to_string();
+            (i32::from(err), msg)
+        }
+        Err(_) => (ZBOX_ERR_PANIC, "panic in zbox".to_string()),
+    };
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = CString::new(msg).ok();
+    });
+    code
+}
+
+// convert a C string to str
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str> {
+    if s.is_null() {
+        return Err(Error::InvalidArgument);
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| Error::InvalidArgument)
+}
+
+// convert a C string to path
+unsafe fn to_path<'a>(s: *const c_char) -> Result<&'a Path> {
+    to_str(s).map(Path::new)
+}
+
+// get a mutable reference from pointer
+unsafe fn to_mut<'a, T>(p: *mut T) -> Result<&'a mut T> {
+    p.as_mut().ok_or(Error::InvalidArgument)
+}
+
+// convert a Rust string to C string, interior nul byte is not allowed
+fn to_c_string(s: &str) -> Result<*mut c_char> {
+    CString::new(s)
+        .map(CString::into_raw)
+        .map_err(|_| Error::InvalidArgument)
+}
+
+// release C string created by `to_c_string`
+unsafe fn free_c_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+// move a vector to C array
+fn to_c_array<T>(vec: Vec<T>, out: &mut *mut T, len: &mut usize) {
+    let boxed = vec.into_boxed_slice();
+    *len = boxed.len();
+    *out = Box::into_raw(boxed) as *mut T;
+}
+
+// take back a C array created by `to_c_array`
+unsafe fn from_c_array<T>(arr: *mut T, len: usize) -> Vec<T> {
+    if arr.is_null() {
+        return Vec::new();
+    }
+    Box::from_raw(ptr::slice_from_raw_parts_mut(arr, len)).into_vec()
+}
+
+// convert system time to seconds since unix epoch
+fn to_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or(0)
+}
+
+/// Opaque repository handle.
+pub struct ZboxRepo {
+    repo: Repo,
+}
+
+/// Opaque file handle.
+pub struct ZboxFile {
+    file: File,
+}
+
+/// Options for opening or creating a repository.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZboxRepoOptions {
+    /// Create repository if it doesn't exist.
+    pub create: bool,
+    /// Always create a new repository, fail if it already exists.
+    pub create_new: bool,
+    /// Open repository in read-only mode.
+    pub read_only: bool,
+    /// Enable compression, only used when creating repository.
+    pub compress: bool,
+    /// Default maximum number of file versions, only used when creating
+    /// repository. Zero means the default value.
+    pub version_limit: u32,
+}
+
+/// Options for opening a file.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ZboxOpenOptions {
+    /// Open for reading.
+    pub read: bool,
+    /// Open for writing.
+    pub write: bool,
+    /// Open in append mode.
+    pub append: bool,
+    /// Truncate file to zero length.
+    pub truncate: bool,
+    /// Create file if it doesn't exist.
+    pub create: bool,
+    /// Always create a new file, fail if it already exists.
+    pub create_new: bool,
+}
+
+/// Seek origin used by `zbox_file_seek`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZboxSeekFrom {
+    /// Offset from start of the file.
+    Start,
+    /// Offset from current position.
+    Current,
+    /// Offset from end of the file.
+    End,
+}
+
+/// File type.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZboxFileType {
+    /// Regular file.
+    File,
+    /// Directory.
+    Dir,
+    /// Symbolic link.
+    Symlink,
+}
+
+impl From<FileType> for ZboxFileType {
+    fn from(ftype: FileType) -> Self {
+        if ftype.is_dir() {
+            ZboxFileType::Dir
+        } else if ftype.is_symlink() {
+            ZboxFileType::Symlink
+        } else {
+            ZboxFileType::File
+        }
+    }
+}
+
+/// Metadata of a file or directory. Times are seconds since unix epoch.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ZboxMetadata {
+    pub file_type: ZboxFileType,
+    pub content_len: u64,
+    pub curr_version: u64,
+    pub created_at: u64,
+    pub modified_at: u64,
+    pub mode: u32,
+}
+
+impl From<Metadata> for ZboxMetadata {
+    fn from(md: Metadata) -> Self {
+        ZboxMetadata {
+            file_type: md.file_type().into(),
+            content_len: md.content_len() as u64,
+            curr_version: md.curr_version() as u64,
+            created_at: to_secs(md.created_at()),
+            modified_at: to_secs(md.modified_at()),
+            mode: md.mode(),
+        }
+    }
+}
+
+/// Directory entry. The strings are owned by the entry array.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ZboxDirEntry {
+    pub path: *mut c_char,
+    pub name: *mut c_char,
+    pub metadata: ZboxMetadata,
+}
+
+impl ZboxDirEntry {
+    fn new(ent: &DirEntry) -> Result<Self> {
+        let path = to_c_string(&ent.path().to_string_lossy())?;
+        let name = match to_c_string(ent.file_name()) {
+            Ok(name) => name,
+            Err(err) => {
+                unsafe { free_c_string(path) };
+                return Err(err);
+            }
+        };
+        Ok(ZboxDirEntry {
+            path,
+            name,
+            metadata: ent.metadata().into(),
+        })
+    }
+}
+
+impl Drop for ZboxDirEntry {
+    fn drop(&mut self) {
+        unsafe {
+            free_c_string(self.path);
+            free_c_string(self.name);
+        }
+    }
+}
+
+/// File content version. Times are seconds since unix epoch.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ZboxVersion {
+    pub num: u64,
+    pub content_len: u64,
+    pub created_at: u64,
+}
+
+impl From<&Version> for ZboxVersion {
+    fn from(ver: &Version) -> Self {
+        ZboxVersion {
+            num: ver.num() as u64,
+            content_len: ver.content_len() as u64,
+            created_at: to_secs(ver.created_at()),
+        }
+    }
+}
+
+/// Initialise zbox environment, must be called before any other function.
+#[no_mangle]
+pub extern "C" fn zbox_init_env() -> i32 {
+    wrap(|| {
+        crate::init_env();
+        Ok(())
+    })
+}
+
+/// Returns the error message of the last failed call on current thread, or
+/// NULL if there is no error. The returned string is valid until next
+/// failed call on the same thread.
+#[no_mangle]
+pub extern "C" fn zbox_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |msg| msg.as_ptr())
+    })
+}
+
+/// Opens a repository.
+///
+/// `opts` can be NULL, which opens an existing repository in read-write
+/// mode. The repository handle is stored to `repo` on success.
+///
+/// # Safety
+///
+/// `uri` and `pwd` must be valid C strings, `repo` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_open(
+    repo: *mut *mut ZboxRepo,
+    uri: *const c_char,
+    pwd: *const c_char,
+    opts: *const ZboxRepoOptions,
+) -> i32 {
+    wrap(|| {
+        let out = to_mut(repo)?;
+        let opts = opts.as_ref().copied().unwrap_or_default();
+        let mut opener = RepoOpener::new();
+        opener
+            .create(opts.create)
+            .create_new(opts.create_new)
+            .read_only(opts.read_only)
+            .compress(opts.compress);
+        if opts.version_limit > 0 {
+            opener.version_limit(opts.version_limit);
+        }
+        let inner = opener.open(to_str(uri)?, to_str(pwd)?)?;
+        *out = Box::into_raw(Box::new(ZboxRepo { repo: inner }));
+        Ok(())
+    })
+}
+
+/// Closes a repository and releases its handle.
+///
+/// # Safety
+///
+/// `repo` must be a handle returned by `zbox_repo_open` or NULL, all files
+/// opened from it should be closed before.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_close(repo: *mut ZboxRepo) {
+    if !repo.is_null() {
+        drop(Box::from_raw(repo));
+    }
+}
+
+/// Checks if a repository exists at `uri`.
+///
+/// # Safety
+///
+/// `uri` must be a valid C string, `result` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_exists(
+    uri: *const c_char,
+    result: *mut bool,
+) -> i32 {
+    wrap(|| {
+        *to_mut(result)? = Repo::exists(to_str(uri)?)?;
+        Ok(())
+    })
+}
+
+/// Checks if `path` points at an existing entity in repository.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle, `path` must be a valid C string and
+/// `result` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_path_exists(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+    result: *mut bool,
+) -> i32 {
+    wrap(|| {
+        let repo = to_mut(repo)?;
+        *to_mut(result)? = repo.repo.path_exists(to_path(path)?)?;
+        Ok(())
+    })
+}
+
+/// Creates a directory, parent directories are created as well if `all` is
+/// true.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle and `path` must be a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_create_dir(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+    all: bool,
+) -> i32 {
+    wrap(|| {
+        let repo = to_mut(repo)?;
+        let path = to_path(path)?;
+        if all {
+            repo.repo.create_dir_all(path)
+        } else {
+            repo.repo.create_dir(path)
+        }
+    })
+}
+
+/// Lists entries of a directory.
+///
+/// The entry array and its length are stored to `entries` and `len`, it
+/// must be released by `zbox_dir_entries_free`.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle, `path` must be a valid C string, `entries`
+/// and `len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_read_dir(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+    entries: *mut *mut ZboxDirEntry,
+    len: *mut usize,
+) -> i32 {
+    wrap(|| {
+        let repo = to_mut(repo)?;
+        let (entries, len) = (to_mut(entries)?, to_mut(len)?);
+        let ents = repo
+            .repo
+            .read_dir(to_path(path)?)?
+            .iter()
+            .map(ZboxDirEntry::new)
+            .collect::<Result<Vec<_>>>()?;
+        to_c_array(ents, entries, len);
+        Ok(())
+    })
+}
+
+/// Releases directory entry array returned by `zbox_repo_read_dir`.
+///
+/// # Safety
+///
+/// `entries` and `len` must be returned by `zbox_repo_read_dir`.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_dir_entries_free(
+    entries: *mut ZboxDirEntry,
+    len: usize,
+) {
+    drop(from_c_array(entries, len));
+}
+
+/// Gets metadata of a file or directory.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle, `path` must be a valid C string and
+/// `metadata` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_metadata(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+    metadata: *mut ZboxMetadata,
+) -> i32 {
+    wrap(|| {
+        let repo = to_mut(repo)?;
+        *to_mut(metadata)? = repo.repo.metadata(to_path(path)?)?.into();
+        Ok(())
+    })
+}
+
+/// Gets history versions of a file.
+///
+/// The version array and its length are stored to `versions` and `len`, it
+/// must be released by `zbox_history_free`.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle, `path` must be a valid C string,
+/// `versions` and `len` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_history(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+    versions: *mut *mut ZboxVersion,
+    len: *mut usize,
+) -> i32 {
+    wrap(|| {
+        let repo = to_mut(repo)?;
+        let (versions, len) = (to_mut(versions)?, to_mut(len)?);
+        let vers = repo.repo.history(to_path(path)?)?;
+        to_c_array(vers.iter().map(ZboxVersion::from).collect(), versions, len);
+        Ok(())
+    })
+}
+
+/// Releases version array returned by `zbox_repo_history` or
+/// `zbox_file_history`.
+///
+/// # Safety
+///
+/// `versions` and `len` must be returned by `zbox_repo_history` or
+/// `zbox_file_history`.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_history_free(
+    versions: *mut ZboxVersion,
+    len: usize,
+) {
+    drop(from_c_array(versions, len));
+}
+
+/// Copies a file.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle, `from` and `to` must be valid C strings.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_copy(
+    repo: *mut ZboxRepo,
+    from: *const c_char,
+    to: *const c_char,
+) -> i32 {
+    wrap(|| to_mut(repo)?.repo.copy(to_path(from)?, to_path(to)?))
+}
+
+/// Renames a file or directory.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle, `from` and `to` must be valid C strings.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_rename(
+    repo: *mut ZboxRepo,
+    from: *const c_char,
+    to: *const c_char,
+) -> i32 {
+    wrap(|| to_mut(repo)?.repo.rename(to_path(from)?, to_path(to)?))
+}
+
+/// Removes a regular file or symbolic link.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle and `path` must be a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_remove_file(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+) -> i32 {
+    wrap(|| to_mut(repo)?.repo.remove_file(to_path(path)?))
+}
+
+/// Removes a directory, its children are removed as well if `all` is true,
+/// otherwise it must be empty.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle and `path` must be a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_repo_remove_dir(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+    all: bool,
+) -> i32 {
+    wrap(|| {
+        let repo = to_mut(repo)?;
+        let path = to_path(path)?;
+        if all {
+            repo.repo.remove_dir_all(path)
+        } else {
+            repo.repo.remove_dir(path)
+        }
+    })
+}
+
+/// Opens a file.
+///
+/// `opts` can be NULL, which opens the file in read-only mode. The file
+/// handle is stored to `file` on success.
+///
+/// # Safety
+///
+/// `repo` must be a valid handle, `path` must be a valid C string and `file`
+/// must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_open(
+    repo: *mut ZboxRepo,
+    path: *const c_char,
+    opts: *const ZboxOpenOptions,
+    file: *mut *mut ZboxFile,
+) -> i32 {
+    wrap(|| {
+        let repo = to_mut(repo)?;
+        let out = to_mut(file)?;
+        let mut open_opts = OpenOptions::new();
+        if let Some(opts) = opts.as_ref() {
+            open_opts
+                .read(opts.read)
+                .write(opts.write)
+                .append(opts.append)
+                .truncate(opts.truncate)
+                .create(opts.create)
+                .create_new(opts.create_new);
+        }
+        let inner = open_opts.open(&mut repo.repo, to_path(path)?)?;
+        *out = Box::into_raw(Box::new(ZboxFile { file: inner }));
+        Ok(())
+    })
+}
+
+/// Closes a file and releases its handle.
+///
+/// # Safety
+///
+/// `file` must be a handle returned by `zbox_file_open` or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_close(file: *mut ZboxFile) {
+    if !file.is_null() {
+        drop(Box::from_raw(file));
+    }
+}
+
+/// Reads data from file at current position.
+///
+/// Number of bytes read is stored to `read`, zero means end of file.
+///
+/// # Safety
+///
+/// `file` must be a valid handle, `buf` must be valid for writing `len`
+/// bytes and `read` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_read(
+    file: *mut ZboxFile,
+    buf: *mut u8,
+    len: usize,
+    read: *mut usize,
+) -> i32 {
+    wrap(|| {
+        let file = to_mut(file)?;
+        let read = to_mut(read)?;
+        if buf.is_null() {
+            return Err(Error::InvalidArgument);
+        }
+        let buf = slice::from_raw_parts_mut(buf, len);
+        *read = file.file.read(buf)?;
+        Ok(())
+    })
+}
+
+/// Writes data to file at current position.
+///
+/// This starts or continues a multi-part write, call `zbox_file_finish` to
+/// create a new version. Number of bytes written is stored to `written`.
+///
+/// # Safety
+///
+/// `file` must be a valid handle, `buf` must be valid for reading `len`
+/// bytes and `written` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_write(
+    file: *mut ZboxFile,
+    buf: *const u8,
+    len: usize,
+    written: *mut usize,
+) -> i32 {
+    wrap(|| {
+        let file = to_mut(file)?;
+        let written = to_mut(written)?;
+        if buf.is_null() {
+            return Err(Error::InvalidArgument);
+        }
+        let buf = slice::from_raw_parts(buf, len);
+        *written = file.file.write(buf)?;
+        Ok(())
+    })
+}
+
+/// Completes multi-part write and creates a new version.
+///
+/// # Safety
+///
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_finish(file: *mut ZboxFile) -> i32 {
+    wrap(|| to_mut(file)?.file.finish())
+}
+
+/// Writes `buf` to file and creates a new version in one call.
+///
+/// # Safety
+///
+/// `file` must be a valid handle and `buf` must be valid for reading `len`
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_write_once(
+    file: *mut ZboxFile,
+    buf: *const u8,
+    len: usize,
+) -> i32 {
+    wrap(|| {
+        let file = to_mut(file)?;
+        if buf.is_null() {
+            return Err(Error::InvalidArgument);
+        }
+        file.file.write_once(slice::from_raw_parts(buf, len))
+    })
+}
+
+/// Seeks to an offset and stores the new position to `pos`.
+///
+/// # Safety
+///
+/// `file` must be a valid handle, `pos` must be a valid pointer or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_seek(
+    file: *mut ZboxFile,
+    offset: i64,
+    whence: ZboxSeekFrom,
+    pos: *mut u64,
+) -> i32 {
+    wrap(|| {
+        let file = to_mut(file)?;
+        let from = match whence {
+            ZboxSeekFrom::Start => {
+                if offset < 0 {
+                    return Err(Error::InvalidArgument);
+                }
+                SeekFrom::Start(offset as u64)
+            }
+            ZboxSeekFrom::Current => SeekFrom::Current(offset),
+            ZboxSeekFrom::End => SeekFrom::End(offset),
+        };
+        let new_pos = file.file.seek(from)?;
+        if let Some(pos) = pos.as_mut() {
+            *pos = new_pos;
+        }
+        Ok(())
+    })
+}
+
+/// Truncates or extends file to `len` and creates a new version.
+///
+/// # Safety
+///
+/// `file` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_set_len(
+    file: *mut ZboxFile,
+    len: usize,
+) -> i32 {
+    wrap(|| to_mut(file)?.file.set_len(len))
+}
+
+/// Gets file metadata.
+///
+/// # Safety
+///
+/// `file` must be a valid handle and `metadata` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_metadata(
+    file: *mut ZboxFile,
+    metadata: *mut ZboxMetadata,
+) -> i32 {
+    wrap(|| {
+        let file = to_mut(file)?;
+        *to_mut(metadata)? = file.file.metadata()?.into();
+        Ok(())
+    })
+}
+
+/// Gets file history versions.
+///
+/// The version array and its length are stored to `versions` and `len`, it
+/// must be released by `zbox_history_free`.
+///
+/// # Safety
+///
+/// `file` must be a valid handle, `versions` and `len` must be valid
+/// pointers.
+#[no_mangle]
+pub unsafe extern "C" fn zbox_file_history(
+    file: *mut ZboxFile,
+    versions: *mut *mut ZboxVersion,
+    len: *mut usize,
+) -> i32 {
+    wrap(|| {
+        let file = to_mut(file)?;
+        let (versions, len) = (to_mut(versions)?, to_mut(len)?);
+        let vers = file.file.history()?;
+        to_c_array(vers.iter().map(ZboxVersion::from).collect(), versions, len);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    #[test]
+    fn ffi_oper() {
+        unsafe {
+            assert_eq!(zbox_init_env(), ZBOX_OK);
+
+            // open repo
+            let mut repo = ptr::null_mut();
+            let opts = ZboxRepoOptions {
+                create: true,
+                version_limit: 3,
+                ..Default::default()
+            };
+            let uri = c("mem://ffi_oper");
+            let pwd = c("pwd");
+            assert_eq!(
+                zbox_repo_open(&mut repo, uri.as_ptr(), pwd.as_ptr(), &opts),
+                ZBOX_OK
+            );
+            assert!(!repo.is_null());
+
+            // wrong password returns error code and message
+            let mut repo2 = ptr::null_mut();
+            let bad = c("bad");
+            let ret =
+                zbox_repo_open(&mut repo2, uri.as_ptr(), bad.as_ptr(), &opts);
+            assert!(ret < 0);
+            assert!(!zbox_last_error_message().is_null());
+
+            // null pointer is rejected
+            assert_eq!(
+                zbox_repo_create_dir(repo, ptr::null(), false),
+                ZboxError::InvalidArgument as i32
+            );
+            assert_eq!(
+                ZboxError::InvalidArgument as i32,
+                i32::from(Error::InvalidArgument)
+            );
+
+            // create dir and write file
+            let dir = c("/dir/sub");
+            assert_eq!(zbox_repo_create_dir(repo, dir.as_ptr(), true), ZBOX_OK);
+            let path = c("/dir/file");
+            let mut file = ptr::null_mut();
+            let fopts = ZboxOpenOptions {
+                read: true,
+                write: true,
+                create: true,
+                ..Default::default()
+            };
+            assert_eq!(
+                zbox_file_open(repo, path.as_ptr(), &fopts, &mut file),
+                ZBOX_OK
+            );
+            let mut written = 0;
+            assert_eq!(
+                zbox_file_write(file, b"hello".as_ptr(), 5, &mut written),
+                ZBOX_OK
+            );
+            assert_eq!(written, 5);
+            assert_eq!(
+                zbox_file_write(file, b" world".as_ptr(), 6, &mut written),
+                ZBOX_OK
+            );
+            assert_eq!(zbox_file_finish(file), ZBOX_OK);
+
+            // seek and read
+            let mut pos = 0;
+            assert_eq!(
+                zbox_file_seek(file, 6, ZboxSeekFrom::Start, &mut pos),
+                ZBOX_OK
+            );
+            assert_eq!(pos, 6);
+            let mut buf = [0u8; 16];
+            let mut read = 0;
+            assert_eq!(
+                zbox_file_read(file, buf.as_mut_ptr(), buf.len(), &mut read),
+                ZBOX_OK
+            );
+            assert_eq!(&buf[..read], b"world");
+
+            let mut md: ZboxMetadata = std::mem::zeroed();
+            assert_eq!(zbox_file_metadata(file, &mut md), ZBOX_OK);
+            assert_eq!(md.file_type, ZboxFileType::File);
+            assert_eq!(md.content_len, 11);
+
+            assert_eq!(zbox_file_write_once(file, b"x".as_ptr(), 1), ZBOX_OK);
+            let mut vers = ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(zbox_file_history(file, &mut vers, &mut len), ZBOX_OK);
+            assert_eq!(len, 3);
+            assert_eq!((*vers.add(1)).content_len, 11);
+            assert_eq!((*vers.add(2)).content_len, 12);
+            zbox_history_free(vers, len);
+            zbox_file_close(file);
+
+            // list directory
+            let dir = c("/dir");
+            let mut ents = ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(
+                zbox_repo_read_dir(repo, dir.as_ptr(), &mut ents, &mut len),
+                ZBOX_OK
+            );
+            assert_eq!(len, 2);
+            let mut names: Vec<String> = (0..len)
+                .map(|i| {
+                    let ent = &*ents.add(i);
+                    CStr::from_ptr(ent.name).to_str().unwrap().to_string()
+                })
+                .collect();
+            names.sort();
+            assert_eq!(names, vec!["file", "sub"]);
+            zbox_dir_entries_free(ents, len);
+
+            // repo level metadata, history and removal
+            assert_eq!(
+                zbox_repo_metadata(repo, dir.as_ptr(), &mut md),
+                ZBOX_OK
+            );
+            assert_eq!(md.file_type, ZboxFileType::Dir);
+            let mut vers = ptr::null_mut();
+            assert_eq!(
+                zbox_repo_history(repo, path.as_ptr(), &mut vers, &mut len),
+                ZBOX_OK
+            );
+            assert_eq!(len, 3);
+            zbox_history_free(vers, len);
+
+            let to = c("/dir/copied");
+            assert_eq!(
+                zbox_repo_copy(repo, path.as_ptr(), to.as_ptr()),
+                ZBOX_OK
+            );
+            assert_eq!(zbox_repo_remove_file(repo, to.as_ptr()), ZBOX_OK);
+            let mut exists = true;
+            assert_eq!(
+                zbox_repo_path_exists(repo, to.as_ptr(), &mut exists),
+                ZBOX_OK
+            );
+            assert!(!exists);
+            assert_eq!(zbox_repo_remove_dir(repo, dir.as_ptr(), true), ZBOX_OK);
+
+            zbox_repo_close(repo);
+        }
+    }
+}
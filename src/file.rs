@@ -1,14 +1,19 @@
-use crate::content::ChunkingAlgorithm;
+use crate::content::{ChunkingAlgorithm, MerkleProof};
 use std::fmt::{self, Debug};
 use std::io::{self, Error as IoError, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::Arc;
 
 use super::{Error, Result};
 use crate::fs::fnode::{
     Fnode, Metadata, Reader as FnodeReader, Version, Writer as FnodeWriter,
 };
-use crate::fs::Handle;
+use crate::fs::{ChangeKind, Handle, ShutterRef};
 use crate::trans::{TxHandle, TxMgr};
 
+#[cfg(feature = "async")]
+use crate::trans::{Txid, TxidGuard};
+
 /// A reader for a specific vesion of file content.
 ///
 /// This reader can be obtained by [`version_reader`] method, and it
@@ -39,6 +44,49 @@ impl VersionReader {
             .cloned()
             .ok_or(Error::NoVersion)
     }
+
+    /// Verifies the content data in `range` against its merkle tree.
+    ///
+    /// Content is hashed in pieces of 256 KiB, every piece overlapping
+    /// `range` is re-read from storage and its hash is compared with the one
+    /// recorded when the content was written. Returns the byte ranges of the
+    /// pieces which cannot be read or do not match, so an empty list means
+    /// the data in `range` is intact. The part of `range` beyond the end of
+    /// content is ignored.
+    ///
+    /// This method doesn't change the reader position.
+    pub fn verify_range(
+        &self,
+        range: Range<usize>,
+    ) -> Result<Vec<Range<usize>>> {
+        if range.start > range.end {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(self.rdr.verify(range))
+    }
+
+    /// Creates an inclusion proof for the content piece at `piece_idx`.
+    ///
+    /// Content is hashed in pieces of 256 KiB. The proof can be used by
+    /// [`verify_merkle_proof`] to check a piece against the root hash from
+    /// [`Version::content_hash`], without having the whole content. The
+    /// verifier also needs `piece_idx` and the content length from
+    /// [`Version::content_len`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidArgument`] if `piece_idx` is beyond the last
+    /// piece.
+    ///
+    /// [`verify_merkle_proof`]: fn.verify_merkle_proof.html
+    /// [`Version::content_hash`]: struct.Version.html#method.content_hash
+    /// [`Version::content_len`]: struct.Version.html#method.content_len
+    /// [`Error::InvalidArgument`]: enum.Error.html
+    pub fn merkle_proof(&self, piece_idx: usize) -> Result<MerkleProof> {
+        self.rdr
+            .merkle_proof(piece_idx)
+            .ok_or(Error::InvalidArgument)
+    }
 }
 
 impl Read for VersionReader {
@@ -55,6 +103,86 @@ impl Seek for VersionReader {
     }
 }
 
+/// A cloneable handle for reading a specific version of file content.
+///
+/// This handle can be obtained by [`read_handle`] method. It doesn't have a
+/// cursor, each read specifies its own offset by [`read_at`], so it can be
+/// shared among threads and serve many reads in parallel. Cloning it is
+/// cheap, all the clones share the same pinned version.
+///
+/// # Examples
+///
+/// ```
+/// # use std::io::Write;
+/// # use std::thread;
+/// # use zbox::{init_env, Result, RepoOpener};
+/// # fn foo() -> Result<()> {
+/// # init_env();
+/// # let mut repo = RepoOpener::new().create(true).open("mem://foo", "pwd")?;
+/// let mut file = repo.create_file("/foo.txt")?;
+/// file.write_once(b"Hello, world!")?;
+///
+/// let handle = file.read_handle()?;
+/// let children: Vec<_> = (0..2)
+///     .map(|i| {
+///         let handle = handle.clone();
+///         thread::spawn(move || {
+///             let mut buf = [0u8; 5];
+///             handle.read_at(&mut buf, i * 7).unwrap();
+///             buf
+///         })
+///     })
+///     .collect();
+/// let bufs: Vec<_> = children.into_iter().map(|c| c.join().unwrap()).collect();
+/// assert_eq!(&bufs[0], b"Hello");
+/// assert_eq!(&bufs[1], b"world");
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+///
+/// [`read_handle`]: struct.File.html#method.read_handle
+/// [`read_at`]: struct.ReadHandle.html#method.read_at
+#[derive(Debug, Clone)]
+pub struct ReadHandle {
+    len: usize,
+    rdr: Arc<FnodeReader>,
+    shutter: ShutterRef,
+}
+
+impl ReadHandle {
+    /// Returns the content version number pinned by this handle.
+    #[inline]
+    pub fn version(&self) -> usize {
+        self.rdr.version_num()
+    }
+
+    /// Returns the content length of the pinned version.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the content of the pinned version is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Reads data at `offset` into `buf`, returns the number of bytes read.
+    ///
+    /// Reading at or beyond the end of content returns 0.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        {
+            let shutter = self.shutter.read().unwrap();
+            if shutter.is_closed() {
+                return Err(Error::RepoClosed);
+            }
+        }
+        self.rdr.read_at(buf, offset).map_err(Error::from)
+    }
+}
+
 /// A reference to an opened file in the repository.
 ///
 /// An instance of a `File` can be read and/or written depending on what options
@@ -113,7 +241,7 @@ impl Seek for VersionReader {
 ///
 /// # Versioning
 ///
-/// `File` contents support up to 255 revision versions. [`Version`] is
+/// `File` contents support multiple revision versions. [`Version`] is
 /// immutable once it is created.
 ///
 /// By default, the maximum number of versions of a `File` is `1`, which is
@@ -123,6 +251,10 @@ impl Seek for VersionReader {
 /// After reaching this limit, the oldest [`Version`] will be automatically
 /// deleted after adding a new one.
 ///
+/// A version can be tagged by [`tag_version`] to keep it, tagged versions
+/// are never deleted automatically and not counted in the limit. They can be
+/// read by [`version_reader_by_tag`].
+///
 /// Version number starts from `1` and continuously increases by 1.
 ///
 /// # Writing
@@ -316,6 +448,8 @@ impl Seek for VersionReader {
 /// [`Version`]: struct.Version.html
 /// [`VersionReader`]: struct.VersionReader.html
 /// [`version_limit`]: struct.OpenOptions.html#method.version_limit
+/// [`tag_version`]: struct.File.html#method.tag_version
+/// [`version_reader_by_tag`]: struct.File.html#method.version_reader_by_tag
 /// [`finish`]: struct.File.html#method.finish
 /// [`write_once`]: struct.File.html#method.write_once
 pub struct File {
@@ -379,6 +513,25 @@ impl File {
         Ok(fnode.curr_ver_num())
     }
 
+    /// Verifies the current version of file content against its merkle tree.
+    ///
+    /// Returns the byte ranges which are corrupted, an empty list means the
+    /// whole content is intact. See [`VersionReader::verify_range`] for
+    /// more details.
+    ///
+    /// [`VersionReader::verify_range`]: struct.VersionReader.html#method.verify_range
+    pub fn verify(&self) -> Result<Vec<Range<usize>>> {
+        self.check_closed()?;
+        if !self.can_read {
+            return Err(Error::CannotRead);
+        }
+        let rdr = FnodeReader::new_current(
+            self.handle.fnode.clone(),
+            &self.handle.store,
+        )?;
+        Ok(rdr.verify(0..self.curr_len()))
+    }
+
     /// Returns content byte size of the current version.
     fn curr_len(&self) -> usize {
         let fnode = self.handle.fnode.read().unwrap();
@@ -401,6 +554,204 @@ impl File {
         VersionReader::new(&self.handle, ver_num)
     }
 
+    /// Returns the byte ranges which are different between two versions.
+    ///
+    /// The ranges are sorted and not overlapped, an empty list means the
+    /// two versions have the same content. If two versions have different
+    /// lengths, the range beyond the shorter one is included.
+    ///
+    /// The difference is found by comparing where the data of each version
+    /// is stored, and then comparing their merkle tree leaves. No content
+    /// data is read, so it is fast even for large files. As the merkle tree
+    /// hashes data in 256KB pieces, a changed range might be extended to the
+    /// piece boundaries.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NoVersion`] will be returned if any of the versions doesn't
+    /// exist.
+    ///
+    /// [`Error::NoVersion`]: enum.Error.html#variant.NoVersion
+    pub fn diff_versions(
+        &self,
+        ver_num: usize,
+        other_ver_num: usize,
+    ) -> Result<Vec<Range<usize>>> {
+        self.check_closed()?;
+        if !self.can_read {
+            return Err(Error::CannotRead);
+        }
+        let store = self.handle.store.upgrade().ok_or(Error::RepoClosed)?;
+        let fnode = self.handle.fnode.read().unwrap();
+        fnode.diff_versions(ver_num, other_ver_num, &store)
+    }
+
+    /// Reads data of the current version at `offset` into `buf`, returns
+    /// the number of bytes read.
+    ///
+    /// Unlike [`read`], this method doesn't use or change the file cursor,
+    /// so it can be called through a shared reference. To serve many reads
+    /// from multiple threads, use [`read_handle`] instead.
+    ///
+    /// [`read`]: https://doc.rust-lang.org/std/io/trait.Read.html#tymethod.read
+    /// [`read_handle`]: struct.File.html#method.read_handle
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.check_closed()?;
+        if !self.can_read {
+            return Err(Error::CannotRead);
+        }
+        let rdr = FnodeReader::new_current(
+            self.handle.fnode.clone(),
+            &self.handle.store,
+        )?;
+        rdr.read_at(buf, offset).map_err(Error::from)
+    }
+
+    /// Creates a read handle for the current version.
+    ///
+    /// The handle keeps reading the same version even after new versions
+    /// are written. See [`ReadHandle`] for more details.
+    ///
+    /// [`ReadHandle`]: struct.ReadHandle.html
+    pub fn read_handle(&self) -> Result<ReadHandle> {
+        self.check_closed()?;
+        if !self.can_read {
+            return Err(Error::CannotRead);
+        }
+        let rdr = FnodeReader::new_current(
+            self.handle.fnode.clone(),
+            &self.handle.store,
+        )?;
+        Ok(ReadHandle {
+            len: rdr.len(),
+            rdr: Arc::new(rdr),
+            shutter: self.handle.shutter.clone(),
+        })
+    }
+
+    /// Get a reader of the version with specified tag.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NoVersion`] will be returned if no version has the tag.
+    ///
+    /// [`Error::NoVersion`]: enum.Error.html#variant.NoVersion
+    pub fn version_reader_by_tag(&self, tag: &str) -> Result<VersionReader> {
+        self.check_closed()?;
+        let ver_num = {
+            let fnode = self.handle.fnode.read().unwrap();
+            fnode.ver_by_tag(tag).ok_or(Error::NoVersion)?.num()
+        };
+        self.version_reader(ver_num)
+    }
+
+    /// Tags a version of content.
+    ///
+    /// Tagged version is kept until its tag is removed by
+    /// [`untag_version`], it is not counted in the [`version_limit`]. A tag
+    /// can only be used by one version in a file, and a version can only
+    /// have one tag, tagging a tagged version will replace its tag.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened for writing
+    /// or not finished writing.
+    ///
+    /// - [`Error::InvalidArgument`] will be returned if `tag` is empty or
+    ///   longer than 255 bytes.
+    /// - [`Error::NoVersion`] will be returned if the version doesn't exist.
+    /// - [`Error::AlreadyExists`] will be returned if `tag` is used by
+    ///   another version.
+    ///
+    /// [`untag_version`]: struct.File.html#method.untag_version
+    /// [`version_limit`]: struct.OpenOptions.html#method.version_limit
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    /// [`Error::NoVersion`]: enum.Error.html#variant.NoVersion
+    /// [`Error::AlreadyExists`]: enum.Error.html#variant.AlreadyExists
+    pub fn tag_version(&mut self, ver_num: usize, tag: &str) -> Result<()> {
+        self.check_meta_writable()?;
+
+        let store = self.handle.store.upgrade().ok_or(Error::RepoClosed)?;
+        let txmgr = self.handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        TxMgr::begin_trans(&txmgr)?.run_all_exclusive(|| {
+            let mut fnode = self.handle.fnode.write().unwrap();
+            Fnode::make_mut(&mut fnode, &store, &txmgr)?
+                .tag_version(ver_num, tag)
+        })
+    }
+
+    /// Removes a version tag.
+    ///
+    /// The version is no longer kept after its tag is removed, so it might
+    /// be deleted immediately if the [`version_limit`] is exceeded.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened for writing
+    /// or not finished writing. [`Error::NoVersion`] will be returned if no
+    /// version has the tag.
+    ///
+    /// [`version_limit`]: struct.OpenOptions.html#method.version_limit
+    /// [`Error::NoVersion`]: enum.Error.html#variant.NoVersion
+    pub fn untag_version(&mut self, tag: &str) -> Result<()> {
+        self.check_meta_writable()?;
+
+        let store = self.handle.store.upgrade().ok_or(Error::RepoClosed)?;
+        let txmgr = self.handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        TxMgr::begin_trans(&txmgr)?.run_all_exclusive(|| {
+            let usage = self.handle.quota_usage();
+            {
+                let mut fnode = self.handle.fnode.write().unwrap();
+                Fnode::make_mut(&mut fnode, &store, &txmgr)?
+                    .untag_version(tag, &store, &txmgr)?;
+            }
+            self.handle.charge_quota(usage)
+        })
+    }
+
+    /// Restores a historical version of content.
+    ///
+    /// A new version is created with the same content as version `ver_num`,
+    /// which becomes the current version. The content is shared with the
+    /// historical version, so no data is copied or stored again.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened for writing
+    /// or not finished writing. [`Error::NoVersion`] will be returned if the
+    /// version doesn't exist.
+    ///
+    /// [`Error::NoVersion`]: enum.Error.html#variant.NoVersion
+    pub fn restore_version(&mut self, ver_num: usize) -> Result<()> {
+        self.check_meta_writable()?;
+
+        let store = self.handle.store.upgrade().ok_or(Error::RepoClosed)?;
+        let txmgr = self.handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        TxMgr::begin_trans(&txmgr)?.run_all_exclusive(|| {
+            let usage = self.handle.quota_usage();
+            {
+                let mut fnode = self.handle.fnode.write().unwrap();
+                Fnode::make_mut(&mut fnode, &store, &txmgr)?
+                    .restore_version(ver_num, &store, &txmgr)?;
+            }
+            self.handle.charge_quota(usage)?;
+            self.handle.record_change(ChangeKind::Write)
+        })?;
+
+        // re-create reader if there is an existing reader
+        if self.rdr.is_some() {
+            self.renew_reader()?;
+        }
+
+        Ok(())
+    }
+
     // calculate the seek position from the start based on file current size
     fn seek_pos(&self, pos: SeekFrom) -> SeekFrom {
         let curr_len = self.curr_len();
@@ -461,6 +812,14 @@ impl File {
         Ok(())
     }
 
+    // bind the pending multi-part write transaction to current thread, this
+    // is used when the file is operated from a different thread. The
+    // transaction is unbound when the returned guard is dropped.
+    #[cfg(feature = "async")]
+    pub(crate) fn attach_thread(&self) -> TxidGuard {
+        Txid::attach(self.tx_handle.as_ref().map(|tx_handle| tx_handle.txid))
+    }
+
     // re-create reader on latest version
     fn renew_reader(&mut self) -> Result<()> {
         let mut rdr = FnodeReader::new_current(
@@ -494,8 +853,10 @@ impl File {
                 let mut end_pos = 0;
 
                 tx_handle.run_all_exclusive(|| {
+                    let usage = self.handle.quota_usage();
                     end_pos = wtr.finish()?;
-                    Ok(())
+                    self.handle.charge_quota(usage)?;
+                    self.handle.record_change(ChangeKind::Write)
                 })?;
 
                 // set position
@@ -527,17 +888,22 @@ impl File {
             Some(_) => Err(Error::NotFinish),
             None => {
                 self.begin_write()?;
-                match self.wtr {
+                let result = match self.wtr {
                     Some(ref mut wtr) => match self.tx_handle {
-                        Some(ref tx_handle) => {
-                            tx_handle.run(|| {
-                                wtr.write_all(buf)?;
-                                Ok(())
-                            })?;
-                        }
+                        Some(ref tx_handle) => tx_handle.run(|| {
+                            wtr.write_all(buf)?;
+                            Ok(())
+                        }),
                         None => unreachable!(),
                     },
                     None => unreachable!(),
+                };
+                if let Err(err) = result {
+                    // when write failed the tx has been aborted, so clean up
+                    // writer and tx handle here
+                    self.wtr.take();
+                    self.tx_handle.take();
+                    return Err(err);
                 }
                 self.finish()
             }
@@ -550,7 +916,8 @@ impl File {
     /// If the size is less than the current content size, then the new
     /// content will be shrunk. If it is greater than the current content size,
     /// then the content will be extended to `size` and have all of the
-    /// intermediate data filled in with 0s.
+    /// intermediate data filled in with 0s. The extended range is a hole,
+    /// which costs no storage.
     ///
     /// This method is atomic.
     ///
@@ -571,12 +938,10 @@ impl File {
         let txmgr = self.handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
         let tx_handle = TxMgr::begin_trans(&txmgr)?;
         tx_handle.run_all_exclusive(|| {
-            Fnode::set_len(
-                self.handle.clone(),
-                len,
-                tx_handle.txid,
-                self.chunker,
-            )
+            let usage = self.handle.quota_usage();
+            Fnode::set_len(self.handle.clone(), len)?;
+            self.handle.charge_quota(usage)?;
+            self.handle.record_change(ChangeKind::SetLen)
         })?;
 
         // re-create reader if there is an existing reader
@@ -586,6 +951,134 @@ impl File {
 
         Ok(())
     }
+
+    /// Deallocates the data in `range` of the file and creates a new version
+    /// of content.
+    ///
+    /// The deallocated range becomes a hole, which is read as zeros and
+    /// costs no storage. The file size is not changed, the part of range
+    /// beyond the end of file is ignored.
+    ///
+    /// Extending a file by [`set_len`], or writing after seeking beyond the
+    /// end of file, also creates a hole in the extended range.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened for writing
+    /// or not finished writing.
+    ///
+    /// [`set_len`]: struct.File.html#method.set_len
+    pub fn punch_hole(&mut self, range: Range<usize>) -> Result<()> {
+        self.check_closed()?;
+        if self.wtr.is_some() {
+            return Err(Error::NotFinish);
+        }
+
+        if !self.can_write {
+            return Err(Error::CannotWrite);
+        }
+
+        let txmgr = self.handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        TxMgr::begin_trans(&txmgr)?.run_all_exclusive(|| {
+            let usage = self.handle.quota_usage();
+            Fnode::punch_hole(self.handle.clone(), range)?;
+            self.handle.charge_quota(usage)?;
+            self.handle.record_change(ChangeKind::Write)
+        })?;
+
+        // re-create reader if there is an existing reader
+        if self.rdr.is_some() {
+            self.renew_reader()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value of an extended attribute, or `None` if it doesn't
+    /// exist.
+    pub fn get_xattr(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        self.check_closed()?;
+        let fnode = self.handle.fnode.read().unwrap();
+        Ok(fnode.get_xattr(name))
+    }
+
+    /// Returns names of all the extended attributes, in ascending order.
+    pub fn list_xattrs(&self) -> Result<Vec<String>> {
+        self.check_closed()?;
+        let fnode = self.handle.fnode.read().unwrap();
+        Ok(fnode.list_xattrs())
+    }
+
+    // check if file metadata, such as extended attributes and version
+    // tags, can be changed
+    fn check_meta_writable(&self) -> Result<()> {
+        self.check_closed()?;
+        if self.wtr.is_some() {
+            return Err(Error::NotFinish);
+        }
+        if !self.can_write {
+            return Err(Error::CannotWrite);
+        }
+        Ok(())
+    }
+
+    /// Sets the value of an extended attribute, replacing the existing
+    /// value if any.
+    ///
+    /// Extended attributes are stored encrypted along with the file. They
+    /// are not versioned, so changing them will not create a new version of
+    /// content.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened for writing
+    /// or not finished writing. `name` must not be empty or longer than 255
+    /// bytes, and `value` must not be longer than 64KB, otherwise
+    /// [`Error::InvalidArgument`] will be returned.
+    ///
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    pub fn set_xattr(&mut self, name: &str, value: &[u8]) -> Result<()> {
+        self.check_meta_writable()?;
+        Fnode::check_xattr(name, value)?;
+
+        let store = self.handle.store.upgrade().ok_or(Error::RepoClosed)?;
+        let txmgr = self.handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        TxMgr::begin_trans(&txmgr)?.run_all_exclusive(|| {
+            let mut fnode = self.handle.fnode.write().unwrap();
+            Fnode::make_mut(&mut fnode, &store, &txmgr)?.set_xattr(name, value)
+        })
+    }
+
+    /// Removes an extended attribute, it is no-op if the attribute doesn't
+    /// exist.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if the file is not opened for writing
+    /// or not finished writing.
+    pub fn remove_xattr(&mut self, name: &str) -> Result<()> {
+        self.check_meta_writable()?;
+        {
+            let fnode = self.handle.fnode.read().unwrap();
+            if !fnode.has_xattr(name) {
+                return Ok(());
+            }
+        }
+
+        let store = self.handle.store.upgrade().ok_or(Error::RepoClosed)?;
+        let txmgr = self.handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        TxMgr::begin_trans(&txmgr)?.run_all_exclusive(|| {
+            let mut fnode = self.handle.fnode.write().unwrap();
+            Fnode::make_mut(&mut fnode, &store, &txmgr)?.remove_xattr(name);
+            Ok(())
+        })
+    }
 }
 
 impl Read for File {
@@ -0,0 +1,672 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::mem;
+use std::ops::Range;
+
+use log::{info, warn};
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+
+use super::fnode::{Cache as FnodeCache, Fnode, FnodeRef};
+use super::journal::JournalRef;
+use super::trash::TrashRef;
+use crate::base::crypto::Hash;
+use crate::content::{Content, ContentRef, SegData, SegRef, Segment, StoreRef};
+use crate::error::Result;
+use crate::trans::cow::{Cow, Cowable};
+use crate::trans::trans::Action;
+use crate::trans::{Eid, Id, TxMgr, TxMgrRef, Txid};
+use crate::volume::{Arm, Reader, VolumeRef};
+
+/// Repository check mode.
+///
+/// It is used by [`Repo::check`] to specify whether the found problems should
+/// be fixed.
+///
+/// [`Repo::check`]: struct.Repo.html#method.check
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CheckMode {
+    /// Only check the repository and report problems.
+    Check,
+
+    /// Check the repository and repair the problems can be fixed.
+    Repair,
+}
+
+/// Kind of internal entity checked by [`Repo::check`].
+///
+/// [`Repo::check`]: struct.Repo.html#method.check
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EntityKind {
+    /// File or directory node.
+    Fnode,
+
+    /// File content, which is a list of segment spans.
+    Content,
+
+    /// Segment, which is a list of data chunks.
+    Segment,
+
+    /// The actual data of a segment.
+    SegData,
+}
+
+/// A problem found by [`Repo::check`].
+///
+/// [`Repo::check`]: struct.Repo.html#method.check
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The entity cannot be loaded, decrypted or its structure is broken.
+    Damaged { kind: EntityKind, id: Eid },
+
+    /// Chunk reference count doesn't match the spans referring it.
+    ChunkRefCnt {
+        seg_id: Eid,
+        idx: usize,
+        found: u32,
+        expected: u32,
+    },
+
+    /// Segment used length doesn't match its referred chunks.
+    SegmentUsed {
+        seg_id: Eid,
+        found: usize,
+        expected: usize,
+    },
+
+    /// Content reference count in content store doesn't match the file
+    /// versions referring it.
+    ContentRefCnt {
+        content_id: Eid,
+        found: u32,
+        expected: u32,
+    },
+
+    /// The entity is saved in storage but not reachable from the root
+    /// directory.
+    Orphan { kind: EntityKind, id: Eid },
+}
+
+impl Problem {
+    /// Returns whether this problem can be fixed by repair.
+    pub fn is_repairable(&self) -> bool {
+        !matches!(*self, Problem::Damaged { .. })
+    }
+}
+
+/// Result of a repository check.
+///
+/// This structure is returned from [`Repo::check`].
+///
+/// [`Repo::check`]: struct.Repo.html#method.check
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    fnode_cnt: usize,
+    content_cnt: usize,
+    segment_cnt: usize,
+    problems: Vec<Problem>,
+    repaired: bool,
+}
+
+impl CheckReport {
+    /// Returns number of fnodes checked.
+    pub fn fnode_cnt(&self) -> usize {
+        self.fnode_cnt
+    }
+
+    /// Returns number of contents checked.
+    pub fn content_cnt(&self) -> usize {
+        self.content_cnt
+    }
+
+    /// Returns number of segments checked.
+    pub fn segment_cnt(&self) -> usize {
+        self.segment_cnt
+    }
+
+    /// Returns the problems found.
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    /// Returns whether no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Returns whether the problems have been repaired.
+    ///
+    /// Repair is skipped if any entity is damaged, because the reference
+    /// counts cannot be safely re-calculated without it.
+    pub fn is_repaired(&self) -> bool {
+        self.repaired
+    }
+}
+
+// segment to be checked
+struct SegCheck {
+    id: Eid,
+    refs: Vec<Range<usize>>, // referred chunk ranges
+}
+
+// fixes found by checker
+#[derive(Default)]
+struct Fixes {
+    // content map entry: (hash, content id, new reference count)
+    content_refs: Vec<(Hash, Eid, u32)>,
+
+    // orphan contents to be deleted
+    contents: Vec<ContentRef>,
+
+    // segments with new chunk reference counts
+    segs: Vec<(SegRef, Vec<u32>)>,
+
+    // orphan segments to be deleted
+    orphan_segs: Vec<SegRef>,
+
+    // orphan fnodes to be deleted
+    fnodes: Vec<FnodeRef>,
+
+    // orphan segment data to be deleted
+    segdata: Vec<Eid>,
+}
+
+// get cow id from a saved cow arm, return None if the arm is not a cow of
+// the specified type
+fn cow_id<'de, T>(addr: &Eid, buf: &'de [u8]) -> Option<Eid>
+where
+    T: Cowable + Deserialize<'de> + Serialize + 'static,
+{
+    let mut de = Deserializer::new(buf);
+    let cow: Cow<T> = Deserialize::deserialize(&mut de).ok()?;
+    let (left, right) = Arm::both_eid(cow.id());
+    if *addr == left || *addr == right {
+        Some(cow.id().clone())
+    } else {
+        None
+    }
+}
+
+/// Repository checker
+pub struct Checker<'a> {
+    root: &'a FnodeRef,
+    fcache: &'a FnodeCache,
+    store: &'a StoreRef,
+    trash: &'a TrashRef,
+    txmgr: &'a TxMgrRef,
+    vol: &'a VolumeRef,
+    report: CheckReport,
+    files: Vec<FnodeRef>,
+    fixes: Fixes,
+    addrs: HashSet<Eid>, // addresses of reachable entities
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(
+        root: &'a FnodeRef,
+        fcache: &'a FnodeCache,
+        store: &'a StoreRef,
+        trash: &'a TrashRef,
+        journal: Option<&JournalRef>,
+        txmgr: &'a TxMgrRef,
+        vol: &'a VolumeRef,
+    ) -> Self {
+        let mut checker = Checker {
+            root,
+            fcache,
+            store,
+            trash,
+            txmgr,
+            vol,
+            report: CheckReport::default(),
+            files: Vec::new(),
+            fixes: Fixes::default(),
+            addrs: HashSet::new(),
+        };
+        let store_id = store.read().unwrap().id().clone();
+        checker.reach(&store_id);
+        let trash_id = trash.read().unwrap().id().clone();
+        checker.reach(&trash_id);
+        if let Some(journal) = journal {
+            let journal_id = journal.read().unwrap().id().clone();
+            checker.reach(&journal_id);
+        }
+        checker
+    }
+
+    // mark both arms of a cow entity as reachable
+    #[inline]
+    fn reach(&mut self, id: &Eid) {
+        let (left, right) = Arm::both_eid(id);
+        self.addrs.insert(left);
+        self.addrs.insert(right);
+    }
+
+    #[inline]
+    fn damaged(&mut self, kind: EntityKind, id: &Eid) {
+        warn!("check: damaged {:?} {:?}", kind, id);
+        self.report.problems.push(Problem::Damaged {
+            kind,
+            id: id.clone(),
+        });
+    }
+
+    #[inline]
+    fn orphan(&mut self, kind: EntityKind, id: &Eid) {
+        self.report.problems.push(Problem::Orphan {
+            kind,
+            id: id.clone(),
+        });
+    }
+
+    // walk through fnode tree and return content reference counts
+    fn check_fnodes(&mut self) -> HashMap<Eid, u32> {
+        let mut ver_refs: HashMap<Eid, u32> = HashMap::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![self.root.clone()];
+
+        // trash trees, frozen fnode copies and removed fnodes kept for
+        // snapshots are reachable as well
+        let (other_roots, pinned): (Vec<Eid>, HashSet<Eid>) = {
+            let trash = self.trash.read().unwrap();
+            let mut roots: Vec<Eid> = trash
+                .items()
+                .values()
+                .map(|t| t.root_id().clone())
+                .collect();
+            let store = self.store.read().unwrap();
+            for (id, pin) in store.pinned() {
+                roots.extend(pin.frozen().iter().map(|(_, id)| id.clone()));
+                if pin.removed().is_some() {
+                    roots.push(id.clone());
+                }
+            }
+            (roots, store.pinned().keys().cloned().collect())
+        };
+        for root_id in other_roots {
+            match self.fcache.get(&root_id, self.vol) {
+                Ok(root) => stack.push(root),
+                Err(_) => self.damaged(EntityKind::Fnode, &root_id),
+            }
+        }
+
+        while let Some(fnode_ref) = stack.pop() {
+            let fnode = fnode_ref.read().unwrap();
+            if !visited.insert(fnode.id().clone()) {
+                continue;
+            }
+            self.reach(fnode.id());
+            self.report.fnode_cnt += 1;
+
+            if fnode.is_file() {
+                for content_id in fnode.content_ids() {
+                    *ver_refs.entry(content_id).or_insert(0) += 1;
+                }
+                self.files.push(fnode_ref.clone());
+            }
+
+            for kid_id in fnode.children_ids() {
+                match self.fcache.get(&kid_id, self.vol) {
+                    Ok(kid) => stack.push(kid),
+
+                    // a removed fnode which no snapshots can see is deleted,
+                    // its frozen copies are checked as roots
+                    Err(_) if pinned.contains(&kid_id) => {}
+
+                    Err(_) => self.damaged(EntityKind::Fnode, &kid_id),
+                }
+            }
+        }
+
+        ver_refs
+    }
+
+    // check contents and content map, return segments to be checked
+    fn check_contents(
+        &mut self,
+        ver_refs: &HashMap<Eid, u32>,
+    ) -> Vec<SegCheck> {
+        let store = self.store.read().unwrap();
+        let mut segs: Vec<SegCheck> = Vec::new();
+        let mut seg_idx: HashMap<Eid, usize> = HashMap::new();
+        let mut hashes: HashMap<Eid, Hash> = HashMap::new();
+
+        // content map entries which are not referred are orphans
+        let map_refs = store.content_refs();
+        let orphans: Vec<Eid> = map_refs
+            .iter()
+            .filter(|(_, id, _)| !ver_refs.contains_key(id))
+            .map(|(_, id, _)| id.clone())
+            .collect();
+
+        let reachable = ver_refs.keys().map(|id| (id, true));
+        let unreachable = orphans.iter().map(|id| (id, false));
+
+        for (content_id, is_reachable) in reachable.chain(unreachable) {
+            self.reach(content_id);
+            let ctn_ref = match store.get_content(content_id) {
+                Ok(ctn_ref) => ctn_ref,
+                Err(_) => {
+                    self.damaged(EntityKind::Content, content_id);
+                    continue;
+                }
+            };
+            self.report.content_cnt += 1;
+
+            let ctn = ctn_ref.read().unwrap();
+            for (seg_id, range) in ctn.chunk_refs() {
+                let idx = *seg_idx.entry(seg_id.clone()).or_insert_with(|| {
+                    segs.push(SegCheck {
+                        id: seg_id.clone(),
+                        refs: Vec::new(),
+                    });
+                    segs.len() - 1
+                });
+                if is_reachable {
+                    segs[idx].refs.push(range);
+                }
+            }
+
+            if is_reachable {
+                hashes.insert(content_id.clone(), ctn.hash().clone());
+            } else {
+                self.orphan(EntityKind::Content, content_id);
+                self.fixes.contents.push(ctn_ref.clone());
+            }
+        }
+
+        // check content reference count in content map, only applied
+        // when file dedup is enabled
+        if store.is_dedup_file() {
+            let mut found_refs: HashMap<Eid, u32> = HashMap::new();
+            for (hash, content_id, refcnt) in map_refs {
+                let expected = ver_refs.get(&content_id).cloned().unwrap_or(0);
+                if expected == 0 {
+                    // orphan content has been reported already
+                    self.fixes.content_refs.push((hash, content_id, 0));
+                    continue;
+                }
+                if refcnt != expected {
+                    self.report.problems.push(Problem::ContentRefCnt {
+                        content_id: content_id.clone(),
+                        found: refcnt,
+                        expected,
+                    });
+                    self.fixes.content_refs.push((
+                        hash,
+                        content_id.clone(),
+                        expected,
+                    ));
+                }
+                found_refs.insert(content_id, refcnt);
+            }
+
+            // contents missing in content map
+            for (content_id, hash) in hashes {
+                if !found_refs.contains_key(&content_id) {
+                    let expected = ver_refs[&content_id];
+                    self.report.problems.push(Problem::ContentRefCnt {
+                        content_id: content_id.clone(),
+                        found: 0,
+                        expected,
+                    });
+                    self.fixes.content_refs.push((hash, content_id, expected));
+                }
+            }
+        }
+
+        segs
+    }
+
+    // check segments and their chunk reference counts
+    fn check_segments(&mut self, segs: Vec<SegCheck>) {
+        let store = self.store.read().unwrap();
+
+        for seg_chk in segs.iter() {
+            self.reach(&seg_chk.id);
+            let seg_ref = match store.get_seg(&seg_chk.id) {
+                Ok(seg_ref) => seg_ref,
+                Err(_) => {
+                    self.damaged(EntityKind::Segment, &seg_chk.id);
+                    continue;
+                }
+            };
+            self.report.segment_cnt += 1;
+            let seg = seg_ref.read().unwrap();
+            self.addrs.insert(seg.data_id().clone());
+            if store.get_segdata(seg.data_id()).is_err() {
+                self.damaged(EntityKind::SegData, seg.data_id());
+            }
+
+            // re-calculate chunk reference counts from spans
+            let mut refs = vec![0u32; seg.chunk_cnt()];
+            if seg_chk.refs.iter().any(|r| r.end > refs.len()) {
+                self.damaged(EntityKind::Segment, &seg_chk.id);
+                continue;
+            }
+            for range in seg_chk.refs.iter() {
+                for refcnt in refs[range.clone()].iter_mut() {
+                    *refcnt += 1;
+                }
+            }
+
+            if refs.iter().all(|r| *r == 0) {
+                self.orphan(EntityKind::Segment, &seg_chk.id);
+                self.fixes.orphan_segs.push(seg_ref.clone());
+                continue;
+            }
+
+            let mut is_broken = false;
+            for (idx, expected) in refs.iter().enumerate() {
+                let found = seg[idx].refcnt();
+                if found != *expected {
+                    self.report.problems.push(Problem::ChunkRefCnt {
+                        seg_id: seg_chk.id.clone(),
+                        idx,
+                        found,
+                        expected: *expected,
+                    });
+                    is_broken = true;
+                }
+            }
+
+            let mut fixed = Segment::clone(&seg);
+            fixed.reset_refs(&refs);
+            if fixed.used() != seg.used() {
+                self.report.problems.push(Problem::SegmentUsed {
+                    seg_id: seg_chk.id.clone(),
+                    found: seg.used(),
+                    expected: fixed.used(),
+                });
+                is_broken = true;
+            }
+
+            if is_broken {
+                self.fixes.segs.push((seg_ref.clone(), refs));
+            }
+        }
+    }
+
+    // find unreachable entities by enumerating all the saved entities
+    fn check_stored(&mut self) -> Result<()> {
+        let mut addrs = {
+            let mut vol = self.vol.write().unwrap();
+            vol.list_ids()?
+        };
+
+        // deleted entities are removed after a few more transactions
+        let deleted = {
+            let txmgr = self.txmgr.read().unwrap();
+            txmgr.deleted_ids()?
+        };
+        self.addrs.extend(deleted);
+        addrs.retain(|addr| !self.addrs.contains(addr));
+        addrs.sort();
+
+        // orphan cows, both arms of a cow might be left
+        let mut datas = Vec::new();
+        let mut orphans = HashSet::new();
+        for addr in addrs {
+            let mut buf = Vec::new();
+            let loaded = Reader::new(&addr, self.vol)
+                .and_then(|mut rdr| Ok(rdr.read_to_end(&mut buf)?));
+            if loaded.is_err() {
+                self.damaged(EntityKind::SegData, &addr);
+                continue;
+            }
+
+            if let Some(id) = cow_id::<Fnode>(&addr, &buf) {
+                if orphans.insert(id.clone()) {
+                    self.orphan_fnode(&id);
+                }
+            } else if let Some(id) = cow_id::<Content>(&addr, &buf) {
+                if orphans.insert(id.clone()) {
+                    self.orphan_content(&id);
+                }
+            } else if let Some(id) = cow_id::<Segment>(&addr, &buf) {
+                if orphans.insert(id.clone()) {
+                    self.orphan_segment(&id);
+                }
+            } else {
+                datas.push(addr);
+            }
+        }
+
+        // segment data is removed along with its orphan segment
+        for data_id in datas {
+            if !self.addrs.contains(&data_id) {
+                self.orphan(EntityKind::SegData, &data_id);
+                self.fixes.segdata.push(data_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn orphan_fnode(&mut self, id: &Eid) {
+        match self.fcache.get(id, self.vol) {
+            Ok(fnode) => {
+                self.orphan(EntityKind::Fnode, id);
+                self.fixes.fnodes.push(fnode);
+            }
+            Err(_) => self.damaged(EntityKind::Fnode, id),
+        }
+    }
+
+    fn orphan_content(&mut self, id: &Eid) {
+        let ctn = self.store.read().unwrap().get_content(id);
+        match ctn {
+            Ok(ctn_ref) => {
+                self.report.content_cnt += 1;
+                self.orphan(EntityKind::Content, id);
+                self.fixes.contents.push(ctn_ref);
+            }
+            Err(_) => self.damaged(EntityKind::Content, id),
+        }
+    }
+
+    fn orphan_segment(&mut self, id: &Eid) {
+        let seg = self.store.read().unwrap().get_seg(id);
+        match seg {
+            Ok(seg_ref) => {
+                self.report.segment_cnt += 1;
+                let data_id = seg_ref.read().unwrap().data_id().clone();
+                self.addrs.insert(data_id);
+                self.orphan(EntityKind::Segment, id);
+                self.fixes.orphan_segs.push(seg_ref);
+            }
+            Err(_) => self.damaged(EntityKind::Segment, id),
+        }
+    }
+
+    // repair problems in one transaction
+    fn repair(&mut self) -> Result<()> {
+        // deleted entities must not be referred when tx is committed
+        let fixes = mem::take(&mut self.fixes);
+        let files = &self.files;
+        let fcache = self.fcache;
+        let store = self.store;
+        let txmgr = self.txmgr;
+
+        TxMgr::begin_trans(txmgr)?.run_all_exclusive(|| {
+            // store evicts the deleted entities from its caches when it
+            // is committed, so it is always added to the transaction
+            {
+                let mut store_cow = store.write().unwrap();
+                let store = store_cow.make_mut(txmgr)?;
+                for (hash, content_id, refcnt) in fixes.content_refs.iter() {
+                    store.reset_content_ref(hash, content_id, *refcnt);
+                }
+            }
+
+            for ctn_ref in fixes.contents {
+                let mut ctn = ctn_ref.write().unwrap();
+                ctn.make_del(txmgr)?;
+            }
+
+            for fnode_ref in fixes.fnodes {
+                let mut fnode = fnode_ref.write().unwrap();
+                fnode.make_del(txmgr)?;
+                fcache.remove(fnode.id());
+            }
+
+            for data_id in fixes.segdata.iter() {
+                SegData::add_to_trans(
+                    data_id,
+                    Action::Delete,
+                    Txid::current()?,
+                    txmgr,
+                )?;
+            }
+
+            for (seg_ref, refs) in fixes.segs.iter() {
+                let mut seg = seg_ref.write().unwrap();
+                seg.make_mut(txmgr)?.reset_refs(refs);
+            }
+
+            for seg_ref in fixes.orphan_segs {
+                let mut seg_cow = seg_ref.write().unwrap();
+                Segment::remove(&mut seg_cow, txmgr)?;
+
+                // remove the deleted segment from chunk maps
+                for file in files.iter() {
+                    let mut fnode = file.write().unwrap();
+                    if fnode.has_chunk_segment(seg_cow.id()) {
+                        fnode
+                            .make_mut(txmgr)?
+                            .remove_chunk_segment(seg_cow.id());
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Run the check, and repair problems if required
+    pub fn run(mut self, mode: CheckMode) -> Result<CheckReport> {
+        let ver_refs = self.check_fnodes();
+        let segs = self.check_contents(&ver_refs);
+        self.check_segments(segs);
+        self.check_stored()?;
+
+        info!(
+            "check: {} fnodes, {} contents, {} segments, {} problems",
+            self.report.fnode_cnt,
+            self.report.content_cnt,
+            self.report.segment_cnt,
+            self.report.problems.len()
+        );
+
+        if mode == CheckMode::Repair
+            && !self.report.is_ok()
+            && self.report.problems.iter().all(Problem::is_repairable)
+        {
+            self.repair()?;
+            self.report.repaired = true;
+            info!("check: problems repaired");
+        }
+
+        Ok(self.report)
+    }
+}
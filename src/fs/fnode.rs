@@ -1,21 +1,26 @@
 #![allow(clippy::module_inception)]
 
 use std::cmp::{min, Ordering};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fmt::{self, Debug};
-use std::io::{Read, Result as IoResult, Seek, SeekFrom, Write};
+use std::io::{
+    Error as IoError, Read, Result as IoResult, Seek, SeekFrom, Write,
+};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 
+use super::quota::{Quota, Usage};
 use super::{Handle, Options};
+use crate::base::crypto::Hash;
 use crate::base::lru::{CountMeter, Lru, PinChecker};
 use crate::base::Time;
 use crate::content::{
-    ChunkMap, ChunkingAlgorithm, Content, ContentReader, Store, StoreRef,
-    StoreWeakRef, Writer as StoreWriter,
+    ChunkMap, ChunkingAlgorithm, Content, ContentReader, MerkleProof, Store,
+    StoreRef, StoreWeakRef, Writer as StoreWriter,
 };
 use crate::error::{Error, Result};
 use crate::trans::cow::{Cow, CowCache, CowRef, CowWeakRef, Cowable, IntoCow};
@@ -26,11 +31,29 @@ use crate::volume::VolumeRef;
 // maximum sub nodes for a fnode
 const SUB_NODES_CNT: usize = 8;
 
+// maximum extended attribute name length, in bytes
+const XATTR_NAME_MAX: usize = 255;
+
+// maximum extended attribute value length, in bytes
+const XATTR_VALUE_MAX: usize = 64 * 1024;
+
+// maximum version tag length, in bytes
+const TAG_MAX_LEN: usize = 255;
+
+// valid POSIX permission bits, including setuid, setgid and sticky bits
+const MODE_MASK: u32 = 0o7777;
+
+// default POSIX permission bits for each file type
+const DEFAULT_FILE_MODE: u32 = 0o644;
+const DEFAULT_DIR_MODE: u32 = 0o755;
+const DEFAULT_SYMLINK_MODE: u32 = 0o777;
+
 /// A structure representing a type of file with accessors for each file type.
 #[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
 pub enum FileType {
     File,
     Dir,
+    Symlink,
 }
 
 impl FileType {
@@ -43,6 +66,11 @@ impl FileType {
     pub fn is_dir(self) -> bool {
         self == FileType::Dir
     }
+
+    /// Test whether this file type represents a symbolic link.
+    pub fn is_symlink(self) -> bool {
+        self == FileType::Symlink
+    }
 }
 
 impl Default for FileType {
@@ -56,6 +84,7 @@ impl From<FileType> for i32 {
         match ftype {
             FileType::File => 0,
             FileType::Dir => 1,
+            FileType::Symlink => 2,
         }
     }
 }
@@ -65,6 +94,7 @@ impl From<FileType> for String {
         match ftype {
             FileType::File => String::from("File"),
             FileType::Dir => String::from("Dir"),
+            FileType::Symlink => String::from("Symlink"),
         }
     }
 }
@@ -94,15 +124,24 @@ pub struct Version {
     content_id: Eid, // content id
     content_len: usize,
     ctime: Time,
+    #[serde(default)]
+    content_hash: Option<Hash>, // content merkle tree root hash
+    #[serde(default)]
+    alloc_len: Option<usize>, // content length excluding holes
+    #[serde(default)]
+    tag: Option<String>, // user defined tag, tagged version is not evicted
 }
 
 impl Version {
-    fn new(num: usize, content_id: &Eid, len: usize) -> Self {
+    fn new(num: usize, content: &Content, content_id: &Eid) -> Self {
         Version {
             num,
             content_id: content_id.clone(),
-            content_len: len,
+            content_len: content.len(),
             ctime: Time::now(),
+            content_hash: Some(content.hash().clone()),
+            alloc_len: Some(content.allocated_len()),
+            tag: None,
         }
     }
 
@@ -118,10 +157,37 @@ impl Version {
         self.content_len
     }
 
+    /// Returns the number of bytes actually stored for this version of
+    /// content.
+    ///
+    /// Holes in a sparse file are not stored, so this can be less than
+    /// [`content_len`].
+    ///
+    /// [`content_len`]: struct.Version.html#method.content_len
+    pub fn allocated_len(&self) -> usize {
+        self.alloc_len.unwrap_or(self.content_len)
+    }
+
     /// Returns the creation time of this version of content.
     pub fn created_at(&self) -> SystemTime {
         self.ctime.to_system_time()
     }
+
+    /// Returns the merkle tree root hash of this version of content.
+    ///
+    /// Two versions with the same hash have the same content, so files can
+    /// be compared without reading them.
+    ///
+    /// Returns `None` if the hash is unknown, that is the version was
+    /// created by an older version of ZboxFS which didn't record it.
+    pub fn content_hash(&self) -> Option<&[u8]> {
+        self.content_hash.as_deref()
+    }
+
+    /// Returns the tag of this version, if it is tagged.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
 }
 
 /// Metadata information about a file or a directory.
@@ -136,9 +202,15 @@ impl Version {
 pub struct Metadata {
     ftype: FileType,
     content_len: usize,
+    alloc_len: usize,
     curr_version: usize,
     ctime: Time,
     mtime: Time,
+    atime: Time,
+    links: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
 }
 
 impl Metadata {
@@ -157,12 +229,25 @@ impl Metadata {
         self.ftype == FileType::File
     }
 
+    /// Returns whether this metadata is for a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        self.ftype == FileType::Symlink
+    }
+
     /// Returns the size of the current version of file, in bytes, this
     /// metadata is for.
     pub fn content_len(&self) -> usize {
         self.content_len
     }
 
+    /// Returns the number of bytes actually stored for the current version of
+    /// file, this can be less than [`content_len`] if the file is sparse.
+    ///
+    /// [`content_len`]: struct.Metadata.html#method.content_len
+    pub fn allocated_len(&self) -> usize {
+        self.alloc_len
+    }
+
     /// Returns current version number of file listed in this metadata.
     pub fn curr_version(&self) -> usize {
         self.curr_version
@@ -177,6 +262,38 @@ impl Metadata {
     pub fn modified_at(&self) -> SystemTime {
         self.mtime.to_system_time()
     }
+
+    /// Returns the last access time listed in this metadata.
+    ///
+    /// Access time is not updated by reading, it can only be changed by
+    /// [`set_times`].
+    ///
+    /// [`set_times`]: struct.Repo.html#method.set_times
+    pub fn accessed_at(&self) -> SystemTime {
+        self.atime.to_system_time()
+    }
+
+    /// Returns number of hard links pointing at the file listed in this
+    /// metadata.
+    pub fn link_count(&self) -> u32 {
+        self.links
+    }
+
+    /// Returns the POSIX permission bits listed in this metadata, including
+    /// the set-user-ID, set-group-ID and sticky bits.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// Returns the owner user ID listed in this metadata.
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    /// Returns the owner group ID listed in this metadata.
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
 }
 
 /// Entries returned by the [`read_dir`] function.
@@ -186,7 +303,7 @@ impl Metadata {
 /// absolute path or other metadata.
 ///
 /// [`read_dir`]: struct.Repo.html#method.read_dir
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DirEntry {
     path: PathBuf,
     name: String,
@@ -229,6 +346,44 @@ pub struct Fnode {
     vers: VecDeque<Version>,
     chk_map: ChunkMap,
 
+    // symbolic link target path
+    #[serde(default)]
+    target: Option<String>,
+
+    // number of hard links
+    #[serde(default = "Fnode::default_links")]
+    links: u32,
+
+    // extended attributes
+    #[serde(default)]
+    xattrs: BTreeMap<String, Vec<u8>>,
+
+    // POSIX permission bits, None means default bits for the file type
+    #[serde(default)]
+    mode: Option<u32>,
+
+    // owner user and group id
+    #[serde(default)]
+    uid: u32,
+    #[serde(default)]
+    gid: u32,
+
+    // last access time, None means same as modification time
+    #[serde(default)]
+    atime: Option<Time>,
+
+    // directory quota, and storage usage of the directory tree which is
+    // only maintained when quota is set
+    #[serde(default)]
+    quota: Option<Quota>,
+    #[serde(default)]
+    usage: Usage,
+
+    // snapshot sequence when the fnode is created, snapshots whose
+    // sequence is not greater than it cannot see the fnode
+    #[serde(default)]
+    born: u64,
+
     // parent fnode
     #[serde(skip_serializing, skip_deserializing, default)]
     parent: Option<FnodeRef>,
@@ -251,6 +406,16 @@ impl Fnode {
             kids: Vec::new(),
             vers: VecDeque::new(),
             chk_map: ChunkMap::new(opts.dedup_chunk),
+            target: None,
+            links: 1,
+            xattrs: BTreeMap::new(),
+            mode: None,
+            uid: 0,
+            gid: 0,
+            atime: None,
+            quota: None,
+            usage: Usage::default(),
+            born: 0,
             parent: None,
             sub_nodes: Self::default_sub_nodes(),
         }
@@ -274,6 +439,7 @@ impl Fnode {
 
             // create child fnode and add the initial version
             let mut kid = Fnode::new(ftype, opts);
+            kid.born = store.read().unwrap().snapshot_seq();
             if kid.is_file() {
                 kid.add_version(Content::new(), store, txmgr)?;
             }
@@ -282,7 +448,39 @@ impl Fnode {
         };
 
         // add child to parent
-        Fnode::add_child(parent, &kid, name, txmgr)?;
+        Fnode::add_child(parent, &kid, name, store, txmgr)?;
+
+        Ok(kid)
+    }
+
+    /// Create new symbolic link fnode under parent
+    pub fn new_symlink_under(
+        parent: &FnodeRef,
+        name: &str,
+        target: &Path,
+        txmgr: &TxMgrRef,
+        store: &StoreRef,
+    ) -> Result<FnodeRef> {
+        let target = target.to_str().ok_or(Error::InvalidPath)?;
+        if target.is_empty() {
+            return Err(Error::InvalidPath);
+        }
+
+        let kid = {
+            let mut pfnode_cow = parent.write().unwrap();
+            let pfnode = pfnode_cow.make_mut(txmgr)?;
+            if !pfnode.is_dir() {
+                return Err(Error::NotDir);
+            }
+
+            let mut kid = Fnode::new(FileType::Symlink, pfnode.opts);
+            kid.born = store.read().unwrap().snapshot_seq();
+            kid.target = Some(target.to_string());
+            kid.into_cow(txmgr)?
+        };
+
+        // add child to parent
+        Fnode::add_child(parent, &kid, name, store, txmgr)?;
 
         Ok(kid)
     }
@@ -292,6 +490,11 @@ impl Fnode {
         Lru::new(SUB_NODES_CNT)
     }
 
+    #[inline]
+    fn default_links() -> u32 {
+        1
+    }
+
     /// Check if fnode is regular file
     #[inline]
     pub fn is_file(&self) -> bool {
@@ -305,31 +508,204 @@ impl Fnode {
     }
 
     /// Check if fnode is root
+    #[allow(dead_code)]
     #[inline]
     pub fn is_root(&self) -> bool {
         self.parent.is_none()
     }
 
+    /// Get symbolic link target path
+    #[inline]
+    pub fn symlink_target(&self) -> Option<PathBuf> {
+        self.target.as_ref().map(PathBuf::from)
+    }
+
+    /// Get number of hard links
+    #[inline]
+    pub fn links(&self) -> u32 {
+        self.links
+    }
+
+    /// Get extended attribute value
+    #[inline]
+    pub fn get_xattr(&self, name: &str) -> Option<Vec<u8>> {
+        self.xattrs.get(name).cloned()
+    }
+
+    /// Get all extended attribute names
+    #[inline]
+    pub fn list_xattrs(&self) -> Vec<String> {
+        self.xattrs.keys().cloned().collect()
+    }
+
+    /// Check if extended attribute exists
+    #[inline]
+    pub fn has_xattr(&self, name: &str) -> bool {
+        self.xattrs.contains_key(name)
+    }
+
+    /// Check extended attribute name and value are valid
+    pub fn check_xattr(name: &str, value: &[u8]) -> Result<()> {
+        if name.is_empty()
+            || name.len() > XATTR_NAME_MAX
+            || value.len() > XATTR_VALUE_MAX
+        {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(())
+    }
+
+    /// Set extended attribute value
+    pub fn set_xattr(&mut self, name: &str, value: &[u8]) -> Result<()> {
+        Self::check_xattr(name, value)?;
+        self.xattrs.insert(name.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    /// Remove extended attribute
+    #[inline]
+    pub fn remove_xattr(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.xattrs.remove(name)
+    }
+
+    /// Increase hard link count
+    #[inline]
+    pub fn inc_links(&mut self) -> Result<u32> {
+        self.links = self.links.checked_add(1).ok_or(Error::RefOverflow)?;
+        Ok(self.links)
+    }
+
+    /// Decrease hard link count
+    #[inline]
+    pub fn dec_links(&mut self) -> Result<u32> {
+        self.links = self.links.checked_sub(1).ok_or(Error::RefUnderflow)?;
+        Ok(self.links)
+    }
+
     /// Get fnode metadata
     pub fn metadata(&self) -> Metadata {
         Metadata {
             ftype: self.ftype,
             content_len: self.curr_len(),
+            alloc_len: self.curr_alloc_len(),
             curr_version: self.curr_ver_num(),
             ctime: self.ctime,
             mtime: self.mtime,
+            atime: self.atime.unwrap_or(self.mtime),
+            links: self.links,
+            mode: self.mode(),
+            uid: self.uid,
+            gid: self.gid,
+        }
+    }
+
+    /// Get POSIX permission bits
+    #[inline]
+    pub fn mode(&self) -> u32 {
+        self.mode.unwrap_or(match self.ftype {
+            FileType::File => DEFAULT_FILE_MODE,
+            FileType::Dir => DEFAULT_DIR_MODE,
+            FileType::Symlink => DEFAULT_SYMLINK_MODE,
+        })
+    }
+
+    /// Set POSIX permission bits
+    pub fn set_mode(&mut self, mode: u32) -> Result<()> {
+        if mode & !MODE_MASK != 0 {
+            return Err(Error::InvalidArgument);
+        }
+        self.mode = Some(mode);
+        Ok(())
+    }
+
+    /// Set owner user and group id, None means unchanged
+    #[inline]
+    pub fn set_owner(&mut self, uid: Option<u32>, gid: Option<u32>) {
+        if let Some(uid) = uid {
+            self.uid = uid;
+        }
+        if let Some(gid) = gid {
+            self.gid = gid;
         }
     }
 
+    /// Set access and modification time
+    #[inline]
+    pub fn set_times(&mut self, atime: Time, mtime: Time) {
+        self.atime = Some(atime);
+        self.mtime = mtime;
+    }
+
     /// Get size of fnode current version
     #[inline]
     pub fn curr_len(&self) -> usize {
         match self.ftype {
             FileType::File => self.curr_ver().content_len(),
             FileType::Dir => 0,
+            FileType::Symlink => self.target.as_ref().map_or(0, String::len),
+        }
+    }
+
+    /// Get allocated size of fnode current version
+    #[inline]
+    pub fn curr_alloc_len(&self) -> usize {
+        match self.ftype {
+            FileType::File => self.curr_ver().allocated_len(),
+            _ => self.curr_len(),
         }
     }
 
+    /// Get storage usage of fnode itself, only file has usage
+    ///
+    /// Versions sharing the same content are counted only once.
+    pub fn own_usage(&self) -> Usage {
+        if !self.is_file() {
+            return Usage::default();
+        }
+        let mut content_ids = HashSet::new();
+        let allocated_bytes = self
+            .vers
+            .iter()
+            .filter(|ver| content_ids.insert(&ver.content_id))
+            .map(|ver| ver.allocated_len() as u64)
+            .sum();
+        Usage::file(self.curr_len() as u64, allocated_bytes)
+    }
+
+    /// Get directory quota
+    #[inline]
+    pub fn quota(&self) -> Option<Quota> {
+        self.quota
+    }
+
+    /// Get storage usage of directory tree, None if quota is not set
+    #[inline]
+    pub fn dir_usage(&self) -> Option<Usage> {
+        self.quota.map(|_| self.usage)
+    }
+
+    /// Set directory quota along with the current usage of its tree
+    #[inline]
+    pub fn set_quota(&mut self, quota: Option<Quota>, usage: Usage) {
+        self.quota = quota;
+        self.usage = usage;
+    }
+
+    /// Update storage usage of directory tree
+    #[inline]
+    pub fn set_usage(&mut self, usage: Usage) {
+        self.usage = usage;
+    }
+
+    /// Get in-memory parent fnode
+    ///
+    /// A file with hard links has more than one parent, this is the one
+    /// which loaded or linked it last.
+    #[inline]
+    pub fn parent(&self) -> Option<FnodeRef> {
+        self.parent.clone()
+    }
+
     /// Get fnode version list
     #[inline]
     pub fn history(&self) -> Vec<Version> {
@@ -392,6 +768,21 @@ impl Fnode {
         self.kids.iter().any(|c| c.name == name)
     }
 
+    /// Check if child entry with specified name links to the fnode
+    #[inline]
+    pub fn is_linked(&self, name: &str, id: &Eid) -> bool {
+        self.kids.iter().any(|c| c.name == name && c.id == *id)
+    }
+
+    /// Get name of the first child entry linking to the fnode
+    #[inline]
+    pub fn child_name(&self, id: &Eid) -> Option<String> {
+        self.kids
+            .iter()
+            .find(|c| c.id == *id)
+            .map(|c| c.name.clone())
+    }
+
     #[inline]
     pub fn children_cnt(&self) -> usize {
         self.kids.len()
@@ -405,23 +796,61 @@ impl Fnode {
         vol: &VolumeRef,
     ) -> Result<FnodeRef> {
         let mut par = parent.write().unwrap();
-        par.make_mut_naive()
+        par.make_mut_visible()
             .load_child(name, parent.clone(), cache, vol)
     }
 
-    fn children_names(&self) -> Vec<String> {
+    #[inline]
+    pub fn children_ids(&self) -> Vec<Eid> {
+        self.kids.iter().map(|k| k.id.clone()).collect()
+    }
+
+    pub fn children_names(&self) -> Vec<String> {
         self.kids.iter().map(|k| k.name.clone()).collect()
     }
 
-    /// Get children dir entry list
+    /// Get one child dir entry and its fnode, the child is seen by
+    /// snapshot if `snap` is specified
+    pub fn child_entry(
+        parent: &FnodeRef,
+        parent_path: &Path,
+        name: &str,
+        snap: Option<(u64, &StoreRef)>,
+        cache: &Cache,
+        vol: &VolumeRef,
+    ) -> Result<(DirEntry, FnodeRef)> {
+        let child_ref = {
+            let mut par = parent.write().unwrap();
+            let par = par.make_mut_visible();
+            match snap {
+                Some(snap) => {
+                    par.load_snapshot_child(name, snap, cache, vol)?
+                }
+                None => par.load_child(name, parent.clone(), cache, vol)?,
+            }
+        };
+        let ent = {
+            let child = child_ref.read().unwrap();
+            DirEntry {
+                path: parent_path.join(name),
+                metadata: child.metadata(),
+                name: name.to_string(),
+            }
+        };
+        Ok((ent, child_ref))
+    }
+
+    /// Get children dir entry list, the children are seen by snapshot if
+    /// `snap` is specified
     pub fn read_dir(
         parent: FnodeRef,
         path: &Path,
+        snap: Option<(u64, &StoreRef)>,
         cache: &Cache,
         vol: &VolumeRef,
     ) -> Result<Vec<DirEntry>> {
         let mut par = parent.write().unwrap();
-        let par = par.make_mut_naive();
+        let par = par.make_mut_visible();
         if !par.is_dir() {
             return Err(Error::NotDir);
         }
@@ -445,7 +874,12 @@ impl Fnode {
         let child_names = par.children_names();
 
         for name in child_names.iter() {
-            let child_ref = par.load_child(name, parent.clone(), cache, vol)?;
+            let child_ref = match snap {
+                Some(snap) => {
+                    par.load_snapshot_child(name, snap, cache, vol)?
+                }
+                None => par.load_child(name, parent.clone(), cache, vol)?,
+            };
             let child = child_ref.read().unwrap();
             ret.push(DirEntry {
                 path: parent_path.join(name),
@@ -462,10 +896,11 @@ impl Fnode {
         parent: &FnodeRef,
         child: &FnodeRef,
         name: &str,
+        store: &StoreRef,
         txmgr: &TxMgrRef,
     ) -> Result<()> {
         let mut parent_cow = parent.write().unwrap();
-        let par = parent_cow.make_mut(txmgr)?;
+        let par = Fnode::make_mut(&mut parent_cow, store, txmgr)?;
 
         // add to child to parent's children list
         let mut kid = child.write().unwrap();
@@ -482,30 +917,72 @@ impl Fnode {
         Ok(())
     }
 
-    /// Remove child fnode from parent
-    pub fn remove_from_parent(
-        fnode: &FnodeRef,
-        txmgr: &TxMgrRef,
-    ) -> Result<()> {
-        let child = fnode.read().unwrap();
-        match child.parent {
-            Some(ref parent) => {
-                let mut par = parent.write().unwrap();
-                let par = par.make_mut(txmgr)?;
-                let child_idx = par
-                    .kids
-                    .iter()
-                    .position(|c| c.id == *child.id())
-                    .ok_or(Error::NotFound)?;
-                {
-                    let name = &par.kids[child_idx].name;
-                    par.sub_nodes.remove(name);
-                }
-                par.kids.remove(child_idx);
-                Ok(())
+    /// Clear the in-memory parent reference of fnode
+    ///
+    /// This is needed when parent is deleted in the same transaction.
+    pub fn clear_parent(fnode: &FnodeRef) {
+        let mut fnode_cow = fnode.write().unwrap();
+        fnode_cow.make_mut_naive().parent = None;
+    }
+
+    /// Detach fnode from its in-memory parent in transaction
+    pub fn detach(fnode: &FnodeRef, txmgr: &TxMgrRef) -> Result<()> {
+        let mut fnode_cow = fnode.write().unwrap();
+        fnode_cow.make_mut(txmgr)?.parent = None;
+        Ok(())
+    }
+
+    /// Get absolute path of fnode by walking up its parents to root
+    ///
+    /// None is returned if the fnode is no longer attached to the tree
+    /// under root. Directories cannot be hard linked so their paths are
+    /// always exact, but for a file with hard links it is the path of any
+    /// of the links. Use the link the file is accessed through instead.
+    pub fn path(fnode_ref: &FnodeRef, root_id: &Eid) -> Option<PathBuf> {
+        let mut names = Vec::new();
+        let mut curr = fnode_ref.clone();
+        loop {
+            let (id, parent) = {
+                let fnode = curr.read().unwrap();
+                (fnode.id().clone(), fnode.parent.clone())
+            };
+            if id == *root_id {
+                break;
             }
-            None => Err(Error::IsRoot),
+            let parent = parent?;
+            {
+                let par = parent.read().unwrap();
+                let kid = par.kids.iter().find(|k| k.id == id)?;
+                names.push(kid.name.clone());
+            }
+            curr = parent;
         }
+
+        let mut path = PathBuf::from("/");
+        path.extend(names.iter().rev());
+        Some(path)
+    }
+
+    /// Remove child entry with specified name from parent
+    ///
+    /// The child fnode itself is not changed, as it might still be linked
+    /// by other entries.
+    pub fn remove_child(
+        parent: &FnodeRef,
+        name: &str,
+        store: &StoreRef,
+        txmgr: &TxMgrRef,
+    ) -> Result<()> {
+        let mut par = parent.write().unwrap();
+        let par = Fnode::make_mut(&mut par, store, txmgr)?;
+        let child_idx = par
+            .kids
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or(Error::NotFound)?;
+        par.sub_nodes.remove(name);
+        par.kids.remove(child_idx);
+        Ok(())
     }
 
     /// get a specified version
@@ -513,6 +990,114 @@ impl Fnode {
         self.vers.iter().find(|v| v.num == ver_num)
     }
 
+    /// Get version by its tag
+    #[inline]
+    pub fn ver_by_tag(&self, tag: &str) -> Option<&Version> {
+        self.vers.iter().find(|v| v.tag() == Some(tag))
+    }
+
+    /// Tag a version, a tag can only be used by one version and a version
+    /// can only have one tag
+    pub fn tag_version(&mut self, ver_num: usize, tag: &str) -> Result<()> {
+        if tag.is_empty() || tag.len() > TAG_MAX_LEN {
+            return Err(Error::InvalidArgument);
+        }
+        if let Some(ver) = self.ver_by_tag(tag) {
+            if ver.num == ver_num {
+                return Ok(());
+            }
+            return Err(Error::AlreadyExists);
+        }
+        let ver = self
+            .vers
+            .iter_mut()
+            .find(|v| v.num == ver_num)
+            .ok_or(Error::NoVersion)?;
+        ver.tag = Some(tag.to_string());
+        Ok(())
+    }
+
+    /// Remove a version tag, the version might be evicted if the version
+    /// limit is exceeded
+    pub fn untag_version(
+        &mut self,
+        tag: &str,
+        store: &StoreRef,
+        txmgr: &TxMgrRef,
+    ) -> Result<()> {
+        let ver = self
+            .vers
+            .iter_mut()
+            .find(|v| v.tag() == Some(tag))
+            .ok_or(Error::NoVersion)?;
+        ver.tag = None;
+        self.evict_versions(store, txmgr).map(|_| ())
+    }
+
+    /// Get version numbers to be evicted
+    ///
+    /// If retention policy is set, they are the versions not kept by the
+    /// policy. Otherwise, they are the oldest untagged versions exceeding the
+    /// version limit.
+    pub fn retired_versions(&self) -> Vec<usize> {
+        if let Some(policy) = self.opts.retention {
+            let vers: Vec<(u64, usize, bool)> = self
+                .vers
+                .iter()
+                .map(|v| (v.ctime.as_secs(), v.content_len, v.tag.is_some()))
+                .collect();
+            return policy
+                .select(&vers, Time::now().as_secs())
+                .iter()
+                .zip(self.vers.iter())
+                .filter(|(keep, _)| !**keep)
+                .map(|(_, ver)| ver.num)
+                .collect();
+        }
+
+        let untagged: Vec<usize> = self
+            .vers
+            .iter()
+            .filter(|v| v.tag.is_none())
+            .map(|v| v.num)
+            .collect();
+        let cnt = untagged
+            .len()
+            .saturating_sub(self.opts.version_limit as usize);
+        untagged[..cnt].to_vec()
+    }
+
+    /// Evict retired versions and return the number of evicted versions
+    pub fn evict_versions(
+        &mut self,
+        store: &StoreRef,
+        txmgr: &TxMgrRef,
+    ) -> Result<usize> {
+        let retires = self.retired_versions();
+        for &ver_num in retires.iter() {
+            self.remove_version(ver_num, store, txmgr)?;
+        }
+        Ok(retires.len())
+    }
+
+    /// Get content ids referred by all versions
+    #[inline]
+    pub fn content_ids(&self) -> Vec<Eid> {
+        self.vers.iter().map(|v| v.content_id.clone()).collect()
+    }
+
+    /// Check if segment is in chunk map
+    #[inline]
+    pub fn has_chunk_segment(&self, seg_id: &Eid) -> bool {
+        self.chk_map.has_segment(seg_id)
+    }
+
+    /// Remove segment from chunk map
+    #[inline]
+    pub fn remove_chunk_segment(&mut self, seg_id: &Eid) {
+        self.chk_map.remove_segment(seg_id)
+    }
+
     // get current version
     fn curr_ver(&self) -> &Version {
         self.vers.back().unwrap()
@@ -576,8 +1161,7 @@ impl Fnode {
         let (no_dup, deduped_id) = Store::dedup_content(store, &content)?;
 
         // create a new version and append to version list
-        let ver =
-            Version::new(self.curr_ver_num() + 1, &deduped_id, content.len());
+        let ver = Version::new(self.curr_ver_num() + 1, &content, &deduped_id);
         self.mtime = ver.ctime;
         self.vers.push_back(ver);
 
@@ -586,11 +1170,8 @@ impl Fnode {
             content.link(store, txmgr)?;
         }
 
-        // evict retired version if any
-        if self.vers.len() > self.opts.version_limit as usize {
-            let retire = self.vers.front().unwrap().num;
-            self.remove_version(retire, store, txmgr)?;
-        }
+        // evict retired versions if any
+        self.evict_versions(store, txmgr)?;
 
         Ok(no_dup)
     }
@@ -613,22 +1194,172 @@ impl Fnode {
     }
 
     /// Clone a new current content
+    #[inline]
     pub fn clone_current_content(&self, store: &StoreRef) -> Result<Content> {
+        self.clone_version_content(self.curr_ver_num(), store)
+    }
+
+    /// Clone a new content of specified version
+    pub fn clone_version_content(
+        &self,
+        ver_num: usize,
+        store: &StoreRef,
+    ) -> Result<Content> {
+        let ver = self.ver(ver_num).ok_or(Error::NoVersion)?;
         let store = store.read().unwrap();
-        let curr_ctn = store.get_content(&self.curr_ver().content_id)?;
-        let content = curr_ctn.read().unwrap();
+        let ctn = store.get_content(&ver.content_id)?;
+        let content = ctn.read().unwrap();
         Ok(content.clone())
     }
 
+    /// Get byte ranges which are different between two versions
+    pub fn diff_versions(
+        &self,
+        ver_num: usize,
+        other_ver_num: usize,
+        store: &StoreRef,
+    ) -> Result<Vec<Range<usize>>> {
+        let content = self.clone_version_content(ver_num, store)?;
+        let other = self.clone_version_content(other_ver_num, store)?;
+        content.diff(&other, store)
+    }
+
+    /// Restore a historical version by adding its content as a new version
+    ///
+    /// The content is shared with the historical version, no data is copied.
+    pub fn restore_version(
+        &mut self,
+        ver_num: usize,
+        store: &StoreRef,
+        txmgr: &TxMgrRef,
+    ) -> Result<()> {
+        let content = self.clone_version_content(ver_num, store)?;
+        self.add_version(content, store, txmgr)?;
+        Ok(())
+    }
+
+    /// Get mutable fnode in transaction, it is frozen first if any snapshot
+    /// sees its current state
+    ///
+    /// This is used instead of `Cow::make_mut` when the change can be seen
+    /// by snapshots.
+    pub fn make_mut<'a>(
+        fnode_cow: &'a mut Cow<Fnode>,
+        store: &StoreRef,
+        txmgr: &TxMgrRef,
+    ) -> Result<&'a mut Fnode> {
+        let id = fnode_cow.id().clone();
+        let fnode = fnode_cow.make_mut(txmgr)?;
+        {
+            let store = store.read().unwrap();
+            let from = store.frozen_seq(&id, fnode.born);
+            if !store.has_snapshot_between(from, u64::MAX) {
+                return Ok(fnode);
+            }
+        }
+
+        let copy_id = fnode.freeze(store, txmgr)?;
+        let mut store_cow = store.write().unwrap();
+        store_cow
+            .make_mut(txmgr)?
+            .add_frozen(&id, fnode.born, &copy_id);
+        Ok(fnode)
+    }
+
+    // copy current state to a new frozen fnode and return its id, the
+    // contents of all versions are referenced again by the copy
+    fn freeze(&self, store: &StoreRef, txmgr: &TxMgrRef) -> Result<Eid> {
+        let mut copy = self.clone();
+        copy.parent = None;
+        copy.sub_nodes = Self::default_sub_nodes();
+        copy.chk_map = ChunkMap::new(copy.opts.dedup_chunk);
+
+        // quota is only maintained in the file system
+        copy.set_quota(None, Usage::default());
+
+        for ver in copy.vers.iter_mut() {
+            let content = self.clone_version_content(ver.num, store)?;
+            let (no_dup, content_id) = Store::dedup_content(store, &content)?;
+            if no_dup {
+                content.link(store, txmgr)?;
+            }
+            ver.content_id = content_id;
+        }
+
+        let copy = copy.into_cow(txmgr)?;
+        let id = copy.read().unwrap().id().clone();
+        Ok(id)
+    }
+
+    #[inline]
+    pub fn born(&self) -> u64 {
+        self.born
+    }
+
+    /// Get the fnode seen by snapshot, which is either the fnode itself or
+    /// one of its frozen copies
+    pub fn frozen_for(
+        fnode: &FnodeRef,
+        seq: u64,
+        store: &StoreRef,
+        cache: &Cache,
+        vol: &VolumeRef,
+    ) -> Result<FnodeRef> {
+        let id = fnode.read().unwrap().id().clone();
+        let copy_id = {
+            let store = store.read().unwrap();
+            store.frozen_for(&id, seq).cloned()
+        };
+        match copy_id {
+            Some(id) => cache.get(&id, vol),
+            None => Ok(fnode.clone()),
+        }
+    }
+
+    /// Get single child fnode seen by snapshot
+    ///
+    /// Fnodes are shared with the file system and other snapshots, so the
+    /// parent and child are not changed.
+    pub fn snapshot_child(
+        parent: &FnodeRef,
+        name: &str,
+        snap: (u64, &StoreRef),
+        cache: &Cache,
+        vol: &VolumeRef,
+    ) -> Result<FnodeRef> {
+        let par = parent.read().unwrap();
+        par.load_snapshot_child(name, snap, cache, vol)
+    }
+
+    // load one child fnode seen by snapshot, the child itself is not loaded
+    // if snapshot sees one of its frozen copies
+    fn load_snapshot_child(
+        &self,
+        name: &str,
+        snap: (u64, &StoreRef),
+        cache: &Cache,
+        vol: &VolumeRef,
+    ) -> Result<FnodeRef> {
+        let (seq, store) = snap;
+        let child = self
+            .kids
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or(Error::NotFound)?;
+        let id = {
+            let store = store.read().unwrap();
+            store
+                .frozen_for(&child.id, seq)
+                .unwrap_or(&child.id)
+                .clone()
+        };
+        cache.get(&id, vol)
+    }
+
     /// Set file to specified length
     ///
     /// if new length is equal to old length, do nothing
-    pub fn set_len(
-        handle: Handle,
-        len: usize,
-        txid: Txid,
-        chunker: ChunkingAlgorithm,
-    ) -> Result<()> {
+    pub fn set_len(handle: Handle, len: usize) -> Result<()> {
         let curr_len = {
             let fnode = handle.fnode.read().unwrap();
             fnode.curr_len()
@@ -647,28 +1378,52 @@ impl Fnode {
                 };
 
                 // dedup content, if it is not duplicated then link the content
-                let fnode = fnode_cow.make_mut(&txmgr)?;
+                let fnode = Fnode::make_mut(&mut fnode_cow, &store, &txmgr)?;
                 fnode.add_version(new_ctn, &store, &txmgr)?;
             }
             Ordering::Less => {
-                // append
-                let mut size = len - curr_len;
-                let buf = vec![0u8; min(size, 16 * 1024)];
-                let mut wtr = Writer::new(handle, txid, chunker)?;
-                wtr.seek(SeekFrom::Start(curr_len as u64))?;
-
-                while size > 0 {
-                    let write_len = min(size, buf.len());
-                    let written = wtr.write(&buf[..write_len])?;
-                    size -= written;
-                }
-                wtr.finish()?;
+                // extend with a hole
+                Fnode::write_hole(handle, curr_len, len - curr_len)?;
             }
             Ordering::Equal => {}
         }
 
         Ok(())
     }
+
+    /// Deallocate a range of file and create a new version, the range will
+    /// be read as zeros
+    pub fn punch_hole(handle: Handle, range: Range<usize>) -> Result<()> {
+        let curr_len = {
+            let fnode = handle.fnode.read().unwrap();
+            fnode.curr_len()
+        };
+
+        // punching hole doesn't change file size
+        let end = min(range.end, curr_len);
+        if range.start >= end {
+            return Ok(());
+        }
+
+        Fnode::write_hole(handle, range.start, end - range.start)
+    }
+
+    // write hole to current content and add it as a new version
+    fn write_hole(handle: Handle, offset: usize, len: usize) -> Result<()> {
+        let store = handle.store.upgrade().ok_or(Error::RepoClosed)?;
+        let txmgr = handle.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        let mut fnode_cow = handle.fnode.write().unwrap();
+        let new_ctn = {
+            let mut ctn = fnode_cow.clone_current_content(&store)?;
+            ctn.write_hole(offset, len, &store)?;
+            ctn
+        };
+
+        let fnode = Fnode::make_mut(&mut fnode_cow, &store, &txmgr)?;
+        fnode.add_version(new_ctn, &store, &txmgr)?;
+
+        Ok(())
+    }
 }
 
 impl Debug for Fnode {
@@ -681,6 +1436,16 @@ impl Debug for Fnode {
             .field("kids", &self.kids)
             .field("vers", &self.vers)
             .field("chk_map", &self.chk_map)
+            .field("target", &self.target)
+            .field("links", &self.links)
+            .field("xattrs", &self.xattrs.keys())
+            .field("mode", &self.mode)
+            .field("uid", &self.uid)
+            .field("gid", &self.gid)
+            .field("atime", &self.atime)
+            .field("quota", &self.quota)
+            .field("usage", &self.usage)
+            .field("born", &self.born)
             .field("sub_nodes", &self.sub_nodes)
             .finish()
     }
@@ -689,20 +1454,15 @@ impl Debug for Fnode {
 impl Cowable for Fnode {
     fn on_commit(&mut self, _vol: &VolumeRef) -> Result<()> {
         // remove deleted fnode from sub nodes cache
-        self.sub_nodes
-            .entries()
-            .filter(|ent| {
-                ent.get()
-                    .upgrade()
-                    .map(|fnode_ref| {
-                        let cow = fnode_ref.read().unwrap();
-                        cow.in_trans() && cow.action() == Action::Delete
-                    })
-                    .unwrap_or(false)
-            })
-            .for_each(|ent| {
-                ent.remove();
-            });
+        self.sub_nodes.remove_if(|fnode| {
+            fnode
+                .upgrade()
+                .map(|fnode_ref| {
+                    let cow = fnode_ref.read().unwrap();
+                    cow.in_trans() && cow.action() == Action::Delete
+                })
+                .unwrap_or(false)
+        });
         Ok(())
     }
 }
@@ -746,6 +1506,26 @@ impl Reader {
     pub fn version_num(&self) -> usize {
         self.ver
     }
+
+    #[inline]
+    pub fn verify(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        self.rdr.verify(range)
+    }
+
+    #[inline]
+    pub fn merkle_proof(&self, piece_idx: usize) -> Option<MerkleProof> {
+        self.rdr.merkle_proof(piece_idx)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.rdr.len()
+    }
+
+    #[inline]
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> IoResult<usize> {
+        self.rdr.read_at(buf, offset)
+    }
 }
 
 impl Read for Reader {
@@ -767,6 +1547,7 @@ impl Seek for Reader {
 pub struct Writer {
     inner: StoreWriter,
     handle: Handle,
+    pos: usize,
 }
 
 impl Writer {
@@ -786,7 +1567,11 @@ impl Writer {
             &handle.store,
             chunker,
         )?;
-        Ok(Writer { inner, handle })
+        Ok(Writer {
+            inner,
+            handle,
+            pos: 0,
+        })
     }
 
     pub fn finish(self) -> Result<usize> {
@@ -805,7 +1590,7 @@ impl Writer {
         };
 
         // dedup content and add deduped content as a new version
-        let fnode = fnode_cow.make_mut(&txmgr)?;
+        let fnode = Fnode::make_mut(&mut fnode_cow, &store, &txmgr)?;
         if !fnode.add_version(merged_ctn, &store, &txmgr)? {
             // content is duplicated, weak unlink the stage content
             stg_ctn.unlink_weak(&mut fnode.chk_map, &store, &txmgr)?;
@@ -819,9 +1604,14 @@ impl Writer {
 }
 
 impl Write for Writer {
-    #[inline]
     fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
-        self.inner.write(buf)
+        // stop writing early if file size will exceed quota
+        self.handle
+            .check_quota(self.pos + buf.len())
+            .map_err(IoError::other)?;
+        let written = self.inner.write(buf)?;
+        self.pos += written;
+        Ok(written)
     }
 
     #[inline]
@@ -833,9 +1623,46 @@ impl Write for Writer {
 impl Seek for Writer {
     #[inline]
     fn seek(&mut self, pos: SeekFrom) -> IoResult<u64> {
-        self.inner.seek(pos)
+        let pos = self.inner.seek(pos)?;
+        self.pos = pos as usize;
+        Ok(pos)
     }
 }
 
 /// Fnode cache
 pub type Cache = CowCache<Fnode>;
+
+#[cfg(test)]
+mod tests {
+    use rmp_serde::{Deserializer, Serializer};
+
+    use super::*;
+    use crate::base::init_env;
+
+    #[test]
+    fn legacy_version_hash() {
+        init_env();
+
+        // version serialized before content hash was added
+        #[derive(Serialize)]
+        struct LegacyVersion {
+            num: usize,
+            content_id: Eid,
+            content_len: usize,
+            ctime: Time,
+        }
+        let legacy = LegacyVersion {
+            num: 1,
+            content_id: Eid::new(),
+            content_len: 42,
+            ctime: Time::now(),
+        };
+        let mut buf = Vec::new();
+        legacy.serialize(&mut Serializer::new(&mut buf)).unwrap();
+
+        let mut de = Deserializer::new(&buf[..]);
+        let ver: Version = Deserialize::deserialize(&mut de).unwrap();
+        assert_eq!(ver.content_len(), 42);
+        assert!(ver.content_hash().is_none());
+    }
+}
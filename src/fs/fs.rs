@@ -1,16 +1,26 @@
-use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::SystemTime;
 
 use log::info;
 use rmp_serde::{Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
 
+use super::check::Checker;
 use super::fnode::{
     Cache as FnodeCache, DirEntry, FileType, Fnode, FnodeRef, Metadata, Version,
 };
-use super::{Config, Handle, Options};
+use super::journal::{self, ChangeKind, ChangeSet, Journal, JournalRef};
+use super::quota::{self, Quota, Usage};
+use super::trash::{Trash, TrashItem, TrashRef};
+use super::{
+    CheckMode, CheckReport, Config, Event, Handle, Options, TrashEntry,
+    WalkDir, Watchers, WatchersRef, DEFAULT_JOURNAL_LIMIT,
+};
 use crate::base::crypto::Cost;
-use crate::base::IntoRef;
+use crate::base::{IntoRef, Time};
 use crate::content::{Store, StoreRef};
 use crate::error::{Error, Result};
 use crate::trans::cow::IntoCow;
@@ -67,6 +77,15 @@ struct Payload {
     walq_id: Eid,
     store_id: Eid,
     opts: Options,
+
+    // trash id, it is empty if the repo is created without trash entity
+    #[serde(default)]
+    trash_id: Eid,
+
+    // journal id, it is empty if journal is not enabled or the repo is
+    // created without journal entity
+    #[serde(default)]
+    journal_id: Eid,
 }
 
 impl Payload {
@@ -74,6 +93,8 @@ impl Payload {
         root_id: &Eid,
         walq_id: &Eid,
         store_id: &Eid,
+        trash_id: &Eid,
+        journal_id: &Eid,
         opts: Options,
     ) -> Self {
         Payload {
@@ -81,9 +102,18 @@ impl Payload {
             walq_id: walq_id.clone(),
             store_id: store_id.clone(),
             opts,
+            trash_id: trash_id.clone(),
+            journal_id: journal_id.clone(),
         }
     }
 
+    // check if any entity is missing, as the repo is created by earlier
+    // version
+    fn is_outdated(&self) -> bool {
+        self.trash_id.is_empty()
+            || (self.opts.journal && self.journal_id.is_empty())
+    }
+
     fn seri(&self) -> Result<Vec<u8>> {
         let mut buf = Vec::new();
         self.serialize(&mut Serializer::new(&mut buf))?;
@@ -97,23 +127,41 @@ impl Payload {
     }
 }
 
+/// Snapshot view token, it is held by the file system opened for snapshot
+#[derive(Debug)]
+struct View {
+    seq: u64,
+}
+
 /// File system
 #[derive(Debug)]
 pub struct Fs {
     root: FnodeRef,
     fcache: FnodeCache,
     store: StoreRef,
+    trash: TrashRef,
+    journal: Option<JournalRef>,
     txmgr: TxMgrRef,
     vol: VolumeRef,
     shutter: ShutterRef,
+    watchers: WatchersRef,
     opts: Options,
     read_only: bool,
+
+    // opened snapshot views, keyed by snapshot name
+    views: RwLock<HashMap<String, Weak<View>>>,
+
+    // view token if this is opened for snapshot
+    view: Option<Arc<View>>,
 }
 
 impl Fs {
     // default cache size
     const FNODE_CACHE_SIZE: usize = 16;
 
+    // maximum number of symbolic links can be followed in path resolution
+    const MAX_SYMLINK_FOLLOWS: usize = 40;
+
     /// Check if fs exists
     pub fn exists(uri: &str) -> Result<bool> {
         let vol = Volume::new(uri)?;
@@ -125,7 +173,20 @@ impl Fs {
         let root_id = Eid::new();
         let walq_id = Eid::new();
         let store_id = Eid::new();
-        let payload = Payload::new(&root_id, &walq_id, &store_id, cfg.opts);
+        let trash_id = Eid::new();
+        let journal_id = if cfg.opts.journal {
+            Eid::new()
+        } else {
+            Eid::new_empty()
+        };
+        let payload = Payload::new(
+            &root_id,
+            &walq_id,
+            &store_id,
+            &trash_id,
+            &journal_id,
+            cfg.opts,
+        );
 
         // create and initialise volume
         let mut vol = Volume::new(uri)?;
@@ -138,18 +199,31 @@ impl Fs {
         // create tx manager and fnode cache
         let txmgr = TxMgr::new(&walq_id, &vol).into_ref();
         let fcache = FnodeCache::new(Self::FNODE_CACHE_SIZE);
+        let watchers = Watchers::new();
+        txmgr.write().unwrap().set_hook(watchers.clone());
 
         // the initial transaction to create root fnode and save store,
         // it must be successful
         let mut store_ref: Option<StoreRef> = None;
+        let mut trash_ref: Option<TrashRef> = None;
+        let mut journal_ref: Option<JournalRef> = None;
         let mut root_ref: Option<FnodeRef> = None;
         TxMgr::begin_trans(&txmgr)?.run_all(|| {
-            let store_cow = Store::new(cfg.opts.dedup_file, &txmgr, &vol)
-                .into_cow_with_id(&store_id, &txmgr)?;
+            let store = Store::new(cfg.opts.dedup_file, &txmgr, &vol);
+            let store_cow = store.into_cow_with_id(&store_id, &txmgr)?;
             let root_cow = Fnode::new(FileType::Dir, cfg.opts)
                 .into_cow_with_id(&root_id, &txmgr)?;
+            let trash_cow = Trash::new().into_cow_with_id(&trash_id, &txmgr)?;
             root_ref = Some(root_cow);
             store_ref = Some(store_cow);
+            trash_ref = Some(trash_cow);
+            if cfg.opts.journal {
+                let limit =
+                    cfg.opts.journal_limit.unwrap_or(DEFAULT_JOURNAL_LIMIT);
+                let journal_cow = Journal::new(limit)
+                    .into_cow_with_id(&journal_id, &txmgr)?;
+                journal_ref = Some(journal_cow);
+            }
             Ok(())
         })?;
 
@@ -159,11 +233,16 @@ impl Fs {
             root: root_ref.unwrap(),
             fcache,
             store: store_ref.unwrap(),
+            trash: trash_ref.unwrap(),
+            journal: journal_ref,
             txmgr,
             vol,
             shutter: Shutter::new(),
+            watchers,
             opts: cfg.opts,
             read_only: false,
+            views: RwLock::new(HashMap::new()),
+            view: None,
         })
     }
 
@@ -187,15 +266,34 @@ impl Fs {
         let vol = vol.into_ref();
 
         // deserialize payload
-        let payload = Payload::deseri(&payload)?;
+        let mut payload = Payload::deseri(&payload)?;
 
         // open transaction manager
         let txmgr = TxMgr::open(&payload.walq_id, &vol)?.into_ref();
+        let watchers = Watchers::new();
+        txmgr.write().unwrap().set_hook(watchers.clone());
 
         // create other file sytem components
         let store = Store::open(&payload.store_id, &txmgr, &vol)?;
         let root = Fnode::load_root(&payload.root_id, &vol)?;
         let fcache = FnodeCache::new(Self::FNODE_CACHE_SIZE);
+        if payload.is_outdated() && !read_only {
+            Self::upgrade(&mut payload, pwd, &txmgr, &vol)?;
+        }
+
+        // read-only repo created by earlier version is not upgraded, so
+        // use an empty trash and no journal instead
+        let trash = if payload.trash_id.is_empty() {
+            Trash::detached()
+        } else {
+            Trash::open(&payload.trash_id, &vol)?
+        };
+        let journal = if payload.opts.journal && !payload.journal_id.is_empty()
+        {
+            Some(Journal::open(&payload.journal_id, &vol)?)
+        } else {
+            None
+        };
 
         info!("repo opened");
 
@@ -203,14 +301,45 @@ impl Fs {
             root,
             fcache,
             store,
+            trash,
+            journal,
             txmgr,
             vol,
             shutter: Shutter::new(),
+            watchers,
             opts: payload.opts,
             read_only,
+            views: RwLock::new(HashMap::new()),
+            view: None,
         })
     }
 
+    // create entities missing in the repo created by earlier version and
+    // save their ids to super block payload
+    fn upgrade(
+        payload: &mut Payload,
+        pwd: &str,
+        txmgr: &TxMgrRef,
+        vol: &VolumeRef,
+    ) -> Result<()> {
+        TxMgr::begin_trans(txmgr)?.run_all(|| {
+            if payload.trash_id.is_empty() {
+                payload.trash_id = Eid::new();
+                Trash::new().into_cow_with_id(&payload.trash_id, txmgr)?;
+            }
+            if payload.opts.journal && payload.journal_id.is_empty() {
+                let limit =
+                    payload.opts.journal_limit.unwrap_or(DEFAULT_JOURNAL_LIMIT);
+                payload.journal_id = Eid::new();
+                Journal::new(limit)
+                    .into_cow_with_id(&payload.journal_id, txmgr)?;
+            }
+            Ok(())
+        })?;
+        let mut vol = vol.write().unwrap();
+        vol.update_payload(pwd, &payload.seri()?)
+    }
+
     #[inline]
     pub fn is_read_only(&self) -> bool {
         self.read_only
@@ -253,21 +382,151 @@ impl Fs {
         vol.repair_super_block(pwd)
     }
 
-    /// Resolve path
+    /// Check file system integrity and repair problems if required
+    pub fn check(&mut self, mode: CheckMode) -> Result<CheckReport> {
+        if self.read_only && mode == CheckMode::Repair {
+            return Err(Error::ReadOnly);
+        }
+
+        Checker::new(
+            &self.root,
+            &self.fcache,
+            &self.store,
+            &self.trash,
+            self.journal.as_ref(),
+            &self.txmgr,
+            &self.vol,
+        )
+        .run(mode)
+    }
+
+    /// Run operations in one transaction, all the transactions begun by the
+    /// operations are joined into it
+    pub fn transaction<F>(&mut self, oper: F) -> Result<()>
+    where
+        F: FnOnce(&mut Fs) -> Result<()>,
+    {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let tx_handle = TxMgr::begin_outer_trans(&self.txmgr)?;
+        tx_handle.run_all_exclusive(|| oper(self))
+    }
+
+    // get snapshot sequence if this is opened for snapshot
+    #[inline]
+    fn snap(&self) -> Option<(u64, &StoreRef)> {
+        self.view.as_ref().map(|view| (view.seq, &self.store))
+    }
+
+    // get child fnode, which is seen by snapshot if this is opened for it
+    fn child(&self, parent: &FnodeRef, name: &str) -> Result<FnodeRef> {
+        match self.snap() {
+            Some(snap) => Fnode::snapshot_child(
+                parent,
+                name,
+                snap,
+                &self.fcache,
+                &self.vol,
+            ),
+            None => Fnode::child(parent, name, &self.fcache, &self.vol),
+        }
+    }
+
+    /// Resolve path, symbolic links are followed
+    #[inline]
     pub fn resolve(&self, path: &Path) -> Result<FnodeRef> {
+        self.resolve_with(path, true)
+    }
+
+    /// Resolve path with follow option
+    ///
+    /// Symbolic links in the middle of path are always followed, the last
+    /// one is followed only if `follow` is true.
+    #[inline]
+    pub fn resolve_with(&self, path: &Path, follow: bool) -> Result<FnodeRef> {
+        self.resolve_link(path, follow).map(|(_, fnode)| fnode)
+    }
+
+    // resolve path to fnode and the link to it, which is its parent and
+    // entry name, root has no link
+    //
+    // A file with hard links can be reached by different links, the link
+    // in the path is used to find the file's parent.
+    fn resolve_link(
+        &self,
+        path: &Path,
+        follow: bool,
+    ) -> Result<(Option<(FnodeRef, String)>, FnodeRef)> {
         // only resolve absolute path
         if !path.has_root() {
             return Err(Error::InvalidPath);
         }
 
-        let mut fnode = self.root.clone();
+        // skip root component
+        let mut names: Vec<String> = path
+            .iter()
+            .skip(1)
+            .map(|name| name.to_str().unwrap().to_string())
+            .collect();
+        let mut follows = 0;
+
+        'resolve: loop {
+            let mut parent = None;
+            let mut fnode = self.root.clone();
+
+            for (idx, name) in names.iter().enumerate() {
+                let child = self.child(&fnode, name)?;
+                parent = Some(fnode);
+                fnode = child;
+
+                let target = {
+                    let child = fnode.read().unwrap();
+                    child.symlink_target()
+                };
+                if let Some(target) = target {
+                    if idx == names.len() - 1 && !follow {
+                        break;
+                    }
+
+                    follows += 1;
+                    if follows > Self::MAX_SYMLINK_FOLLOWS {
+                        return Err(Error::TooManyLinks);
+                    }
+
+                    // relative target starts from the link's parent, and
+                    // absolute target will replace the whole path
+                    let mut link_path = PathBuf::from("/");
+                    link_path.extend(&names[..idx]);
+                    link_path.push(target);
+                    link_path.extend(&names[idx + 1..]);
+                    names = Self::normalize(&link_path)?;
+                    continue 'resolve;
+                }
+            }
+
+            let link = parent.zip(names.last().cloned());
+            return Ok((link, fnode));
+        }
+    }
 
-        // loop through path component and skip root
-        for name in path.iter().skip(1) {
-            let name = name.to_str().unwrap();
-            fnode = Fnode::child(&fnode, name, &self.fcache, &self.vol)?;
+    // normalize path to name list, "." and ".." are resolved lexically
+    fn normalize(path: &Path) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for comp in path.components() {
+            match comp {
+                Component::Normal(name) => {
+                    let name = name.to_str().ok_or(Error::InvalidPath)?;
+                    names.push(name.to_string());
+                }
+                Component::ParentDir => {
+                    names.pop();
+                }
+                _ => {}
+            }
         }
-        Ok(fnode)
+        Ok(names)
     }
 
     // resolve path to parent fnode and child file name
@@ -281,14 +540,250 @@ impl Fs {
         Ok((parent, file_name.to_string()))
     }
 
+    // resolve path to parent fnode, child file name and child fnode, the
+    // child is not followed if it is a symbolic link
+    fn resolve_entry(
+        &self,
+        path: &Path,
+    ) -> Result<(FnodeRef, String, FnodeRef)> {
+        let (parent, name) = self.resolve_parent(path)?;
+        let fnode = self.child(&parent, &name)?;
+        Ok((parent, name, fnode))
+    }
+
+    // get absolute path of directory fnode, symbolic links are resolved
+    fn fnode_path(&self, fnode: &FnodeRef) -> Result<PathBuf> {
+        let root_id = {
+            let root = self.root.read().unwrap();
+            root.id().clone()
+        };
+        Fnode::path(fnode, &root_id).ok_or(Error::NotFound)
+    }
+
+    // record a change of an entry under parent to journal and watchers, it
+    // must be called in transaction
+    fn record_change(
+        &self,
+        kind: ChangeKind,
+        fnode: &FnodeRef,
+        parent: &FnodeRef,
+        name: &str,
+    ) -> Result<()> {
+        if !journal::is_recording(self.journal.as_ref(), &self.watchers) {
+            return Ok(());
+        }
+        let path = self.fnode_path(parent)?.join(name);
+        journal::record(
+            kind,
+            fnode,
+            &path,
+            None,
+            self.journal.as_ref(),
+            &self.txmgr,
+            &self.watchers,
+        )
+    }
+
+    // charge usage of a fnode tree added to or removed from a directory to
+    // quotas, it must be called in transaction after the tree is added or
+    // removed
+    //
+    // Other links of hard linked files in the tree might still be in the
+    // quota trees, so their usage is recalculated.
+    fn charge_tree(
+        &self,
+        dir: &FnodeRef,
+        fnode: &FnodeRef,
+        is_added: bool,
+    ) -> Result<()> {
+        if !quota::is_limited(dir) {
+            return Ok(());
+        }
+        let (usage, has_links) =
+            quota::tree_usage_links(fnode, &self.fcache, &self.vol)?;
+        if has_links {
+            return quota::recharge(dir, &self.fcache, &self.vol, &self.txmgr);
+        }
+        let empty = Usage::default();
+        if is_added {
+            quota::charge(dir, &empty, &usage, &self.txmgr)
+        } else {
+            quota::charge(dir, &usage, &empty, &self.txmgr)
+        }
+    }
+
+    // release fnode after its entry is removed from parent, the fnode is
+    // deleted if no other hard links are pointing at it and no snapshots
+    // can see it
+    fn release_fnode(&self, fnode_ref: &FnodeRef) -> Result<()> {
+        {
+            let mut fnode = fnode_ref.write().unwrap();
+            if fnode.links() > 1 {
+                Fnode::make_mut(&mut fnode, &self.store, &self.txmgr)?
+                    .dec_links()?;
+                return Ok(());
+            }
+
+            // keep the fnode as removed if any snapshot sees its current
+            // state, its frozen copies are kept by the store anyway
+            let mut store_cow = self.store.write().unwrap();
+            let from = store_cow.frozen_seq(fnode.id(), fnode.born());
+            if store_cow.has_snapshot_between(from, u64::MAX) {
+                store_cow
+                    .make_mut(&self.txmgr)?
+                    .set_removed(fnode.id(), fnode.born());
+                drop(fnode);
+                Fnode::clear_parent(fnode_ref);
+                return Ok(());
+            }
+        }
+        self.delete_fnode(fnode_ref)
+    }
+
+    // delete fnode along with contents of its versions
+    fn delete_fnode(&self, fnode_ref: &FnodeRef) -> Result<()> {
+        let mut fnode = fnode_ref.write().unwrap();
+        if fnode.is_file() {
+            fnode
+                .make_mut(&self.txmgr)?
+                .clear_versions(&self.store, &self.txmgr)?;
+        }
+        fnode.make_del(&self.txmgr)?;
+        self.fcache.remove(fnode.id());
+        Ok(())
+    }
+
+    // remove an entry from parent, the fnode is moved to trash if trash is
+    // enabled and no other hard links are pointing at it, otherwise it is
+    // released
+    fn discard_entry(
+        &self,
+        parent: &FnodeRef,
+        name: &str,
+        fnode_ref: &FnodeRef,
+        path: &Path,
+    ) -> Result<()> {
+        self.record_change(ChangeKind::Remove, fnode_ref, parent, name)?;
+        Fnode::remove_child(parent, name, &self.store, &self.txmgr)?;
+        self.charge_tree(parent, fnode_ref, false)?;
+
+        let to_trash = {
+            let fnode = fnode_ref.read().unwrap();
+            self.opts.trash && fnode.links() <= 1
+        };
+        if !to_trash {
+            // other links might outlive this parent, so don't keep it alive
+            // through the in-memory parent of the fnode
+            let is_parent = {
+                let fnode = fnode_ref.read().unwrap();
+                fnode.links() > 1
+                    && fnode.parent().is_some_and(|p| Arc::ptr_eq(&p, parent))
+            };
+            if is_parent {
+                Fnode::detach(fnode_ref, &self.txmgr)?;
+            }
+            return self.release_fnode(fnode_ref);
+        }
+
+        Fnode::detach(fnode_ref, &self.txmgr)?;
+        let size = self.tree_size(fnode_ref)?;
+        {
+            let fnode = fnode_ref.read().unwrap();
+            let mut trash_cow = self.trash.write().unwrap();
+            let trash = trash_cow.make_mut(&self.txmgr)?;
+            trash.add(fnode.id(), &path.to_string_lossy(), size);
+        }
+        self.purge_expired_trash().map(|_| ())
+    }
+
+    // get total content length of all file versions in a fnode tree
+    fn tree_size(&self, fnode_ref: &FnodeRef) -> Result<u64> {
+        let mut size = 0;
+        let mut stack = vec![fnode_ref.clone()];
+        while let Some(fnode_ref) = stack.pop() {
+            let names = {
+                let fnode = fnode_ref.read().unwrap();
+                if fnode.is_file() {
+                    size += fnode
+                        .history()
+                        .iter()
+                        .map(|ver| ver.content_len() as u64)
+                        .sum::<u64>();
+                }
+                fnode.children_names()
+            };
+            for name in names {
+                let kid =
+                    Fnode::child(&fnode_ref, &name, &self.fcache, &self.vol)?;
+                stack.push(kid);
+            }
+        }
+        Ok(size)
+    }
+
+    // purge trash entries which exceed the max age or max size, the oldest
+    // entries are purged first, return the number of purged entries
+    fn purge_expired_trash(&self) -> Result<usize> {
+        let expired: Vec<u64> = {
+            let trash = self.trash.read().unwrap();
+            let now = Time::now().as_secs();
+            let mut total: u64 = trash.items().values().map(|t| t.size()).sum();
+            let mut ids = Vec::new();
+
+            // trash entries are ordered by deletion time
+            for (id, item) in trash.items().iter() {
+                let dtime = item.deleted_at().as_secs();
+                let is_expired = self
+                    .opts
+                    .trash_max_age
+                    .is_some_and(|age| now.saturating_sub(dtime) > age);
+                let is_oversize =
+                    self.opts.trash_max_size.is_some_and(|max| total > max);
+                if !is_expired && !is_oversize {
+                    break;
+                }
+                total -= item.size();
+                ids.push(*id);
+            }
+            ids
+        };
+
+        for id in expired.iter() {
+            self.purge_trash_entry(*id)?;
+        }
+        Ok(expired.len())
+    }
+
+    // remove an entry from trash and release its fnode tree
+    fn purge_trash_entry(&self, id: u64) -> Result<()> {
+        let root_id = {
+            let trash = self.trash.read().unwrap();
+            let item = trash.get(id).ok_or(Error::NotFound)?;
+            item.root_id().clone()
+        };
+        let root = self.fcache.get(&root_id, &self.vol)?;
+        self.release_tree(&root)?;
+        let mut trash_cow = self.trash.write().unwrap();
+        trash_cow.make_mut(&self.txmgr)?.remove(id);
+        Ok(())
+    }
+
     /// Open fnode
     pub fn open_fnode(&mut self, path: &Path) -> Result<Handle> {
-        let fnode = self.resolve(path)?;
+        let (link, fnode) = self.resolve_link(path, true)?;
+        let root_id = {
+            let root = self.root.read().unwrap();
+            root.id().clone()
+        };
         Ok(Handle {
             fnode,
+            link,
+            root_id,
             store: Arc::downgrade(&self.store),
+            journal: self.journal.as_ref().map(Arc::downgrade),
             txmgr: Arc::downgrade(&self.txmgr),
             shutter: self.shutter.clone(),
+            watchers: self.watchers.clone(),
         })
     }
 
@@ -326,7 +821,8 @@ impl Fs {
                 &self.txmgr,
                 &self.store,
             )?;
-            Ok(())
+            self.charge_tree(&parent, &fnode, true)?;
+            self.record_change(ChangeKind::Create, &fnode, &parent, &name)
         })?;
 
         Ok(fnode)
@@ -350,7 +846,13 @@ impl Fs {
     /// Read directory entries
     pub fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>> {
         let parent = self.resolve(path)?;
-        Fnode::read_dir(parent, path, &self.fcache, &self.vol)
+        Fnode::read_dir(parent, path, self.snap(), &self.fcache, &self.vol)
+    }
+
+    /// Create a builder to recursively walk the specified directory
+    pub fn walk_dir(&self, path: &Path) -> Result<WalkDir<'_>> {
+        let fnode = self.resolve(path)?;
+        WalkDir::new(fnode, path, self.snap(), &self.fcache, &self.vol)
     }
 
     /// Get metadata of specified path
@@ -360,101 +862,323 @@ impl Fs {
         Ok(fnode.metadata())
     }
 
-    /// Get file version list of specified path
-    pub fn history(&self, path: &Path) -> Result<Vec<Version>> {
-        let fnode_ref = self.resolve(path)?;
+    /// Get metadata of specified path without following symbolic link
+    pub fn symlink_metadata(&self, path: &Path) -> Result<Metadata> {
+        let fnode_ref = self.resolve_with(path, false)?;
         let fnode = fnode_ref.read().unwrap();
-        if fnode.is_dir() {
-            return Err(Error::IsDir);
-        }
-        Ok(fnode.history())
+        Ok(fnode.metadata())
     }
 
-    /// Copy a regular file to another
-    pub fn copy(&mut self, from: &Path, to: &Path) -> Result<()> {
+    // resolve path and apply a change to its fnode in a transaction
+    fn update_fnode<F>(&mut self, path: &Path, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Fnode) -> Result<()>,
+    {
         if self.read_only {
             return Err(Error::ReadOnly);
         }
 
-        let opts;
-        let src = self.resolve(from)?;
-        {
-            let fnode = src.read().unwrap();
-            if !fnode.is_file() {
-                return Err(Error::NotFile);
-            }
-            opts = fnode.get_opts();
-        }
-
-        let tgt = {
-            match self.open_fnode(to) {
-                Ok(tgt) => {
-                    {
-                        // if target and source are same fnode, do nothing
-                        if Arc::ptr_eq(&tgt.fnode, &src) {
-                            return Ok(());
-                        }
+        let fnode_ref = self.resolve(path)?;
 
-                        let fnode = tgt.fnode.read().unwrap();
-                        if !fnode.is_file() {
-                            return Err(Error::NotFile);
-                        }
-                    }
-                    tgt
-                }
-                Err(ref err) if *err == Error::NotFound => {
-                    // target file doesn't exist
-                    self.create_fnode(to, FileType::File, opts)?;
-                    self.open_fnode(to)?
-                }
-                Err(err) => return Err(err),
-            }
-        };
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            let mut fnode = fnode_ref.write().unwrap();
+            f(Fnode::make_mut(&mut fnode, &self.store, &self.txmgr)?)
+        })
+    }
 
-        // begin and run transaction
-        let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
-        tx_handle.run_all_exclusive(|| {
-            // get current version of source
-            let ctn = {
-                let fnode = src.read().unwrap();
-                fnode.clone_current_content(&self.store)?
-            };
+    /// Set POSIX permission bits of specified path
+    #[inline]
+    pub fn set_permissions(&mut self, path: &Path, mode: u32) -> Result<()> {
+        self.update_fnode(path, |fnode| fnode.set_mode(mode))
+    }
 
-            // then add it to target
-            let mut fnode_cow = tgt.fnode.write().unwrap();
-            let fnode = fnode_cow.make_mut(&self.txmgr)?;
-            let result = fnode.add_version(ctn, &self.store, &self.txmgr)?;
-            assert!(!(self.opts.dedup_file && result));
+    /// Set owner user and group id of specified path
+    #[inline]
+    pub fn set_owner(
+        &mut self,
+        path: &Path,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<()> {
+        self.update_fnode(path, |fnode| {
+            fnode.set_owner(uid, gid);
+            Ok(())
+        })
+    }
 
+    /// Set access and modification time of specified path
+    pub fn set_times(
+        &mut self,
+        path: &Path,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<()> {
+        let atime =
+            Time::from_system_time(atime).ok_or(Error::InvalidArgument)?;
+        let mtime =
+            Time::from_system_time(mtime).ok_or(Error::InvalidArgument)?;
+        self.update_fnode(path, |fnode| {
+            fnode.set_times(atime, mtime);
             Ok(())
-        })?;
+        })
+    }
 
-        Ok(())
+    /// Get extended attribute value of specified path
+    pub fn get_xattr(
+        &self,
+        path: &Path,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let fnode_ref = self.resolve(path)?;
+        let fnode = fnode_ref.read().unwrap();
+        Ok(fnode.get_xattr(name))
     }
 
-    /// Copy a dir to another recursively
-    pub fn copy_dir_all(&mut self, from: &Path, to: &Path) -> Result<()> {
+    /// Get extended attribute names of specified path
+    pub fn list_xattrs(&self, path: &Path) -> Result<Vec<String>> {
+        let fnode_ref = self.resolve(path)?;
+        let fnode = fnode_ref.read().unwrap();
+        Ok(fnode.list_xattrs())
+    }
+
+    /// Set extended attribute value of specified path
+    pub fn set_xattr(
+        &mut self,
+        path: &Path,
+        name: &str,
+        value: &[u8],
+    ) -> Result<()> {
+        Fnode::check_xattr(name, value)?;
+        self.update_fnode(path, |fnode| fnode.set_xattr(name, value))
+    }
+
+    /// Remove extended attribute of specified path
+    pub fn remove_xattr(&mut self, path: &Path, name: &str) -> Result<()> {
         if self.read_only {
             return Err(Error::ReadOnly);
         }
 
-        // if target and source are same fnode, do nothing
-        if from == to {
-            return Ok(());
+        let fnode_ref = self.resolve(path)?;
+        {
+            let fnode = fnode_ref.read().unwrap();
+            if !fnode.has_xattr(name) {
+                return Ok(());
+            }
         }
 
-        if to.starts_with(from) {
-            return Err(Error::InvalidArgument);
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            let mut fnode = fnode_ref.write().unwrap();
+            Fnode::make_mut(&mut fnode, &self.store, &self.txmgr)?
+                .remove_xattr(name);
+            Ok(())
+        })
+    }
+
+    /// Create a symbolic link pointing at target
+    pub fn symlink(&mut self, target: &Path, path: &Path) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
         }
 
-        // sanity check source and target
+        let (parent, name) = self.resolve_parent(path)?;
+
         {
-            let src = self.resolve(from)?;
-            {
-                let fnode = src.read().unwrap();
-                if !fnode.is_dir() {
-                    return Err(Error::NotDir);
-                }
+            let parent = parent.read().unwrap();
+            if !parent.is_dir() {
+                return Err(Error::NotDir);
+            }
+            if parent.has_child(&name) {
+                return Err(Error::AlreadyExists);
+            }
+        }
+
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            let fnode = Fnode::new_symlink_under(
+                &parent,
+                &name,
+                target,
+                &self.txmgr,
+                &self.store,
+            )?;
+            self.record_change(ChangeKind::Create, &fnode, &parent, &name)
+        })
+    }
+
+    /// Read symbolic link target
+    pub fn read_link(&self, path: &Path) -> Result<PathBuf> {
+        let fnode_ref = self.resolve_with(path, false)?;
+        let fnode = fnode_ref.read().unwrap();
+        fnode.symlink_target().ok_or(Error::NotLink)
+    }
+
+    /// Create a hard link pointing at the same fnode as source
+    pub fn hard_link(&mut self, src: &Path, dst: &Path) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let src = self.resolve_with(src, false)?;
+        {
+            let fnode = src.read().unwrap();
+            if fnode.is_dir() {
+                return Err(Error::IsDir);
+            }
+        }
+
+        let (parent, name) = self.resolve_parent(dst)?;
+        {
+            let parent = parent.read().unwrap();
+            if !parent.is_dir() {
+                return Err(Error::NotDir);
+            }
+            if parent.has_child(&name) {
+                return Err(Error::AlreadyExists);
+            }
+        }
+
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            Fnode::add_child(&parent, &src, &name, &self.store, &self.txmgr)?;
+            {
+                let mut fnode = src.write().unwrap();
+                Fnode::make_mut(&mut fnode, &self.store, &self.txmgr)?
+                    .inc_links()?;
+            }
+            self.charge_tree(&parent, &src, true)?;
+            self.record_change(ChangeKind::Create, &src, &parent, &name)
+        })
+    }
+
+    /// Get file version list of specified path
+    pub fn history(&self, path: &Path) -> Result<Vec<Version>> {
+        let fnode_ref = self.resolve(path)?;
+        let fnode = fnode_ref.read().unwrap();
+        if fnode.is_dir() {
+            return Err(Error::IsDir);
+        }
+        Ok(fnode.history())
+    }
+
+    /// Restore a historical version of a file as its new current version
+    pub fn restore_version(
+        &mut self,
+        path: &Path,
+        ver_num: usize,
+    ) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let (link, fnode_ref) = self.resolve_link(path, true)?;
+        {
+            let fnode = fnode_ref.read().unwrap();
+            if !fnode.is_file() {
+                return Err(Error::NotFile);
+            }
+        }
+        let (parent, name) = link.ok_or(Error::IsRoot)?;
+
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            let usage = quota::file_usage(&parent, &fnode_ref);
+            {
+                let mut fnode = fnode_ref.write().unwrap();
+                Fnode::make_mut(&mut fnode, &self.store, &self.txmgr)?
+                    .restore_version(ver_num, &self.store, &self.txmgr)?;
+            }
+            quota::charge_file(&parent, &fnode_ref, usage, &self.txmgr)?;
+            self.record_change(ChangeKind::Write, &fnode_ref, &parent, &name)
+        })
+    }
+
+    /// Copy a regular file to another
+    pub fn copy(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let opts;
+        let src = self.resolve(from)?;
+        {
+            let fnode = src.read().unwrap();
+            if !fnode.is_file() {
+                return Err(Error::NotFile);
+            }
+            opts = fnode.get_opts();
+        }
+
+        let tgt = {
+            match self.open_fnode(to) {
+                Ok(tgt) => {
+                    {
+                        // if target and source are same fnode, do nothing
+                        if Arc::ptr_eq(&tgt.fnode, &src) {
+                            return Ok(());
+                        }
+
+                        let fnode = tgt.fnode.read().unwrap();
+                        if !fnode.is_file() {
+                            return Err(Error::NotFile);
+                        }
+                    }
+                    tgt
+                }
+                Err(ref err) if *err == Error::NotFound => {
+                    // target file doesn't exist
+                    self.create_fnode(to, FileType::File, opts)?;
+                    self.open_fnode(to)?
+                }
+                Err(err) => return Err(err),
+            }
+        };
+
+        // begin and run transaction
+        let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
+        tx_handle.run_all_exclusive(|| {
+            // get current version of source
+            let ctn = {
+                let fnode = src.read().unwrap();
+                fnode.clone_current_content(&self.store)?
+            };
+
+            // then add it to target
+            let usage = tgt.quota_usage();
+            {
+                let mut fnode_cow = tgt.fnode.write().unwrap();
+                let fnode =
+                    Fnode::make_mut(&mut fnode_cow, &self.store, &self.txmgr)?;
+                let result =
+                    fnode.add_version(ctn, &self.store, &self.txmgr)?;
+                assert!(!(self.opts.dedup_file && result));
+            }
+            tgt.charge_quota(usage)?;
+            tgt.record_change(ChangeKind::Write)
+        })?;
+
+        Ok(())
+    }
+
+    /// Copy a dir to another recursively
+    pub fn copy_dir_all(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        // if target and source are same fnode, do nothing
+        if from == to {
+            return Ok(());
+        }
+
+        if to.starts_with(from) {
+            return Err(Error::InvalidArgument);
+        }
+
+        // sanity check source and target
+        {
+            let src = self.resolve(from)?;
+            {
+                let fnode = src.read().unwrap();
+                if !fnode.is_dir() {
+                    return Err(Error::NotDir);
+                }
             }
 
             match self.resolve(to) {
@@ -480,22 +1204,32 @@ impl Fs {
             match child.metadata().file_type() {
                 FileType::File => self.copy(child_from, &child_to)?,
                 FileType::Dir => self.copy_dir_all(child_from, &child_to)?,
+                FileType::Symlink => {
+                    // replace target entry with a new symbolic link
+                    let target = self.read_link(child_from)?;
+                    match self.resolve_with(&child_to, false) {
+                        Ok(_) => self.remove_file(&child_to)?,
+                        Err(ref err) if *err == Error::NotFound => {}
+                        Err(err) => return Err(err),
+                    }
+                    self.symlink(&target, &child_to)?;
+                }
             }
         }
 
         Ok(())
     }
 
-    /// Remove a regular file
+    /// Remove a regular file or symbolic link
     pub fn remove_file(&mut self, path: &Path) -> Result<()> {
         if self.read_only {
             return Err(Error::ReadOnly);
         }
 
-        let fnode_ref = self.resolve(path)?;
+        let (parent, name, fnode_ref) = self.resolve_entry(path)?;
         {
             let fnode = fnode_ref.read().unwrap();
-            if !fnode.is_file() {
+            if fnode.is_dir() {
                 return Err(Error::NotFile);
             }
         }
@@ -503,14 +1237,7 @@ impl Fs {
         // begin and run transaction
         let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
         tx_handle.run_all_exclusive(move || {
-            Fnode::remove_from_parent(&fnode_ref, &self.txmgr)?;
-            let mut fnode = fnode_ref.write().unwrap();
-            fnode
-                .make_mut(&self.txmgr)?
-                .clear_versions(&self.store, &self.txmgr)?;
-            fnode.make_del(&self.txmgr)?;
-            self.fcache.remove(fnode.id());
-            Ok(())
+            self.discard_entry(&parent, &name, &fnode_ref, path)
         })?;
 
         Ok(())
@@ -522,15 +1249,12 @@ impl Fs {
             return Err(Error::ReadOnly);
         }
 
-        let fnode_ref = self.resolve(path)?;
+        let (parent, name, fnode_ref) = self.resolve_entry(path)?;
         {
             let fnode = fnode_ref.read().unwrap();
             if !fnode.is_dir() {
                 return Err(Error::NotDir);
             }
-            if fnode.is_root() {
-                return Err(Error::IsRoot);
-            }
             if fnode.children_cnt() > 0 {
                 return Err(Error::NotEmpty);
             }
@@ -538,12 +1262,8 @@ impl Fs {
 
         // begin and run transaction
         let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
-        tx_handle.run_all(move || {
-            Fnode::remove_from_parent(&fnode_ref, &self.txmgr)?;
-            let mut fnode = fnode_ref.write().unwrap();
-            fnode.make_del(&self.txmgr)?;
-            self.fcache.remove(fnode.id());
-            Ok(())
+        tx_handle.run_all_exclusive(move || {
+            self.discard_entry(&parent, &name, &fnode_ref, path)
         })?;
 
         Ok(())
@@ -551,10 +1271,32 @@ impl Fs {
 
     /// Remove an existing directory recursively
     pub fn remove_dir_all(&mut self, path: &Path) -> Result<()> {
+        {
+            let fnode_ref = self.resolve_with(path, false)?;
+            let fnode = fnode_ref.read().unwrap();
+            if !fnode.is_dir() {
+                return Err(Error::NotDir);
+            }
+        }
+
+        // move the whole directory tree to trash, except the root
+        if self.opts.trash && path.parent().is_some() {
+            if self.read_only {
+                return Err(Error::ReadOnly);
+            }
+            let (parent, name, fnode_ref) = self.resolve_entry(path)?;
+            let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
+            return tx_handle.run_all_exclusive(move || {
+                self.discard_entry(&parent, &name, &fnode_ref, path)
+            });
+        }
+
         for child in self.read_dir(path)? {
             let child_path = child.path();
             match child.metadata().file_type() {
-                FileType::File => self.remove_file(child_path)?,
+                FileType::File | FileType::Symlink => {
+                    self.remove_file(child_path)?
+                }
                 FileType::Dir => self.remove_dir_all(child_path)?,
             }
         }
@@ -579,29 +1321,31 @@ impl Fs {
             return Err(Error::InvalidArgument);
         }
 
-        let src = self.resolve(from)?;
-        let tgt = match self.resolve(to) {
-            Ok(tgt) => Some(tgt),
-            Err(ref err) if *err == Error::NotFound => None,
-            Err(err) => return Err(err),
-        };
+        let (src_parent, src_name, src) = self.resolve_entry(from)?;
+        let (tgt_parent, name) = self.resolve_parent(to)?;
+        let tgt =
+            match Fnode::child(&tgt_parent, &name, &self.fcache, &self.vol) {
+                Ok(tgt) => Some(tgt),
+                Err(ref err) if *err == Error::NotFound => None,
+                Err(err) => return Err(err),
+            };
 
         {
-            let src_fnode = src.read().unwrap();
-            if src_fnode.is_root() {
-                return Err(Error::IsRoot);
+            // hard links pointing at the same fnode, do nothing
+            if let Some(ref tgt_fnode) = tgt {
+                if Arc::ptr_eq(tgt_fnode, &src) {
+                    return Ok(());
+                }
             }
 
+            let src_fnode = src.read().unwrap();
             if let Some(ref tgt_fnode) = tgt {
                 let tgt_fnode = tgt_fnode.read().unwrap();
-                if tgt_fnode.is_root() {
-                    return Err(Error::IsRoot);
-                }
-                if src_fnode.is_file() && tgt_fnode.is_dir() {
+                if !src_fnode.is_dir() && tgt_fnode.is_dir() {
                     return Err(Error::IsDir);
                 }
                 if src_fnode.is_dir() {
-                    if tgt_fnode.is_file() {
+                    if !tgt_fnode.is_dir() {
                         return Err(Error::NotDir);
                     }
                     if tgt_fnode.children_cnt() > 0 {
@@ -611,31 +1355,464 @@ impl Fs {
             }
         }
 
-        let (tgt_parent, name) = self.resolve_parent(to)?;
-
         // begin and run transaction
         TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
             // remove from source
-            Fnode::remove_from_parent(&src, &self.txmgr)?;
+            Fnode::remove_child(
+                &src_parent,
+                &src_name,
+                &self.store,
+                &self.txmgr,
+            )?;
+            self.charge_tree(&src_parent, &src, false)?;
 
-            // remove target if it exists
+            // remove target if it exists, it is moved to trash as well
             if let Some(tgt_fnode) = tgt {
-                Fnode::remove_from_parent(&tgt_fnode, &self.txmgr)?;
-                let mut tgt_fnode = tgt_fnode.write().unwrap();
-                if tgt_fnode.is_file() {
-                    tgt_fnode
-                        .make_mut(&self.txmgr)?
-                        .clear_versions(&self.store, &self.txmgr)?;
-                }
-                tgt_fnode.make_del(&self.txmgr)?;
-                self.fcache.remove(tgt_fnode.id());
+                self.discard_entry(&tgt_parent, &name, &tgt_fnode, to)?;
             }
 
             // and then add to target
-            Fnode::add_child(&tgt_parent, &src, &name, &self.txmgr)
+            Fnode::add_child(
+                &tgt_parent,
+                &src,
+                &name,
+                &self.store,
+                &self.txmgr,
+            )?;
+            self.charge_tree(&tgt_parent, &src, true)?;
+
+            if journal::is_recording(self.journal.as_ref(), &self.watchers) {
+                let from = self.fnode_path(&src_parent)?.join(&src_name);
+                let to = self.fnode_path(&tgt_parent)?.join(&name);
+                journal::record(
+                    ChangeKind::Rename,
+                    &src,
+                    &from,
+                    Some(&to),
+                    self.journal.as_ref(),
+                    &self.txmgr,
+                    &self.watchers,
+                )?;
+            }
+            Ok(())
         })
     }
 
+    /// Apply retention policy or version limit to all files, return the
+    /// number of removed versions
+    pub fn apply_retention(&mut self) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let mut removed = 0;
+        let mut visited = HashSet::new();
+        let mut stack = vec![(self.root.clone(), None)];
+
+        // file is charged to quotas through the directory it is found in
+        while let Some((fnode_ref, dir)) = stack.pop() {
+            let (is_file, names) = {
+                let fnode = fnode_ref.read().unwrap();
+                if !visited.insert(fnode.id().clone()) {
+                    continue;
+                }
+                (
+                    fnode.is_file() && !fnode.retired_versions().is_empty(),
+                    fnode.children_names(),
+                )
+            };
+
+            if let (true, Some(dir)) = (is_file, dir) {
+                let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
+                tx_handle.run_all_exclusive(|| {
+                    let usage = quota::file_usage(&dir, &fnode_ref);
+                    {
+                        let mut fnode = fnode_ref.write().unwrap();
+                        removed += Fnode::make_mut(
+                            &mut fnode,
+                            &self.store,
+                            &self.txmgr,
+                        )?
+                        .evict_versions(&self.store, &self.txmgr)?;
+                    }
+                    quota::charge_file(&dir, &fnode_ref, usage, &self.txmgr)
+                })?;
+            }
+
+            for name in names {
+                let kid =
+                    Fnode::child(&fnode_ref, &name, &self.fcache, &self.vol)?;
+                stack.push((kid, Some(fnode_ref.clone())));
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Create a snapshot of the whole file system
+    pub fn create_snapshot(&mut self, name: &str) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if name.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+        {
+            let store = self.store.read().unwrap();
+            if store.get_snapshot(name).is_some() {
+                return Err(Error::AlreadyExists);
+            }
+        }
+
+        // begin and run transaction
+        let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
+        tx_handle.run_all_exclusive(|| {
+            let mut store_cow = self.store.write().unwrap();
+            let store = store_cow.make_mut(&self.txmgr)?;
+            store.add_snapshot(name);
+            Ok(())
+        })
+    }
+
+    /// Get snapshot names and creation times
+    pub fn snapshots(&self) -> Vec<(String, SystemTime)> {
+        let store = self.store.read().unwrap();
+        store
+            .snapshots()
+            .iter()
+            .map(|(name, snapshot)| {
+                (name.clone(), snapshot.created_at().to_system_time())
+            })
+            .collect()
+    }
+
+    /// Open a snapshot as a read-only file system
+    pub fn open_snapshot(&self, name: &str) -> Result<(Fs, SystemTime)> {
+        let (seq, ctime) = {
+            let store = self.store.read().unwrap();
+            let snapshot = store.get_snapshot(name).ok_or(Error::NotFound)?;
+            (snapshot.seq(), snapshot.created_at())
+        };
+        let root = Fnode::frozen_for(
+            &self.root,
+            seq,
+            &self.store,
+            &self.fcache,
+            &self.vol,
+        )?;
+
+        // views of the same snapshot share one token
+        let view = {
+            let mut views = self.views.write().unwrap();
+            match views.get(name).and_then(Weak::upgrade) {
+                Some(view) => view,
+                None => {
+                    let view = Arc::new(View { seq });
+                    views.insert(name.to_string(), Arc::downgrade(&view));
+                    view
+                }
+            }
+        };
+
+        let fs = Fs {
+            root,
+            fcache: self.fcache.clone(),
+            store: self.store.clone(),
+            trash: self.trash.clone(),
+            journal: self.journal.clone(),
+            txmgr: self.txmgr.clone(),
+            vol: self.vol.clone(),
+            shutter: Shutter::new(),
+            watchers: self.watchers.clone(),
+            opts: self.opts,
+            read_only: true,
+            views: RwLock::new(HashMap::new()),
+            view: Some(view),
+        };
+        Ok((fs, ctime.to_system_time()))
+    }
+
+    /// Delete a snapshot, fnodes and contents only seen by it are removed
+    pub fn delete_snapshot(&mut self, name: &str) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        {
+            let store = self.store.read().unwrap();
+            if store.get_snapshot(name).is_none() {
+                return Err(Error::NotFound);
+            }
+        }
+
+        // snapshot cannot be deleted while it is opened
+        {
+            let views = self.views.read().unwrap();
+            if views.get(name).is_some_and(|view| view.strong_count() > 0) {
+                return Err(Error::InUse);
+            }
+        }
+
+        // begin and run transaction
+        let tx_handle = TxMgr::begin_trans(&self.txmgr)?;
+        tx_handle.run_all_exclusive(|| {
+            let pinned: Vec<Eid> = {
+                let mut store_cow = self.store.write().unwrap();
+                let store = store_cow.make_mut(&self.txmgr)?;
+                store.remove_snapshot(name);
+                store.pinned().keys().cloned().collect()
+            };
+            for fnode_id in pinned {
+                if self.prune_fnode(&fnode_id)? {
+                    let mut store_cow = self.store.write().unwrap();
+                    store_cow.make_mut(&self.txmgr)?.unpin(&fnode_id);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    // delete frozen copies of a pinned fnode which no snapshots can see,
+    // and the fnode itself if it is removed and no snapshots can see it
+    // either, return true if the fnode is not pinned anymore
+    //
+    // The fnode is loaded only when it is removed, so the fnode in the tree
+    // is never duplicated in memory.
+    fn prune_fnode(&self, fnode_id: &Eid) -> Result<bool> {
+        let pin = {
+            let store = self.store.read().unwrap();
+            store.pinned().get(fnode_id).cloned().unwrap_or_default()
+        };
+
+        // a copy is seen by snapshots after the previous copy, the first
+        // copy is seen by snapshots after the fnode is born
+        let mut deleted = Vec::new();
+        let mut from = pin.born();
+        {
+            let store = self.store.read().unwrap();
+            for (seq, copy_id) in pin.frozen() {
+                if !store.has_snapshot_between(from, *seq) {
+                    deleted.push(copy_id.clone());
+                }
+                from = *seq;
+            }
+        }
+        for copy_id in deleted.iter() {
+            let copy = self.fcache.get(copy_id, &self.vol)?;
+            self.delete_fnode(&copy)?;
+            let mut store_cow = self.store.write().unwrap();
+            store_cow
+                .make_mut(&self.txmgr)?
+                .remove_frozen(fnode_id, copy_id);
+        }
+        let is_frozen = deleted.len() < pin.frozen().len();
+
+        // the removed fnode is seen by snapshots after its last copy
+        if let Some(seq) = pin.removed() {
+            let is_seen = {
+                let store = self.store.read().unwrap();
+                store.has_snapshot_between(from, seq)
+            };
+            if is_seen {
+                return Ok(false);
+            }
+            let fnode_ref = self.fcache.get(fnode_id, &self.vol)?;
+            self.delete_fnode(&fnode_ref)?;
+            let mut store_cow = self.store.write().unwrap();
+            store_cow.make_mut(&self.txmgr)?.clear_removed(fnode_id);
+        }
+
+        Ok(!is_frozen)
+    }
+
+    // release a whole fnode tree which is not linked to any parent
+    fn release_tree(&self, fnode_ref: &FnodeRef) -> Result<()> {
+        let names = {
+            let fnode = fnode_ref.read().unwrap();
+            fnode.children_names()
+        };
+        for name in names {
+            let kid = Fnode::child(fnode_ref, &name, &self.fcache, &self.vol)?;
+            Fnode::clear_parent(&kid);
+            self.release_tree(&kid)?;
+        }
+        self.release_fnode(fnode_ref)
+    }
+
+    /// Get all entries in trash, ordered by deletion time
+    pub fn trash_entries(&self) -> Result<Vec<TrashEntry>> {
+        let items: Vec<(u64, TrashItem)> = {
+            let trash = self.trash.read().unwrap();
+            trash
+                .items()
+                .iter()
+                .map(|(id, item)| (*id, item.clone()))
+                .collect()
+        };
+
+        let mut ret = Vec::new();
+        for (id, item) in items {
+            let fnode_ref = self.fcache.get(item.root_id(), &self.vol)?;
+            let fnode = fnode_ref.read().unwrap();
+            ret.push(TrashEntry::new(
+                id,
+                item.path(),
+                item.deleted_at().to_system_time(),
+                item.size(),
+                fnode.metadata(),
+            ));
+        }
+        Ok(ret)
+    }
+
+    /// Restore an entry in trash to its original path or a new path
+    pub fn undelete(&mut self, id: u64, to: Option<&Path>) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let (root_id, path) = {
+            let trash = self.trash.read().unwrap();
+            let item = trash.get(id).ok_or(Error::NotFound)?;
+            (item.root_id().clone(), PathBuf::from(item.path()))
+        };
+        let to = to.unwrap_or(&path);
+        let (parent, name) = self.resolve_parent(to)?;
+        {
+            let parent = parent.read().unwrap();
+            if !parent.is_dir() {
+                return Err(Error::NotDir);
+            }
+            if parent.has_child(&name) {
+                return Err(Error::AlreadyExists);
+            }
+        }
+        let fnode_ref = self.fcache.get(&root_id, &self.vol)?;
+
+        // begin and run transaction
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            Fnode::add_child(
+                &parent,
+                &fnode_ref,
+                &name,
+                &self.store,
+                &self.txmgr,
+            )?;
+            self.charge_tree(&parent, &fnode_ref, true)?;
+            {
+                let mut trash_cow = self.trash.write().unwrap();
+                trash_cow.make_mut(&self.txmgr)?.remove(id);
+            }
+            self.record_change(ChangeKind::Create, &fnode_ref, &parent, &name)
+        })
+    }
+
+    /// Purge trash entries which exceed the max age or max size, return the
+    /// number of purged entries
+    pub fn purge_trash(&mut self) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let mut purged = 0;
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            purged = self.purge_expired_trash()?;
+            Ok(())
+        })?;
+        Ok(purged)
+    }
+
+    /// Purge all entries in trash, return the number of purged entries
+    pub fn empty_trash(&mut self) -> Result<usize> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let ids: Vec<u64> = {
+            let trash = self.trash.read().unwrap();
+            trash.items().keys().cloned().collect()
+        };
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            for id in ids.iter() {
+                self.purge_trash_entry(*id)?;
+            }
+            Ok(())
+        })?;
+        Ok(ids.len())
+    }
+
+    /// Set quota of a directory, quota is removed if it is None
+    pub fn set_quota(
+        &mut self,
+        path: &Path,
+        quota: Option<Quota>,
+    ) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        let fnode_ref = self.resolve(path)?;
+        {
+            let fnode = fnode_ref.read().unwrap();
+            if !fnode.is_dir() {
+                return Err(Error::NotDir);
+            }
+        }
+
+        TxMgr::begin_trans(&self.txmgr)?.run_all_exclusive(|| {
+            let usage = match quota {
+                Some(_) => {
+                    quota::tree_usage(&fnode_ref, &self.fcache, &self.vol)?
+                }
+                None => Usage::default(),
+            };
+            let mut fnode = fnode_ref.write().unwrap();
+            fnode.make_mut(&self.txmgr)?.set_quota(quota, usage);
+            Ok(())
+        })
+    }
+
+    /// Get quota of a directory
+    pub fn quota(&self, path: &Path) -> Result<Option<Quota>> {
+        let fnode_ref = self.resolve(path)?;
+        let fnode = fnode_ref.read().unwrap();
+        if !fnode.is_dir() {
+            return Err(Error::NotDir);
+        }
+        Ok(fnode.quota())
+    }
+
+    /// Get storage usage of a file or directory tree
+    pub fn usage(&self, path: &Path) -> Result<Usage> {
+        let fnode_ref = self.resolve(path)?;
+        quota::tree_usage(&fnode_ref, &self.fcache, &self.vol)
+    }
+
+    /// Get changes recorded in journal after the cursor
+    pub fn changes_since(&self, cursor: u64) -> Result<ChangeSet> {
+        match self.journal {
+            Some(ref journal) => {
+                let journal = journal.read().unwrap();
+                journal.changes_since(cursor)
+            }
+            None => Ok(ChangeSet::default()),
+        }
+    }
+
+    /// Watch changes on path, events are sent after transaction is
+    /// committed
+    pub fn watch(
+        &self,
+        path: &Path,
+        recursive: bool,
+    ) -> Result<Receiver<Event>> {
+        if !path.has_root() {
+            return Err(Error::InvalidPath);
+        }
+        let mut watch_path = PathBuf::from("/");
+        watch_path.extend(Self::normalize(path)?);
+        Ok(self.watchers.watch(&watch_path, recursive))
+    }
+
     /// Destroy the whole file system
     #[inline]
     pub fn destroy(uri: &str) -> Result<()> {
@@ -658,3 +1835,169 @@ impl Drop for Fs {
         info!("repo closed");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::base::init_env;
+    use crate::repo::open_file_with_options;
+    use crate::trans::Finish;
+    use crate::volume::Writer;
+    use crate::{EntityKind, OpenOptions, Problem};
+
+    #[test]
+    fn check_repair() {
+        init_env();
+
+        let uri = "mem://fs_check_repair";
+        let mut fs = Fs::create(uri, "pwd", &Config::default()).unwrap();
+        let mut opts = OpenOptions::new();
+        opts.create(true).write(true);
+        for path in ["/file", "/file2"] {
+            let mut file =
+                open_file_with_options(&mut fs, path, &opts).unwrap();
+            file.write_once(path.as_bytes()).unwrap();
+        }
+        assert!(fs.check(CheckMode::Check).unwrap().is_ok());
+
+        {
+            let fnode = fs.resolve(Path::new("/file")).unwrap();
+            let root = fs.root.clone();
+            let store = fs.store.clone();
+            let txmgr = fs.txmgr.clone();
+            fs.transaction(|_| {
+                // link a copy of content which is not used by any version
+                let fnode = fnode.read().unwrap();
+                let content = fnode.clone_current_content(&store)?;
+                Store::dedup_content(&store, &content)?;
+                content.link(&store, &txmgr)?;
+
+                // detach a file from its parent without releasing it
+                Fnode::remove_child(&root, "file2", &store, &txmgr)
+            })
+            .unwrap();
+        }
+
+        // save data which is not used by any segment
+        let mut wtr =
+            Writer::new(&Eid::new(), &Arc::downgrade(&fs.vol)).unwrap();
+        wtr.write_all(b"foo").unwrap();
+        wtr.finish().unwrap();
+
+        let report = fs.check(CheckMode::Check).unwrap();
+        let orphans = |kind: EntityKind| {
+            report
+                .problems()
+                .iter()
+                .filter(|p| match p {
+                    Problem::Orphan { kind: k, .. } => *k == kind,
+                    _ => false,
+                })
+                .count()
+        };
+        assert_eq!(orphans(EntityKind::Fnode), 1);
+        assert_eq!(orphans(EntityKind::Content), 2);
+        assert_eq!(orphans(EntityKind::Segment), 1);
+        assert_eq!(orphans(EntityKind::SegData), 1);
+        assert!(report.problems().iter().any(|p| matches!(
+            p,
+            Problem::ChunkRefCnt {
+                found: 2,
+                expected: 1,
+                ..
+            }
+        )));
+        assert!(report.problems().iter().all(Problem::is_repairable));
+
+        let report = fs.check(CheckMode::Repair).unwrap();
+        assert!(report.is_repaired());
+        assert!(fs.check(CheckMode::Check).unwrap().is_ok());
+
+        // repaired repo should survive re-open
+        drop(fs);
+        let mut fs = Fs::open(uri, "pwd", false, false).unwrap();
+        assert!(fs.check(CheckMode::Check).unwrap().is_ok());
+        assert!(fs.resolve(Path::new("/file2")).is_err());
+    }
+
+    #[test]
+    fn open_outdated() {
+        init_env();
+
+        // clear entity ids in payload, as the repo is created by earlier
+        // version without trash and journal entities
+        let uri = "mem://fs_open_outdated";
+        let mut cfg = Config::default();
+        cfg.opts.journal = true;
+        let fs = Fs::create(uri, "pwd", &cfg).unwrap();
+        drop(fs);
+        {
+            let mut vol = Volume::new(uri).unwrap();
+            let mut payload =
+                Payload::deseri(&vol.open("pwd", false).unwrap()).unwrap();
+            payload.trash_id = Eid::new_empty();
+            payload.journal_id = Eid::new_empty();
+            vol.update_payload("pwd", &payload.seri().unwrap()).unwrap();
+        }
+
+        // entities are created on open and their ids are saved
+        let mut fs = Fs::open(uri, "pwd", false, false).unwrap();
+        let trash_id = fs.trash.read().unwrap().id().clone();
+        let journal_id =
+            fs.journal.as_ref().unwrap().read().unwrap().id().clone();
+        let mut opts = OpenOptions::new();
+        opts.create(true).write(true);
+        open_file_with_options(&mut fs, "/file", &opts).unwrap();
+        assert_eq!(fs.changes_since(0).unwrap().changes().len(), 1);
+
+        // the entities created with repo are orphans now
+        assert!(fs.check(CheckMode::Repair).unwrap().is_repaired());
+        assert!(fs.check(CheckMode::Check).unwrap().is_ok());
+        drop(fs);
+        let fs = Fs::open(uri, "pwd", false, false).unwrap();
+        assert_eq!(fs.trash.read().unwrap().id(), &trash_id);
+        assert_eq!(
+            fs.journal.as_ref().unwrap().read().unwrap().id(),
+            &journal_id
+        );
+        assert_eq!(fs.changes_since(0).unwrap().changes().len(), 1);
+    }
+
+    #[test]
+    fn open_outdated_read_only() {
+        init_env();
+
+        let uri = "mem://fs_open_outdated_read_only";
+        let mut cfg = Config::default();
+        cfg.opts.journal = true;
+        let fs = Fs::create(uri, "pwd", &cfg).unwrap();
+        drop(fs);
+        let mut vol = Volume::new(uri).unwrap();
+        let mut payload =
+            Payload::deseri(&vol.open("pwd", false).unwrap()).unwrap();
+        payload.trash_id = Eid::new_empty();
+        payload.journal_id = Eid::new_empty();
+        vol.update_payload("pwd", &payload.seri().unwrap()).unwrap();
+        let mut ids = vol.list_ids().unwrap();
+        ids.sort();
+        drop(vol);
+
+        // read-only open must not upgrade the repo
+        let mut fs = Fs::open(uri, "pwd", true, false).unwrap();
+        assert!(fs.trash_entries().unwrap().is_empty());
+        assert!(fs.changes_since(0).unwrap().changes().is_empty());
+        assert!(fs.read_dir(Path::new("/")).unwrap().is_empty());
+        fs.check(CheckMode::Check).unwrap();
+        drop(fs);
+
+        let mut vol = Volume::new(uri).unwrap();
+        let payload =
+            Payload::deseri(&vol.open("pwd", false).unwrap()).unwrap();
+        assert!(payload.is_outdated());
+        let mut ids2 = vol.list_ids().unwrap();
+        ids2.sort();
+        assert_eq!(ids2, ids);
+    }
+}
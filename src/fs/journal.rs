@@ -0,0 +1,333 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::fnode::{FileType, Fnode, FnodeRef};
+use super::watch::{Event, WatchersRef};
+use super::Handle;
+use crate::base::Time;
+use crate::error::{Error, Result};
+use crate::trans::cow::{Cow, CowRef, CowWeakRef, Cowable, IntoCow};
+use crate::trans::{Eid, TxMgrRef, Txid};
+use crate::volume::VolumeRef;
+
+/// The kind of a change recorded in the change journal.
+///
+/// See [`Repo::changes_since`] for details.
+///
+/// [`Repo::changes_since`]: struct.Repo.html#method.changes_since
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ChangeKind {
+    /// A file, directory or symbolic link was created.
+    Create,
+
+    /// A new version of file content was created by writing, copying or
+    /// restoring.
+    Write,
+
+    /// A file or directory was renamed, the new path is in
+    /// [`Change::new_path`]. An existing entry at the new path is replaced,
+    /// its removal is recorded as a [`Remove`] change before this one.
+    ///
+    /// [`Change::new_path`]: struct.Change.html#method.new_path
+    /// [`Remove`]: enum.ChangeKind.html#variant.Remove
+    Rename,
+
+    /// A file, directory or symbolic link was removed. When a directory is
+    /// moved to trash as a whole, only the directory itself is recorded.
+    Remove,
+
+    /// A file was truncated or extended by [`File::set_len`].
+    ///
+    /// [`File::set_len`]: struct.File.html#method.set_len
+    SetLen,
+}
+
+/// A committed change recorded in the change journal.
+///
+/// See [`Repo::changes_since`] for details.
+///
+/// [`Repo::changes_since`]: struct.Repo.html#method.changes_since
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Change {
+    seq: u64,
+    txid: Txid,
+    kind: ChangeKind,
+    ftype: FileType,
+    path: PathBuf,
+    new_path: Option<PathBuf>,
+    version: Option<usize>,
+    time: Time,
+}
+
+impl Change {
+    /// Returns the sequence number of this change, it is unique and
+    /// increasing in the repository.
+    #[inline]
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Returns the id of the transaction which committed this change.
+    ///
+    /// Changes made in one transaction, such as the ones in
+    /// [`Repo::transaction`], share the same transaction id.
+    ///
+    /// [`Repo::transaction`]: struct.Repo.html#method.transaction
+    #[inline]
+    pub fn txid(&self) -> u64 {
+        self.txid.val()
+    }
+
+    /// Returns the kind of this change.
+    #[inline]
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// Returns the type of the changed file, directory or symbolic link.
+    #[inline]
+    pub fn file_type(&self) -> FileType {
+        self.ftype
+    }
+
+    /// Returns the absolute path of the changed entity, for rename this is
+    /// the path before renaming.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Returns the path after renaming, or `None` if this change is not a
+    /// rename.
+    #[inline]
+    pub fn new_path(&self) -> Option<&Path> {
+        self.new_path.as_deref()
+    }
+
+    /// Returns the current version number of file content after this
+    /// change, or `None` if it is not a file.
+    #[inline]
+    pub fn version(&self) -> Option<usize> {
+        self.version
+    }
+
+    /// Returns the time when this change was made.
+    #[inline]
+    pub fn changed_at(&self) -> SystemTime {
+        self.time.to_system_time()
+    }
+}
+
+/// A list of changes returned by [`Repo::changes_since`].
+///
+/// [`Repo::changes_since`]: struct.Repo.html#method.changes_since
+#[derive(Debug, Clone, Default)]
+pub struct ChangeSet {
+    changes: Vec<Change>,
+    cursor: u64,
+    truncated: bool,
+}
+
+impl ChangeSet {
+    /// Returns the changes in the order they were committed.
+    #[inline]
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Returns the new cursor, which can be used in the next call of
+    /// [`Repo::changes_since`].
+    ///
+    /// [`Repo::changes_since`]: struct.Repo.html#method.changes_since
+    #[inline]
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+
+    /// Returns whether some changes after the given cursor were already
+    /// dropped from the journal because of [`journal_limit`].
+    ///
+    /// When this is true, the changes are incomplete and a full scan is
+    /// needed.
+    ///
+    /// [`journal_limit`]: struct.RepoOpener.html#method.journal_limit
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl IntoIterator for ChangeSet {
+    type Item = Change;
+    type IntoIter = ::std::vec::IntoIter<Change>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+
+/// Change journal
+///
+/// Journal is a separate entity owned by file system, it is updated in the
+/// same transaction as the change and only the latest changes are kept.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Journal {
+    limit: usize,
+    seq: u64,
+    changes: VecDeque<Change>,
+}
+
+impl Journal {
+    pub fn new(limit: usize) -> Self {
+        Journal {
+            limit: limit.max(1),
+            seq: 0,
+            changes: VecDeque::new(),
+        }
+    }
+
+    #[inline]
+    pub fn open(journal_id: &Eid, vol: &VolumeRef) -> Result<JournalRef> {
+        Cow::<Journal>::load(journal_id, vol)
+    }
+
+    // append a change, the oldest changes are dropped if limit is exceeded
+    fn append(
+        &mut self,
+        txid: Txid,
+        kind: ChangeKind,
+        ftype: FileType,
+        path: &Path,
+        new_path: Option<&Path>,
+        version: Option<usize>,
+    ) {
+        self.seq += 1;
+        self.changes.push_back(Change {
+            seq: self.seq,
+            txid,
+            kind,
+            ftype,
+            path: path.to_path_buf(),
+            new_path: new_path.map(Path::to_path_buf),
+            version,
+            time: Time::now(),
+        });
+        while self.changes.len() > self.limit {
+            self.changes.pop_front();
+        }
+    }
+
+    /// Get changes after the cursor
+    pub fn changes_since(&self, cursor: u64) -> Result<ChangeSet> {
+        if cursor > self.seq {
+            return Err(Error::InvalidArgument);
+        }
+
+        // the first change kept in journal
+        let first = self.changes.front().map_or(self.seq + 1, |c| c.seq);
+        Ok(ChangeSet {
+            changes: self
+                .changes
+                .iter()
+                .filter(|c| c.seq > cursor)
+                .cloned()
+                .collect(),
+            cursor: self.seq,
+            truncated: cursor + 1 < first,
+        })
+    }
+}
+
+impl Cowable for Journal {}
+impl<'de> IntoCow<'de> for Journal {}
+
+/// Journal reference type
+pub type JournalRef = CowRef<Journal>;
+pub type JournalWeakRef = CowWeakRef<Journal>;
+
+/// Check if changes need to be recorded, that is journal is enabled or
+/// there are watchers
+#[inline]
+pub fn is_recording(
+    journal: Option<&JournalRef>,
+    watchers: &WatchersRef,
+) -> bool {
+    journal.is_some() || !watchers.is_empty()
+}
+
+/// Record a change of fnode to journal and watchers, it must be called in
+/// transaction
+///
+/// Nothing is recorded if journal is not enabled and there are no watchers.
+pub fn record(
+    kind: ChangeKind,
+    fnode_ref: &FnodeRef,
+    path: &Path,
+    new_path: Option<&Path>,
+    journal: Option<&JournalRef>,
+    txmgr: &TxMgrRef,
+    watchers: &WatchersRef,
+) -> Result<()> {
+    let md = {
+        let fnode = fnode_ref.read().unwrap();
+        fnode.metadata()
+    };
+    let version = if md.is_file() {
+        Some(md.curr_version())
+    } else {
+        None
+    };
+
+    let ftype = md.file_type();
+    let txid = Txid::current()?;
+
+    // events are sent to watchers after transaction is committed
+    if !watchers.is_empty() {
+        watchers.push(Event::new(txid, kind, ftype, path, new_path, version));
+    }
+
+    if let Some(journal) = journal {
+        let mut journal_cow = journal.write().unwrap();
+        journal_cow
+            .make_mut(txmgr)?
+            .append(txid, kind, ftype, path, new_path, version);
+    }
+    Ok(())
+}
+
+impl Handle {
+    /// Record a change of the opened file to journal and watchers, it must
+    /// be called in transaction
+    ///
+    /// Nothing is recorded if the file is no longer in the tree.
+    pub fn record_change(&self, kind: ChangeKind) -> Result<()> {
+        let journal = match self.journal {
+            Some(ref journal) => {
+                Some(journal.upgrade().ok_or(Error::RepoClosed)?)
+            }
+            None => None,
+        };
+        if !is_recording(journal.as_ref(), &self.watchers) {
+            return Ok(());
+        }
+        let txmgr = self.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        let path = self.link().and_then(|(parent, name)| {
+            Fnode::path(&parent, &self.root_id).map(|path| path.join(name))
+        });
+        match path {
+            Some(path) => record(
+                kind,
+                &self.fnode,
+                &path,
+                None,
+                journal.as_ref(),
+                &txmgr,
+                &self.watchers,
+            ),
+            None => Ok(()),
+        }
+    }
+}
@@ -2,29 +2,66 @@
 //! fs module document
 //!
 
+mod check;
 pub mod fnode;
 mod fs;
+mod journal;
+mod quota;
+mod retention;
+mod trash;
+mod walk;
+mod watch;
 
 use serde::{Deserialize, Serialize};
 
+pub use self::check::{CheckMode, CheckReport, EntityKind, Problem};
 pub use self::fnode::{DirEntry, FileType, Fnode, FnodeRef, Metadata, Version};
 pub use self::fs::{Fs, ShutterRef};
+pub use self::journal::{Change, ChangeKind, ChangeSet};
+pub use self::quota::{Quota, Usage};
+pub use self::retention::RetentionPolicy;
+pub use self::trash::TrashEntry;
+pub use self::walk::{WalkDir, WalkDirIter};
+pub use self::watch::{Event, Watchers, WatchersRef};
 
+use self::journal::JournalWeakRef;
 use crate::base::crypto::{Cipher, Cost, Crypto};
 use crate::content::StoreWeakRef;
-use crate::trans::TxMgrWeakRef;
+use crate::trans::{Eid, Id, TxMgrWeakRef};
 use crate::ChunkingAlgorithm;
 
 // Default file versoin limit
-const DEFAULT_VERSION_LIMIT: u8 = 1;
+const DEFAULT_VERSION_LIMIT: u32 = 1;
+
+// Default maximum number of changes kept in change journal
+const DEFAULT_JOURNAL_LIMIT: usize = 1024;
 
 // Options
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct Options {
-    pub version_limit: u8,
+    pub version_limit: u32,
     pub dedup_chunk: bool,
     pub dedup_file: bool,
     pub chunking_algorithm: ChunkingAlgorithm,
+
+    // retention policy replaces version limit if it is set
+    #[serde(default)]
+    pub retention: Option<RetentionPolicy>,
+
+    // removed files and directories are kept in trash if it is enabled,
+    // max age is in seconds and max size is in bytes
+    #[serde(default)]
+    pub trash: bool,
+    #[serde(default)]
+    pub trash_max_age: Option<u64>,
+    #[serde(default)]
+    pub trash_max_size: Option<u64>,
+
+    // change journal keeps the latest changes if it is enabled
+    #[serde(default)]
+    pub journal: bool,
+    #[serde(default)]
+    pub journal_limit: Option<usize>,
 }
 
 impl Default for Options {
@@ -34,6 +71,12 @@ impl Default for Options {
             dedup_chunk: false,
             dedup_file: false,
             chunking_algorithm: ChunkingAlgorithm::default(),
+            retention: None,
+            trash: false,
+            trash_max_age: None,
+            trash_max_size: None,
+            journal: false,
+            journal_limit: None,
         }
     }
 }
@@ -66,7 +109,40 @@ impl Default for Config {
 #[derive(Debug, Clone)]
 pub struct Handle {
     pub fnode: FnodeRef,
+    pub link: Option<(FnodeRef, String)>, // parent and name it's opened by
+    pub root_id: Eid,
     pub store: StoreWeakRef,
+    pub journal: Option<JournalWeakRef>,
     pub txmgr: TxMgrWeakRef,
     pub shutter: ShutterRef,
+    pub watchers: WatchersRef,
+}
+
+impl Handle {
+    /// Get parent directory and name of the entry linking to the fnode
+    ///
+    /// It is the link which the fnode is opened by if it still exists,
+    /// otherwise the fnode was renamed or the link was removed after it is
+    /// opened, then its in-memory parent is used.
+    pub fn link(&self) -> Option<(FnodeRef, String)> {
+        let id = {
+            let fnode = self.fnode.read().unwrap();
+            fnode.id().clone()
+        };
+        if let Some((ref parent, ref name)) = self.link {
+            let par = parent.read().unwrap();
+            if par.is_linked(name, &id) {
+                return Some((parent.clone(), name.clone()));
+            }
+        }
+        let parent = {
+            let fnode = self.fnode.read().unwrap();
+            fnode.parent()?
+        };
+        let name = {
+            let par = parent.read().unwrap();
+            par.child_name(&id)?
+        };
+        Some((parent, name))
+    }
 }
@@ -0,0 +1,401 @@
+use std::collections::HashSet;
+use std::ops::{Add, AddAssign};
+
+use serde::{Deserialize, Serialize};
+
+use super::fnode::{Cache as FnodeCache, Fnode, FnodeRef};
+use super::Handle;
+use crate::error::{Error, Result};
+use crate::trans::{Id, TxMgrRef};
+use crate::volume::VolumeRef;
+
+/// Storage quota of a repository or a directory.
+///
+/// A quota limits the total storage usage of all files under a directory,
+/// including the files in its sub-directories. Each limit is optional, an
+/// operation which makes the usage exceed any limit will fail with
+/// [`Error::QuotaExceeded`] and its transaction is rolled back.
+///
+/// The repository quota is set by [`RepoOpener::quota`], it is the quota of
+/// the root directory. Quota of other directories is set by
+/// [`Repo::set_quota`].
+///
+/// A file with hard links is counted once in a directory tree. Changes made
+/// to it are charged to the quotas on the path it is opened through.
+///
+/// # Examples
+///
+/// ```
+/// # use zbox::{init_env, Result, RepoOpener, Quota};
+/// # fn foo() -> Result<()> {
+/// # init_env();
+/// let quota = Quota::new().max_logical_bytes(1024 * 1024).max_files(100);
+/// let mut repo = RepoOpener::new()
+///     .create(true)
+///     .quota(quota)
+///     .open("mem://foo", "pwd")?;
+/// assert_eq!(repo.quota("/")?, Some(quota));
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+///
+/// [`Error::QuotaExceeded`]: enum.Error.html#variant.QuotaExceeded
+/// [`RepoOpener::quota`]: struct.RepoOpener.html#method.quota
+/// [`Repo::set_quota`]: struct.Repo.html#method.set_quota
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize,
+)]
+pub struct Quota {
+    logical_bytes: Option<u64>,
+    allocated_bytes: Option<u64>,
+    files: Option<u64>,
+}
+
+impl Quota {
+    /// Creates an empty quota, which has no limits.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the total length of current version of all files.
+    #[inline]
+    pub fn max_logical_bytes(mut self, bytes: u64) -> Self {
+        self.logical_bytes = Some(bytes);
+        self
+    }
+
+    /// Limits the total number of bytes allocated for all versions of all
+    /// files.
+    ///
+    /// See [`Usage::allocated_bytes`] for details.
+    ///
+    /// [`Usage::allocated_bytes`]: struct.Usage.html#method.allocated_bytes
+    #[inline]
+    pub fn max_allocated_bytes(mut self, bytes: u64) -> Self {
+        self.allocated_bytes = Some(bytes);
+        self
+    }
+
+    /// Limits the total number of files.
+    #[inline]
+    pub fn max_files(mut self, files: u64) -> Self {
+        self.files = Some(files);
+        self
+    }
+
+    /// Returns the limit of logical bytes, if it is set.
+    #[inline]
+    pub fn logical_bytes_limit(&self) -> Option<u64> {
+        self.logical_bytes
+    }
+
+    /// Returns the limit of allocated bytes, if it is set.
+    #[inline]
+    pub fn allocated_bytes_limit(&self) -> Option<u64> {
+        self.allocated_bytes
+    }
+
+    /// Returns the limit of file count, if it is set.
+    #[inline]
+    pub fn files_limit(&self) -> Option<u64> {
+        self.files
+    }
+
+    // check if usage exceeds the quota, only the increased parts are checked
+    // so that usage can always be reduced
+    fn check(&self, usage: &Usage, old: &Usage, new: &Usage) -> Result<()> {
+        let exceeds = |limit: Option<u64>, used: u64, old: u64, new: u64| {
+            new > old && limit.is_some_and(|limit| used > limit)
+        };
+        if exceeds(
+            self.logical_bytes,
+            usage.logical_bytes,
+            old.logical_bytes,
+            new.logical_bytes,
+        ) || exceeds(
+            self.allocated_bytes,
+            usage.allocated_bytes,
+            old.allocated_bytes,
+            new.allocated_bytes,
+        ) || exceeds(self.files, usage.files, old.files, new.files)
+        {
+            return Err(Error::QuotaExceeded);
+        }
+        Ok(())
+    }
+}
+
+/// Storage usage of a repository or a directory.
+///
+/// This structure is returned from [`Repo::usage`].
+///
+/// [`Repo::usage`]: struct.Repo.html#method.usage
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize,
+)]
+pub struct Usage {
+    logical_bytes: u64,
+    allocated_bytes: u64,
+    files: u64,
+}
+
+impl Usage {
+    // usage of a single file
+    pub(super) fn file(logical_bytes: u64, allocated_bytes: u64) -> Self {
+        Usage {
+            logical_bytes,
+            allocated_bytes,
+            files: 1,
+        }
+    }
+
+    /// Returns the total length of current version of all files.
+    #[inline]
+    pub fn logical_bytes(&self) -> u64 {
+        self.logical_bytes
+    }
+
+    /// Returns the total number of bytes allocated for all versions of all
+    /// files.
+    ///
+    /// Holes in sparse files are not allocated, and file versions sharing
+    /// the same content, such as a restored version, are only counted once.
+    ///
+    /// This is counted per file. Chunks deduplicated across versions with
+    /// different content or across files are counted in each of them, so it
+    /// can be larger than the bytes physically stored in the repository.
+    #[inline]
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Returns the total number of files.
+    #[inline]
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    // replace the old part of usage with the new one
+    fn replace(&self, old: &Usage, new: &Usage) -> Self {
+        Usage {
+            logical_bytes: self.logical_bytes.saturating_sub(old.logical_bytes)
+                + new.logical_bytes,
+            allocated_bytes: self
+                .allocated_bytes
+                .saturating_sub(old.allocated_bytes)
+                + new.allocated_bytes,
+            files: self.files.saturating_sub(old.files) + new.files,
+        }
+    }
+}
+
+impl Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            logical_bytes: self.logical_bytes + other.logical_bytes,
+            allocated_bytes: self.allocated_bytes + other.allocated_bytes,
+            files: self.files + other.files,
+        }
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        *self = *self + other;
+    }
+}
+
+// Quota ancestors are found by walking up the in-memory parents of
+// directories, which are exact as directories cannot be hard linked. A file
+// is charged through the directory of the link it is changed by.
+
+/// Check if a directory or any of its ancestors has quota
+pub fn is_limited(dir: &FnodeRef) -> bool {
+    let mut curr = Some(dir.clone());
+    while let Some(fnode_ref) = curr {
+        let fnode = fnode_ref.read().unwrap();
+        if fnode.quota().is_some() {
+            return true;
+        }
+        curr = fnode.parent();
+    }
+    false
+}
+
+/// Get storage usage of a whole fnode tree, and whether the tree has any
+/// file with hard links
+///
+/// A file with hard links in the tree is only counted once.
+pub fn tree_usage_links(
+    fnode_ref: &FnodeRef,
+    fcache: &FnodeCache,
+    vol: &VolumeRef,
+) -> Result<(Usage, bool)> {
+    let mut usage = Usage::default();
+    let mut linked = HashSet::new();
+    let mut stack = vec![fnode_ref.clone()];
+    while let Some(fnode_ref) = stack.pop() {
+        let names = {
+            let fnode = fnode_ref.read().unwrap();
+            if fnode.links() > 1 && !linked.insert(fnode.id().clone()) {
+                continue;
+            }
+            usage += fnode.own_usage();
+            fnode.children_names()
+        };
+        for name in names {
+            stack.push(Fnode::child(&fnode_ref, &name, fcache, vol)?);
+        }
+    }
+    Ok((usage, !linked.is_empty()))
+}
+
+/// Get storage usage of a whole fnode tree
+#[inline]
+pub fn tree_usage(
+    fnode_ref: &FnodeRef,
+    fcache: &FnodeCache,
+    vol: &VolumeRef,
+) -> Result<Usage> {
+    tree_usage_links(fnode_ref, fcache, vol).map(|(usage, _)| usage)
+}
+
+/// Charge usage change to quotas of a directory and its ancestors, it must
+/// be called in transaction
+///
+/// The `old` usage is replaced with the `new` usage in each quota, and
+/// `QuotaExceeded` error is returned if any quota is exceeded.
+pub fn charge(
+    dir: &FnodeRef,
+    old: &Usage,
+    new: &Usage,
+    txmgr: &TxMgrRef,
+) -> Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let mut curr = Some(dir.clone());
+    while let Some(fnode_ref) = curr {
+        let mut fnode_cow = fnode_ref.write().unwrap();
+        if let Some(quota) = fnode_cow.quota() {
+            let usage = fnode_cow.dir_usage().unwrap().replace(old, new);
+            quota.check(&usage, old, new)?;
+            fnode_cow.make_mut(txmgr)?.set_usage(usage);
+        }
+        curr = fnode_cow.parent();
+    }
+    Ok(())
+}
+
+/// Recalculate usage of quotas of a directory and its ancestors from their
+/// whole trees, it must be called in transaction after the trees are
+/// changed
+///
+/// This is used when a tree with hard linked files is added or removed,
+/// as other links of the files might still be in the quota trees.
+pub fn recharge(
+    dir: &FnodeRef,
+    fcache: &FnodeCache,
+    vol: &VolumeRef,
+    txmgr: &TxMgrRef,
+) -> Result<()> {
+    let mut curr = Some(dir.clone());
+    while let Some(fnode_ref) = curr {
+        let (quota, old, parent) = {
+            let fnode = fnode_ref.read().unwrap();
+            (fnode.quota(), fnode.dir_usage(), fnode.parent())
+        };
+        if let (Some(quota), Some(old)) = (quota, old) {
+            let new = tree_usage(&fnode_ref, fcache, vol)?;
+            quota.check(&new, &old, &new)?;
+            let mut fnode_cow = fnode_ref.write().unwrap();
+            fnode_cow.make_mut(txmgr)?.set_usage(new);
+        }
+        curr = parent;
+    }
+    Ok(())
+}
+
+/// Get usage of a file in a directory if it is under any quota
+pub fn file_usage(dir: &FnodeRef, fnode_ref: &FnodeRef) -> Option<Usage> {
+    if is_limited(dir) {
+        let fnode = fnode_ref.read().unwrap();
+        Some(fnode.own_usage())
+    } else {
+        None
+    }
+}
+
+/// Charge usage change of a file in a directory to quotas, it must be
+/// called in transaction
+///
+/// The `old` usage is got by `file_usage` before the file is changed,
+/// nothing is charged if it is `None`.
+pub fn charge_file(
+    dir: &FnodeRef,
+    fnode_ref: &FnodeRef,
+    old: Option<Usage>,
+    txmgr: &TxMgrRef,
+) -> Result<()> {
+    let old = match old {
+        Some(old) => old,
+        None => return Ok(()),
+    };
+    let new = {
+        let fnode = fnode_ref.read().unwrap();
+        fnode.own_usage()
+    };
+    charge(dir, &old, &new, txmgr)
+}
+
+impl Handle {
+    /// Get usage of the opened file if it is under any quota
+    #[inline]
+    pub fn quota_usage(&self) -> Option<Usage> {
+        let (dir, _) = self.link()?;
+        file_usage(&dir, &self.fnode)
+    }
+
+    /// Charge usage change of the opened file to quotas, it must be called
+    /// in transaction
+    pub fn charge_quota(&self, old: Option<Usage>) -> Result<()> {
+        let txmgr = self.txmgr.upgrade().ok_or(Error::RepoClosed)?;
+        match self.link() {
+            Some((dir, _)) => charge_file(&dir, &self.fnode, old, &txmgr),
+            None => Ok(()),
+        }
+    }
+
+    /// Check if the opened file can grow to `len` bytes without exceeding
+    /// logical bytes quota
+    ///
+    /// This is used to stop writing early, allocated bytes are checked when
+    /// the writing is finished.
+    pub fn check_quota(&self, len: usize) -> Result<()> {
+        let old = {
+            let fnode = self.fnode.read().unwrap();
+            fnode.own_usage()
+        };
+        if len as u64 <= old.logical_bytes {
+            return Ok(());
+        }
+        let new = Usage {
+            logical_bytes: len as u64,
+            ..old
+        };
+        let mut curr = self.link().map(|(dir, _)| dir);
+        while let Some(fnode_ref) = curr {
+            let fnode = fnode_ref.read().unwrap();
+            if let Some(quota) = fnode.quota() {
+                let usage = fnode.dir_usage().unwrap().replace(&old, &new);
+                quota.check(&usage, &old, &new)?;
+            }
+            curr = fnode.parent();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+// seconds in one hour and one day
+const SECS_PER_HOUR: u64 = 60 * 60;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+
+// convert days since unix epoch to (year, month), month is in [1, 12]
+//
+// algorithm from http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: u64) -> (u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month)
+}
+
+// time bucket index of a time, in seconds since unix epoch
+#[derive(Debug, Clone, Copy)]
+enum Bucket {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Bucket {
+    fn index(self, secs: u64) -> u64 {
+        let days = secs / SECS_PER_DAY;
+        match self {
+            Bucket::Day => days,
+
+            // unix epoch is Thursday, weeks start from Monday
+            Bucket::Week => (days + 3) / 7,
+
+            Bucket::Month => {
+                let (year, month) = civil_from_days(days);
+                year * 12 + month - 1
+            }
+            Bucket::Year => civil_from_days(days).0,
+        }
+    }
+}
+
+/// A policy to decide which file versions are kept.
+///
+/// The retention policy can be set on [`RepoOpener`] as the default policy
+/// for all files, or on [`OpenOptions`] for a single file. When it is set,
+/// it replaces the fixed [`version_limit`].
+///
+/// A version is kept if it matches any of the time rules below. If no time
+/// rule is set, all versions are matched.
+///
+/// - `keep_within`: all versions created within the duration.
+/// - `daily`, `weekly`, `monthly` and `yearly`: the latest version in each
+///   of the last n days, weeks, months or years. The time periods are in
+///   UTC and weeks start from Monday.
+///
+/// The matched versions are then kept from the newest to the oldest, until
+/// the total content length of kept versions would exceed
+/// `max_history_size`.
+///
+/// The current version and [tagged] versions are always kept, and tagged
+/// versions are not counted in `max_history_size`.
+///
+/// The policy is applied when a new version is added to a file. As versions
+/// also expire over time, use [`Repo::apply_retention`] to apply the policy
+/// to all files.
+///
+/// # Examples
+///
+/// Keep all versions from the last 24 hours, daily versions for 30 days and
+/// monthly versions for a year.
+///
+/// ```
+/// # use std::time::Duration;
+/// # use zbox::{init_env, Result, RepoOpener, RetentionPolicy};
+/// # fn foo() -> Result<()> {
+/// # init_env();
+/// let policy = RetentionPolicy::new()
+///     .keep_within(Duration::from_secs(24 * 60 * 60))
+///     .daily(30)
+///     .monthly(12);
+/// let mut repo = RepoOpener::new()
+///     .create(true)
+///     .retention_policy(policy)
+///     .open("mem://foo", "pwd")?;
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+///
+/// [`RepoOpener`]: struct.RepoOpener.html
+/// [`OpenOptions`]: struct.OpenOptions.html
+/// [`version_limit`]: struct.OpenOptions.html#method.version_limit
+/// [tagged]: struct.File.html#method.tag_version
+/// [`Repo::apply_retention`]: struct.Repo.html#method.apply_retention
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize,
+)]
+pub struct RetentionPolicy {
+    within: u64, // in seconds
+    daily: u32,
+    weekly: u32,
+    monthly: u32,
+    yearly: u32,
+    max_size: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// Creates an empty policy, which keeps all versions.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps all versions created within the duration.
+    #[inline]
+    pub fn keep_within(mut self, duration: Duration) -> Self {
+        self.within = duration.as_secs();
+        self
+    }
+
+    /// Keeps the latest version in each of the last `days` days.
+    #[inline]
+    pub fn daily(mut self, days: u32) -> Self {
+        self.daily = days;
+        self
+    }
+
+    /// Keeps the latest version in each of the last `weeks` weeks.
+    #[inline]
+    pub fn weekly(mut self, weeks: u32) -> Self {
+        self.weekly = weeks;
+        self
+    }
+
+    /// Keeps the latest version in each of the last `months` months.
+    #[inline]
+    pub fn monthly(mut self, months: u32) -> Self {
+        self.monthly = months;
+        self
+    }
+
+    /// Keeps the latest version in each of the last `years` years.
+    #[inline]
+    pub fn yearly(mut self, years: u32) -> Self {
+        self.yearly = years;
+        self
+    }
+
+    /// Keeps versions until their total content length exceeds `size`
+    /// bytes.
+    #[inline]
+    pub fn max_history_size(mut self, size: u64) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    // check if any time rule is set
+    fn has_time_rule(&self) -> bool {
+        self.within > 0
+            || self.daily > 0
+            || self.weekly > 0
+            || self.monthly > 0
+            || self.yearly > 0
+    }
+
+    /// Select versions to be kept
+    ///
+    /// Versions are given as (creation time in seconds since unix epoch,
+    /// content length, is tagged) from the oldest to the newest, returns
+    /// whether each version should be kept.
+    pub(crate) fn select(
+        &self,
+        vers: &[(u64, usize, bool)],
+        now: u64,
+    ) -> Vec<bool> {
+        let cnt = vers.len();
+        let mut matched = vec![!self.has_time_rule(); cnt];
+
+        for (idx, &(ctime, _, _)) in vers.iter().enumerate() {
+            if now.saturating_sub(ctime) < self.within {
+                matched[idx] = true;
+            }
+        }
+
+        // keep the newest version in each bucket within the range
+        let rules = [
+            (Bucket::Day, self.daily),
+            (Bucket::Week, self.weekly),
+            (Bucket::Month, self.monthly),
+            (Bucket::Year, self.yearly),
+        ];
+        for &(bucket, range) in rules.iter().filter(|r| r.1 > 0) {
+            let curr = bucket.index(now);
+            let mut seen = HashSet::new();
+            for (idx, &(ctime, _, _)) in vers.iter().enumerate().rev() {
+                let index = bucket.index(ctime);
+                if curr.saturating_sub(index) < u64::from(range)
+                    && seen.insert(index)
+                {
+                    matched[idx] = true;
+                }
+            }
+        }
+
+        // keep matched versions from the newest, until size limit is reached
+        let mut keep = vec![false; cnt];
+        let mut total = 0u64;
+        let mut exceeded = false;
+        for (idx, &(_, len, tagged)) in vers.iter().enumerate().rev() {
+            if tagged {
+                keep[idx] = true;
+                continue;
+            }
+            let is_curr = idx == cnt - 1;
+            if !is_curr && (exceeded || !matched[idx]) {
+                continue;
+            }
+            total += len as u64;
+            if let Some(max_size) = self.max_size {
+                if !is_curr && total > max_size {
+                    exceeded = true;
+                    continue;
+                }
+            }
+            keep[idx] = true;
+        }
+
+        keep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2020-03-15 12:00:00 UTC, Sunday
+    const NOW: u64 = 1_584_273_600;
+
+    fn hours_ago(hours: u64) -> u64 {
+        NOW - hours * SECS_PER_HOUR
+    }
+
+    #[test]
+    fn civil_date() {
+        assert_eq!(civil_from_days(0), (1970, 1));
+        assert_eq!(civil_from_days(NOW / SECS_PER_DAY), (2020, 3));
+        assert_eq!(civil_from_days(18_321), (2020, 2)); // 2020-02-29
+        assert_eq!(
+            Bucket::Week.index(NOW),
+            Bucket::Week.index(hours_ago(6 * 24))
+        );
+        assert!(
+            Bucket::Week.index(NOW) > Bucket::Week.index(hours_ago(7 * 24))
+        );
+    }
+
+    #[test]
+    fn retention_select() {
+        let vers = vec![
+            (hours_ago(24 * 70), 10, false), // january
+            (hours_ago(24 * 40), 10, true),  // tagged
+            (hours_ago(24 * 35), 10, false), // february
+            (hours_ago(24 * 34), 10, false), // february
+            (hours_ago(50), 10, false),      // 2 days ago
+            (hours_ago(49), 10, false),      // 2 days ago
+            (hours_ago(30), 10, false),      // 1 day ago
+            (hours_ago(2), 10, false),
+            (hours_ago(1), 10, false),
+        ];
+
+        // empty policy keeps all
+        let keep = RetentionPolicy::new().select(&vers, NOW);
+        assert!(keep.iter().all(|k| *k));
+
+        let keep = RetentionPolicy::new()
+            .keep_within(Duration::from_secs(24 * SECS_PER_HOUR))
+            .select(&vers, NOW);
+        assert_eq!(
+            keep,
+            vec![false, true, false, false, false, false, false, true, true]
+        );
+
+        let keep = RetentionPolicy::new().daily(3).select(&vers, NOW);
+        assert_eq!(
+            keep,
+            vec![false, true, false, false, false, true, true, false, true]
+        );
+
+        let keep = RetentionPolicy::new().monthly(3).select(&vers, NOW);
+        assert_eq!(
+            keep,
+            vec![true, true, false, true, false, false, false, false, true]
+        );
+
+        // size limit, tagged version is not counted
+        let keep = RetentionPolicy::new()
+            .max_history_size(35)
+            .select(&vers, NOW);
+        assert_eq!(
+            keep,
+            vec![false, true, false, false, false, false, true, true, true]
+        );
+        let keep = RetentionPolicy::new()
+            .monthly(3)
+            .max_history_size(0)
+            .select(&vers, NOW);
+        assert_eq!(
+            keep,
+            vec![false, true, false, false, false, false, false, false, true]
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use super::fnode::Metadata;
+use crate::base::Time;
+use crate::error::Result;
+use crate::trans::cow::{Cow, CowRef, Cowable, IntoCow};
+use crate::trans::Eid;
+use crate::volume::VolumeRef;
+
+/// An entry in the trash.
+///
+/// When trash is enabled by [`RepoOpener::trash`], removed files and
+/// directories are moved to trash along with their history. This entry is
+/// returned by [`Repo::list_trash`], its id can be used to restore it by
+/// [`Repo::undelete`].
+///
+/// [`RepoOpener::trash`]: struct.RepoOpener.html#method.trash
+/// [`Repo::list_trash`]: struct.Repo.html#method.list_trash
+/// [`Repo::undelete`]: struct.Repo.html#method.undelete
+#[derive(Debug, Clone)]
+pub struct TrashEntry {
+    id: u64,
+    path: PathBuf,
+    dtime: SystemTime,
+    size: u64,
+    metadata: Metadata,
+}
+
+impl TrashEntry {
+    pub(super) fn new(
+        id: u64,
+        path: &str,
+        dtime: SystemTime,
+        size: u64,
+        metadata: Metadata,
+    ) -> Self {
+        TrashEntry {
+            id,
+            path: PathBuf::from(path),
+            dtime,
+            size,
+            metadata,
+        }
+    }
+
+    /// Returns the id of this entry, it is unique in the repository.
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the original absolute path of the removed file or directory.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Returns the time when it was removed.
+    #[inline]
+    pub fn deleted_at(&self) -> SystemTime {
+        self.dtime
+    }
+
+    /// Returns the total content length of all file versions in it, in
+    /// bytes.
+    ///
+    /// This size is used by [`RepoOpener::trash_max_size`].
+    ///
+    /// [`RepoOpener::trash_max_size`]: struct.RepoOpener.html#method.trash_max_size
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the metadata of the removed file or directory.
+    #[inline]
+    pub fn metadata(&self) -> Metadata {
+        self.metadata
+    }
+}
+
+/// Trash item
+///
+/// A removed fnode tree is kept in trash, its root fnode is recorded here.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(super) struct TrashItem {
+    root_id: Eid,
+    path: String,
+    dtime: Time,
+    size: u64,
+}
+
+impl TrashItem {
+    #[inline]
+    pub fn root_id(&self) -> &Eid {
+        &self.root_id
+    }
+
+    #[inline]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[inline]
+    pub fn deleted_at(&self) -> Time {
+        self.dtime
+    }
+
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Trash
+///
+/// Removed fnode trees kept in trash, keyed by trash id.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub(super) struct Trash {
+    items: BTreeMap<u64, TrashItem>,
+
+    // the last used trash id, ids are never reused
+    seq: u64,
+}
+
+impl Trash {
+    #[inline]
+    pub fn new() -> Self {
+        Trash::default()
+    }
+
+    #[inline]
+    pub fn open(trash_id: &Eid, vol: &VolumeRef) -> Result<TrashRef> {
+        Cow::<Trash>::load(trash_id, vol)
+    }
+
+    // empty trash for read-only repo which hasn't got trash yet
+    #[inline]
+    pub fn detached() -> TrashRef {
+        Cow::detached(Trash::new())
+    }
+
+    #[inline]
+    pub fn items(&self) -> &BTreeMap<u64, TrashItem> {
+        &self.items
+    }
+
+    #[inline]
+    pub fn get(&self, id: u64) -> Option<&TrashItem> {
+        self.items.get(&id)
+    }
+
+    // add a removed fnode tree to trash and return its trash id
+    pub fn add(&mut self, root_id: &Eid, path: &str, size: u64) -> u64 {
+        self.seq += 1;
+        let id = self.seq;
+        self.items.insert(
+            id,
+            TrashItem {
+                root_id: root_id.clone(),
+                path: path.to_string(),
+                dtime: Time::now(),
+                size,
+            },
+        );
+        id
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: u64) -> Option<TrashItem> {
+        self.items.remove(&id)
+    }
+}
+
+impl Cowable for Trash {}
+impl<'de> IntoCow<'de> for Trash {}
+
+/// Trash reference type
+pub(super) type TrashRef = CowRef<Trash>;
@@ -0,0 +1,484 @@
+use std::fmt::{self, Debug};
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "walk-regex")]
+use regex::Regex;
+
+use super::fnode::{Cache as FnodeCache, DirEntry, Fnode, FnodeRef};
+use crate::content::StoreRef;
+use crate::error::{Error, Result};
+use crate::volume::VolumeRef;
+
+// glob pattern token
+#[derive(Debug, Clone)]
+enum GlobToken {
+    Any,                          // `*`, any sequence of characters
+    One,                          // `?`, any single character
+    Char(char),                   // literal character
+    Set(bool, Vec<(char, char)>), // `[...]` character ranges, negated if true
+}
+
+impl GlobToken {
+    // check if a single character matches this token
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            GlobToken::Any | GlobToken::One => true,
+            GlobToken::Char(ch) => ch == c,
+            GlobToken::Set(negated, ref ranges) => {
+                ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != negated
+            }
+        }
+    }
+}
+
+// parse a glob pattern
+//
+// supported syntax: `*` matches any sequence of characters, `?` matches any
+// single character, `[...]` matches one character in the set and `[!...]`
+// matches one character not in the set, set can contain ranges like `a-z`
+fn parse_glob(pattern: &str) -> Result<Vec<GlobToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => GlobToken::Any,
+            '?' => GlobToken::One,
+            '[' => {
+                let mut negated = false;
+                let mut ranges = Vec::new();
+                let mut first = true;
+                loop {
+                    match chars.next() {
+                        Some(']') if !first => break,
+                        Some('!') if first && !negated => {
+                            negated = true;
+                            continue;
+                        }
+                        Some(lo) => {
+                            let mut hi = lo;
+                            let mut rest = chars.clone();
+                            if rest.next() == Some('-') {
+                                match rest.next() {
+                                    Some(c) if c != ']' => {
+                                        hi = c;
+                                        chars = rest;
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            ranges.push((lo, hi));
+                        }
+                        None => return Err(Error::InvalidArgument),
+                    }
+                    first = false;
+                }
+                GlobToken::Set(negated, ranges)
+            }
+            c => GlobToken::Char(c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+// check if a name matches the whole glob pattern, the last `*` is
+// backtracked on mismatch
+fn glob_match(tokens: &[GlobToken], name: &str) -> bool {
+    let chars: Vec<char> = name.chars().collect();
+    let (mut t, mut c) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while c < chars.len() {
+        match tokens.get(t) {
+            Some(GlobToken::Any) => {
+                star = Some((t, c));
+                t += 1;
+                continue;
+            }
+            Some(token) if token.matches(chars[c]) => {
+                t += 1;
+                c += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match star {
+            Some((star_t, star_c)) => {
+                t = star_t + 1;
+                c = star_c + 1;
+                star = Some((star_t, star_c + 1));
+            }
+            None => return false,
+        }
+    }
+
+    tokens[t..]
+        .iter()
+        .all(|token| matches!(token, GlobToken::Any))
+}
+
+// file name filter
+#[derive(Debug)]
+enum NameFilter {
+    Glob(Vec<GlobToken>),
+    #[cfg(feature = "walk-regex")]
+    Regex(Regex),
+}
+
+impl NameFilter {
+    fn is_match(&self, name: &str) -> bool {
+        match *self {
+            NameFilter::Glob(ref tokens) => glob_match(tokens, name),
+            #[cfg(feature = "walk-regex")]
+            NameFilter::Regex(ref re) => re.is_match(name),
+        }
+    }
+}
+
+// callback to filter entries
+type EntryFilter<'a> = Box<dyn FnMut(&DirEntry) -> bool + 'a>;
+
+// directory being walked through
+struct Frame {
+    fnode: FnodeRef,
+    path: PathBuf,
+    names: Vec<String>,
+    next: usize,
+
+    // directory entry to be returned after its contents
+    entry: Option<DirEntry>,
+}
+
+/// A builder to create an iterator for recursively walking a directory.
+///
+/// This builder is returned by [`Repo::walk_dir`]. The directory is walked
+/// lazily, each sub-directory is only read when the iterator reaches it.
+///
+/// The directory being walked is not included in the iteration, its children
+/// are at depth 1. Symbolic links are returned as entries but never followed.
+///
+/// # Examples
+///
+/// Find all the `.txt` files, but skip the hidden directories.
+///
+/// ```
+/// # use zbox::{init_env, Result, RepoOpener};
+/// # fn foo() -> Result<()> {
+/// # init_env();
+/// # let mut repo = RepoOpener::new()
+/// #     .create(true)
+/// #     .open("mem://foo", "pwd")?;
+/// repo.create_dir_all("/dir/.hidden")?;
+/// repo.create_file("/dir/foo.txt")?;
+/// repo.create_file("/dir/.hidden/bar.txt")?;
+///
+/// let paths: Vec<_> = repo
+///     .walk_dir("/")?
+///     .glob("*.txt")?
+///     .filter_entry(|ent| !ent.file_name().starts_with('.'))
+///     .into_iter()
+///     .map(|ent| ent.map(|ent| ent.path().to_path_buf()))
+///     .collect::<Result<_>>()?;
+/// assert_eq!(paths.len(), 1);
+/// # Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+///
+/// [`Repo::walk_dir`]: struct.Repo.html#method.walk_dir
+pub struct WalkDir<'a> {
+    root: FnodeRef,
+    path: PathBuf,
+    min_depth: usize,
+    max_depth: usize,
+    contents_first: bool,
+    name_filter: Option<NameFilter>,
+    entry_filter: Option<EntryFilter<'a>>,
+    snap: Option<(u64, &'a StoreRef)>,
+    fcache: &'a FnodeCache,
+    vol: &'a VolumeRef,
+}
+
+impl<'a> WalkDir<'a> {
+    pub(super) fn new(
+        root: FnodeRef,
+        path: &Path,
+        snap: Option<(u64, &'a StoreRef)>,
+        fcache: &'a FnodeCache,
+        vol: &'a VolumeRef,
+    ) -> Result<Self> {
+        {
+            let fnode = root.read().unwrap();
+            if !fnode.is_dir() {
+                return Err(Error::NotDir);
+            }
+        }
+
+        Ok(WalkDir {
+            root,
+            path: path.to_path_buf(),
+            min_depth: 1,
+            max_depth: usize::MAX,
+            contents_first: false,
+            name_filter: None,
+            entry_filter: None,
+            snap,
+            fcache,
+            vol,
+        })
+    }
+
+    /// Sets the minimum depth of entries to be returned.
+    ///
+    /// The direct children of the walked directory are at depth 1, entries
+    /// shallower than this depth are not returned but still walked through.
+    /// Default is 1.
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Sets the maximum depth of entries to be returned.
+    ///
+    /// Directories deeper than this depth are not walked into, so
+    /// `max_depth(1)` is same as [`Repo::read_dir`]. Default is no limit.
+    ///
+    /// [`Repo::read_dir`]: struct.Repo.html#method.read_dir
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Sets whether a directory is returned after its contents.
+    ///
+    /// By default, a directory is returned before its contents (pre-order).
+    /// Set this to `true` to return directory after its contents
+    /// (post-order), which is useful for removing a directory tree.
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.contents_first = contents_first;
+        self
+    }
+
+    /// Only returns entries whose file name matches a glob pattern.
+    ///
+    /// The pattern supports `*`, `?`, `[...]` and `[!...]`. Directories
+    /// which don't match are still walked into. It replaces any name filter
+    /// set before.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidArgument`] will be returned if the pattern is invalid.
+    ///
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    pub fn glob(mut self, pattern: &str) -> Result<Self> {
+        let tokens = parse_glob(pattern)?;
+        self.name_filter = Some(NameFilter::Glob(tokens));
+        Ok(self)
+    }
+
+    /// Only returns entries whose file name matches a regular expression.
+    ///
+    /// The expression can match any part of file name, use `^` and `$` to
+    /// match the whole name. Directories which don't match are still walked
+    /// into. It replaces any name filter set before.
+    ///
+    /// This method is only available with the `walk-regex` feature.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidArgument`] will be returned if the expression is
+    /// invalid.
+    ///
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    #[cfg(feature = "walk-regex")]
+    pub fn regex(mut self, re: &str) -> Result<Self> {
+        let re = Regex::new(re).map_err(|_| Error::InvalidArgument)?;
+        self.name_filter = Some(NameFilter::Regex(re));
+        Ok(self)
+    }
+
+    /// Sets a callback to skip entries.
+    ///
+    /// If the callback returns `false` for an entry, the entry is not
+    /// returned. If the entry is a directory, its whole subtree is skipped as
+    /// well.
+    pub fn filter_entry<P>(mut self, predicate: P) -> Self
+    where
+        P: FnMut(&DirEntry) -> bool + 'a,
+    {
+        self.entry_filter = Some(Box::new(predicate));
+        self
+    }
+}
+
+impl<'a> IntoIterator for WalkDir<'a> {
+    type Item = Result<DirEntry>;
+    type IntoIter = WalkDirIter<'a>;
+
+    fn into_iter(self) -> WalkDirIter<'a> {
+        let names = {
+            let fnode = self.root.read().unwrap();
+            fnode.children_names()
+        };
+        let frame = Frame {
+            fnode: self.root.clone(),
+            path: self.path.clone(),
+            names,
+            next: 0,
+            entry: None,
+        };
+        WalkDirIter {
+            opts: self,
+            stack: vec![frame],
+        }
+    }
+}
+
+impl<'a> Debug for WalkDir<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WalkDir")
+            .field("path", &self.path)
+            .field("min_depth", &self.min_depth)
+            .field("max_depth", &self.max_depth)
+            .field("contents_first", &self.contents_first)
+            .field("name_filter", &self.name_filter)
+            .finish()
+    }
+}
+
+/// An iterator for recursively walking a directory.
+///
+/// This iterator is created by [`WalkDir`], it yields an error if a
+/// sub-directory cannot be read, and continues with the next entry.
+///
+/// [`WalkDir`]: struct.WalkDir.html
+pub struct WalkDirIter<'a> {
+    opts: WalkDir<'a>,
+    stack: Vec<Frame>,
+}
+
+impl<'a> WalkDirIter<'a> {
+    // check if an entry at depth should be returned
+    fn is_yield(&self, ent: &DirEntry, depth: usize) -> bool {
+        if depth < self.opts.min_depth {
+            return false;
+        }
+        match self.opts.name_filter {
+            Some(ref filter) => filter.is_match(ent.file_name()),
+            None => true,
+        }
+    }
+}
+
+impl<'a> Iterator for WalkDirIter<'a> {
+    type Item = Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Result<DirEntry>> {
+        loop {
+            let depth = self.stack.len();
+            let frame = self.stack.last_mut()?;
+
+            // all children are walked through, leave this directory
+            if frame.next >= frame.names.len() {
+                let frame = self.stack.pop().unwrap();
+                match frame.entry {
+                    Some(ent) if self.is_yield(&ent, depth - 1) => {
+                        return Some(Ok(ent));
+                    }
+                    _ => continue,
+                }
+            }
+
+            let name = frame.names[frame.next].clone();
+            frame.next += 1;
+
+            let (ent, child) = match Fnode::child_entry(
+                &frame.fnode,
+                &frame.path,
+                &name,
+                self.opts.snap,
+                self.opts.fcache,
+                self.opts.vol,
+            ) {
+                Ok(child) => child,
+                Err(err) => return Some(Err(err)),
+            };
+
+            if let Some(ref mut filter) = self.opts.entry_filter {
+                if !filter(&ent) {
+                    continue;
+                }
+            }
+
+            // walk into sub-directory
+            if ent.metadata().is_dir() && depth < self.opts.max_depth {
+                let names = {
+                    let fnode = child.read().unwrap();
+                    fnode.children_names()
+                };
+                let is_yield = self.is_yield(&ent, depth);
+                let contents_first = self.opts.contents_first;
+                self.stack.push(Frame {
+                    fnode: child,
+                    path: ent.path().to_path_buf(),
+                    names,
+                    next: 0,
+                    entry: if contents_first {
+                        Some(ent.clone())
+                    } else {
+                        None
+                    },
+                });
+                if is_yield && !contents_first {
+                    return Some(Ok(ent));
+                }
+                continue;
+            }
+
+            if self.is_yield(&ent, depth) {
+                return Some(Ok(ent));
+            }
+        }
+    }
+}
+
+impl<'a> Debug for WalkDirIter<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WalkDirIter")
+            .field("opts", &self.opts)
+            .field("depth", &self.stack.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, name: &str) -> bool {
+        glob_match(&parse_glob(pattern).unwrap(), name)
+    }
+
+    #[test]
+    fn glob_pattern_match() {
+        assert!(is_match("*.txt", "foo.txt"));
+        assert!(is_match("*.txt", ".txt"));
+        assert!(!is_match("*.txt", "foo.txt.bak"));
+        assert!(is_match("foo?", "foo1"));
+        assert!(!is_match("foo?", "foo"));
+        assert!(is_match("[abc]*", "bar"));
+        assert!(!is_match("[!abc]*", "bar"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("a.b+c", "a.b+c"));
+        assert!(!is_match("a.b+c", "axbbc"));
+        assert!(is_match("[a-c]?", "b1"));
+        assert!(!is_match("[!a-c]?", "b1"));
+        assert!(is_match("[a-]", "-"));
+        assert!(is_match("*a*b", "xaybzab"));
+        assert!(!is_match("*a*b", "xaybzb1"));
+        assert!(is_match("**", ""));
+        assert!(parse_glob("[abc").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use super::fnode::FileType;
+use super::journal::ChangeKind;
+use crate::trans::{TxHook, Txid};
+
+/// An event of a committed change, received from [`Repo::watch`].
+///
+/// [`Repo::watch`]: struct.Repo.html#method.watch
+#[derive(Debug, Clone)]
+pub struct Event {
+    txid: Txid,
+    kind: ChangeKind,
+    ftype: FileType,
+    path: PathBuf,
+    new_path: Option<PathBuf>,
+    version: Option<usize>,
+}
+
+impl Event {
+    pub(super) fn new(
+        txid: Txid,
+        kind: ChangeKind,
+        ftype: FileType,
+        path: &Path,
+        new_path: Option<&Path>,
+        version: Option<usize>,
+    ) -> Self {
+        Event {
+            txid,
+            kind,
+            ftype,
+            path: path.to_path_buf(),
+            new_path: new_path.map(Path::to_path_buf),
+            version,
+        }
+    }
+
+    /// Returns the id of the transaction which committed this change.
+    ///
+    /// Events of one transaction, such as the ones in
+    /// [`Repo::transaction`], share the same transaction id.
+    ///
+    /// [`Repo::transaction`]: struct.Repo.html#method.transaction
+    #[inline]
+    pub fn txid(&self) -> u64 {
+        self.txid.val()
+    }
+
+    /// Returns the kind of this change.
+    #[inline]
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// Returns the type of the changed file, directory or symbolic link.
+    #[inline]
+    pub fn file_type(&self) -> FileType {
+        self.ftype
+    }
+
+    /// Returns the absolute path of the changed entity, for rename this is
+    /// the path before renaming.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        self.path.as_path()
+    }
+
+    /// Returns the path after renaming, or `None` if this event is not a
+    /// rename.
+    #[inline]
+    pub fn new_path(&self) -> Option<&Path> {
+        self.new_path.as_deref()
+    }
+
+    /// Returns the current version number of file content after this
+    /// change, or `None` if it is not a file.
+    #[inline]
+    pub fn version(&self) -> Option<usize> {
+        self.version
+    }
+}
+
+// a subscription on path
+#[derive(Debug)]
+struct Watch {
+    path: PathBuf,
+    recursive: bool,
+    sender: Sender<Event>,
+}
+
+impl Watch {
+    // check if a path is the watched path or under it
+    fn covers(&self, path: &Path) -> bool {
+        if self.recursive {
+            path.starts_with(&self.path)
+        } else {
+            path == self.path || path.parent() == Some(&self.path)
+        }
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        self.covers(&event.path)
+            || event.new_path.as_ref().is_some_and(|p| self.covers(p))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    watches: Vec<Watch>,
+
+    // events of uncommitted transactions
+    pending: HashMap<Txid, Vec<Event>>,
+}
+
+/// Watchers
+///
+/// Events are collected during transaction and sent to watchers after the
+/// transaction is committed.
+#[derive(Debug, Default)]
+pub struct Watchers {
+    inner: Mutex<Inner>,
+}
+
+impl Watchers {
+    pub fn new() -> WatchersRef {
+        Arc::new(Watchers::default())
+    }
+
+    /// Check if there is no watchers
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.watches.is_empty()
+    }
+
+    /// Add a watcher on path
+    pub fn watch(&self, path: &Path, recursive: bool) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        let mut inner = self.inner.lock().unwrap();
+        inner.watches.push(Watch {
+            path: path.to_path_buf(),
+            recursive,
+            sender,
+        });
+        receiver
+    }
+
+    /// Add an event of uncommitted transaction
+    pub fn push(&self, event: Event) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending.entry(event.txid).or_default().push(event);
+    }
+}
+
+impl TxHook for Watchers {
+    fn on_complete(&self, txid: Txid, committed: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let events = match inner.pending.remove(&txid) {
+            Some(events) => events,
+            None => return,
+        };
+        if !committed {
+            return;
+        }
+
+        // send events and remove the watchers whose receiver is dropped
+        inner.watches.retain(|watch| {
+            events
+                .iter()
+                .filter(|event| watch.matches(event))
+                .all(|event| watch.sender.send(event.clone()).is_ok())
+        });
+    }
+}
+
+/// Watchers reference type
+pub type WatchersRef = Arc<Watchers>;
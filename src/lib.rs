@@ -115,24 +115,40 @@ macro_rules! from_io_err {
     };
 }
 
+#[cfg(feature = "async")]
+mod async_repo;
 mod base;
 mod content;
 mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod file;
 mod fs;
 mod repo;
 mod trans;
+mod transfer;
 mod version;
 mod volume;
 
 pub use self::base::crypto::{Cipher, MemLimit, OpsLimit};
 pub use self::base::{init_env, zbox_version};
-pub use self::content::ChunkingAlgorithm;
+pub use self::content::{verify_merkle_proof, ChunkingAlgorithm, MerkleProof};
 pub use self::error::{Error, Result};
-pub use self::file::{File, VersionReader};
+pub use self::file::{File, ReadHandle, VersionReader};
 pub use self::fs::fnode::{DirEntry, FileType, Metadata, Version};
-pub use self::repo::{OpenOptions, Repo, RepoInfo, RepoOpener};
+pub use self::fs::{
+    Change, ChangeKind, ChangeSet, CheckMode, CheckReport, EntityKind, Event,
+    Problem, Quota, RetentionPolicy, TrashEntry, Usage, WalkDir, WalkDirIter,
+};
+pub use self::repo::{
+    OpenOptions, Repo, RepoInfo, RepoOpener, Snapshot, SnapshotInfo,
+    Transaction,
+};
 pub use self::trans::Eid;
+pub use self::transfer::{ExportOptions, ImportOptions, TransferProgress};
+
+#[cfg(feature = "async")]
+pub use self::async_repo::{AsyncFile, AsyncRepo};
 
 #[cfg(any(feature = "storage-faulty", feature = "storage-zbox-faulty"))]
 pub use self::volume::FaultyController;
@@ -1,15 +1,23 @@
 use std::fmt::{self, Debug};
 use std::io::SeekFrom;
-use std::path::Path;
-use std::time::SystemTime;
+#[cfg(feature = "tar-archive")]
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, SystemTime};
 
 use super::{File, Result};
 use crate::base::crypto::{Cipher, Cost, MemLimit, OpsLimit};
 use crate::base::{self, Time};
 use crate::content::ChunkingAlgorithm;
 use crate::error::Error;
-use crate::fs::{Config, DirEntry, FileType, Fs, Metadata, Options, Version};
+use crate::fs::{
+    ChangeSet, CheckMode, CheckReport, Config, DirEntry, Event, FileType, Fs,
+    Metadata, Options, Quota, RetentionPolicy, TrashEntry, Usage, Version,
+    WalkDir,
+};
 use crate::trans::Eid;
+use crate::transfer::{self, ExportOptions, ImportOptions, TransferProgress};
 
 /// A builder used to create a repository [`Repo`] in various manners.
 ///
@@ -69,6 +77,7 @@ pub struct RepoOpener {
     create_new: bool,
     read_only: bool,
     force: bool,
+    quota: Option<Quota>,
 }
 
 impl RepoOpener {
@@ -140,13 +149,14 @@ impl RepoOpener {
 
     /// Sets the default maximum number of file version.
     ///
-    /// The `version_limit` must be within [1, 255], default is 1. This
-    /// setting is a repository-wise setting, individual file can overwrite it
-    /// by setting [`version_limit`] in [`OpenOptions`].
+    /// The `version_limit` must be greater than 0, default is 1. Use
+    /// `u32::MAX` to keep all versions. Tagged versions are not counted in
+    /// this limit. This setting is a repository-wise setting, individual file
+    /// can overwrite it by setting [`version_limit`] in [`OpenOptions`].
     ///
     /// [`version_limit`]: struct.OpenOptions.html#method.version_limit
     /// [`OpenOptions`]: struct.OpenOptions.html
-    pub fn version_limit(&mut self, version_limit: u8) -> &mut Self {
+    pub fn version_limit(&mut self, version_limit: u32) -> &mut Self {
         self.cfg.opts.version_limit = version_limit;
         self
     }
@@ -189,7 +199,8 @@ impl RepoOpener {
     /// Normally, repo will be exclusively locked once it is opened. But when
     /// this option is set to true, the repo will be opened regardless the repo
     /// lock. This option breaks exclusive access to repo, so use it cautiously.
-    /// Default is false.
+    /// A repo lock left by a crashed process is released automatically, so
+    /// this option is not needed in that case. Default is false.
     pub fn force(&mut self, force: bool) -> &mut Self {
         self.force = force;
         self
@@ -212,6 +223,105 @@ impl RepoOpener {
         self
     }
 
+    /// Sets the default retention policy of file versions.
+    ///
+    /// When it is set, the [`version_limit`] is ignored and file versions
+    /// are kept according to the policy. This setting is a repository-wise
+    /// setting, individual file can overwrite it by setting
+    /// [`retention_policy`] in [`OpenOptions`]. Default is not set.
+    ///
+    /// [`version_limit`]: struct.RepoOpener.html#method.version_limit
+    /// [`retention_policy`]: struct.OpenOptions.html#method.retention_policy
+    /// [`OpenOptions`]: struct.OpenOptions.html
+    pub fn retention_policy(&mut self, policy: RetentionPolicy) -> &mut Self {
+        self.cfg.opts.retention = Some(policy);
+        self
+    }
+
+    /// Sets the option for trash.
+    ///
+    /// When trash is enabled, removed files and directories are moved to
+    /// trash along with their history, instead of being deleted
+    /// immediately. They can be restored by [`Repo::undelete`], or purged
+    /// by [`Repo::empty_trash`]. Default is false.
+    ///
+    /// This option is only used when creating a repository.
+    ///
+    /// [`Repo::undelete`]: struct.Repo.html#method.undelete
+    /// [`Repo::empty_trash`]: struct.Repo.html#method.empty_trash
+    pub fn trash(&mut self, trash: bool) -> &mut Self {
+        self.cfg.opts.trash = trash;
+        self
+    }
+
+    /// Sets the maximum age of entries in trash.
+    ///
+    /// Entries older than this age are purged when another entry is moved
+    /// to trash, or when [`Repo::purge_trash`] is called. Default is no
+    /// limit.
+    ///
+    /// This option is only used when creating a repository.
+    ///
+    /// [`Repo::purge_trash`]: struct.Repo.html#method.purge_trash
+    pub fn trash_max_age(&mut self, max_age: Duration) -> &mut Self {
+        self.cfg.opts.trash_max_age = Some(max_age.as_secs());
+        self
+    }
+
+    /// Sets the maximum size of trash, in bytes.
+    ///
+    /// The size of trash is the total content length of all file versions
+    /// in it. When it exceeds this size, the oldest entries are purged. It
+    /// is checked at the same time as [`trash_max_age`]. Default is no
+    /// limit.
+    ///
+    /// This option is only used when creating a repository.
+    ///
+    /// [`trash_max_age`]: struct.RepoOpener.html#method.trash_max_age
+    pub fn trash_max_size(&mut self, max_size: u64) -> &mut Self {
+        self.cfg.opts.trash_max_size = Some(max_size);
+        self
+    }
+
+    /// Sets the option for change journal.
+    ///
+    /// When change journal is enabled, committed changes are recorded in
+    /// the repository and can be retrieved by [`Repo::changes_since`].
+    /// Default is false.
+    ///
+    /// This option is only used when creating a repository.
+    ///
+    /// [`Repo::changes_since`]: struct.Repo.html#method.changes_since
+    pub fn journal(&mut self, journal: bool) -> &mut Self {
+        self.cfg.opts.journal = journal;
+        self
+    }
+
+    /// Sets the maximum number of changes kept in change journal.
+    ///
+    /// When it is exceeded, the oldest changes are dropped. Default is 1024.
+    ///
+    /// This option is only used when creating a repository.
+    pub fn journal_limit(&mut self, limit: usize) -> &mut Self {
+        self.cfg.opts.journal_limit = Some(limit);
+        self
+    }
+
+    /// Sets the storage quota of the repository.
+    ///
+    /// The repository quota is the quota of root directory, it replaces the
+    /// existing quota when opening a repository. It is ignored if the
+    /// repository is opened as read-only. Default is not set, which keeps
+    /// the existing quota.
+    ///
+    /// See [`Repo::set_quota`] for details.
+    ///
+    /// [`Repo::set_quota`]: struct.Repo.html#method.set_quota
+    pub fn quota(&mut self, quota: Quota) -> &mut Self {
+        self.quota = Some(quota);
+        self
+    }
+
     /// Opens a repository at URI with the password and options specified by
     /// `self`.
     ///
@@ -279,7 +389,7 @@ impl RepoOpener {
             return Err(Error::InvalidArgument);
         }
 
-        if self.create {
+        let mut repo = if self.create {
             if self.read_only {
                 return Err(Error::InvalidArgument);
             }
@@ -287,13 +397,22 @@ impl RepoOpener {
                 if self.create_new {
                     return Err(Error::RepoExists);
                 }
-                Repo::open(uri, pwd, self.read_only, self.force)
+                Repo::open(uri, pwd, self.read_only, self.force)?
             } else {
-                Repo::create(uri, pwd, &self.cfg)
+                Repo::create(uri, pwd, &self.cfg)?
             }
         } else {
-            Repo::open(uri, pwd, self.read_only, self.force)
+            Repo::open(uri, pwd, self.read_only, self.force)?
+        };
+
+        // apply repository quota
+        if let Some(quota) = self.quota {
+            if !repo.fs.is_read_only() && repo.quota("/")? != Some(quota) {
+                repo.set_quota("/", Some(quota))?;
+            }
         }
+
+        Ok(repo)
     }
 }
 
@@ -337,7 +456,7 @@ impl RepoOpener {
 /// [`new`]: struct.OpenOptions.html#method.new
 /// [`open`]: struct.OpenOptions.html#method.open
 /// [`Result`]: type.Result.html
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct OpenOptions {
     read: bool,
     write: bool,
@@ -345,9 +464,10 @@ pub struct OpenOptions {
     truncate: bool,
     create: bool,
     create_new: bool,
-    version_limit: Option<u8>,
+    version_limit: Option<u32>,
     dedup_chunk: Option<bool>,
     chunking_algorithm: Option<ChunkingAlgorithm>,
+    retention: Option<RetentionPolicy>,
 }
 
 impl OpenOptions {
@@ -426,11 +546,13 @@ impl OpenOptions {
 
     /// Sets the maximum number of file versions allowed.
     ///
-    /// The `version_limit` must be within [1, 255], default is 1. It will fall
-    /// back to repository's [`version_limit`] if it is not set.
+    /// The `version_limit` must be greater than 0, default is 1. Use
+    /// `u32::MAX` to keep all versions. Tagged versions are not counted in
+    /// this limit. It will fall back to repository's [`version_limit`] if it
+    /// is not set.
     ///
     /// [`version_limit`]: struct.RepoOpener.html#method.version_limit
-    pub fn version_limit(&mut self, version_limit: u8) -> &mut OpenOptions {
+    pub fn version_limit(&mut self, version_limit: u32) -> &mut OpenOptions {
         self.version_limit = Some(version_limit);
         self
     }
@@ -461,6 +583,23 @@ impl OpenOptions {
         self
     }
 
+    /// Sets the retention policy of file versions.
+    ///
+    /// When it is set, the [`version_limit`] is ignored and file versions
+    /// are kept according to the policy. It will fall back to repository's
+    /// [`retention_policy`] if neither this option nor [`version_limit`] is
+    /// set.
+    ///
+    /// [`version_limit`]: struct.OpenOptions.html#method.version_limit
+    /// [`retention_policy`]: struct.RepoOpener.html#method.retention_policy
+    pub fn retention_policy(
+        &mut self,
+        policy: RetentionPolicy,
+    ) -> &mut OpenOptions {
+        self.retention = Some(policy);
+        self
+    }
+
     /// Opens a file at path with the options specified by `self`.
     pub fn open<P: AsRef<Path>>(
         &self,
@@ -492,12 +631,15 @@ pub struct RepoInfo {
     cost: Cost,
     cipher: Cipher,
     compress: bool,
-    version_limit: u8,
+    version_limit: u32,
     dedup_chunk: bool,
     dedup_file: bool,
     read_only: bool,
     ctime: Time,
     chunking_algorithm: ChunkingAlgorithm,
+    retention: Option<RetentionPolicy>,
+    trash: bool,
+    journal: bool,
 }
 
 impl RepoInfo {
@@ -551,7 +693,7 @@ impl RepoInfo {
 
     /// Returns the default maximum number of file versions.
     #[inline]
-    pub fn version_limit(&self) -> u8 {
+    pub fn version_limit(&self) -> u32 {
         self.version_limit
     }
 
@@ -584,10 +726,28 @@ impl RepoInfo {
     pub fn chunking_algorithm(&self) -> ChunkingAlgorithm {
         self.chunking_algorithm
     }
+
+    /// Returns the default retention policy of file versions, if it is set.
+    #[inline]
+    pub fn retention_policy(&self) -> Option<RetentionPolicy> {
+        self.retention
+    }
+
+    /// Returns whether trash is enabled for this repository.
+    #[inline]
+    pub fn trash(&self) -> bool {
+        self.trash
+    }
+
+    /// Returns whether change journal is enabled for this repository.
+    #[inline]
+    pub fn journal(&self) -> bool {
+        self.journal
+    }
 }
 
 // open a regular file with options
-fn open_file_with_options<P: AsRef<Path>>(
+pub(crate) fn open_file_with_options<P: AsRef<Path>>(
     fs: &mut Fs,
     path: P,
     open_opts: &OpenOptions,
@@ -614,6 +774,10 @@ fn open_file_with_options<P: AsRef<Path>>(
             let mut opts = fs.get_opts();
             if let Some(version_limit) = open_opts.version_limit {
                 opts.version_limit = version_limit;
+                opts.retention = None;
+            }
+            if open_opts.retention.is_some() {
+                opts.retention = open_opts.retention;
             }
             if let Some(dedup_chunk) = open_opts.dedup_chunk {
                 opts.dedup_chunk = dedup_chunk;
@@ -661,187 +825,531 @@ fn open_file_with_options<P: AsRef<Path>>(
     Ok(file)
 }
 
-/// An encrypted repository contains the whole file system.
-///
-/// A `Repo` represents a secure collection which consists of files,
-/// directories and their associated data. Similar to [`std::fs`], `Repo`
-/// provides methods to manipulate the enclosed file system.
-///
-/// # Storages
-///
-/// ZboxFS supports a variety of underlying storages, which are listed below.
-///
-/// | Storage            | URI identifier  | Cargo Feature       |
-/// | ------------------ | --------------- | ------------------- |
-/// | Memory             | "mem://"        | N/A                 |
-/// | OS file system     | "file://"       | storage-file        |
-/// | SQLite             | "sqlite://"     | storage-sqlite      |
-/// | Redis              | "redis://"      | storage-redis       |
-/// | Zbox Cloud Storage | "zbox://"       | storage-zbox-native |
-///
-/// \* Visit [zbox.io](https://zbox.io) to learn more about Zbox Cloud Storage.
-///
-/// By default, only memory storage is enabled. To use other storages, you need
-/// to specify it as dependency features in your Cargo.toml.
-///
-/// For example, to use OS file as underlying storage, specify its feature in
-/// your project's Cargo.toml file.
-///
-/// ```toml
-/// [dependencies]
-/// zbox = { version = "0.9.2", features = ["storage-file"] }
-/// ```
-///
-/// # Create and open `Repo`
-///
-/// `Repo` can be created on different underlying storages using [`RepoOpener`].
-/// It uses an URI-like string to specify its storage type and location. The
-/// URI string starts with an identifier which specifies the storage type, as
-/// shown in above table. You can check more location URI details at:
-/// [RepoOpener](struct.RepoOpener.html#method.open).
-///
-/// `Repo` can only be opened once at a time. After opened, it keeps locked
-/// from other open attempts until it goes out of scope. Once `Repo` is dropped,
-/// any opened files or version readers won't be accessible.
-///
-/// Optionally, `Repo` can be opened in [`read-only`] mode if you only need
-/// read access.
-///
-/// # Examples
-///
-/// Create an OS file system based repository.
-///
-/// ```no_run
-/// # #![allow(unused_mut, unused_variables, dead_code)]
-/// # use zbox::Result;
-/// use zbox::{init_env, RepoOpener};
-///
-/// # fn foo() -> Result<()> {
-/// init_env();
-/// let mut repo = RepoOpener::new()
-///     .create(true)
-///     .open("file:///path/to/repo", "pwd")?;
-/// # Ok(())
-/// # }
-/// ```
-///
-/// Create a memory based repository.
-///
-/// ```
-/// # #![allow(unused_mut, unused_variables, dead_code)]
-/// # use zbox::{init_env, Result, RepoOpener};
-/// # fn foo() -> Result<()> {
-/// # init_env();
-/// let mut repo = RepoOpener::new().create(true).open("mem://foo", "pwd")?;
-/// # Ok(())
-/// # }
-/// ```
+/// A transaction-scoped handle to a repository.
 ///
-/// Open a repository in read-only mode.
+/// This handle is passed to the closure run by [`Repo::transaction`], all the
+/// operations done through it are committed together when the closure
+/// succeeds, or rolled back together when it fails.
 ///
-/// ```no_run
-/// # #![allow(unused_mut, unused_variables, dead_code)]
-/// # use zbox::{Result, RepoOpener};
-/// # fn foo() -> Result<()> {
-/// let mut repo = RepoOpener::new()
-///     .read_only(true)
-///     .open("file:///path/to/repo", "pwd")?;
-/// # Ok(())
-/// # }
-/// ```
+/// Once an operation returned an error, the transaction cannot be committed
+/// anymore. Any later operation will return [`Error::Uncompleted`] and the
+/// whole transaction will be rolled back, even if the error is ignored.
 ///
-/// [`std::fs`]: https://doc.rust-lang.org/std/fs/index.html
-/// [`init_env`]: fn.init_env.html
-/// [`RepoOpener`]: struct.RepoOpener.html
-/// [`read-only`]: struct.RepoOpener.html#method.read_only
-pub struct Repo {
-    fs: Fs,
+/// [`Repo::transaction`]: struct.Repo.html#method.transaction
+/// [`Error::Uncompleted`]: enum.Error.html#variant.Uncompleted
+pub struct Transaction<'a> {
+    fs: &'a mut Fs,
+    failed: bool,
 }
 
-impl Repo {
-    /// Returns whether the URI points at an existing repository.
-    #[inline]
-    pub fn exists(uri: &str) -> Result<bool> {
-        Fs::exists(uri)
+impl<'a> Transaction<'a> {
+    // run an operation which changes the repo and track its failure
+    fn run<T, F>(&mut self, oper: F) -> Result<T>
+    where
+        F: FnOnce(&mut Fs) -> Result<T>,
+    {
+        if self.failed {
+            return Err(Error::Uncompleted);
+        }
+        oper(self.fs).inspect_err(|_| self.failed = true)
     }
 
-    // create repo
+    /// Returns whether the path points at an existing entity.
+    ///
+    /// Changes made earlier in this transaction are visible.
+    pub fn path_exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.fs.resolve(path.as_ref()).is_ok()
+    }
+
+    /// Get the metadata about a file or directory at specified path.
     #[inline]
-    fn create(uri: &str, pwd: &str, cfg: &Config) -> Result<Repo> {
-        let fs = Fs::create(uri, pwd, cfg)?;
-        Ok(Repo { fs })
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.fs.metadata(path.as_ref())
     }
 
-    // open repo
+    /// Returns a vector of all the entries within a directory.
     #[inline]
-    fn open(
-        uri: &str,
-        pwd: &str,
-        read_only: bool,
-        force: bool,
-    ) -> Result<Repo> {
-        let fs = Fs::open(uri, pwd, read_only, force)?;
-        Ok(Repo { fs })
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<DirEntry>> {
+        self.fs.read_dir(path.as_ref())
     }
 
-    /// Get repository metadata information.
-    pub fn info(&self) -> Result<RepoInfo> {
-        let meta = self.fs.info();
-        Ok(RepoInfo {
-            volume_id: meta.vol_info.id.clone(),
-            ver: meta.vol_info.ver.clone(),
-            uri: meta.vol_info.uri.clone(),
-            cost: meta.vol_info.cost,
-            cipher: meta.vol_info.cipher,
-            compress: meta.vol_info.compress,
-            version_limit: meta.opts.version_limit,
-            dedup_chunk: meta.opts.dedup_chunk,
-            dedup_file: meta.opts.dedup_file,
-            read_only: meta.read_only,
-            ctime: meta.vol_info.ctime,
-            chunking_algorithm: meta.opts.chunking_algorithm,
+    /// Creates a new, empty directory at the specified path.
+    #[inline]
+    pub fn create_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.run(|fs| {
+            fs.create_fnode(path.as_ref(), FileType::Dir, Options::default())
+                .map(|_| ())
         })
     }
 
-    /// Reset password for the repository.
-    ///
-    /// Note: if this method failed due to IO error, super block might be
-    /// damaged. If it is the case, use
-    /// [repair_super_block](struct.Repo.html#method.repair_super_block)
-    /// to restore super block before re-opening the repo.
+    /// Recursively create a directory and all of its parent components if they
+    /// are missing.
     #[inline]
-    pub fn reset_password(
+    pub fn create_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.run(|fs| fs.create_dir_all(path.as_ref()))
+    }
+
+    /// Writes `buf` as the whole content of a file.
+    ///
+    /// The file will be created if it does not exist, otherwise its content
+    /// will be replaced.
+    pub fn write_file<P: AsRef<Path>>(
         &mut self,
-        old_pwd: &str,
-        new_pwd: &str,
-        ops_limit: OpsLimit,
-        mem_limit: MemLimit,
+        path: P,
+        buf: &[u8],
     ) -> Result<()> {
-        let cost = Cost::new(ops_limit, mem_limit);
-        self.fs.reset_password(old_pwd, new_pwd, cost)
+        self.run(|fs| {
+            let mut file = open_file_with_options(
+                fs,
+                path,
+                OpenOptions::new().create(true).truncate(true),
+            )?;
+            file.write_once(buf)
+        })
     }
 
-    /// Repair possibly damaged super block.
-    ///
-    /// This method will try to repair super block using backup. One scenario
-    /// is when [reset_password](struct.Repo.html#method.reset_password) failed
-    /// due to IO error, super block might be damaged. Using this method can
-    /// restore the damaged super block from backup. If super block is all
-    /// good, this method is no-op.
-    ///
-    /// This method is not useful for memory-based storage and must be called
-    /// when repo is closed.
+    /// Copies the content of one file to another.
     #[inline]
-    pub fn repair_super_block(uri: &str, pwd: &str) -> Result<()> {
-        Fs::repair_super_block(uri, pwd)
+    pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        from: P,
+        to: Q,
+    ) -> Result<()> {
+        self.run(|fs| fs.copy(from.as_ref(), to.as_ref()))
     }
 
-    /// Returns whether the path points at an existing entity in repository.
-    ///
-    /// `path` must be an absolute path.
-    pub fn path_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
-        Ok(self
-            .fs
+    /// Rename a file or directory to a new name, replacing the original file
+    /// if `to` already exists.
+    #[inline]
+    pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        from: P,
+        to: Q,
+    ) -> Result<()> {
+        self.run(|fs| fs.rename(from.as_ref(), to.as_ref()))
+    }
+
+    /// Removes a regular file or symbolic link.
+    #[inline]
+    pub fn remove_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.run(|fs| fs.remove_file(path.as_ref()))
+    }
+
+    /// Remove an existing empty directory.
+    #[inline]
+    pub fn remove_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.run(|fs| fs.remove_dir(path.as_ref()))
+    }
+
+    /// Removes a directory at this path, after removing all its children.
+    #[inline]
+    pub fn remove_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.run(|fs| fs.remove_dir_all(path.as_ref()))
+    }
+}
+
+impl<'a> Debug for Transaction<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("failed", &self.failed)
+            .finish()
+    }
+}
+
+/// Information about a snapshot.
+///
+/// A list of snapshot information is returned by [`Repo::list_snapshots`].
+///
+/// [`Repo::list_snapshots`]: struct.Repo.html#method.list_snapshots
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    name: String,
+    ctime: SystemTime,
+}
+
+impl SnapshotInfo {
+    /// Returns the name of this snapshot.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the creation time of this snapshot.
+    #[inline]
+    pub fn created_at(&self) -> SystemTime {
+        self.ctime
+    }
+}
+
+/// A read-only view of a repository snapshot.
+///
+/// This view is returned by [`Repo::open_snapshot`], it shows the files and
+/// directories exactly as they were when the snapshot was created, no matter
+/// how the repository is changed afterwards.
+///
+/// A file in snapshot has the versions it had when the snapshot was created.
+///
+/// The snapshot view keeps the repository open until it is dropped, and the
+/// snapshot cannot be deleted while it is open.
+///
+/// [`Repo::open_snapshot`]: struct.Repo.html#method.open_snapshot
+pub struct Snapshot {
+    name: String,
+    ctime: SystemTime,
+    fs: Fs,
+}
+
+impl Snapshot {
+    /// Returns the name of this snapshot.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the creation time of this snapshot.
+    #[inline]
+    pub fn created_at(&self) -> SystemTime {
+        self.ctime
+    }
+
+    /// Returns whether the path points at an existing entity in snapshot.
+    ///
+    /// `path` must be an absolute path.
+    pub fn path_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        Ok(self.fs.resolve(path.as_ref()).is_ok())
+    }
+
+    /// Returns whether the path exists in snapshot and is pointing at a
+    /// regular file.
+    ///
+    /// `path` must be an absolute path.
+    pub fn is_file<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        match self.fs.resolve(path.as_ref()) {
+            Ok(fnode_ref) => {
+                let fnode = fnode_ref.read().unwrap();
+                Ok(fnode.is_file())
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Returns whether the path exists in snapshot and is pointing at a
+    /// directory.
+    ///
+    /// `path` must be an absolute path.
+    pub fn is_dir<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        match self.fs.resolve(path.as_ref()) {
+            Ok(fnode_ref) => {
+                let fnode = fnode_ref.read().unwrap();
+                Ok(fnode.is_dir())
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    /// Opens a regular file in snapshot in read-only mode.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn open_file<P: AsRef<Path>>(&mut self, path: P) -> Result<File> {
+        open_file_with_options(&mut self.fs, path, &OpenOptions::new())
+    }
+
+    /// Returns a vector of all the entries within a directory.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<DirEntry>> {
+        self.fs.read_dir(path.as_ref())
+    }
+
+    /// Returns a builder to recursively walk a directory.
+    ///
+    /// See [`Repo::walk_dir`] for details.
+    ///
+    /// [`Repo::walk_dir`]: struct.Repo.html#method.walk_dir
+    #[inline]
+    pub fn walk_dir<P: AsRef<Path>>(&self, path: P) -> Result<WalkDir<'_>> {
+        self.fs.walk_dir(path.as_ref())
+    }
+
+    /// Get the metadata about a file or directory at specified path.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.fs.metadata(path.as_ref())
+    }
+
+    /// Get the metadata about a file or directory at specified path, without
+    /// following symbolic link.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn symlink_metadata<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Metadata> {
+        self.fs.symlink_metadata(path.as_ref())
+    }
+
+    /// Returns the target path of a symbolic link.
+    ///
+    /// `path` must be an absolute path to a symbolic link.
+    #[inline]
+    pub fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.fs.read_link(path.as_ref())
+    }
+
+    /// Returns the value of an extended attribute of a file or directory, or
+    /// `None` if it doesn't exist.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn get_xattr<P: AsRef<Path>>(
+        &self,
+        path: P,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        self.fs.get_xattr(path.as_ref(), name)
+    }
+
+    /// Returns names of all the extended attributes of a file or directory,
+    /// in ascending order.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn list_xattrs<P: AsRef<Path>>(&self, path: P) -> Result<Vec<String>> {
+        self.fs.list_xattrs(path.as_ref())
+    }
+}
+
+impl Debug for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("name", &self.name)
+            .field("ctime", &self.ctime)
+            .finish()
+    }
+}
+
+/// An encrypted repository contains the whole file system.
+///
+/// A `Repo` represents a secure collection which consists of files,
+/// directories and their associated data. Similar to [`std::fs`], `Repo`
+/// provides methods to manipulate the enclosed file system.
+///
+/// # Storages
+///
+/// ZboxFS supports a variety of underlying storages, which are listed below.
+///
+/// | Storage            | URI identifier  | Cargo Feature       |
+/// | ------------------ | --------------- | ------------------- |
+/// | Memory             | "mem://"        | N/A                 |
+/// | OS file system     | "file://"       | storage-file        |
+/// | SQLite             | "sqlite://"     | storage-sqlite      |
+/// | Redis              | "redis://"      | storage-redis       |
+/// | Zbox Cloud Storage | "zbox://"       | storage-zbox-native |
+///
+/// \* Visit [zbox.io](https://zbox.io) to learn more about Zbox Cloud Storage.
+///
+/// By default, only memory storage is enabled. To use other storages, you need
+/// to specify it as dependency features in your Cargo.toml.
+///
+/// For example, to use OS file as underlying storage, specify its feature in
+/// your project's Cargo.toml file.
+///
+/// ```toml
+/// [dependencies]
+/// zbox = { version = "0.9.2", features = ["storage-file"] }
+/// ```
+///
+/// # Create and open `Repo`
+///
+/// `Repo` can be created on different underlying storages using [`RepoOpener`].
+/// It uses an URI-like string to specify its storage type and location. The
+/// URI string starts with an identifier which specifies the storage type, as
+/// shown in above table. You can check more location URI details at:
+/// [RepoOpener](struct.RepoOpener.html#method.open).
+///
+/// `Repo` can only be opened once at a time. After opened, it keeps locked
+/// from other open attempts until it goes out of scope. Once `Repo` is dropped,
+/// any opened files or version readers won't be accessible. Opening a locked
+/// repo fails with [`Error::RepoOpened`], and the lock holder is logged as a
+/// warning.
+///
+/// The lock doesn't outlive a crashed process. File storage uses OS advisory
+/// lock on Unix, SQLite and Redis storages use a lease which is renewed while
+/// the repo is opened and expires in 30 seconds after the holder is gone.
+///
+/// Optionally, `Repo` can be opened in [`read-only`] mode if you only need
+/// read access.
+///
+/// # Examples
+///
+/// Create an OS file system based repository.
+///
+/// ```no_run
+/// # #![allow(unused_mut, unused_variables, dead_code)]
+/// # use zbox::Result;
+/// use zbox::{init_env, RepoOpener};
+///
+/// # fn foo() -> Result<()> {
+/// init_env();
+/// let mut repo = RepoOpener::new()
+///     .create(true)
+///     .open("file:///path/to/repo", "pwd")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Create a memory based repository.
+///
+/// ```
+/// # #![allow(unused_mut, unused_variables, dead_code)]
+/// # use zbox::{init_env, Result, RepoOpener};
+/// # fn foo() -> Result<()> {
+/// # init_env();
+/// let mut repo = RepoOpener::new().create(true).open("mem://foo", "pwd")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Open a repository in read-only mode.
+///
+/// ```no_run
+/// # #![allow(unused_mut, unused_variables, dead_code)]
+/// # use zbox::{Result, RepoOpener};
+/// # fn foo() -> Result<()> {
+/// let mut repo = RepoOpener::new()
+///     .read_only(true)
+///     .open("file:///path/to/repo", "pwd")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`std::fs`]: https://doc.rust-lang.org/std/fs/index.html
+/// [`init_env`]: fn.init_env.html
+/// [`RepoOpener`]: struct.RepoOpener.html
+/// [`Error::RepoOpened`]: enum.Error.html#variant.RepoOpened
+/// [`read-only`]: struct.RepoOpener.html#method.read_only
+pub struct Repo {
+    fs: Fs,
+}
+
+impl Repo {
+    /// Returns whether the URI points at an existing repository.
+    #[inline]
+    pub fn exists(uri: &str) -> Result<bool> {
+        Fs::exists(uri)
+    }
+
+    // create repo
+    #[inline]
+    fn create(uri: &str, pwd: &str, cfg: &Config) -> Result<Repo> {
+        let fs = Fs::create(uri, pwd, cfg)?;
+        Ok(Repo { fs })
+    }
+
+    // open repo
+    #[inline]
+    fn open(
+        uri: &str,
+        pwd: &str,
+        read_only: bool,
+        force: bool,
+    ) -> Result<Repo> {
+        let fs = Fs::open(uri, pwd, read_only, force)?;
+        Ok(Repo { fs })
+    }
+
+    /// Get repository metadata information.
+    pub fn info(&self) -> Result<RepoInfo> {
+        let meta = self.fs.info();
+        Ok(RepoInfo {
+            volume_id: meta.vol_info.id.clone(),
+            ver: meta.vol_info.ver.clone(),
+            uri: meta.vol_info.uri.clone(),
+            cost: meta.vol_info.cost,
+            cipher: meta.vol_info.cipher,
+            compress: meta.vol_info.compress,
+            version_limit: meta.opts.version_limit,
+            dedup_chunk: meta.opts.dedup_chunk,
+            dedup_file: meta.opts.dedup_file,
+            read_only: meta.read_only,
+            ctime: meta.vol_info.ctime,
+            chunking_algorithm: meta.opts.chunking_algorithm,
+            retention: meta.opts.retention,
+            trash: meta.opts.trash,
+            journal: meta.opts.journal,
+        })
+    }
+
+    /// Reset password for the repository.
+    ///
+    /// Note: if this method failed due to IO error, super block might be
+    /// damaged. If it is the case, use
+    /// [repair_super_block](struct.Repo.html#method.repair_super_block)
+    /// to restore super block before re-opening the repo.
+    #[inline]
+    pub fn reset_password(
+        &mut self,
+        old_pwd: &str,
+        new_pwd: &str,
+        ops_limit: OpsLimit,
+        mem_limit: MemLimit,
+    ) -> Result<()> {
+        let cost = Cost::new(ops_limit, mem_limit);
+        self.fs.reset_password(old_pwd, new_pwd, cost)
+    }
+
+    /// Repair possibly damaged super block.
+    ///
+    /// This method will try to repair super block using backup. One scenario
+    /// is when [reset_password](struct.Repo.html#method.reset_password) failed
+    /// due to IO error, super block might be damaged. Using this method can
+    /// restore the damaged super block from backup. If super block is all
+    /// good, this method is no-op.
+    ///
+    /// This method is not useful for memory-based storage and must be called
+    /// when repo is closed.
+    #[inline]
+    pub fn repair_super_block(uri: &str, pwd: &str) -> Result<()> {
+        Fs::repair_super_block(uri, pwd)
+    }
+
+    /// Check integrity of the whole repository.
+    ///
+    /// This method walks through all files and directories from the root,
+    /// confirms every internal entity they refer to can be loaded and
+    /// decrypted, and re-calculates the reference counts from actual
+    /// references. All the entities saved in storage are then enumerated,
+    /// those which cannot be reached from the root are reported as orphans.
+    ///
+    /// Data written by a file which is not finished yet is not reachable
+    /// either, so it is better to finish all files before checking.
+    ///
+    /// In [`CheckMode::Repair`] mode the wrong reference counts are fixed and
+    /// orphans are deleted in one transaction. Damaged entities cannot be
+    /// repaired, and if any is found nothing will be changed.
+    ///
+    /// # Errors
+    ///
+    /// Repair on a read-only repo will return [`Error::ReadOnly`].
+    ///
+    /// [`CheckMode::Repair`]: enum.CheckMode.html#variant.Repair
+    /// [`Error::ReadOnly`]: enum.Error.html#variant.ReadOnly
+    #[inline]
+    pub fn check(&mut self, mode: CheckMode) -> Result<CheckReport> {
+        self.fs.check(mode)
+    }
+
+    /// Returns whether the path points at an existing entity in repository.
+    ///
+    /// `path` must be an absolute path.
+    pub fn path_exists<P: AsRef<Path>>(&self, path: P) -> Result<bool> {
+        Ok(self
+            .fs
             .resolve(path.as_ref())
             .map(|_| true)
             .unwrap_or(false))
@@ -941,35 +1449,355 @@ impl Repo {
     ///
     /// `path` must be an absolute path.
     ///
-    /// This method is **not** atomic in whole, but creating each entry is
-    /// atomic.
+    /// This method is **not** atomic in whole, but creating each entry is
+    /// atomic.
+    #[inline]
+    pub fn create_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.fs.create_dir_all(path.as_ref())
+    }
+
+    /// Returns a vector of all the entries within a directory.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<DirEntry>> {
+        self.fs.read_dir(path.as_ref())
+    }
+
+    /// Returns a builder to recursively walk a directory.
+    ///
+    /// `path` must be an absolute path. The returned [`WalkDir`] can be
+    /// configured with depth limits, walking order and filters, then turned
+    /// into a lazy iterator of [`DirEntry`].
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NotDir`] will be returned if `path` is not a directory.
+    ///
+    /// [`WalkDir`]: struct.WalkDir.html
+    /// [`DirEntry`]: struct.DirEntry.html
+    /// [`Error::NotDir`]: enum.Error.html#variant.NotDir
+    #[inline]
+    pub fn walk_dir<P: AsRef<Path>>(&self, path: P) -> Result<WalkDir<'_>> {
+        self.fs.walk_dir(path.as_ref())
+    }
+
+    /// Get the metadata about a file or directory at specified path.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.fs.metadata(path.as_ref())
+    }
+
+    /// Get the metadata about a file or directory at specified path, without
+    /// following symbolic link.
+    ///
+    /// If `path` is a symbolic link, the metadata of the link itself is
+    /// returned.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn symlink_metadata<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Metadata> {
+        self.fs.symlink_metadata(path.as_ref())
+    }
+
+    /// Changes the POSIX permission bits of a file or directory.
+    ///
+    /// `path` must be an absolute path. Symbolic link is followed. `mode`
+    /// can only contain permission bits in `0o7777`, otherwise
+    /// [`Error::InvalidArgument`] will be returned.
+    ///
+    /// The permission bits are only stored for the file, they are not
+    /// enforced by the repository.
+    ///
+    /// This method is atomic.
+    ///
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    #[inline]
+    pub fn set_permissions<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        mode: u32,
+    ) -> Result<()> {
+        self.fs.set_permissions(path.as_ref(), mode)
+    }
+
+    /// Changes the owner user ID and group ID of a file or directory.
+    ///
+    /// `path` must be an absolute path. Symbolic link is followed. The ID
+    /// will not be changed if it is `None`.
+    ///
+    /// This method is atomic.
+    #[inline]
+    pub fn set_owner<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<()> {
+        self.fs.set_owner(path.as_ref(), uid, gid)
+    }
+
+    /// Changes the last access time and last modification time of a file or
+    /// directory.
+    ///
+    /// `path` must be an absolute path. Symbolic link is followed. Times
+    /// earlier than unix epoch are not supported and will return
+    /// [`Error::InvalidArgument`].
+    ///
+    /// Changing times will not create a new version of the file, but any
+    /// later write will update the modification time again.
+    ///
+    /// This method is atomic.
+    ///
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    #[inline]
+    pub fn set_times<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        atime: SystemTime,
+        mtime: SystemTime,
+    ) -> Result<()> {
+        self.fs.set_times(path.as_ref(), atime, mtime)
+    }
+
+    /// Returns the value of an extended attribute of a file or directory, or
+    /// `None` if it doesn't exist.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn get_xattr<P: AsRef<Path>>(
+        &self,
+        path: P,
+        name: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        self.fs.get_xattr(path.as_ref(), name)
+    }
+
+    /// Returns names of all the extended attributes of a file or directory,
+    /// in ascending order.
+    ///
+    /// `path` must be an absolute path.
+    #[inline]
+    pub fn list_xattrs<P: AsRef<Path>>(&self, path: P) -> Result<Vec<String>> {
+        self.fs.list_xattrs(path.as_ref())
+    }
+
+    /// Sets the value of an extended attribute of a file or directory.
+    ///
+    /// `path` must be an absolute path. See [`File::set_xattr`] for the
+    /// limits of `name` and `value`.
+    ///
+    /// This method is atomic.
+    ///
+    /// [`File::set_xattr`]: struct.File.html#method.set_xattr
+    #[inline]
+    pub fn set_xattr<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: &str,
+        value: &[u8],
+    ) -> Result<()> {
+        self.fs.set_xattr(path.as_ref(), name, value)
+    }
+
+    /// Removes an extended attribute of a file or directory, it is no-op if
+    /// the attribute doesn't exist.
+    ///
+    /// `path` must be an absolute path.
+    ///
+    /// This method is atomic.
+    #[inline]
+    pub fn remove_xattr<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        name: &str,
+    ) -> Result<()> {
+        self.fs.remove_xattr(path.as_ref(), name)
+    }
+
+    /// Creates a new symbolic link at `path` pointing at `target`.
+    ///
+    /// `path` must be an absolute path. `target` can be an absolute path, or
+    /// a path relative to the parent directory of the link. It is not
+    /// required to exist.
+    ///
+    /// Symbolic links are followed when resolving paths, except the last
+    /// component of the path given to [`remove_file`], [`rename`],
+    /// [`read_link`] and [`symlink_metadata`].
+    ///
+    /// This method is atomic.
+    ///
+    /// [`remove_file`]: struct.Repo.html#method.remove_file
+    /// [`rename`]: struct.Repo.html#method.rename
+    /// [`read_link`]: struct.Repo.html#method.read_link
+    /// [`symlink_metadata`]: struct.Repo.html#method.symlink_metadata
+    #[inline]
+    pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        target: P,
+        path: Q,
+    ) -> Result<()> {
+        self.fs.symlink(target.as_ref(), path.as_ref())
+    }
+
+    /// Reads the target of a symbolic link.
+    ///
+    /// `path` must be an absolute path to a symbolic link.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NotLink`] will be returned if `path` is not a symbolic link.
+    ///
+    /// [`Error::NotLink`]: enum.Error.html#variant.NotLink
+    #[inline]
+    pub fn read_link<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.fs.read_link(path.as_ref())
+    }
+
+    /// Creates a new hard link at `dst` pointing at the same file as `src`.
+    ///
+    /// All hard links share the same content, versions and metadata. The
+    /// file is removed when its last link is removed.
+    ///
+    /// `src` and `dst` must be absolute paths, and `src` must not be a
+    /// directory.
+    ///
+    /// This method is atomic.
+    #[inline]
+    pub fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        src: P,
+        dst: Q,
+    ) -> Result<()> {
+        self.fs.hard_link(src.as_ref(), dst.as_ref())
+    }
+
+    /// Return a vector of history versions of a regular file at specified path.
+    ///
+    /// `path` must be an absolute path to a regular file.
+    #[inline]
+    pub fn history<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Version>> {
+        self.fs.history(path.as_ref())
+    }
+
+    /// Removes the file versions which are no longer kept.
+    ///
+    /// The [`RetentionPolicy`], or the version limit if no policy is set, is
+    /// applied when a new version is added to a file. Because time based
+    /// rules also expire versions over time, this method walks through all
+    /// the files and applies the policy again. Returns the number of removed
+    /// versions.
+    ///
+    /// This method is **not** atomic in whole, but applying the policy to
+    /// each file is atomic.
+    ///
+    /// [`RetentionPolicy`]: struct.RetentionPolicy.html
+    #[inline]
+    pub fn apply_retention(&mut self) -> Result<usize> {
+        self.fs.apply_retention()
+    }
+
+    /// Sets the storage quota of a directory, or removes it if `quota` is
+    /// `None`.
+    ///
+    /// `path` must be an absolute path to a directory. The quota limits the
+    /// total [`Usage`] of all files under the directory. Setting quota on
+    /// root directory is the same as [`RepoOpener::quota`].
+    ///
+    /// Quotas are checked when files are written, copied, moved in or
+    /// restored. An operation which makes the usage exceed the quota of
+    /// the directory or any of its ancestors fails with
+    /// [`Error::QuotaExceeded`], and its transaction is rolled back. The
+    /// usage can be higher than the quota if the quota is set lower than
+    /// the current usage, in this case only the operations which reduce
+    /// usage are allowed.
+    ///
+    /// Usage of a directory is maintained along with its quota, so each
+    /// change under the directory also updates the directory. Concurrent
+    /// transactions changing files under the same directory with quota
+    /// might fail with [`Error::InUse`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use zbox::{init_env, Result, RepoOpener, Error, Quota};
+    /// # fn foo() -> Result<()> {
+    /// # init_env();
+    /// let mut repo = RepoOpener::new().create(true).open("mem://foo", "pwd")?;
+    /// repo.create_dir("/foo")?;
+    /// repo.set_quota("/foo", Some(Quota::new().max_files(1)))?;
+    ///
+    /// repo.create_file("/foo/file1")?;
+    /// assert_eq!(
+    ///     repo.create_file("/foo/file2").unwrap_err(),
+    ///     Error::QuotaExceeded
+    /// );
+    /// assert_eq!(repo.usage("/foo")?.files(), 1);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// [`Usage`]: struct.Usage.html
+    /// [`RepoOpener::quota`]: struct.RepoOpener.html#method.quota
+    /// [`Error::QuotaExceeded`]: enum.Error.html#variant.QuotaExceeded
+    /// [`Error::InUse`]: enum.Error.html#variant.InUse
     #[inline]
-    pub fn create_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        self.fs.create_dir_all(path.as_ref())
+    pub fn set_quota<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        quota: Option<Quota>,
+    ) -> Result<()> {
+        self.fs.set_quota(path.as_ref(), quota)
     }
 
-    /// Returns a vector of all the entries within a directory.
+    /// Returns the storage quota of a directory, or `None` if it is not set.
     ///
-    /// `path` must be an absolute path.
+    /// `path` must be an absolute path to a directory.
     #[inline]
-    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<Vec<DirEntry>> {
-        self.fs.read_dir(path.as_ref())
+    pub fn quota<P: AsRef<Path>>(&self, path: P) -> Result<Option<Quota>> {
+        self.fs.quota(path.as_ref())
     }
 
-    /// Get the metadata about a file or directory at specified path.
+    /// Returns the storage usage of a file or directory.
     ///
-    /// `path` must be an absolute path.
+    /// `path` must be an absolute path. Usage of a directory includes all
+    /// files under it. If the directory has quota, its usage is maintained
+    /// and returned directly, otherwise the directory tree is walked
+    /// through to calculate the usage.
     #[inline]
-    pub fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
-        self.fs.metadata(path.as_ref())
+    pub fn usage<P: AsRef<Path>>(&self, path: P) -> Result<Usage> {
+        self.fs.usage(path.as_ref())
     }
 
-    /// Return a vector of history versions of a regular file at specified path.
+    /// Restores a historical version of a file as its current version.
     ///
-    /// `path` must be an absolute path to a regular file.
+    /// `path` must be an absolute path to a regular file. A new version is
+    /// created with the same content as version `ver_num`, the content is
+    /// shared and not copied. See [`File::restore_version`] for more details.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotFile`] will be returned if `path` is not a regular file.
+    /// - [`Error::NoVersion`] will be returned if the version doesn't exist.
+    ///
+    /// [`File::restore_version`]: struct.File.html#method.restore_version
+    /// [`Error::NotFile`]: enum.Error.html#variant.NotFile
+    /// [`Error::NoVersion`]: enum.Error.html#variant.NoVersion
     #[inline]
-    pub fn history<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Version>> {
-        self.fs.history(path.as_ref())
+    pub fn restore_version<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        ver_num: usize,
+    ) -> Result<()> {
+        self.fs.restore_version(path.as_ref(), ver_num)
     }
 
     /// Copies the content of one file to another.
@@ -1015,11 +1843,155 @@ impl Repo {
         self.fs.copy_dir_all(from.as_ref(), to.as_ref())
     }
 
-    /// Removes a regular file from the repository.
+    /// Recursively imports a directory tree from host file system.
+    ///
+    /// `host` is a directory on host, its content is copied to `path` in
+    /// the repository. Regular files, directories and symbolic links are
+    /// imported, other host entries are treated as regular files. Times and
+    /// permissions are preserved where supported, see [`ImportOptions`].
+    ///
+    /// Entries are imported in batched transactions, if any error happened
+    /// the current batch is rolled back, but the previous batches are kept.
+    /// Running the same import again will skip the files which are not
+    /// changed since last import. `path` must be an absolute path.
+    ///
+    /// This method is **not** atomic.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotDir`] will be returned if `host` is not a directory.
+    /// - [`Error::AlreadyExists`] will be returned if an entry in repository
+    ///   has different type from the host entry.
+    ///
+    /// [`ImportOptions`]: struct.ImportOptions.html
+    /// [`Error::NotDir`]: enum.Error.html#variant.NotDir
+    /// [`Error::AlreadyExists`]: enum.Error.html#variant.AlreadyExists
+    #[inline]
+    pub fn import_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        host: P,
+        path: Q,
+        opts: &mut ImportOptions,
+    ) -> Result<TransferProgress> {
+        transfer::import_dir(&mut self.fs, host.as_ref(), path.as_ref(), opts)
+    }
+
+    /// Recursively exports a directory tree to host file system.
+    ///
+    /// The content of directory at `path` in the repository is copied to
+    /// `host`, which will be created if it doesn't exist. Symbolic links are
+    /// exported with their targets unchanged, they are skipped on non-Unix
+    /// platforms. Times and permissions are preserved where supported, see
+    /// [`ExportOptions`].
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotDir`] will be returned if `path` is not a directory.
+    /// - [`Error::AlreadyExists`] will be returned if a file already exists
+    ///   on host and overwrite is not enabled.
+    ///
+    /// [`ExportOptions`]: struct.ExportOptions.html
+    /// [`Error::NotDir`]: enum.Error.html#variant.NotDir
+    /// [`Error::AlreadyExists`]: enum.Error.html#variant.AlreadyExists
+    #[inline]
+    pub fn export_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        path: P,
+        host: Q,
+        opts: &mut ExportOptions,
+    ) -> Result<TransferProgress> {
+        transfer::export_dir(&mut self.fs, path.as_ref(), host.as_ref(), opts)
+    }
+
+    /// Imports a tar archive stream to a directory in the repository.
+    ///
+    /// Archive members are mapped to files, directories, symbolic links and
+    /// hard links under `dest`, which will be created if it doesn't exist.
+    /// Their permission bits and modification times are kept, other member
+    /// types are skipped. Member content is streamed into the file without
+    /// staging, so identical chunks are deduplicated across members.
+    ///
+    /// Existing files are overwritten, each member is imported in its own
+    /// transaction. `dest` must be an absolute path.
+    ///
+    /// This method is **not** atomic.
+    ///
+    /// This method is only available with the `tar-archive` feature.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::InvalidPath`] will be returned if a member path contains
+    ///   parent directory component.
+    /// - [`Error::AlreadyExists`] will be returned if a member conflicts with
+    ///   an existing directory in repository.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use zbox::{init_env, Result, RepoOpener};
+    /// # fn foo() -> Result<()> {
+    /// # init_env();
+    /// # let mut repo = RepoOpener::new()
+    /// #     .create(true)
+    /// #     .open("mem://foo", "pwd")?;
+    /// repo.create_dir("/src")?;
+    /// repo.create_file("/src/foo.txt")?.write_once(b"foo")?;
+    ///
+    /// let mut archive = Vec::new();
+    /// repo.export_tar("/src", &mut archive)?;
+    /// repo.import_tar(&archive[..], "/dst")?;
+    /// assert!(repo.is_file("/dst/foo.txt")?);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// [`Error::InvalidPath`]: enum.Error.html#variant.InvalidPath
+    /// [`Error::AlreadyExists`]: enum.Error.html#variant.AlreadyExists
+    #[cfg(feature = "tar-archive")]
+    #[inline]
+    pub fn import_tar<R: Read, P: AsRef<Path>>(
+        &mut self,
+        reader: R,
+        dest: P,
+    ) -> Result<TransferProgress> {
+        transfer::import_tar(&mut self.fs, reader, dest.as_ref())
+    }
+
+    /// Exports a directory in the repository to a tar archive stream.
+    ///
+    /// The content of directory at `src` is written to the archive with
+    /// paths relative to `src`. Permission bits, owner IDs, modification
+    /// times and symbolic links are kept. Hard links are exported as
+    /// regular files.
+    ///
+    /// This method is only available with the `tar-archive` feature.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NotDir`] will be returned if `src` is not a directory.
+    ///
+    /// [`Error::NotDir`]: enum.Error.html#variant.NotDir
+    #[cfg(feature = "tar-archive")]
+    #[inline]
+    pub fn export_tar<P: AsRef<Path>, W: Write>(
+        &mut self,
+        src: P,
+        writer: W,
+    ) -> Result<TransferProgress> {
+        transfer::export_tar(&mut self.fs, src.as_ref(), writer)
+    }
+
+    /// Removes a regular file or symbolic link from the repository.
+    ///
+    /// If the file has other hard links, only this link is removed. If
+    /// [trash] is enabled, the file is moved to trash with its history.
     ///
     /// `path` must be an absolute path.
     ///
     /// This method is atomic.
+    ///
+    /// [trash]: struct.RepoOpener.html#method.trash
     #[inline]
     pub fn remove_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         self.fs.remove_file(path.as_ref())
@@ -1027,9 +1999,13 @@ impl Repo {
 
     /// Remove an existing empty directory.
     ///
+    /// If [trash] is enabled, the directory is moved to trash.
+    ///
     /// `path` must be an absolute path.
     ///
     /// This method is atomic.
+    ///
+    /// [trash]: struct.RepoOpener.html#method.trash
     #[inline]
     pub fn remove_dir<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         self.fs.remove_dir(path.as_ref())
@@ -1038,10 +2014,15 @@ impl Repo {
     /// Removes a directory at this path, after removing all its children.
     /// Use carefully!
     ///
+    /// If [trash] is enabled, the whole directory tree is moved to trash as
+    /// one entry, and this method is atomic.
+    ///
     /// `path` must be an absolute path.
     ///
     /// This method is **not** atomic in whole, but removing each entry is
     /// atomic.
+    ///
+    /// [trash]: struct.RepoOpener.html#method.trash
     #[inline]
     pub fn remove_dir_all<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         self.fs.remove_dir_all(path.as_ref())
@@ -1050,9 +2031,14 @@ impl Repo {
     /// Rename a file or directory to a new name, replacing the original file
     /// if `to` already exists.
     ///
+    /// If [trash] is enabled, the replaced file or directory is moved to
+    /// trash.
+    ///
     /// `from` and `to` must be absolute paths.
     ///
     /// This method is atomic.
+    ///
+    /// [trash]: struct.RepoOpener.html#method.trash
     #[inline]
     pub fn rename<P: AsRef<Path>, Q: AsRef<Path>>(
         &mut self,
@@ -1062,6 +2048,366 @@ impl Repo {
         self.fs.rename(from.as_ref(), to.as_ref())
     }
 
+    /// Runs multiple operations in one atomic transaction.
+    ///
+    /// The closure is given a [`Transaction`] handle to manipulate files and
+    /// directories. If the closure returns `Ok`, all the changes are committed
+    /// at once. If it returns an error, or any operation in it failed, all the
+    /// changes are rolled back and the repository is left unchanged.
+    ///
+    /// Only one transaction can run at a time, it is exclusive with other
+    /// exclusive operations such as [`File::finish`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use zbox::{init_env, Result, RepoOpener};
+    /// # fn foo() -> Result<()> {
+    /// # init_env();
+    /// # let mut repo = RepoOpener::new()
+    /// #     .create(true)
+    /// #     .open("mem://foo", "pwd")?;
+    /// repo.create_dir("/dir")?;
+    /// repo.create_file("/b")?;
+    /// repo.create_file("/c")?;
+    ///
+    /// repo.transaction(|tx| {
+    ///     tx.write_file("/dir/a", b"foo")?;
+    ///     tx.rename("/b", "/dir/b")?;
+    ///     tx.remove_file("/c")
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// [`Error::Uncompleted`] will be returned if the closure returns `Ok`
+    /// but an operation in it failed. The transaction is rolled back in
+    /// this case as well.
+    ///
+    /// [`Transaction`]: struct.Transaction.html
+    /// [`File::finish`]: struct.File.html#method.finish
+    /// [`Error::Uncompleted`]: enum.Error.html#variant.Uncompleted
+    pub fn transaction<T, F>(&mut self, oper: F) -> Result<T>
+    where
+        F: FnOnce(&mut Transaction) -> Result<T>,
+    {
+        let mut ret = None;
+        self.fs.transaction(|fs| {
+            let mut tx = Transaction { fs, failed: false };
+            ret = Some(oper(&mut tx)?);
+            if tx.failed {
+                return Err(Error::Uncompleted);
+            }
+            Ok(())
+        })?;
+        Ok(ret.unwrap())
+    }
+
+    /// Creates a snapshot of the whole repository.
+    ///
+    /// A snapshot is a consistent, read-only view of all the files and
+    /// directories at the time it is created. The current content of each
+    /// file is pinned by the snapshot, so later changes to the repository
+    /// don't affect it. The snapshot can be opened by [`open_snapshot`].
+    ///
+    /// Creating a snapshot doesn't copy anything, the snapshot shares the
+    /// directory tree with the repository. A file or directory is copied only
+    /// when it is changed or removed after the snapshot, and the copy is
+    /// kept until no snapshots can see it.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::InvalidArgument`] will be returned if `name` is empty.
+    /// - [`Error::AlreadyExists`] will be returned if a snapshot with the same
+    ///   name already exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use zbox::{init_env, Result, RepoOpener, OpenOptions};
+    /// # use std::io::Read;
+    /// # fn foo() -> Result<()> {
+    /// # init_env();
+    /// # let mut repo = RepoOpener::new()
+    /// #     .create(true)
+    /// #     .open("mem://foo", "pwd")?;
+    /// repo.create_file("/foo.txt")?.write_once(b"old")?;
+    /// repo.create_snapshot("backup")?;
+    /// OpenOptions::new()
+    ///     .write(true)
+    ///     .truncate(true)
+    ///     .open(&mut repo, "/foo.txt")?
+    ///     .write_once(b"new")?;
+    ///
+    /// let mut snapshot = repo.open_snapshot("backup")?;
+    /// let mut content = String::new();
+    /// snapshot.open_file("/foo.txt")?.read_to_string(&mut content).unwrap();
+    /// assert_eq!(content, "old");
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// [`open_snapshot`]: struct.Repo.html#method.open_snapshot
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    /// [`Error::AlreadyExists`]: enum.Error.html#variant.AlreadyExists
+    #[inline]
+    pub fn create_snapshot(&mut self, name: &str) -> Result<()> {
+        self.fs.create_snapshot(name)
+    }
+
+    /// Returns information of all the snapshots, ordered by name.
+    pub fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
+        Ok(self
+            .fs
+            .snapshots()
+            .into_iter()
+            .map(|(name, ctime)| SnapshotInfo { name, ctime })
+            .collect())
+    }
+
+    /// Opens a snapshot as a read-only view.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::NotFound`] will be returned if the snapshot doesn't exist.
+    ///
+    /// [`Error::NotFound`]: enum.Error.html#variant.NotFound
+    pub fn open_snapshot(&self, name: &str) -> Result<Snapshot> {
+        let (fs, ctime) = self.fs.open_snapshot(name)?;
+        Ok(Snapshot {
+            name: name.to_string(),
+            ctime,
+            fs,
+        })
+    }
+
+    /// Deletes a snapshot.
+    ///
+    /// The files, directories and file contents which are only kept for this
+    /// snapshot will be removed.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotFound`] will be returned if the snapshot doesn't exist.
+    /// - [`Error::InUse`] will be returned if the snapshot is still opened.
+    ///
+    /// [`Error::NotFound`]: enum.Error.html#variant.NotFound
+    /// [`Error::InUse`]: enum.Error.html#variant.InUse
+    #[inline]
+    pub fn delete_snapshot(&mut self, name: &str) -> Result<()> {
+        self.fs.delete_snapshot(name)
+    }
+
+    /// Returns all entries in trash, ordered by deletion time.
+    ///
+    /// Trash must be enabled by [`RepoOpener::trash`], otherwise it is
+    /// always empty.
+    ///
+    /// [`RepoOpener::trash`]: struct.RepoOpener.html#method.trash
+    #[inline]
+    pub fn list_trash(&self) -> Result<Vec<TrashEntry>> {
+        self.fs.trash_entries()
+    }
+
+    /// Restores an entry in trash to its original path.
+    ///
+    /// The file or directory is restored along with its history.
+    ///
+    /// This method is atomic.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::NotFound`] will be returned if the entry doesn't exist, or
+    ///   the parent directory of original path doesn't exist.
+    /// - [`Error::AlreadyExists`] will be returned if the original path
+    ///   already exists, use [`undelete_to`] to restore it to another path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use zbox::{init_env, Result, RepoOpener};
+    /// # fn foo() -> Result<()> {
+    /// # init_env();
+    /// let mut repo = RepoOpener::new()
+    ///     .create(true)
+    ///     .trash(true)
+    ///     .open("mem://foo", "pwd")?;
+    /// repo.create_file("/foo.txt")?;
+    /// repo.remove_file("/foo.txt")?;
+    ///
+    /// let trash = repo.list_trash()?;
+    /// assert_eq!(trash[0].path().to_str().unwrap(), "/foo.txt");
+    /// repo.undelete(trash[0].id())?;
+    /// assert!(repo.is_file("/foo.txt")?);
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// [`undelete_to`]: struct.Repo.html#method.undelete_to
+    /// [`Error::NotFound`]: enum.Error.html#variant.NotFound
+    /// [`Error::AlreadyExists`]: enum.Error.html#variant.AlreadyExists
+    #[inline]
+    pub fn undelete(&mut self, id: u64) -> Result<()> {
+        self.fs.undelete(id, None)
+    }
+
+    /// Restores an entry in trash to a new path.
+    ///
+    /// `path` must be an absolute path and not exist, its parent directory
+    /// must exist. See [`undelete`] for more details.
+    ///
+    /// This method is atomic.
+    ///
+    /// [`undelete`]: struct.Repo.html#method.undelete
+    #[inline]
+    pub fn undelete_to<P: AsRef<Path>>(
+        &mut self,
+        id: u64,
+        path: P,
+    ) -> Result<()> {
+        self.fs.undelete(id, Some(path.as_ref()))
+    }
+
+    /// Purges the entries in trash which exceed [`trash_max_age`] or
+    /// [`trash_max_size`], returns the number of purged entries.
+    ///
+    /// These limits are also checked when an entry is moved to trash, so
+    /// this method is only needed to purge expired entries when there is
+    /// no removal.
+    ///
+    /// This method is atomic.
+    ///
+    /// [`trash_max_age`]: struct.RepoOpener.html#method.trash_max_age
+    /// [`trash_max_size`]: struct.RepoOpener.html#method.trash_max_size
+    #[inline]
+    pub fn purge_trash(&mut self) -> Result<usize> {
+        self.fs.purge_trash()
+    }
+
+    /// Permanently deletes all entries in trash, returns the number of
+    /// deleted entries.
+    ///
+    /// This method is atomic.
+    #[inline]
+    pub fn empty_trash(&mut self) -> Result<usize> {
+        self.fs.empty_trash()
+    }
+
+    /// Returns the changes committed after `cursor`.
+    ///
+    /// Change journal must be enabled by [`RepoOpener::journal`], it
+    /// records creating, writing, renaming, removing and resizing of files,
+    /// directories and symbolic links, in the same transaction as the
+    /// change itself.
+    ///
+    /// The returned [`ChangeSet`] contains the changes in committed order
+    /// and a new cursor. Use zero as the initial cursor, and the new cursor
+    /// in the next call to get only the later changes. If some changes
+    /// after `cursor` were already dropped because of [`journal_limit`],
+    /// [`ChangeSet::is_truncated`] returns true.
+    ///
+    /// If change journal is not enabled, the returned change set is always
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidArgument`] will be returned if `cursor` is greater
+    /// than the latest cursor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use zbox::{init_env, Result, RepoOpener, ChangeKind};
+    /// # fn foo() -> Result<()> {
+    /// # init_env();
+    /// let mut repo = RepoOpener::new()
+    ///     .create(true)
+    ///     .journal(true)
+    ///     .open("mem://foo", "pwd")?;
+    /// let changes = repo.changes_since(0)?;
+    /// let cursor = changes.cursor();
+    ///
+    /// repo.create_dir("/foo")?;
+    /// repo.rename("/foo", "/bar")?;
+    ///
+    /// let changes = repo.changes_since(cursor)?;
+    /// assert_eq!(changes.changes().len(), 2);
+    /// assert_eq!(changes.changes()[1].kind(), ChangeKind::Rename);
+    /// assert_eq!(changes.changes()[1].new_path().unwrap().to_str(), Some("/bar"));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// [`RepoOpener::journal`]: struct.RepoOpener.html#method.journal
+    /// [`journal_limit`]: struct.RepoOpener.html#method.journal_limit
+    /// [`ChangeSet`]: struct.ChangeSet.html
+    /// [`ChangeSet::is_truncated`]: struct.ChangeSet.html#method.is_truncated
+    /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+    #[inline]
+    pub fn changes_since(&self, cursor: u64) -> Result<ChangeSet> {
+        self.fs.changes_since(cursor)
+    }
+
+    /// Watch changes on a path.
+    ///
+    /// Returns a receiver of [`Event`]s for creations, new versions,
+    /// renames and removals of the watched path and its direct children.
+    /// If `recursive` is true, changes of all descendants are included as
+    /// well. A rename is sent if either its old or new path is watched.
+    ///
+    /// Events are sent after the transaction which makes the changes is
+    /// committed, changes in an aborted transaction are never sent. Events
+    /// of one transaction are sent in the order they were made.
+    ///
+    /// The path doesn't need to exist. Dropping the receiver cancels the
+    /// watch.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidPath`] will be returned if `path` is not absolute.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use zbox::{init_env, Result, RepoOpener, ChangeKind};
+    /// # fn foo() -> Result<()> {
+    /// # init_env();
+    /// let mut repo = RepoOpener::new().create(true).open("mem://foo", "pwd")?;
+    /// let events = repo.watch("/", true)?;
+    ///
+    /// repo.create_dir_all("/foo/bar")?;
+    ///
+    /// let event = events.recv().unwrap();
+    /// assert_eq!(event.kind(), ChangeKind::Create);
+    /// assert_eq!(event.path().to_str(), Some("/foo"));
+    /// let event = events.recv().unwrap();
+    /// assert_eq!(event.path().to_str(), Some("/foo/bar"));
+    /// # Ok(())
+    /// # }
+    /// # foo().unwrap();
+    /// ```
+    ///
+    /// [`Event`]: struct.Event.html
+    /// [`Error::InvalidPath`]: enum.Error.html#variant.InvalidPath
+    #[inline]
+    pub fn watch<P: AsRef<Path>>(
+        &self,
+        path: P,
+        recursive: bool,
+    ) -> Result<Receiver<Event>> {
+        self.fs.watch(path.as_ref(), recursive)
+    }
+
     /// Permanently destroy a repository specified by `uri`.
     ///
     /// This will permanently delete all files and directories in a repository
@@ -153,6 +153,23 @@ where
         self.inner_mut()
     }
 
+    /// Get mutable reference of inner object visible to current thread,
+    /// without adding the cow to transaction
+    ///
+    /// This is same as `make_mut_naive`, except it returns the uncommitted
+    /// copy if the cow is being updated in current thread's transaction.
+    pub fn make_mut_visible(&mut self) -> &mut T {
+        let curr_txid = Txid::current_or_empty();
+        if self.txid.is_none()
+            || self.txid != Some(curr_txid)
+            || self.action == Some(Action::New)
+        {
+            self.inner_mut()
+        } else {
+            self.other_inner_mut()
+        }
+    }
+
     /// Mark cow as deleted
     #[inline]
     pub fn make_del(&mut self, txmgr: &TxMgrRef) -> Result<()> {
@@ -237,6 +254,17 @@ where
         self.inner_mut_by(arm)
     }
 
+    // create cow only living in memory, it is not saved to volume so it
+    // must not be changed in transaction
+    pub fn detached(inner: T) -> CowRef<T> {
+        let cow_ref = Cow::new(&Eid::new(), inner).into_ref();
+        {
+            let mut c = cow_ref.write().unwrap();
+            c.self_ref = Arc::downgrade(&cow_ref);
+        }
+        cow_ref
+    }
+
     // load cow from volume
     pub fn load(id: &Eid, vol: &VolumeRef) -> Result<CowRef<T>> {
         let vol_armor = VolumeArmor::<Cow<T>>::new(vol);
@@ -416,8 +444,9 @@ where
     fn abort(&mut self) {
         match self.action {
             Some(action) => {
-                if let Action::Update = action {
-                    // discard the new inner object
+                // discard the new inner object, a deleted cow might be
+                // updated before it is deleted in the same transaction
+                if action != Action::New {
                     self.other_mut().take();
                 }
             }
@@ -528,15 +557,10 @@ where
     // remove deleted items in cache
     pub fn remove_deleted(&self) {
         let mut lru = self.lru.write().unwrap();
-        lru.entries()
-            .filter(|ent| {
-                let cow_ref = ent.get();
-                let cow = cow_ref.read().unwrap();
-                cow.in_trans() && cow.action() == Action::Delete
-            })
-            .for_each(|ent| {
-                ent.remove();
-            });
+        lru.remove_if(|cow_ref| {
+            let cow = cow_ref.read().unwrap();
+            cow.in_trans() && cow.action() == Action::Delete
+        });
     }
 }
 
@@ -44,6 +44,19 @@ impl Eid {
         ret
     }
 
+    /// Parse entity ID from its hex string, None if it is invalid
+    #[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+    pub(crate) fn from_hex(s: &str) -> Option<Self> {
+        if s.len() != Eid::EID_SIZE * 2 || !s.is_ascii() {
+            return None;
+        }
+        let mut ret = Eid::new_empty();
+        for (idx, byte) in ret.0.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[idx * 2..idx * 2 + 2], 16).ok()?;
+        }
+        Some(ret)
+    }
+
     pub(crate) fn to_path_buf<P: AsRef<Path>>(&self, base: P) -> PathBuf {
         let s = self.to_string();
         base.as_ref().join(&s[0..2]).join(&s[2..4]).join(&s)
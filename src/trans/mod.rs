@@ -11,7 +11,9 @@ mod wal;
 
 pub use self::eid::{Eid, Id};
 pub use self::txid::Txid;
-pub use self::txmgr::{TxHandle, TxMgr, TxMgrRef, TxMgrWeakRef};
+#[cfg(feature = "async")]
+pub use self::txid::TxidGuard;
+pub use self::txmgr::{TxHandle, TxHook, TxMgr, TxMgrRef, TxMgrWeakRef};
 pub use self::wal::EntityType;
 
 use std::io::Write;
@@ -1,5 +1,7 @@
 use std::cell::RefCell;
 use std::fmt::{self, Debug, Display, Formatter};
+#[cfg(feature = "async")]
+use std::marker::PhantomData;
 use std::result::Result as StdResult;
 use std::u64;
 
@@ -57,6 +59,21 @@ impl Txid {
         TXID.with(|t| *t.borrow_mut() = 0);
     }
 
+    /// Bind a transaction to current thread, used to move a transaction to
+    /// another thread
+    ///
+    /// The binding is removed when the returned guard is dropped, even if
+    /// the thread panics.
+    #[cfg(feature = "async")]
+    pub fn attach(txid: Option<Txid>) -> TxidGuard {
+        if let Some(txid) = txid {
+            TXID.with(|t| *t.borrow_mut() = txid.0);
+        }
+        TxidGuard {
+            _not_send: PhantomData,
+        }
+    }
+
     /// Get next txid by increase one
     pub fn next(&mut self) -> Txid {
         self.0 = self.0.checked_add(1).unwrap();
@@ -72,6 +89,22 @@ impl Txid {
     }
 }
 
+/// Thread transaction binding guard
+///
+/// It resets current thread transaction ID when dropped. It cannot be sent
+/// to other threads as the binding is per-thread.
+#[cfg(feature = "async")]
+pub struct TxidGuard {
+    _not_send: PhantomData<*const ()>,
+}
+
+#[cfg(feature = "async")]
+impl Drop for TxidGuard {
+    fn drop(&mut self) {
+        Txid::reset_current();
+    }
+}
+
 impl Debug for Txid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Txid({})", self.0)
@@ -124,3 +157,28 @@ impl<'de> Deserialize<'de> for Txid {
         deserializer.deserialize_u64(TxidVisitor)
     }
 }
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::*;
+    use std::panic;
+
+    #[test]
+    fn txid_guard() {
+        let txid = Txid(42);
+
+        {
+            let _guard = Txid::attach(Some(txid));
+            assert_eq!(Txid::current().unwrap(), txid);
+        }
+        assert!(!Txid::is_in_trans());
+
+        // binding is removed even if the thread panics
+        let result = panic::catch_unwind(|| {
+            let _guard = Txid::attach(Some(txid));
+            panic!("oper failed");
+        });
+        assert!(result.is_err());
+        assert!(!Txid::is_in_trans());
+    }
+}
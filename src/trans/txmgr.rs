@@ -13,6 +13,12 @@ use crate::base::IntoRef;
 use crate::error::{Error, Result};
 use crate::volume::{Arm, VolumeRef};
 
+/// Hook notified when a transaction is completed
+pub trait TxHook: Send + Sync {
+    /// Called after a transaction is committed or aborted
+    fn on_complete(&self, txid: Txid, committed: bool);
+}
+
 /// Tranaction manager
 #[derive(Default)]
 pub struct TxMgr {
@@ -25,6 +31,14 @@ pub struct TxMgr {
     // wal queue manager
     walq_mgr: WalQueueMgr,
 
+    // outer transaction spanning multiple operations, and whether any of
+    // its operations failed
+    outer: Option<Txid>,
+    outer_failed: bool,
+
+    // hook notified when transaction is completed
+    hook: Option<Arc<dyn TxHook>>,
+
     vol: VolumeRef,
 }
 
@@ -34,6 +48,9 @@ impl TxMgr {
             txs: LinkedHashMap::new(),
             ents: HashMap::new(),
             walq_mgr: WalQueueMgr::new(walq_id, vol),
+            outer: None,
+            outer_failed: false,
+            hook: None,
             vol: vol.clone(),
         }
     }
@@ -46,10 +63,25 @@ impl TxMgr {
     }
 
     /// Begin a transaction
+    ///
+    /// If current thread is running an outer transaction, the returned
+    /// handle joins the outer transaction instead.
     pub fn begin_trans(txmgr: &TxMgrRef) -> Result<TxHandle> {
         // check if current thread is already in transaction
         if Txid::is_in_trans() {
-            return Err(Error::InTrans);
+            let tm = txmgr.read().unwrap();
+            let txid = Txid::current()?;
+            if tm.outer != Some(txid) {
+                return Err(Error::InTrans);
+            }
+            if tm.outer_failed {
+                return Err(Error::Uncompleted);
+            }
+            return Ok(TxHandle {
+                txid,
+                txmgr: Arc::downgrade(txmgr),
+                nested: true,
+            });
         }
 
         let mut tm = txmgr.write().unwrap();
@@ -86,9 +118,43 @@ impl TxMgr {
         Ok(TxHandle {
             txid,
             txmgr: Arc::downgrade(txmgr),
+            nested: false,
         })
     }
 
+    /// Begin an outer transaction, which can be joined by the transactions
+    /// begun later in the same thread
+    ///
+    /// The joined transactions will not commit by themselves, all their
+    /// changes are committed or aborted together with the outer transaction.
+    pub fn begin_outer_trans(txmgr: &TxMgrRef) -> Result<TxHandle> {
+        let tx_handle = TxMgr::begin_trans(txmgr)?;
+        let mut tm = txmgr.write().unwrap();
+        tm.outer = Some(tx_handle.txid);
+        tm.outer_failed = false;
+        Ok(tx_handle)
+    }
+
+    /// Set hook which is notified when transaction is completed
+    #[inline]
+    pub fn set_hook(&mut self, hook: Arc<dyn TxHook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Get addresses of deleted entities which are not removed yet
+    #[inline]
+    pub fn deleted_ids(&self) -> Result<Vec<Eid>> {
+        self.walq_mgr.deleted_ids()
+    }
+
+    // notify hook the transaction is completed
+    #[inline]
+    fn notify_hook(&self, txid: Txid, committed: bool) {
+        if let Some(ref hook) = self.hook {
+            hook.on_complete(txid, committed);
+        }
+    }
+
     /// Add entity to transaction
     pub fn add_to_trans(
         &mut self,
@@ -115,11 +181,21 @@ impl TxMgr {
     fn remove_trans(&mut self, txid: Txid) {
         self.txs.remove(&txid);
         self.ents.retain(|_, &mut v| v != txid);
+        if self.outer == Some(txid) {
+            self.outer = None;
+            self.outer_failed = false;
+        }
         Txid::reset_current();
     }
 
     // commit transaction
     fn commit_trans(&mut self, txid: Txid) -> Result<()> {
+        // outer transaction cannot commit if any of its operations failed
+        if self.outer == Some(txid) && self.outer_failed {
+            self.abort_trans(txid);
+            return Err(Error::Uncompleted);
+        }
+
         let result = {
             let tx_ref = self.txs.get(&txid).unwrap().clone();
             let mut tx = tx_ref.write().unwrap();
@@ -145,6 +221,7 @@ impl TxMgr {
         } else {
             // commit succeed, remove tx from tx manager
             self.remove_trans(txid);
+            self.notify_hook(txid, true);
         }
 
         // return the original result during commit
@@ -172,6 +249,7 @@ impl TxMgr {
 
         // remove tx from tx manager
         self.remove_trans(txid);
+        self.notify_hook(txid, false);
     }
 }
 
@@ -181,6 +259,8 @@ impl Debug for TxMgr {
             .field("txs", &self.txs)
             .field("ents", &self.ents)
             .field("walq_mgr", &self.walq_mgr)
+            .field("outer", &self.outer)
+            .field("outer_failed", &self.outer_failed)
             .finish()
     }
 }
@@ -197,10 +277,14 @@ lazy_static! {
 }
 
 // Transaction handle
+//
+// A nested handle joins an outer transaction, it doesn't commit or abort the
+// transaction by itself.
 #[derive(Debug, Default, Clone)]
 pub struct TxHandle {
     pub txid: Txid,
     pub txmgr: TxMgrWeakRef,
+    nested: bool,
 }
 
 impl TxHandle {
@@ -234,6 +318,10 @@ impl TxHandle {
     where
         F: FnOnce() -> Result<()>,
     {
+        // outer transaction already holds the lock
+        if self.nested {
+            return self.run_all(oper);
+        }
         let _lock = EXCL_TX_LOCK.lock().unwrap();
         self.run_all(oper)
     }
@@ -241,6 +329,9 @@ impl TxHandle {
     /// Commit a transaction
     #[inline]
     pub fn commit(&self) -> Result<()> {
+        if self.nested {
+            return Ok(());
+        }
         let txmgr = self.txmgr.upgrade().ok_or(Error::RepoClosed)?;
         let mut tm = txmgr.write().unwrap();
         tm.commit_trans(self.txid)
@@ -252,6 +343,16 @@ impl TxHandle {
         let mut tm = txmgr.write().unwrap();
 
         debug!("run tx failed: {:?}", err);
+
+        // the failed operation could be partially done, so mark the outer
+        // transaction as failed and let it abort
+        if self.nested {
+            if tm.outer == Some(self.txid) {
+                tm.outer_failed = true;
+            }
+            return Err(err);
+        }
+
         tm.abort_trans(self.txid);
 
         // return the original error
@@ -450,6 +551,41 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn abort_updated_then_deleted() {
+        let vol = setup_mem_vol("txmgr.abort_updated_then_deleted");
+        let tm = TxMgr::new(&Eid::new(), &vol).into_ref();
+        let val = 42;
+        let mut a = Arc::default();
+
+        let tx = TxMgr::begin_trans(&tm).unwrap();
+        tx.run_all(|| {
+            a = Obj::new(val).into_cow(&tm)?;
+            Ok(())
+        })
+        .unwrap();
+
+        // the updated copy is discarded when abort
+        let tx = TxMgr::begin_trans(&tm).unwrap();
+        tx.run_all(|| {
+            let mut a_cow = a.write().unwrap();
+            a_cow.make_mut(&tm)?.val += 1;
+            a_cow.make_del(&tm)?;
+            Err(Error::InvalidArgument)
+        })
+        .unwrap_err();
+        Obj::ensure(&a, val, Arm::Right);
+
+        let tx = TxMgr::begin_trans(&tm).unwrap();
+        tx.run_all(|| {
+            let mut a_cow = a.write().unwrap();
+            assert_eq!(a_cow.make_mut(&tm)?.val, val);
+            Ok(())
+        })
+        .unwrap();
+        Obj::ensure(&a, val, Arm::Left);
+    }
+
     #[test]
     fn test_trans_mem() {
         {
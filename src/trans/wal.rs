@@ -94,16 +94,12 @@ impl Wal {
     }
 
     // recycle tx entries in a wal
-    fn recycle(
-        &self,
-        wal_armor: &VolumeWalArmor<Self>,
-        vol: &VolumeRef,
-    ) -> Result<()> {
+    fn recycle(&self, vol: &VolumeRef) -> Result<()> {
         for ent in self.entries.values() {
             match ent.action {
                 Action::New | Action::Update => {} // do nothing
                 Action::Delete => match ent.ent_type {
-                    EntityType::Cow => wal_armor.remove_all_arms(&ent.id)?,
+                    EntityType::Cow => Arm::remove_all(&ent.id, vol)?,
                     EntityType::Direct => {
                         let mut vol = vol.write().unwrap();
                         vol.del(&ent.id)?;
@@ -114,6 +110,23 @@ impl Wal {
         Ok(())
     }
 
+    // get addresses of deleted entries, which are not recycled yet
+    fn deleted_ids(&self, ids: &mut Vec<Eid>) {
+        for ent in self.entries.values() {
+            if ent.action != Action::Delete {
+                continue;
+            }
+            match ent.ent_type {
+                EntityType::Cow => {
+                    let (left, right) = Arm::both_eid(&ent.id);
+                    ids.push(left);
+                    ids.push(right);
+                }
+                EntityType::Direct => ids.push(ent.id.clone()),
+            }
+        }
+    }
+
     // clean each aborted entry in wal
     pub fn clean_aborted(&self, vol: &VolumeRef) -> Result<()> {
         for ent in self.entries.values() {
@@ -286,7 +299,7 @@ impl WalQueue {
         match self.wal_armor.load_item(&retiree_id) {
             Ok(retiree) => {
                 // recycle and remove the wal
-                retiree.recycle(&self.wal_armor, &self.vol)?;
+                retiree.recycle(&self.vol)?;
                 self.wal_armor.remove_all_arms(&retiree_id)
             }
             Err(ref err) if *err == Error::NotFound => {
@@ -298,6 +311,18 @@ impl WalQueue {
         }
     }
 
+    fn deleted_ids(&self) -> Result<Vec<Eid>> {
+        let mut ids = Vec::new();
+        for txid in self.done.iter() {
+            match self.wal_armor.load_item(&Wal::derive_id(*txid)) {
+                Ok(wal) => wal.deleted_ids(&mut ids),
+                Err(ref err) if *err == Error::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(ids)
+    }
+
     fn commit_trans(&mut self, wal: Wal) -> Result<()> {
         // recycle the retired trans
         while self.done.len() >= Self::COMMITTED_QUEUE_SIZE {
@@ -518,6 +543,13 @@ impl WalQueueMgr {
         .and_then(|_| self.walq_armor.save_item(&mut self.walq))
     }
 
+    // get addresses of entities deleted by the completed txs, they are
+    // still saved until the txs are recycled
+    #[inline]
+    pub fn deleted_ids(&self) -> Result<Vec<Eid>> {
+        self.walq.deleted_ids()
+    }
+
     pub fn begin_trans(&mut self, txid: Txid) -> Result<()> {
         self.backup_walq();
         self.walq.begin_trans(txid);
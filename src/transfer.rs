@@ -0,0 +1,885 @@
+#[cfg(feature = "tar-archive")]
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::fs::{self as host_fs, File as HostFile, FileTimes};
+use std::io;
+#[cfg(feature = "tar-archive")]
+use std::io::{Read, Write};
+#[cfg(feature = "tar-archive")]
+use std::path::Component;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+#[cfg(feature = "tar-archive")]
+use std::time::{Duration, UNIX_EPOCH};
+
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+
+#[cfg(feature = "tar-archive")]
+use tar::{Archive, Builder, Entry as TarEntry, EntryType, Header};
+
+use crate::error::{Error, Result};
+use crate::fs::{Fs, Metadata};
+use crate::repo::{open_file_with_options, OpenOptions};
+#[cfg(feature = "tar-archive")]
+use crate::trans::{Eid, Id};
+
+// default number of entries imported in one transaction
+const DEFAULT_BATCH_SIZE: usize = 64;
+
+type ProgressFn = Box<dyn FnMut(&TransferProgress)>;
+
+/// Progress of a directory tree import or export.
+///
+/// It is passed to the progress callback after each entry is processed, and
+/// the final progress is returned by [`Repo::import_dir`] and
+/// [`Repo::export_dir`] as a summary.
+///
+/// [`Repo::import_dir`]: struct.Repo.html#method.import_dir
+/// [`Repo::export_dir`]: struct.Repo.html#method.export_dir
+#[derive(Debug, Clone, Default)]
+pub struct TransferProgress {
+    path: PathBuf,
+    files: usize,
+    dirs: usize,
+    symlinks: usize,
+    skipped: usize,
+    bytes: u64,
+}
+
+impl TransferProgress {
+    /// Returns the destination path of the last processed entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns number of regular files copied.
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Returns number of directories copied.
+    pub fn dirs(&self) -> usize {
+        self.dirs
+    }
+
+    /// Returns number of symbolic links copied.
+    pub fn symlinks(&self) -> usize {
+        self.symlinks
+    }
+
+    /// Returns number of entries skipped because they are unchanged or not
+    /// supported.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Returns number of content bytes copied.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+/// Options and flags which can be used to configure [`Repo::import_dir`].
+///
+/// # Examples
+///
+/// ```no_run
+/// # use zbox::{init_env, Result, RepoOpener, ImportOptions};
+/// # fn foo() -> Result<()> {
+/// # init_env();
+/// # let mut repo = RepoOpener::new()
+/// #     .create(true)
+/// #     .open("mem://foo", "pwd")?;
+/// let report = repo.import_dir(
+///     "/home/alice/docs",
+///     "/docs",
+///     ImportOptions::new()
+///         .batch_size(100)
+///         .progress(|p| println!("imported {}", p.path().display())),
+/// )?;
+/// println!("{} files, {} skipped", report.files(), report.skipped());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`Repo::import_dir`]: struct.Repo.html#method.import_dir
+pub struct ImportOptions {
+    batch_size: usize,
+    incremental: bool,
+    preserve_times: bool,
+    preserve_permissions: bool,
+    progress: Option<ProgressFn>,
+}
+
+impl ImportOptions {
+    /// Creates a default set of import options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of entries imported in one transaction.
+    ///
+    /// Default is 64. Zero will be treated as 1.
+    pub fn batch_size(&mut self, batch_size: usize) -> &mut Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets the option for incremental import.
+    ///
+    /// When this option is true, a file is skipped if its size and
+    /// modification time match the file in repository, that is, it is not
+    /// changed since the last import. This requires modification time to be
+    /// preserved.
+    ///
+    /// Default is true.
+    pub fn incremental(&mut self, incremental: bool) -> &mut Self {
+        self.incremental = incremental;
+        self
+    }
+
+    /// Sets the option for preserving access and modification times.
+    ///
+    /// Default is true.
+    pub fn preserve_times(&mut self, preserve_times: bool) -> &mut Self {
+        self.preserve_times = preserve_times;
+        self
+    }
+
+    /// Sets the option for preserving permission bits.
+    ///
+    /// Permissions are only available on Unix platforms, this option is
+    /// ignored on other platforms.
+    ///
+    /// Default is true.
+    pub fn preserve_permissions(
+        &mut self,
+        preserve_permissions: bool,
+    ) -> &mut Self {
+        self.preserve_permissions = preserve_permissions;
+        self
+    }
+
+    /// Sets the progress callback, which is called after each entry is
+    /// processed.
+    pub fn progress<F>(&mut self, progress: F) -> &mut Self
+    where
+        F: FnMut(&TransferProgress) + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    fn report(&mut self, progress: &TransferProgress) {
+        if let Some(ref mut f) = self.progress {
+            f(progress);
+        }
+    }
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            batch_size: DEFAULT_BATCH_SIZE,
+            incremental: true,
+            preserve_times: true,
+            preserve_permissions: true,
+            progress: None,
+        }
+    }
+}
+
+impl Debug for ImportOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImportOptions")
+            .field("batch_size", &self.batch_size)
+            .field("incremental", &self.incremental)
+            .field("preserve_times", &self.preserve_times)
+            .field("preserve_permissions", &self.preserve_permissions)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+/// Options and flags which can be used to configure [`Repo::export_dir`].
+///
+/// [`Repo::export_dir`]: struct.Repo.html#method.export_dir
+pub struct ExportOptions {
+    overwrite: bool,
+    preserve_times: bool,
+    preserve_permissions: bool,
+    progress: Option<ProgressFn>,
+}
+
+impl ExportOptions {
+    /// Creates a default set of export options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the option for overwriting existing files on host.
+    ///
+    /// When this option is false, [`Error::AlreadyExists`] will be returned
+    /// if a file or symbolic link already exists on host. Existing
+    /// directories are always merged.
+    ///
+    /// Default is false.
+    ///
+    /// [`Error::AlreadyExists`]: enum.Error.html#variant.AlreadyExists
+    pub fn overwrite(&mut self, overwrite: bool) -> &mut Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Sets the option for preserving access and modification times.
+    ///
+    /// Default is true.
+    pub fn preserve_times(&mut self, preserve_times: bool) -> &mut Self {
+        self.preserve_times = preserve_times;
+        self
+    }
+
+    /// Sets the option for preserving permission bits.
+    ///
+    /// Permissions are only available on Unix platforms, this option is
+    /// ignored on other platforms.
+    ///
+    /// Default is true.
+    pub fn preserve_permissions(
+        &mut self,
+        preserve_permissions: bool,
+    ) -> &mut Self {
+        self.preserve_permissions = preserve_permissions;
+        self
+    }
+
+    /// Sets the progress callback, which is called after each entry is
+    /// processed.
+    pub fn progress<F>(&mut self, progress: F) -> &mut Self
+    where
+        F: FnMut(&TransferProgress) + 'static,
+    {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    fn report(&mut self, progress: &TransferProgress) {
+        if let Some(ref mut f) = self.progress {
+            f(progress);
+        }
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            overwrite: false,
+            preserve_times: true,
+            preserve_permissions: true,
+            progress: None,
+        }
+    }
+}
+
+impl Debug for ExportOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ExportOptions")
+            .field("overwrite", &self.overwrite)
+            .field("preserve_times", &self.preserve_times)
+            .field("preserve_permissions", &self.preserve_permissions)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+// host entry kind
+#[derive(Debug)]
+enum HostKind {
+    Dir,
+    File(u64),
+    Symlink(PathBuf),
+}
+
+// host entry to be imported
+#[derive(Debug)]
+struct HostEntry {
+    host: PathBuf,
+    path: PathBuf,
+    kind: HostKind,
+    atime: SystemTime,
+    mtime: SystemTime,
+    mode: Option<u32>,
+}
+
+impl HostEntry {
+    fn new(
+        host: PathBuf,
+        path: PathBuf,
+        md: &host_fs::Metadata,
+    ) -> Result<Self> {
+        let kind = if md.file_type().is_symlink() {
+            HostKind::Symlink(host_fs::read_link(&host)?)
+        } else if md.is_dir() {
+            HostKind::Dir
+        } else {
+            HostKind::File(md.len())
+        };
+        let mtime = md.modified()?;
+        let atime = md.accessed().unwrap_or(mtime);
+
+        #[cfg(unix)]
+        let mode = Some(md.permissions().mode() & 0o7777);
+        #[cfg(not(unix))]
+        let mode = None;
+
+        Ok(HostEntry {
+            host,
+            path,
+            kind,
+            atime,
+            mtime,
+            mode,
+        })
+    }
+
+    #[inline]
+    fn is_dir(&self) -> bool {
+        matches!(self.kind, HostKind::Dir)
+    }
+}
+
+// recursively collect host entries under a directory, parent directory is
+// always collected before its children
+fn collect_host(
+    host: &Path,
+    path: &Path,
+    entries: &mut Vec<HostEntry>,
+) -> Result<()> {
+    let mut children = host_fs::read_dir(host)?
+        .map(|ent| ent.map(|ent| ent.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    children.sort();
+
+    for child in children {
+        let name = child.file_name().unwrap().to_owned();
+        let md = host_fs::symlink_metadata(&child)?;
+        let entry = HostEntry::new(child, path.join(name), &md)?;
+        if entry.is_dir() {
+            let (host, path) = (entry.host.clone(), entry.path.clone());
+            entries.push(entry);
+            collect_host(&host, &path, entries)?;
+        } else {
+            entries.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+// apply host times and permissions to repo entry
+fn apply_attrs(
+    fs: &mut Fs,
+    entry: &HostEntry,
+    opts: &ImportOptions,
+) -> Result<()> {
+    if opts.preserve_permissions {
+        if let Some(mode) = entry.mode {
+            fs.set_permissions(&entry.path, mode)?;
+        }
+    }
+    if opts.preserve_times {
+        fs.set_times(&entry.path, entry.atime, entry.mtime)?;
+    }
+    Ok(())
+}
+
+// import a single host entry to repo
+fn import_entry(
+    fs: &mut Fs,
+    entry: &HostEntry,
+    opts: &ImportOptions,
+    progress: &mut TransferProgress,
+) -> Result<()> {
+    let existing = fs.symlink_metadata(&entry.path).ok();
+
+    match entry.kind {
+        HostKind::Dir => {
+            match existing {
+                Some(md) if !md.is_dir() => return Err(Error::AlreadyExists),
+                Some(_) => {}
+                None => fs.create_dir_all(&entry.path)?,
+            }
+            progress.dirs += 1;
+        }
+        HostKind::File(len) => {
+            let mut truncate = false;
+            if let Some(md) = existing {
+                if !md.is_file() {
+                    return Err(Error::AlreadyExists);
+                }
+                if opts.incremental
+                    && md.content_len() as u64 == len
+                    && md.modified_at() == entry.mtime
+                {
+                    progress.skipped += 1;
+                    return Ok(());
+                }
+                truncate = md.content_len() as u64 > len;
+            }
+
+            let mut src = HostFile::open(&entry.host)?;
+            let mut file = open_file_with_options(
+                fs,
+                &entry.path,
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(truncate),
+            )?;
+            let written = io::copy(&mut src, &mut file)?;
+            if written > 0 {
+                file.finish()?;
+            }
+            apply_attrs(fs, entry, opts)?;
+            progress.files += 1;
+            progress.bytes += written;
+        }
+        HostKind::Symlink(ref target) => {
+            if let Some(md) = existing {
+                if !md.is_symlink() {
+                    return Err(Error::AlreadyExists);
+                }
+                if fs.read_link(&entry.path)? == *target {
+                    progress.skipped += 1;
+                    return Ok(());
+                }
+                fs.remove_file(&entry.path)?;
+            }
+            fs.symlink(target, &entry.path)?;
+            progress.symlinks += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Import a host directory tree to repo
+pub(crate) fn import_dir(
+    fs: &mut Fs,
+    host: &Path,
+    path: &Path,
+    opts: &mut ImportOptions,
+) -> Result<TransferProgress> {
+    let md = host_fs::metadata(host)?;
+    if !md.is_dir() {
+        return Err(Error::NotDir);
+    }
+
+    let mut entries = Vec::new();
+    entries.push(HostEntry::new(host.to_path_buf(), path.to_path_buf(), &md)?);
+    collect_host(host, path, &mut entries)?;
+
+    let mut progress = TransferProgress::default();
+    for batch in entries.chunks(opts.batch_size) {
+        fs.transaction(|fs| {
+            for entry in batch {
+                import_entry(fs, entry, opts, &mut progress)?;
+                progress.path = entry.path.clone();
+                opts.report(&progress);
+            }
+            Ok(())
+        })?;
+    }
+
+    // directory attributes are applied at last, because adding children
+    // changes the directory modification time
+    if opts.preserve_times || opts.preserve_permissions {
+        for batch in entries.rchunks(opts.batch_size) {
+            fs.transaction(|fs| {
+                for entry in batch.iter().rev().filter(|ent| ent.is_dir()) {
+                    apply_attrs(fs, entry, opts)?;
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(progress)
+}
+
+// apply repo times and permissions to host entry
+fn apply_host_attrs(
+    host: &Path,
+    md: &Metadata,
+    opts: &ExportOptions,
+) -> Result<()> {
+    if opts.preserve_times {
+        let times = FileTimes::new()
+            .set_accessed(md.accessed_at())
+            .set_modified(md.modified_at());
+        HostFile::open(host)?.set_times(times)?;
+    }
+
+    #[cfg(unix)]
+    {
+        if opts.preserve_permissions {
+            let perm = host_fs::Permissions::from_mode(md.mode());
+            host_fs::set_permissions(host, perm)?;
+        }
+    }
+
+    Ok(())
+}
+
+// make sure a host path can be written
+fn check_host(host: &Path, opts: &ExportOptions) -> Result<()> {
+    if let Ok(md) = host_fs::symlink_metadata(host) {
+        if !opts.overwrite || md.is_dir() {
+            return Err(Error::AlreadyExists);
+        }
+        if md.file_type().is_symlink() {
+            host_fs::remove_file(host)?;
+        }
+    }
+    Ok(())
+}
+
+// recursively export a repo directory to host
+fn export_tree(
+    fs: &mut Fs,
+    path: &Path,
+    host: &Path,
+    opts: &mut ExportOptions,
+    progress: &mut TransferProgress,
+    dirs: &mut Vec<(PathBuf, Metadata)>,
+) -> Result<()> {
+    for ent in fs.read_dir(path)? {
+        let host = host.join(ent.file_name());
+        let md = fs.symlink_metadata(ent.path())?;
+
+        if md.is_dir() {
+            host_fs::create_dir_all(&host)?;
+            progress.dirs += 1;
+            dirs.push((host.clone(), md));
+            progress.path = host.clone();
+            opts.report(progress);
+            export_tree(fs, ent.path(), &host, opts, progress, dirs)?;
+            continue;
+        }
+
+        if md.is_file() {
+            check_host(&host, opts)?;
+            let mut src =
+                open_file_with_options(fs, ent.path(), &OpenOptions::new())?;
+            let mut dst = HostFile::create(&host)?;
+            progress.bytes += io::copy(&mut src, &mut dst)?;
+            drop(dst);
+            apply_host_attrs(&host, &md, opts)?;
+            progress.files += 1;
+        } else {
+            #[cfg(unix)]
+            {
+                check_host(&host, opts)?;
+                let target = fs.read_link(ent.path())?;
+                std::os::unix::fs::symlink(target, &host)?;
+                progress.symlinks += 1;
+            }
+            #[cfg(not(unix))]
+            {
+                progress.skipped += 1;
+            }
+        }
+
+        progress.path = host;
+        opts.report(progress);
+    }
+
+    Ok(())
+}
+
+/// Export a repo directory tree to host
+pub(crate) fn export_dir(
+    fs: &mut Fs,
+    path: &Path,
+    host: &Path,
+    opts: &mut ExportOptions,
+) -> Result<TransferProgress> {
+    let md = fs.metadata(path)?;
+    if !md.is_dir() {
+        return Err(Error::NotDir);
+    }
+
+    let mut progress = TransferProgress::default();
+    let mut dirs = Vec::new();
+
+    host_fs::create_dir_all(host)?;
+    progress.dirs += 1;
+    dirs.push((host.to_path_buf(), md));
+    progress.path = host.to_path_buf();
+    opts.report(&progress);
+
+    export_tree(fs, path, host, opts, &mut progress, &mut dirs)?;
+
+    // directory attributes are applied at last, because adding children
+    // changes the directory modification time
+    for (host, md) in dirs.iter().rev() {
+        apply_host_attrs(host, md, opts)?;
+    }
+
+    Ok(progress)
+}
+
+// convert an archive member path to relative path, root and current
+// directory components are stripped and parent directory is not allowed
+#[cfg(feature = "tar-archive")]
+fn member_path(path: &Path) -> Result<PathBuf> {
+    let mut rel = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::Normal(name) => rel.push(name),
+            Component::ParentDir => return Err(Error::InvalidPath),
+            _ => {}
+        }
+    }
+    Ok(rel)
+}
+
+// make sure a directory exists in repo
+#[cfg(feature = "tar-archive")]
+fn ensure_dir(fs: &mut Fs, path: &Path) -> Result<()> {
+    match fs.metadata(path) {
+        Ok(md) if md.is_dir() => Ok(()),
+        Ok(_) => Err(Error::AlreadyExists),
+        Err(ref err) if *err == Error::NotFound => fs.create_dir_all(path),
+        Err(err) => Err(err),
+    }
+}
+
+// make sure a non-directory entry can be created at path, the existing
+// symbolic link is removed and the existing regular file is kept only if
+// `keep_file` is true, returns whether the regular file is kept
+#[cfg(feature = "tar-archive")]
+fn prepare_entry(fs: &mut Fs, path: &Path, keep_file: bool) -> Result<bool> {
+    if let Some(parent) = path.parent() {
+        ensure_dir(fs, parent)?;
+    }
+    match fs.symlink_metadata(path) {
+        Ok(md) if md.is_dir() => Err(Error::AlreadyExists),
+        Ok(md) if md.is_file() && keep_file => Ok(true),
+        Ok(_) => fs.remove_file(path).map(|_| false),
+        Err(ref err) if *err == Error::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+// apply archive member mode and modification time to repo entry
+#[cfg(feature = "tar-archive")]
+fn apply_member_attrs(
+    fs: &mut Fs,
+    path: &Path,
+    mode: Option<u32>,
+    mtime: Option<SystemTime>,
+) -> Result<()> {
+    if let Some(mode) = mode {
+        fs.set_permissions(path, mode)?;
+    }
+    if let Some(mtime) = mtime {
+        fs.set_times(path, mtime, mtime)?;
+    }
+    Ok(())
+}
+
+// import a single tar archive member to repo
+#[cfg(feature = "tar-archive")]
+fn import_member<R: Read>(
+    fs: &mut Fs,
+    entry: &mut TarEntry<R>,
+    path: &Path,
+    dest: &Path,
+    progress: &mut TransferProgress,
+) -> Result<()> {
+    let header = entry.header();
+    let mode = header.mode().ok().map(|mode| mode & 0o7777);
+    let mtime = header
+        .mtime()
+        .ok()
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+
+    match header.entry_type() {
+        EntryType::Regular | EntryType::Continuous => {
+            let exists = prepare_entry(fs, path, true)?;
+            let mut file = open_file_with_options(
+                fs,
+                path,
+                OpenOptions::new().write(true).create(true).truncate(exists),
+            )?;
+            let written = io::copy(entry, &mut file)?;
+            if written > 0 {
+                file.finish()?;
+            }
+            apply_member_attrs(fs, path, mode, mtime)?;
+            progress.files += 1;
+            progress.bytes += written;
+        }
+        EntryType::Symlink => {
+            let target = entry.link_name()?.ok_or(Error::InvalidArgument)?;
+            prepare_entry(fs, path, false)?;
+            fs.symlink(&target, path)?;
+            progress.symlinks += 1;
+        }
+        EntryType::Link => {
+            let target = entry.link_name()?.ok_or(Error::InvalidArgument)?;
+            let target = dest.join(member_path(&target)?);
+            prepare_entry(fs, path, false)?;
+            fs.hard_link(&target, path)?;
+            progress.files += 1;
+        }
+        _ => progress.skipped += 1,
+    }
+
+    Ok(())
+}
+
+/// Import a tar archive stream to repo
+#[cfg(feature = "tar-archive")]
+pub(crate) fn import_tar<R: Read>(
+    fs: &mut Fs,
+    reader: R,
+    dest: &Path,
+) -> Result<TransferProgress> {
+    let mut archive = Archive::new(reader);
+    let mut progress = TransferProgress::default();
+    let mut dirs = Vec::new();
+
+    ensure_dir(fs, dest)?;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = dest.join(member_path(&entry.path()?)?);
+
+        // directory attributes are applied at last, because adding children
+        // changes the directory modification time
+        if entry.header().entry_type() == EntryType::Directory {
+            ensure_dir(fs, &path)?;
+            let header = entry.header();
+            let mode = header.mode().ok().map(|mode| mode & 0o7777);
+            let mtime = header.mtime().ok();
+            dirs.push((path.clone(), mode, mtime));
+            progress.dirs += 1;
+        } else {
+            fs.transaction(|fs| {
+                import_member(fs, &mut entry, &path, dest, &mut progress)
+            })?;
+        }
+        progress.path = path;
+    }
+
+    fs.transaction(|fs| {
+        for (path, mode, mtime) in dirs.iter().rev() {
+            let mtime =
+                mtime.map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            apply_member_attrs(fs, path, *mode, mtime)?;
+        }
+        Ok(())
+    })?;
+
+    Ok(progress)
+}
+
+// create a tar header from repo entry metadata
+#[cfg(feature = "tar-archive")]
+fn member_header(md: &Metadata, entry_type: EntryType) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_mode(md.mode());
+    header.set_uid(u64::from(md.uid()));
+    header.set_gid(u64::from(md.gid()));
+    header.set_mtime(
+        md.modified_at()
+            .duration_since(UNIX_EPOCH)
+            .map(|dur| dur.as_secs())
+            .unwrap_or(0),
+    );
+    header.set_size(0);
+    header
+}
+
+// recursively append a repo directory to tar archive, files with hard links
+// are appended once and their archive paths are kept in `links`
+#[cfg(feature = "tar-archive")]
+fn export_member<W: Write>(
+    fs: &mut Fs,
+    path: &Path,
+    rel: &Path,
+    builder: &mut Builder<W>,
+    progress: &mut TransferProgress,
+    links: &mut HashMap<Eid, PathBuf>,
+) -> Result<()> {
+    for ent in fs.read_dir(path)? {
+        let rel = rel.join(ent.file_name());
+        let md = fs.symlink_metadata(ent.path())?;
+
+        if md.is_dir() {
+            let mut header = member_header(&md, EntryType::Directory);
+            builder.append_data(&mut header, &rel, io::empty())?;
+            progress.dirs += 1;
+            export_member(fs, ent.path(), &rel, builder, progress, links)?;
+        } else if md.is_symlink() {
+            let target = fs.read_link(ent.path())?;
+            let mut header = member_header(&md, EntryType::Symlink);
+            builder.append_link(&mut header, &rel, &target)?;
+            progress.symlinks += 1;
+        } else {
+            if md.link_count() > 1 {
+                let fnode_ref = fs.resolve_with(ent.path(), false)?;
+                let id = fnode_ref.read().unwrap().id().clone();
+                if let Some(target) = links.get(&id) {
+                    let mut header = member_header(&md, EntryType::Link);
+                    builder.append_link(&mut header, &rel, target)?;
+                    progress.files += 1;
+                    progress.path = ent.path().to_path_buf();
+                    continue;
+                }
+                links.insert(id, rel.clone());
+            }
+
+            let file =
+                open_file_with_options(fs, ent.path(), &OpenOptions::new())?;
+            let mut header = member_header(&md, EntryType::Regular);
+            header.set_size(md.content_len() as u64);
+            builder.append_data(&mut header, &rel, file)?;
+            progress.files += 1;
+            progress.bytes += md.content_len() as u64;
+        }
+        progress.path = ent.path().to_path_buf();
+    }
+    Ok(())
+}
+
+/// Export a repo directory to a tar archive stream
+#[cfg(feature = "tar-archive")]
+pub(crate) fn export_tar<W: Write>(
+    fs: &mut Fs,
+    src: &Path,
+    writer: W,
+) -> Result<TransferProgress> {
+    let md = fs.metadata(src)?;
+    if !md.is_dir() {
+        return Err(Error::NotDir);
+    }
+
+    let mut builder = Builder::new(writer);
+    let mut progress = TransferProgress::default();
+    let mut links = HashMap::new();
+    export_member(
+        fs,
+        src,
+        Path::new(""),
+        &mut builder,
+        &mut progress,
+        &mut links,
+    )?;
+    builder.into_inner()?.flush()?;
+    Ok(progress)
+}
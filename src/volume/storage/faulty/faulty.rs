@@ -97,6 +97,12 @@ impl Storable for FaultyStorage {
         self.inner.del_address(id)
     }
 
+    #[inline]
+    fn list_addresses(&mut self) -> Result<Vec<Eid>> {
+        self.ctlr.make_random_error()?;
+        self.inner.list_addresses()
+    }
+
     #[inline]
     fn get_blocks(&mut self, dst: &mut [u8], span: Span) -> Result<()> {
         self.ctlr.make_random_error()?;
@@ -13,11 +13,12 @@ use crate::error::{Error, Result};
 use crate::trans::Eid;
 use crate::volume::address::Span;
 use crate::volume::storage::index_mgr::{IndexMgr, Lsmt, MemTab, Tab};
+use crate::volume::storage::lock::Holder;
 use crate::volume::storage::Storable;
 
 /// File Storage
 pub struct FileStorage {
-    is_attached: bool, // attached to underlying os file system
+    lock_file: Option<vio::File>, // repo lock file held by this storage
     base: PathBuf,
     wal_base: PathBuf,
     idx_mgr: IndexMgr,
@@ -49,7 +50,7 @@ impl FileStorage {
         );
 
         FileStorage {
-            is_attached: false,
+            lock_file: None,
             base: base.to_path_buf(),
             wal_base: base.join(Self::WAL_DIR),
             idx_mgr,
@@ -92,20 +93,96 @@ impl FileStorage {
         self.sec_mgr.set_crypto_ctx(crypto, key, hash_key);
     }
 
+    // try to take OS advisory lock on the lock file, return None if it is
+    // locked by others
+    //
+    // The lock is released by OS when the process exits, so a lock file left
+    // by a crashed process doesn't lock the repo.
+    #[cfg(unix)]
+    fn try_lock(path: &Path) -> Result<Option<vio::File>> {
+        use std::io::Error as IoError;
+        use std::os::unix::fs::MetadataExt;
+        use std::os::unix::io::AsRawFd;
+
+        loop {
+            // don't truncate lock file before it is locked, it might be
+            // held by others
+            let file = vio::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            let result = unsafe {
+                libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB)
+            };
+            if result != 0 {
+                let err = IoError::last_os_error();
+                if err.kind() == ErrorKind::WouldBlock {
+                    return Ok(None);
+                }
+                return Err(Error::from(err));
+            }
+
+            // the lock file might be removed by its previous holder before
+            // we locked it, in that case try again with the new one
+            let locked = file.metadata()?;
+            match vio::metadata(path) {
+                Ok(md)
+                    if md.dev() == locked.dev() && md.ino() == locked.ino() =>
+                {
+                    return Ok(Some(file));
+                }
+                Ok(_) => {}
+                Err(ref err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(Error::from(err)),
+            }
+        }
+    }
+
+    // without OS advisory lock, the existence of lock file locks the repo
+    #[cfg(not(unix))]
+    fn try_lock(path: &Path) -> Result<Option<vio::File>> {
+        match vio::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)
+        {
+            Ok(file) => Ok(Some(file)),
+            Err(ref err) if err.kind() == ErrorKind::AlreadyExists => Ok(None),
+            Err(err) => Err(Error::from(err)),
+        }
+    }
+
+    // read lock holder description from the lock file
+    fn read_holder(path: &Path) -> String {
+        let mut lock = String::new();
+        let _ = vio::File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut lock));
+        Holder::describe(&lock)
+    }
+
     fn lock_repo(&mut self, force: bool) -> Result<()> {
         let lock_path = self.lock_path();
-        if lock_path.exists() {
-            if force {
-                warn!("Repo was locked, forced to open");
-            } else {
-                return Err(Error::RepoOpened);
+        match Self::try_lock(&lock_path)? {
+            Some(mut file) => {
+                // record lock holder in the lock file
+                let holder = Holder::current();
+                file.set_len(0)?;
+                file.write_all(holder.encode().as_bytes())
+                    .and_then(|_| file.flush())?;
+                self.lock_file = Some(file);
+            }
+            None => {
+                let holder = Self::read_holder(&lock_path);
+                if !force {
+                    warn!("Repo is locked by {}", holder);
+                    return Err(Error::RepoOpened);
+                }
+                warn!("Repo was locked by {}, forced to open", holder);
             }
         }
-        let _ = vio::OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&lock_path)?;
-        self.is_attached = true;
         Ok(())
     }
 }
@@ -215,6 +292,11 @@ impl Storable for FileStorage {
         self.idx_mgr.delete(id)
     }
 
+    #[inline]
+    fn list_addresses(&mut self) -> Result<Vec<Eid>> {
+        self.idx_mgr.ids()
+    }
+
     #[inline]
     fn get_blocks(&mut self, dst: &mut [u8], span: Span) -> Result<()> {
         self.sec_mgr.read_blocks(dst, span)
@@ -247,10 +329,11 @@ impl Storable for FileStorage {
 
 impl Drop for FileStorage {
     fn drop(&mut self) {
-        if self.is_attached {
-            // remove repo lock file and ignore errors
+        if let Some(file) = self.lock_file.take() {
+            // remove repo lock file before releasing the lock and ignore
+            // errors
             let _ = vio::remove_file(self.lock_path());
-            self.is_attached = false;
+            drop(file);
         }
     }
 }
@@ -462,6 +545,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn repo_lock() {
+        let (dir, _tmpdir) = setup();
+        let (crypto, key) = (Crypto::default(), Key::new_empty());
+        let mut fs = FileStorage::new(&dir);
+        fs.init(crypto.clone(), key.clone()).unwrap();
+
+        // repo is locked by the first storage
+        let mut fs2 = FileStorage::new(&dir);
+        assert_eq!(
+            fs2.open(crypto.clone(), key.clone(), false).unwrap_err(),
+            Error::RepoOpened
+        );
+        let lock_path = dir.join(FileStorage::REPO_LOCK_FILE_NAME);
+        let holder = FileStorage::read_holder(&lock_path);
+        assert!(holder.contains(&std::process::id().to_string()));
+        drop(fs2);
+        drop(fs);
+        assert!(!lock_path.exists());
+
+        // lock file left by a crashed process doesn't lock the repo
+        #[cfg(unix)]
+        {
+            fs::write(&lock_path, "1@host#1").unwrap();
+            let mut fs = FileStorage::new(&dir);
+            fs.open(crypto.clone(), key.clone(), false).unwrap();
+        }
+    }
+
     #[test]
     fn index_manager() {
         let (dir, _tmpdir) = setup();
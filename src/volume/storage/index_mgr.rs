@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fmt::{self, Debug};
 use std::iter::FromIterator;
 use std::ops::Deref;
@@ -405,6 +406,29 @@ impl Lsmt {
         Err(Error::NotFound)
     }
 
+    // collect ids of all addresses, ids in `seen` are newer and skipped
+    fn collect_ids(
+        &self,
+        seen: &mut HashSet<Eid>,
+        ids: &mut Vec<Eid>,
+        tab_armor: &TabArmor,
+    ) -> Result<()> {
+        for lvl in self.lvls.iter() {
+            // level 0 tabs may be overlapping, the newer one wins
+            for tab_info in lvl.tabs.iter().rev() {
+                let tab = tab_armor.load(&tab_info.id)?;
+                for item in tab.iter() {
+                    // empty address is deletion mark
+                    if seen.insert(item.id().clone()) && !item.addr().is_empty()
+                    {
+                        ids.push(item.id().clone());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     // read all tabs in specified level
     fn read_all_tabs_in_level(
         &self,
@@ -785,6 +809,20 @@ impl IndexMgr {
         self.insert(id, &[])
     }
 
+    /// Get ids of all addresses
+    pub fn ids(&self) -> Result<Vec<Eid>> {
+        let mut seen = HashSet::new();
+        let mut ids = Vec::new();
+        for (id, addr) in self.memtab.map.iter() {
+            if seen.insert(id.clone()) && !addr.is_empty() {
+                ids.push(id.clone());
+            }
+        }
+        self.lsmt
+            .collect_ids(&mut seen, &mut ids, &self.tab_armor)?;
+        Ok(ids)
+    }
+
     pub fn flush(&mut self) -> Result<()> {
         if self.memtab.is_changed {
             self.memtab_armor.save(&mut self.memtab)?;
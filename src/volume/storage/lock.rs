@@ -0,0 +1,226 @@
+//! Repo lock helpers shared by storages.
+//!
+//! A repo lock records its holder, which is logged when opening the repo
+//! fails because of the lock. Storages which cannot rely on OS locks use
+//! expiring leases, which are renewed by a [`Heartbeat`] while the repo is
+//! opened and expire by themselves if the holder crashed.
+//!
+//! [`Heartbeat`]: struct.Heartbeat.html
+
+use std::fmt::{self, Display};
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+use std::time::Duration;
+#[cfg(feature = "storage-sqlite")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+use std::sync::Arc;
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+use std::thread::{self, JoinHandle};
+
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+use log::warn;
+
+use crate::base::crypto::Crypto;
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+use crate::error::{Error, Result};
+
+/// Lease time-to-live, a lease expires if it is not renewed in this time
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+pub const LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// Lease renewal interval
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+pub const LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+// get host name of this machine
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let result = unsafe {
+        libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len())
+    };
+    if result != 0 {
+        return "localhost".to_string();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "localhost".to_string())
+}
+
+/// Get current time as milliseconds since unix epoch
+#[cfg(feature = "storage-sqlite")]
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Repo lock holder
+///
+/// The random token distinguishes repo instances opened in the same
+/// process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holder {
+    pid: u32,
+    host: String,
+    token: u64,
+}
+
+impl Holder {
+    /// Create a holder for current process
+    pub fn current() -> Self {
+        let mut token = [0u8; 8];
+        Crypto::random_buf(&mut token);
+        Holder {
+            pid: std::process::id(),
+            host: hostname(),
+            token: u64::from_le_bytes(token),
+        }
+    }
+
+    /// Parse holder from string created by `encode`
+    pub fn parse(s: &str) -> Option<Self> {
+        let (pid, rest) = s.split_once('@')?;
+        let (host, token) = rest.rsplit_once('#')?;
+        Some(Holder {
+            pid: pid.parse().ok()?,
+            host: host.to_string(),
+            token: token.parse().ok()?,
+        })
+    }
+
+    /// Encode holder to string, which is saved in the lock
+    pub fn encode(&self) -> String {
+        format!("{}@{}#{}", self.pid, self.host, self.token)
+    }
+
+    /// Describe the holder saved in a lock, used in log message
+    pub fn describe(lock: &str) -> String {
+        match Holder::parse(lock) {
+            Some(holder) => holder.to_string(),
+            None => "unknown holder".to_string(),
+        }
+    }
+}
+
+impl Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "process {} on host {}", self.pid, self.host)
+    }
+}
+
+/// Lease heartbeat
+///
+/// Heartbeat renews a lease periodically in a background thread until it
+/// is dropped. The renew function returns false if the lease is lost, for
+/// example it was expired and then taken by others. After that the storage
+/// must not write anymore, see `check`.
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+pub struct Heartbeat {
+    lost: Arc<AtomicBool>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+impl Heartbeat {
+    #[inline]
+    pub fn start<F>(renew: F) -> Self
+    where
+        F: FnMut() -> Result<bool> + Send + 'static,
+    {
+        Self::with_interval(LEASE_RENEW_INTERVAL, renew)
+    }
+
+    pub fn with_interval<F>(interval: Duration, mut renew: F) -> Self
+    where
+        F: FnMut() -> Result<bool> + Send + 'static,
+    {
+        let lost = Arc::new(AtomicBool::new(false));
+        let (stop, rx) = channel::<()>();
+        let thread_lost = lost.clone();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(interval)
+            {
+                match renew() {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        warn!("Repo lease is lost, repo cannot be written");
+                        thread_lost.store(true, Ordering::SeqCst);
+                        break;
+                    }
+                    Err(err) => warn!("Renew repo lease failed: {}", err),
+                }
+            }
+        });
+        Heartbeat {
+            lost,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Check if the lease is still held, return `Error::RepoOpened` if it
+    /// is lost to others
+    #[inline]
+    pub fn check(&self) -> Result<()> {
+        if self.lost.load(Ordering::SeqCst) {
+            Err(Error::RepoOpened)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        // dropping the sender wakes up and stops the heartbeat thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::init_env;
+
+    #[test]
+    fn holder_encode() {
+        init_env();
+        let holder = Holder::current();
+        let lock = holder.encode();
+        assert_eq!(Holder::parse(&lock), Some(holder.clone()));
+        assert_eq!(Holder::describe(&lock), holder.to_string());
+        assert_eq!(Holder::describe(""), "unknown holder");
+    }
+
+    #[cfg(any(feature = "storage-sqlite", feature = "storage-redis"))]
+    #[test]
+    fn heartbeat_lost() {
+        let interval = Duration::from_millis(10);
+
+        let hb = Heartbeat::with_interval(interval, || Ok(true));
+        thread::sleep(interval * 5);
+        assert!(hb.check().is_ok());
+
+        let hb = Heartbeat::with_interval(interval, || Ok(false));
+        thread::sleep(interval * 5);
+        assert_eq!(hb.check().unwrap_err(), Error::RepoOpened);
+    }
+}
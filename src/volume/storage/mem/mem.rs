@@ -156,6 +156,12 @@ impl Storable for MemStorage {
         Ok(())
     }
 
+    fn list_addresses(&mut self) -> Result<Vec<Eid>> {
+        let storages = STORAGES.lock().unwrap();
+        let depot = storages.get(&self.loc).unwrap();
+        Ok(depot.addr_map.keys().cloned().collect())
+    }
+
     fn get_blocks(&mut self, dst: &mut [u8], span: Span) -> Result<()> {
         assert_eq!(dst.len(), span.bytes_len());
         let storages = STORAGES.lock().unwrap();
@@ -33,6 +33,13 @@ mod zbox;
 #[cfg(any(feature = "storage-file", feature = "storage-zbox"))]
 mod index_mgr;
 
+#[cfg(any(
+    feature = "storage-file",
+    feature = "storage-sqlite",
+    feature = "storage-redis"
+))]
+mod lock;
+
 use std::fmt::Debug;
 
 use crate::base::crypto::{Crypto, Key};
@@ -74,6 +81,9 @@ pub trait Storable: Debug + Send + Sync {
     fn put_address(&mut self, id: &Eid, addr: &[u8]) -> Result<()>;
     fn del_address(&mut self, id: &Eid) -> Result<()>;
 
+    // get ids of all addresses, used to find unreachable entities
+    fn list_addresses(&mut self) -> Result<Vec<Eid>>;
+
     // block read/write, can be buffered
     // storage doesn't need to gurantee update is persistent
     fn get_blocks(&mut self, dst: &mut [u8], span: Span) -> Result<()>;
@@ -157,6 +167,11 @@ impl Storable for DummyStorage {
         unimplemented!()
     }
 
+    #[inline]
+    fn list_addresses(&mut self) -> Result<Vec<Eid>> {
+        unimplemented!()
+    }
+
     #[inline]
     fn get_blocks(&mut self, _dst: &mut [u8], _span: Span) -> Result<()> {
         unimplemented!()
@@ -2,13 +2,14 @@ use std::fmt::{self, Debug};
 use std::sync::Mutex;
 
 use log::warn;
-use redis::{self, Client, Commands, Connection};
+use redis::{self, Client, Commands, Connection, Script};
 
 use crate::base::crypto::{Crypto, Key};
 use crate::base::IntoRef;
 use crate::error::{Error, Result};
 use crate::trans::Eid;
 use crate::volume::address::Span;
+use crate::volume::storage::lock::{Heartbeat, Holder, LEASE_TTL};
 use crate::volume::storage::Storable;
 use crate::volume::BLK_SIZE;
 
@@ -18,6 +19,22 @@ fn repo_lock_key() -> String {
     "repo_lock:".to_string()
 }
 
+// lua script to extend repo lease, only if it is held by the holder
+const RENEW_LEASE_SCRIPT: &str = r"
+    if redis.call('get', KEYS[1]) == ARGV[1] then
+        return redis.call('pexpire', KEYS[1], ARGV[2])
+    end
+    return 0
+";
+
+// lua script to release repo lease, only if it is held by the holder
+const RELEASE_LEASE_SCRIPT: &str = r"
+    if redis.call('get', KEYS[1]) == ARGV[1] then
+        return redis.call('del', KEYS[1])
+    end
+    return 0
+";
+
 // redis key for super block
 #[inline]
 fn super_blk_key(suffix: u64) -> String {
@@ -30,10 +47,19 @@ fn wal_key(id: &Eid) -> String {
     format!("wal:{}", id.to_string())
 }
 
+// redis key prefix for address
+const ADDR_KEY_PREFIX: &str = "address:";
+
 // redis key for address
 #[inline]
 fn addr_key(id: &Eid) -> String {
-    format!("address:{}", id.to_string())
+    format!("{}{}", ADDR_KEY_PREFIX, id.to_string())
+}
+
+// redis key pattern matching all addresses
+#[inline]
+fn addr_key_pattern() -> String {
+    format!("{}*", ADDR_KEY_PREFIX)
 }
 
 // redis key for block
@@ -44,7 +70,8 @@ fn blk_key(blk_idx: usize) -> String {
 
 /// Redis Storage
 pub struct RedisStorage {
-    is_attached: bool, // attached to redis
+    lease: Option<String>, // repo lease holder, if the lease is held by us
+    heartbeat: Option<Heartbeat>,
     client: Client,
     conn: Option<Mutex<Connection>>,
 }
@@ -62,7 +89,8 @@ impl RedisStorage {
         let client = Client::open(url.as_str())?;
 
         Ok(RedisStorage {
-            is_attached: false,
+            lease: None,
+            heartbeat: None,
             client,
             conn: None,
         })
@@ -86,7 +114,7 @@ impl RedisStorage {
         match self.conn {
             Some(ref conn) => {
                 let mut conn = conn.lock().unwrap();
-                conn.set(key, val)?;
+                conn.set::<_, _, ()>(key, val)?;
                 Ok(())
             }
             None => unreachable!(),
@@ -97,7 +125,7 @@ impl RedisStorage {
         match self.conn {
             Some(ref conn) => {
                 let mut conn = conn.lock().unwrap();
-                conn.del(key)?;
+                conn.del::<_, ()>(key)?;
                 Ok(())
             }
             None => unreachable!(),
@@ -106,22 +134,73 @@ impl RedisStorage {
 
     fn lock_repo(&mut self, force: bool) -> Result<()> {
         let key = repo_lock_key();
-        match self.get_bytes(&key) {
-            Ok(_) => {
-                // repo is locked
-                if force {
-                    warn!("Repo was locked, forced to open");
-                } else {
-                    return Err(Error::RepoOpened);
+        let holder = Holder::current().encode();
+        let ttl = LEASE_TTL.as_millis() as u64;
+
+        match self.conn {
+            Some(ref conn) => {
+                let mut conn = conn.lock().unwrap();
+
+                // take repo lease if no one holds it
+                let taken: Option<String> = redis::cmd("SET")
+                    .arg(&key)
+                    .arg(&holder)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(ttl)
+                    .query(&mut *conn)?;
+                if taken.is_none() {
+                    // repo is locked, but a lock without expiration is
+                    // left by older versions and is taken over
+                    let lease: String = conn.get(&key).unwrap_or_default();
+                    let lease_ttl: i64 = conn.pttl(&key)?;
+                    if lease_ttl >= 0 {
+                        let lease_holder = Holder::describe(&lease);
+                        if !force {
+                            warn!("Repo is locked by {}", lease_holder);
+                            return Err(Error::RepoOpened);
+                        }
+                        warn!(
+                            "Repo was locked by {}, forced to open",
+                            lease_holder
+                        );
+                    } else if lease_ttl == -1 {
+                        warn!("Repo lock without expiration is taken over");
+                    }
+                    redis::cmd("SET")
+                        .arg(&key)
+                        .arg(&holder)
+                        .arg("PX")
+                        .arg(ttl)
+                        .query::<()>(&mut *conn)?;
                 }
             }
-            Err(ref err) if *err == Error::NotFound => {}
-            Err(err) => return Err(err),
+            None => unreachable!(),
         }
-        self.set_bytes(&key, &Vec::new())?;
-        self.is_attached = true;
+
+        // lease expires if it is not renewed, for example the process
+        // crashed, so keep renewing it while the repo is opened
+        let mut conn = self.client.get_connection()?;
+        let script = Script::new(RENEW_LEASE_SCRIPT);
+        let lease = holder.clone();
+        self.heartbeat = Some(Heartbeat::start(move || {
+            let renewed: i32 =
+                script.key(&key).arg(&lease).arg(ttl).invoke(&mut conn)?;
+            Ok(renewed == 1)
+        }));
+        self.lease = Some(holder);
         Ok(())
     }
+
+    // writes must fail once the lease is lost, as the repo might be
+    // opened by others
+    #[inline]
+    fn check_lease(&self) -> Result<()> {
+        match self.heartbeat {
+            Some(ref heartbeat) => heartbeat.check(),
+            None => Ok(()),
+        }
+    }
 }
 
 impl Storable for RedisStorage {
@@ -154,8 +233,8 @@ impl Storable for RedisStorage {
         self.get_bytes(&key)
     }
 
-    #[inline]
     fn put_super_block(&mut self, super_blk: &[u8], suffix: u64) -> Result<()> {
+        self.check_lease()?;
         let key = super_blk_key(suffix);
         self.set_bytes(&key, super_blk)
     }
@@ -166,14 +245,14 @@ impl Storable for RedisStorage {
         self.get_bytes(&key)
     }
 
-    #[inline]
     fn put_wal(&mut self, id: &Eid, wal: &[u8]) -> Result<()> {
+        self.check_lease()?;
         let key = wal_key(id);
         self.set_bytes(&key, wal)
     }
 
-    #[inline]
     fn del_wal(&mut self, id: &Eid) -> Result<()> {
+        self.check_lease()?;
         let key = wal_key(id);
         self.del(&key)
     }
@@ -184,18 +263,35 @@ impl Storable for RedisStorage {
         self.get_bytes(&key)
     }
 
-    #[inline]
     fn put_address(&mut self, id: &Eid, addr: &[u8]) -> Result<()> {
+        self.check_lease()?;
         let key = addr_key(id);
         self.set_bytes(&key, addr)
     }
 
-    #[inline]
     fn del_address(&mut self, id: &Eid) -> Result<()> {
+        self.check_lease()?;
         let key = addr_key(id);
         self.del(&key)
     }
 
+    fn list_addresses(&mut self) -> Result<Vec<Eid>> {
+        match self.conn {
+            Some(ref conn) => {
+                let mut conn = conn.lock().unwrap();
+                let keys: Vec<String> = conn.keys(addr_key_pattern())?;
+                Ok(keys
+                    .iter()
+                    .filter_map(|key| {
+                        key.strip_prefix(ADDR_KEY_PREFIX)
+                            .and_then(Eid::from_hex)
+                    })
+                    .collect())
+            }
+            None => unreachable!(),
+        }
+    }
+
     fn get_blocks(&mut self, dst: &mut [u8], span: Span) -> Result<()> {
         let mut read = 0;
         for blk_idx in span {
@@ -210,6 +306,7 @@ impl Storable for RedisStorage {
     }
 
     fn put_blocks(&mut self, span: Span, mut blks: &[u8]) -> Result<()> {
+        self.check_lease()?;
         for blk_idx in span {
             let key = blk_key(blk_idx);
             self.set_bytes(&key, &blks[..BLK_SIZE])?;
@@ -220,6 +317,7 @@ impl Storable for RedisStorage {
     }
 
     fn del_blocks(&mut self, span: Span) -> Result<()> {
+        self.check_lease()?;
         for blk_idx in span {
             let key = blk_key(blk_idx);
             self.del(&key)?;
@@ -254,11 +352,16 @@ impl Storable for RedisStorage {
 
 impl Drop for RedisStorage {
     fn drop(&mut self) {
-        if self.is_attached {
-            // remove repo lock and ignore errors
-            let key = repo_lock_key();
-            let _ = self.del(&key);
-            self.is_attached = false;
+        // stop lease heartbeat, then release repo lease and ignore errors
+        self.heartbeat.take();
+        if let Some(holder) = self.lease.take() {
+            if let Some(ref conn) = self.conn {
+                let mut conn = conn.lock().unwrap();
+                let _ = Script::new(RELEASE_LEASE_SCRIPT)
+                    .key(repo_lock_key())
+                    .arg(holder)
+                    .invoke::<i32>(&mut *conn);
+            }
         }
     }
 }
@@ -1,6 +1,6 @@
 use std::ffi::{CStr, CString};
 use std::fmt::{self, Debug};
-use std::os::raw::{c_int, c_void};
+use std::os::raw::{c_char, c_int, c_void};
 use std::ptr;
 use std::thread::panicking;
 
@@ -12,6 +12,7 @@ use crate::base::vio;
 use crate::error::{Error, Result};
 use crate::trans::Eid;
 use crate::volume::address::Span;
+use crate::volume::storage::lock::{now_millis, Heartbeat, Holder, LEASE_TTL};
 use crate::volume::storage::Storable;
 use crate::volume::BLK_SIZE;
 
@@ -33,6 +34,21 @@ fn reset_stmt(stmt: *mut ffi::sqlite3_stmt) -> Result<()> {
     Ok(())
 }
 
+// run sql statements without result, such as DDL and transaction control
+fn exec(db: *mut ffi::sqlite3, sql: &str) -> Result<()> {
+    let sql = CString::new(sql).unwrap();
+    let result = unsafe {
+        ffi::sqlite3_exec(
+            db,
+            sql.as_ptr(),
+            None,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    check_result(result)
+}
+
 // bind integer parameter
 fn bind_int(
     stmt: *mut ffi::sqlite3_stmt,
@@ -43,6 +59,36 @@ fn bind_int(
     check_result(result)
 }
 
+// bind 64-bit integer parameter
+fn bind_int64(
+    stmt: *mut ffi::sqlite3_stmt,
+    col_idx: c_int,
+    n: u64,
+) -> Result<()> {
+    let result = unsafe {
+        ffi::sqlite3_bind_int64(stmt, col_idx, n as ffi::sqlite3_int64)
+    };
+    check_result(result)
+}
+
+// bind text parameter
+fn bind_text(
+    stmt: *mut ffi::sqlite3_stmt,
+    col_idx: c_int,
+    text: &CStr,
+) -> Result<()> {
+    let result = unsafe {
+        ffi::sqlite3_bind_text(
+            stmt,
+            col_idx,
+            text.as_ptr(),
+            -1,
+            ffi::SQLITE_STATIC(),
+        )
+    };
+    check_result(result)
+}
+
 // bind EID parameter
 fn bind_id(
     stmt: *mut ffi::sqlite3_stmt,
@@ -117,9 +163,92 @@ fn run_select_blob(stmt: *mut ffi::sqlite3_stmt) -> Result<Vec<u8>> {
     }
 }
 
+// busy timeout in milliseconds, used when the db is locked by lease
+// heartbeat or other connections
+const BUSY_TIMEOUT: c_int = 5000;
+
+// sql to renew repo lease
+const RENEW_LEASE_SQL: &str =
+    "UPDATE repo_lease SET expire_at = ? WHERE id = 1 AND holder = ?";
+
+// open a db connection
+fn open_db(file_path: &CStr) -> Result<*mut ffi::sqlite3> {
+    let mut db = ptr::null_mut();
+    let result = unsafe {
+        ffi::sqlite3_open_v2(
+            file_path.as_ptr(),
+            &mut db,
+            ffi::SQLITE_OPEN_READWRITE
+                | ffi::SQLITE_OPEN_CREATE
+                | ffi::SQLITE_OPEN_FULLMUTEX,
+            ptr::null(),
+        )
+    };
+    if result != ffi::SQLITE_OK {
+        let err = ffi::Error::new(result);
+        if !db.is_null() {
+            unsafe { ffi::sqlite3_close(db) };
+        }
+        return Err(Error::from(err));
+    }
+    unsafe { ffi::sqlite3_busy_timeout(db, BUSY_TIMEOUT) };
+    Ok(db)
+}
+
+// connection used by lease heartbeat to renew repo lease
+struct LeaseConn {
+    holder: CString,
+    db: *mut ffi::sqlite3,
+    stmt: *mut ffi::sqlite3_stmt,
+}
+
+impl LeaseConn {
+    fn open(file_path: &CStr, holder: CString) -> Result<Self> {
+        let db = open_db(file_path)?;
+        let mut conn = LeaseConn {
+            holder,
+            db,
+            stmt: ptr::null_mut(),
+        };
+        let sql = CString::new(RENEW_LEASE_SQL).unwrap();
+        let result = unsafe {
+            ffi::sqlite3_prepare_v2(
+                conn.db,
+                sql.as_ptr(),
+                -1,
+                &mut conn.stmt,
+                ptr::null_mut(),
+            )
+        };
+        check_result(result)?;
+        Ok(conn)
+    }
+
+    // extend lease expiration, return false if the lease is not held by us
+    fn renew(&mut self) -> Result<bool> {
+        reset_stmt(self.stmt)?;
+        bind_int64(self.stmt, 1, now_millis() + LEASE_TTL.as_millis() as u64)?;
+        bind_text(self.stmt, 2, &self.holder)?;
+        run_dml(self.stmt)?;
+        Ok(unsafe { ffi::sqlite3_changes(self.db) } > 0)
+    }
+}
+
+impl Drop for LeaseConn {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::sqlite3_finalize(self.stmt);
+            ffi::sqlite3_close(self.db);
+        }
+    }
+}
+
+unsafe impl Send for LeaseConn {}
+
 /// Sqlite Storage
 pub struct SqliteStorage {
-    is_attached: bool,  // attached to sqlite db
+    lease: Option<CString>, // repo lease holder, if the lease is held by us
+    heartbeat: Option<Heartbeat>,
     file_path: CString, // database file path
     db: *mut ffi::sqlite3,
     stmts: Vec<*mut ffi::sqlite3_stmt>,
@@ -127,7 +256,7 @@ pub struct SqliteStorage {
 
 impl SqliteStorage {
     // table name constants
-    const TBL_REPO_LOCK: &'static str = "repo_lock";
+    const TBL_REPO_LEASE: &'static str = "repo_lease";
     const TBL_SUPER_BLOCK: &'static str = "super_block";
     const TBL_WALS: &'static str = "wals";
     const TBL_ADDRESSES: &'static str = "addresses";
@@ -135,7 +264,8 @@ impl SqliteStorage {
 
     pub fn new(file_path: &str) -> Self {
         SqliteStorage {
-            is_attached: false,
+            lease: None,
+            heartbeat: None,
             file_path: CString::new(file_path).unwrap(),
             db: ptr::null_mut(),
             stmts: Vec::with_capacity(14),
@@ -163,30 +293,46 @@ impl SqliteStorage {
     // prepare and cache all sql statements
     fn prepare_stmts(&mut self) -> Result<()> {
         // check if all statements are prepared
-        if self.stmts.len() == 14 {
+        if self.stmts.len() == 15 {
             return Ok(());
         }
 
         self.stmts.clear();
 
-        // repo lock sql
+        // repo lease table is created here, because repos created by older
+        // versions don't have it
+        exec(
+            self.db,
+            &format!(
+                "
+            CREATE TABLE IF NOT EXISTS {} (
+                id          INTEGER PRIMARY KEY,
+                holder      TEXT,
+                expire_at   INTEGER
+            );
+        ",
+                Self::TBL_REPO_LEASE
+            ),
+        )?;
+
+        // repo lease sql
         self.prepare_sql(format!(
             "
-            SELECT lock FROM {} WHERE lock = 1
+            SELECT holder, expire_at FROM {} WHERE id = 1
         ",
-            Self::TBL_REPO_LOCK
+            Self::TBL_REPO_LEASE
         ))?;
         self.prepare_sql(format!(
             "
-            INSERT OR REPLACE INTO {}(lock) VALUES (1)
+            INSERT OR REPLACE INTO {}(id, holder, expire_at) VALUES (1, ?, ?)
         ",
-            Self::TBL_REPO_LOCK
+            Self::TBL_REPO_LEASE
         ))?;
         self.prepare_sql(format!(
             "
-            DELETE FROM {} WHERE lock = 1
+            DELETE FROM {} WHERE id = 1 AND holder = ?
         ",
-            Self::TBL_REPO_LOCK
+            Self::TBL_REPO_LEASE
         ))?;
 
         // super block sql
@@ -263,33 +409,98 @@ impl SqliteStorage {
             Self::TBL_BLOCKS
         ))?;
 
+        // list addresses sql
+        self.prepare_sql(format!(
+            "
+            SELECT id FROM {}
+        ",
+            Self::TBL_ADDRESSES
+        ))?;
+
         Ok(())
     }
 
-    fn lock_repo(&mut self, force: bool) -> Result<()> {
+    // read repo lease holder, return None if there is no unexpired lease
+    fn read_lease(&mut self) -> Result<Option<String>> {
         let stmt = self.stmts[0];
         reset_stmt(stmt)?;
         let result = unsafe { ffi::sqlite3_step(stmt) };
-        match result {
+        let lease = match result {
             ffi::SQLITE_ROW => {
-                // repo is locked
-                if force {
-                    warn!("Repo was locked, forced to open");
-                    self.is_attached = true;
-                    Ok(())
+                let (holder, expire_at) = unsafe {
+                    let holder = ffi::sqlite3_column_text(stmt, 0);
+                    let holder = if holder.is_null() {
+                        String::new()
+                    } else {
+                        CStr::from_ptr(holder as *const c_char)
+                            .to_string_lossy()
+                            .into_owned()
+                    };
+                    (holder, ffi::sqlite3_column_int64(stmt, 1) as u64)
+                };
+                if expire_at > now_millis() {
+                    Some(holder)
                 } else {
-                    Err(Error::RepoOpened)
+                    None
                 }
             }
-            ffi::SQLITE_DONE => {
-                // repo is not locked yet, lock it now
-                let stmt = self.stmts[1];
-                reset_stmt(stmt)?;
-                run_dml(stmt)?;
-                self.is_attached = true;
-                Ok(())
+            ffi::SQLITE_DONE => None,
+            _ => return Err(Error::from(ffi::Error::new(result))),
+        };
+        reset_stmt(stmt)?;
+        Ok(lease)
+    }
+
+    // take repo lease if it is not held by others
+    fn take_lease(&mut self, holder: &CStr, force: bool) -> Result<()> {
+        if let Some(lease) = self.read_lease()? {
+            let lease_holder = Holder::describe(&lease);
+            if !force {
+                warn!("Repo is locked by {}", lease_holder);
+                return Err(Error::RepoOpened);
             }
-            _ => Err(Error::from(ffi::Error::new(result))),
+            warn!("Repo was locked by {}, forced to open", lease_holder);
+        }
+
+        let stmt = self.stmts[1];
+        reset_stmt(stmt)?;
+        bind_text(stmt, 1, holder)?;
+        bind_int64(stmt, 2, now_millis() + LEASE_TTL.as_millis() as u64)?;
+        run_dml(stmt)
+    }
+
+    fn lock_repo(&mut self, force: bool) -> Result<()> {
+        let holder = CString::new(Holder::current().encode()).unwrap();
+
+        // check and take lease in one write transaction, so that other
+        // connections cannot take it at the same time
+        exec(self.db, "BEGIN IMMEDIATE")?;
+        match self.take_lease(&holder, force) {
+            Ok(_) => exec(self.db, "COMMIT")?,
+            Err(err) => {
+                let _ = exec(self.db, "ROLLBACK");
+                return Err(err);
+            }
+        }
+
+        // lease expires if it is not renewed, for example the process
+        // crashed, so keep renewing it while the repo is opened. In-memory
+        // database is private to its connection, so it needs no renewal.
+        if self.file_path.as_bytes() != b":memory:" {
+            let mut conn = LeaseConn::open(&self.file_path, holder.clone())?;
+            self.heartbeat = Some(Heartbeat::start(move || conn.renew()));
+        }
+        self.lease = Some(holder);
+        Ok(())
+    }
+
+    // writes must fail once the lease is lost, as the repo might be
+    // opened by others
+    #[inline]
+    fn check_lease(&self) -> Result<()> {
+        match self.heartbeat {
+            Some(ref heartbeat) => heartbeat.check(),
+            None => Ok(()),
         }
     }
 }
@@ -312,25 +523,7 @@ impl Storable for SqliteStorage {
     }
 
     fn connect(&mut self, _force: bool) -> Result<()> {
-        let result = unsafe {
-            ffi::sqlite3_open_v2(
-                self.file_path.as_ptr(),
-                &mut self.db,
-                ffi::SQLITE_OPEN_READWRITE
-                    | ffi::SQLITE_OPEN_CREATE
-                    | ffi::SQLITE_OPEN_FULLMUTEX,
-                ptr::null(),
-            )
-        };
-        if result != ffi::SQLITE_OK {
-            let err = ffi::Error::new(result);
-            if !self.db.is_null() {
-                unsafe { ffi::sqlite3_close(self.db) };
-                self.db = ptr::null_mut();
-            }
-            return Err(Error::from(err));
-        }
-
+        self.db = open_db(&self.file_path)?;
         Ok(())
     }
 
@@ -338,9 +531,6 @@ impl Storable for SqliteStorage {
         // create tables
         let sql = format!(
             "
-            CREATE TABLE {} (
-                lock        INTEGER
-            );
             CREATE TABLE {} (
                 suffix      INTEGER PRIMARY KEY,
                 data        BLOB
@@ -358,7 +548,6 @@ impl Storable for SqliteStorage {
                 data        BLOB
             );
         ",
-            Self::TBL_REPO_LOCK,
             Self::TBL_SUPER_BLOCK,
             Self::TBL_WALS,
             Self::TBL_ADDRESSES,
@@ -399,6 +588,7 @@ impl Storable for SqliteStorage {
     }
 
     fn put_super_block(&mut self, super_blk: &[u8], suffix: u64) -> Result<()> {
+        self.check_lease()?;
         let stmt = self.stmts[4];
         reset_stmt(stmt)?;
 
@@ -419,6 +609,7 @@ impl Storable for SqliteStorage {
     }
 
     fn put_wal(&mut self, id: &Eid, wal: &[u8]) -> Result<()> {
+        self.check_lease()?;
         let stmt = self.stmts[6];
         reset_stmt(stmt)?;
 
@@ -430,6 +621,7 @@ impl Storable for SqliteStorage {
     }
 
     fn del_wal(&mut self, id: &Eid) -> Result<()> {
+        self.check_lease()?;
         let stmt = self.stmts[7];
         reset_stmt(stmt)?;
 
@@ -450,6 +642,7 @@ impl Storable for SqliteStorage {
     }
 
     fn put_address(&mut self, id: &Eid, addr: &[u8]) -> Result<()> {
+        self.check_lease()?;
         let stmt = self.stmts[9];
         reset_stmt(stmt)?;
 
@@ -461,6 +654,7 @@ impl Storable for SqliteStorage {
     }
 
     fn del_address(&mut self, id: &Eid) -> Result<()> {
+        self.check_lease()?;
         let stmt = self.stmts[10];
         reset_stmt(stmt)?;
 
@@ -470,6 +664,36 @@ impl Storable for SqliteStorage {
         run_dml(stmt)
     }
 
+    fn list_addresses(&mut self) -> Result<Vec<Eid>> {
+        let stmt = self.stmts[14];
+        reset_stmt(stmt)?;
+
+        let mut ids = Vec::new();
+        loop {
+            let result = unsafe { ffi::sqlite3_step(stmt) };
+            match result {
+                ffi::SQLITE_ROW => {
+                    let id = unsafe {
+                        let id = ffi::sqlite3_column_text(stmt, 0);
+                        if id.is_null() {
+                            continue;
+                        }
+                        CStr::from_ptr(id as *const c_char)
+                            .to_string_lossy()
+                            .into_owned()
+                    };
+                    if let Some(id) = Eid::from_hex(&id) {
+                        ids.push(id);
+                    }
+                }
+                ffi::SQLITE_DONE => break,
+                _ => return Err(Error::from(ffi::Error::new(result))),
+            }
+        }
+        reset_stmt(stmt)?;
+        Ok(ids)
+    }
+
     fn get_blocks(&mut self, dst: &mut [u8], span: Span) -> Result<()> {
         let stmt = self.stmts[11];
 
@@ -490,6 +714,7 @@ impl Storable for SqliteStorage {
     }
 
     fn put_blocks(&mut self, span: Span, mut blks: &[u8]) -> Result<()> {
+        self.check_lease()?;
         let stmt = self.stmts[12];
 
         for blk_idx in span {
@@ -508,6 +733,7 @@ impl Storable for SqliteStorage {
     }
 
     fn del_blocks(&mut self, span: Span) -> Result<()> {
+        self.check_lease()?;
         let stmt = self.stmts[13];
 
         for blk_idx in span {
@@ -530,13 +756,8 @@ impl Storable for SqliteStorage {
     #[inline]
     fn destroy(&mut self) -> Result<()> {
         self.connect(false)?;
-        if self.prepare_stmts().is_ok() {
-            let stmt = self.stmts[0];
-            reset_stmt(stmt)?;
-            if let ffi::SQLITE_ROW = unsafe { ffi::sqlite3_step(stmt) } {
-                // repo is locked
-                warn!("Destroy an opened repo");
-            }
+        if self.prepare_stmts().is_ok() && self.read_lease()?.is_some() {
+            warn!("Destroy an opened repo");
         }
         vio::remove_file(self.file_path.to_str().unwrap())?;
         Ok(())
@@ -545,13 +766,16 @@ impl Storable for SqliteStorage {
 
 impl Drop for SqliteStorage {
     fn drop(&mut self) {
-        // release repo lock and ignore the result
-        if self.is_attached {
+        // stop lease heartbeat, then release repo lease and ignore the
+        // result
+        self.heartbeat.take();
+        if let Some(holder) = self.lease.take() {
             let stmt = self.stmts[2];
-            let _ = reset_stmt(stmt).map(|_| unsafe {
-                ffi::sqlite3_step(stmt);
-            });
-            self.is_attached = false;
+            let _ = reset_stmt(stmt)
+                .and_then(|_| bind_text(stmt, 1, &holder))
+                .map(|_| unsafe {
+                    ffi::sqlite3_step(stmt);
+                });
         }
 
         // release statements
@@ -588,6 +812,8 @@ unsafe impl Sync for SqliteStorage {}
 mod tests {
     use super::*;
     use crate::base::init_env;
+    use std::thread;
+    use std::time::Duration;
     use tempdir::TempDir;
 
     #[test]
@@ -662,4 +888,49 @@ mod tests {
             Error::NotFound
         );
     }
+
+    #[test]
+    fn sqlite_lease() {
+        init_env();
+        let tmpdir = TempDir::new("zbox_test").expect("Create temp dir failed");
+        let dir = tmpdir.path().join("storage.db");
+        let mut ss = SqliteStorage::new(dir.to_str().unwrap());
+        ss.connect(false).unwrap();
+        ss.init(Crypto::default(), Key::new_empty()).unwrap();
+
+        // repo is locked by the first storage
+        let mut ss2 = SqliteStorage::new(dir.to_str().unwrap());
+        ss2.connect(false).unwrap();
+        assert_eq!(
+            ss2.open(Crypto::default(), Key::new_empty(), false)
+                .unwrap_err(),
+            Error::RepoOpened
+        );
+        let lease = ss2.read_lease().unwrap().unwrap();
+        assert!(
+            Holder::describe(&lease).contains(&std::process::id().to_string())
+        );
+        drop(ss2);
+
+        // writes fail after the lease is lost
+        let id = Eid::new();
+        ss.put_wal(&id, &[1, 2, 3]).unwrap();
+        let interval = Duration::from_millis(10);
+        ss.heartbeat = Some(Heartbeat::with_interval(interval, || Ok(false)));
+        thread::sleep(interval * 5);
+        assert_eq!(ss.put_wal(&id, &[1, 2, 3]).unwrap_err(), Error::RepoOpened);
+        assert_eq!(ss.del_wal(&id).unwrap_err(), Error::RepoOpened);
+        ss.get_wal(&id).unwrap();
+
+        // simulate crash, lease is not released but expired
+        ss.heartbeat.take();
+        ss.lease.take();
+        exec(ss.db, "UPDATE repo_lease SET expire_at = 0").unwrap();
+        drop(ss);
+
+        // expired lease doesn't lock the repo
+        let mut ss = SqliteStorage::new(dir.to_str().unwrap());
+        ss.connect(false).unwrap();
+        ss.open(Crypto::default(), Key::new_empty(), false).unwrap();
+    }
 }
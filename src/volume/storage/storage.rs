@@ -299,6 +299,12 @@ impl Storage {
         Ok(())
     }
 
+    // get ids of all saved entities
+    #[inline]
+    pub fn list_ids(&mut self) -> Result<Vec<Eid>> {
+        self.depot.list_addresses()
+    }
+
     // flush underlying storage
     #[inline]
     pub fn flush(&mut self) -> Result<()> {
@@ -246,6 +246,11 @@ impl Storable for ZboxStorage {
         self.idx_mgr.delete(id)
     }
 
+    #[inline]
+    fn list_addresses(&mut self) -> Result<Vec<Eid>> {
+        self.idx_mgr.ids()
+    }
+
     #[inline]
     fn get_blocks(&mut self, dst: &mut [u8], span: Span) -> Result<()> {
         assert_eq!(dst.len(), span.bytes_len());
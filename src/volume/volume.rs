@@ -157,6 +157,14 @@ impl Volume {
         Ok(())
     }
 
+    /// Update super block payload
+    pub fn update_payload(&mut self, pwd: &str, payload: &[u8]) -> Result<()> {
+        let mut storage = self.storage.write().unwrap();
+        let mut super_blk = SuperBlk::load(pwd, &mut storage)?;
+        super_blk.body.payload = payload.to_vec();
+        super_blk.save(pwd, &mut storage)
+    }
+
     // get volume info
     #[inline]
     pub fn info(&self) -> Info {
@@ -184,6 +192,13 @@ impl Volume {
         storage.del(id)
     }
 
+    // get ids of all saved entities
+    #[inline]
+    pub fn list_ids(&mut self) -> Result<Vec<Eid>> {
+        let mut storage = self.storage.write().unwrap();
+        storage.list_ids()
+    }
+
     #[inline]
     pub fn flush(&mut self) -> Result<()> {
         let mut storage = self.storage.write().unwrap();
@@ -0,0 +1,132 @@
+#![cfg(all(feature = "async", feature = "storage-mem"))]
+
+extern crate tokio;
+extern crate zbox;
+
+use std::io::SeekFrom;
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use zbox::{init_env, AsyncRepo, Error, OpenOptions, RepoOpener};
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn async_file_oper() {
+    init_env();
+
+    let repo = AsyncRepo::open(
+        RepoOpener::new().create(true).version_limit(4),
+        "mem://async_file_oper",
+        "pwd",
+    )
+    .await
+    .unwrap();
+
+    // multi-part write spanning several blocking operations
+    let buf: Vec<u8> = (0..3 * 1024 * 1024).map(|i| i as u8).collect();
+    let mut f = repo.create_file("/file").await.unwrap();
+    f.write_all(&buf[..1000]).await.unwrap();
+    f.write_all(&buf[1000..]).await.unwrap();
+    f.flush().await.unwrap();
+    f.finish().await.unwrap();
+    assert_eq!(f.metadata().await.unwrap().content_len(), buf.len());
+
+    // read back
+    f.seek(SeekFrom::Start(0)).await.unwrap();
+    let mut dst = Vec::new();
+    f.read_to_end(&mut dst).await.unwrap();
+    assert_eq!(dst, buf);
+
+    // seek then read partially
+    let pos = f.seek(SeekFrom::Start(42)).await.unwrap();
+    assert_eq!(pos, 42);
+    let mut dst = [0u8; 10];
+    f.read_exact(&mut dst).await.unwrap();
+    assert_eq!(&dst[..], &buf[42..52]);
+
+    // shutdown should finish the pending write
+    let mut f = repo
+        .open_file_with(OpenOptions::new().write(true).truncate(true), "/file")
+        .await
+        .unwrap();
+    f.write_all(b"new content").await.unwrap();
+    f.shutdown().await.unwrap();
+    let ver = f.curr_version().await.unwrap();
+    assert_eq!(f.history().await.unwrap().last().unwrap().num(), ver);
+    f.seek(SeekFrom::Start(0)).await.unwrap();
+    let mut s = String::new();
+    f.read_to_string(&mut s).await.unwrap();
+    assert_eq!(s, "new content");
+
+    // write once and set_len
+    f.write_once(b"foo").await.unwrap();
+    f.set_len(10).await.unwrap();
+    assert_eq!(f.metadata().await.unwrap().content_len(), 10);
+    assert_eq!(f.curr_version().await.unwrap(), ver + 2);
+
+    // read-only file cannot be written
+    let mut f = repo.open_file("/file").await.unwrap();
+    assert!(f.write_all(b"bar").await.is_err());
+    assert_eq!(
+        f.run(|file| file.write_once(b"bar")).await.unwrap_err(),
+        Error::CannotWrite
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn async_repo_oper() {
+    init_env();
+
+    let repo = AsyncRepo::open(
+        RepoOpener::new().create(true),
+        "mem://async_repo_oper",
+        "pwd",
+    )
+    .await
+    .unwrap();
+
+    repo.create_dir_all("/dir/sub").await.unwrap();
+    assert!(repo.is_dir("/dir/sub").await.unwrap());
+
+    // concurrent writes from multiple tasks
+    let mut tasks = Vec::new();
+    for i in 0..8 {
+        let repo = repo.clone();
+        tasks.push(tokio::spawn(async move {
+            let path = format!("/dir/file{}", i);
+            let mut f = repo.create_file(&path).await.unwrap();
+            f.write_all(path.as_bytes()).await.unwrap();
+            f.finish().await.unwrap();
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(repo.read_dir("/dir").await.unwrap().len(), 9);
+
+    for i in 0..8 {
+        let path = format!("/dir/file{}", i);
+        let mut f = repo.open_file(&path).await.unwrap();
+        let mut s = String::new();
+        f.read_to_string(&mut s).await.unwrap();
+        assert_eq!(s, path);
+    }
+
+    repo.copy("/dir/file0", "/copied").await.unwrap();
+    repo.rename("/copied", "/renamed").await.unwrap();
+    assert!(repo.is_file("/renamed").await.unwrap());
+    repo.remove_file("/renamed").await.unwrap();
+    assert!(!repo.path_exists("/renamed").await.unwrap());
+
+    // transaction committed on the blocking pool
+    repo.transaction(|tx| {
+        tx.create_dir("/tx")?;
+        tx.write_file("/tx/a", b"a")?;
+        Ok(())
+    })
+    .await
+    .unwrap();
+    assert!(repo.is_file("/tx/a").await.unwrap());
+
+    repo.remove_dir_all("/dir").await.unwrap();
+    assert!(!repo.path_exists("/dir").await.unwrap());
+    assert!(repo.info().await.unwrap().version_limit() > 0);
+}
@@ -5,10 +5,11 @@ extern crate zbox;
 
 mod common;
 
+use std::io::Read;
 use std::sync::{Arc, RwLock};
 use std::{thread, time};
 
-use zbox::Error;
+use zbox::{CheckMode, Error, OpenOptions};
 
 #[test]
 fn dir_create_st() {
@@ -218,3 +219,307 @@ fn dir_copy() {
     repo.copy_dir_all("/ccc/ccc1", "/ccc").unwrap();
     assert!(repo.path_exists("/ccc/ccc11").unwrap());
 }
+
+#[test]
+fn dir_symlink() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    repo.create_dir_all("/aaa/bbb").unwrap();
+    repo.create_file("/aaa/bbb/file")
+        .unwrap()
+        .write_once(b"foo")
+        .unwrap();
+
+    // absolute and relative links
+    repo.symlink("/aaa/bbb", "/abs").unwrap();
+    repo.symlink("bbb/file", "/aaa/rel").unwrap();
+    repo.symlink("../.././aaa/bbb", "/aaa/bbb/up").unwrap();
+    repo.symlink("/not_exist", "/dangling").unwrap();
+    assert_eq!(
+        repo.symlink("/aaa", "/abs").unwrap_err(),
+        Error::AlreadyExists
+    );
+
+    assert_eq!(repo.read_link("/abs").unwrap().to_str(), Some("/aaa/bbb"));
+    assert_eq!(
+        repo.read_link("/aaa/rel").unwrap().to_str(),
+        Some("bbb/file")
+    );
+    assert_eq!(repo.read_link("/aaa").unwrap_err(), Error::NotLink);
+
+    // links are followed
+    let mut buf = String::new();
+    repo.open_file("/abs/file")
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "foo");
+    assert!(repo.is_file("/aaa/rel").unwrap());
+    assert!(repo.is_dir("/aaa/bbb/up/up/up").unwrap());
+    assert_eq!(repo.read_dir("/abs").unwrap().len(), 2);
+    assert!(repo.metadata("/aaa/rel").unwrap().is_file());
+    assert!(repo.symlink_metadata("/aaa/rel").unwrap().is_symlink());
+    assert!(!repo.path_exists("/dangling").unwrap());
+
+    // link loop
+    repo.symlink("/loop2", "/loop1").unwrap();
+    repo.symlink("/loop1", "/loop2").unwrap();
+    assert_eq!(repo.metadata("/loop1").unwrap_err(), Error::TooManyLinks);
+
+    // remove and rename links, not the targets
+    repo.rename("/aaa/rel", "/aaa/rel2").unwrap();
+    assert_eq!(
+        repo.read_link("/aaa/rel2").unwrap().to_str(),
+        Some("bbb/file")
+    );
+    repo.remove_file("/abs").unwrap();
+    assert!(!repo.path_exists("/abs").unwrap());
+    assert!(repo.is_dir("/aaa/bbb").unwrap());
+
+    // copy dir keeps links
+    repo.copy_dir_all("/aaa", "/ccc").unwrap();
+    assert_eq!(
+        repo.read_link("/ccc/rel2").unwrap().to_str(),
+        Some("bbb/file")
+    );
+    assert!(repo.is_file("/ccc/rel2").unwrap());
+
+    repo.remove_dir_all("/aaa").unwrap();
+    assert!(!repo.path_exists("/aaa").unwrap());
+    assert!(repo.is_file("/ccc/bbb/file").unwrap());
+}
+
+#[test]
+fn dir_hard_link() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    repo.create_dir_all("/aaa/bbb").unwrap();
+    repo.create_file("/aaa/file")
+        .unwrap()
+        .write_once(b"foo")
+        .unwrap();
+
+    repo.hard_link("/aaa/file", "/aaa/bbb/link").unwrap();
+    assert_eq!(repo.metadata("/aaa/file").unwrap().link_count(), 2);
+    assert_eq!(
+        repo.hard_link("/aaa/bbb", "/link_dir").unwrap_err(),
+        Error::IsDir
+    );
+    assert_eq!(
+        repo.hard_link("/aaa/file", "/aaa/bbb/link").unwrap_err(),
+        Error::AlreadyExists
+    );
+
+    // write through one link is seen by the other
+    OpenOptions::new()
+        .write(true)
+        .append(true)
+        .open(repo, "/aaa/bbb/link")
+        .unwrap()
+        .write_once(b"bar")
+        .unwrap();
+    let mut buf = String::new();
+    repo.open_file("/aaa/file")
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "foobar");
+
+    // removing one link keeps the file
+    repo.remove_file("/aaa/file").unwrap();
+    assert!(!repo.path_exists("/aaa/file").unwrap());
+    assert_eq!(repo.metadata("/aaa/bbb/link").unwrap().link_count(), 1);
+    let mut buf = String::new();
+    repo.open_file("/aaa/bbb/link")
+        .unwrap()
+        .read_to_string(&mut buf)
+        .unwrap();
+    assert_eq!(buf, "foobar");
+
+    // rename over a hard link of the same file is no-op
+    repo.hard_link("/aaa/bbb/link", "/aaa/link2").unwrap();
+    repo.rename("/aaa/link2", "/aaa/bbb/link").unwrap();
+    assert!(repo.path_exists("/aaa/link2").unwrap());
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+
+    // remove directory holding the link the file was last linked through
+    repo.create_dir("/ccc").unwrap();
+    repo.hard_link("/aaa/link2", "/ccc/link").unwrap();
+    repo.remove_dir_all("/ccc").unwrap();
+    assert_eq!(repo.metadata("/aaa/link2").unwrap().link_count(), 2);
+
+    repo.remove_dir_all("/aaa").unwrap();
+    assert!(!repo.path_exists("/aaa").unwrap());
+}
+
+#[test]
+fn dir_permissions() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    repo.create_dir("/dir").unwrap();
+    repo.create_file("/dir/file").unwrap();
+    repo.symlink("/dir/file", "/link").unwrap();
+
+    // default mode and owner
+    let md = repo.metadata("/dir").unwrap();
+    assert_eq!(md.mode(), 0o755);
+    assert_eq!(md.uid(), 0);
+    assert_eq!(md.gid(), 0);
+    assert_eq!(repo.metadata("/dir/file").unwrap().mode(), 0o644);
+    assert_eq!(repo.symlink_metadata("/link").unwrap().mode(), 0o777);
+
+    // set mode through symbolic link
+    repo.set_permissions("/link", 0o4600).unwrap();
+    assert_eq!(repo.metadata("/dir/file").unwrap().mode(), 0o4600);
+    assert_eq!(repo.symlink_metadata("/link").unwrap().mode(), 0o777);
+    assert_eq!(
+        repo.set_permissions("/dir", 0o10755).unwrap_err(),
+        Error::InvalidArgument
+    );
+
+    // set owner, None keeps the original id
+    repo.set_owner("/dir", Some(1000), Some(100)).unwrap();
+    repo.set_owner("/dir", None, Some(200)).unwrap();
+    let md = repo.metadata("/dir").unwrap();
+    assert_eq!(md.uid(), 1000);
+    assert_eq!(md.gid(), 200);
+
+    // set times without creating new version
+    let atime = time::UNIX_EPOCH + time::Duration::from_secs(1_000_000);
+    let mtime = time::UNIX_EPOCH + time::Duration::from_secs(2_000_000);
+    repo.set_times("/dir/file", atime, mtime).unwrap();
+    let md = repo.metadata("/dir/file").unwrap();
+    assert_eq!(md.accessed_at(), atime);
+    assert_eq!(md.modified_at(), mtime);
+    assert_eq!(md.curr_version(), 1);
+    let before_epoch = time::UNIX_EPOCH - time::Duration::from_secs(1);
+    assert_eq!(
+        repo.set_times("/dir/file", before_epoch, mtime)
+            .unwrap_err(),
+        Error::InvalidArgument
+    );
+
+    // writing file updates modification time but not access time
+    OpenOptions::new()
+        .write(true)
+        .open(repo, "/dir/file")
+        .unwrap()
+        .write_once(b"foo")
+        .unwrap();
+    let md = repo.metadata("/dir/file").unwrap();
+    assert_eq!(md.accessed_at(), atime);
+    assert!(md.modified_at() > mtime);
+    assert_eq!(md.mode(), 0o4600);
+
+    assert_eq!(
+        repo.set_permissions("/non-exists", 0o600).unwrap_err(),
+        Error::NotFound
+    );
+}
+
+#[test]
+fn dir_walk() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    // /a
+    // /a/b.txt
+    // /a/c
+    // /a/c/d.txt
+    // /a/c/e
+    // /f.log
+    // /g -> /a
+    repo.create_dir_all("/a/c").unwrap();
+    repo.create_file("/a/b.txt").unwrap();
+    repo.create_file("/a/c/d.txt").unwrap();
+    repo.create_file("/a/c/e").unwrap();
+    repo.create_file("/f.log").unwrap();
+    repo.symlink("/a", "/g").unwrap();
+
+    let walk = |walker: zbox::WalkDir| -> Vec<String> {
+        walker
+            .into_iter()
+            .map(|ent| ent.unwrap().path().to_str().unwrap().to_string())
+            .collect()
+    };
+
+    // pre-order, symbolic link is not followed
+    let mut paths = walk(repo.walk_dir("/").unwrap());
+    assert_eq!(paths.len(), 7);
+    let pos = |paths: &Vec<String>, p: &str| {
+        paths.iter().position(|x| x == p).unwrap()
+    };
+    assert!(pos(&paths, "/a") < pos(&paths, "/a/c"));
+    assert!(pos(&paths, "/a/c") < pos(&paths, "/a/c/d.txt"));
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            "/a",
+            "/a/b.txt",
+            "/a/c",
+            "/a/c/d.txt",
+            "/a/c/e",
+            "/f.log",
+            "/g"
+        ]
+    );
+
+    // post-order
+    let paths = walk(repo.walk_dir("/").unwrap().contents_first(true));
+    assert_eq!(paths.len(), 7);
+    assert!(pos(&paths, "/a") > pos(&paths, "/a/c"));
+    assert!(pos(&paths, "/a/c") > pos(&paths, "/a/c/d.txt"));
+    assert!(pos(&paths, "/a/c") > pos(&paths, "/a/c/e"));
+
+    // depth limits
+    let mut paths = walk(repo.walk_dir("/").unwrap().max_depth(1));
+    paths.sort();
+    assert_eq!(paths, vec!["/a", "/f.log", "/g"]);
+    let mut paths = walk(repo.walk_dir("/").unwrap().min_depth(2).max_depth(2));
+    paths.sort();
+    assert_eq!(paths, vec!["/a/b.txt", "/a/c"]);
+    let mut paths = walk(repo.walk_dir("/a").unwrap().min_depth(2));
+    paths.sort();
+    assert_eq!(paths, vec!["/a/c/d.txt", "/a/c/e"]);
+
+    // skip subtree
+    let mut paths = walk(
+        repo.walk_dir("/")
+            .unwrap()
+            .filter_entry(|ent| ent.file_name() != "c"),
+    );
+    paths.sort();
+    assert_eq!(paths, vec!["/a", "/a/b.txt", "/f.log", "/g"]);
+
+    // name filters
+    let mut paths = walk(repo.walk_dir("/").unwrap().glob("*.txt").unwrap());
+    paths.sort();
+    assert_eq!(paths, vec!["/a/b.txt", "/a/c/d.txt"]);
+    #[cfg(feature = "walk-regex")]
+    {
+        let mut paths =
+            walk(repo.walk_dir("/").unwrap().regex(r"^[a-e]$").unwrap());
+        paths.sort();
+        assert_eq!(paths, vec!["/a", "/a/c", "/a/c/e"]);
+        assert_eq!(
+            repo.walk_dir("/").unwrap().regex("(").unwrap_err(),
+            Error::InvalidArgument
+        );
+    }
+    assert_eq!(
+        repo.walk_dir("/").unwrap().glob("[a").unwrap_err(),
+        Error::InvalidArgument
+    );
+
+    // walk through symbolic link to directory
+    let mut paths = walk(repo.walk_dir("/g").unwrap().max_depth(1));
+    paths.sort();
+    assert_eq!(paths, vec!["/g/b.txt", "/g/c"]);
+
+    assert_eq!(repo.walk_dir("/f.log").unwrap_err(), Error::NotDir);
+    assert_eq!(repo.walk_dir("/non-exists").unwrap_err(), Error::NotFound);
+}
@@ -12,7 +12,9 @@ use rand_xorshift::XorShiftRng;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::sync::{Arc, RwLock};
 use std::thread;
-use zbox::{Error, File, OpenOptions};
+use zbox::{
+    verify_merkle_proof, Error, File, OpenOptions, Quota, RetentionPolicy,
+};
 
 #[test]
 fn file_open_close() {
@@ -845,3 +847,522 @@ fn file_rename() {
         assert!(repo.path_exists("/file6").unwrap());
     }
 }
+
+#[test]
+fn file_verify() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    let mut rng = XorShiftRng::from_seed([42u8; 16]);
+    let mut buf = vec![0; 1024 * 1024 + 3];
+    rng.fill_bytes(&mut buf);
+
+    let mut f = OpenOptions::new()
+        .create(true)
+        .version_limit(2)
+        .open(repo, "/file")
+        .unwrap();
+    f.write_once(&buf[..]).unwrap();
+    assert!(f.verify().unwrap().is_empty());
+
+    // overwrite the middle of the file and verify the new version
+    f.seek(SeekFrom::Start(300 * 1024)).unwrap();
+    f.write_once(&buf[..1000]).unwrap();
+    assert!(f.verify().unwrap().is_empty());
+
+    // verify ranges of the previous version
+    let hist = f.history().unwrap();
+    let rdr = f.version_reader(hist[0].num()).unwrap();
+    assert!(rdr.verify_range(0..buf.len()).unwrap().is_empty());
+    assert!(rdr.verify_range(100..200).unwrap().is_empty());
+    assert!(rdr
+        .verify_range(buf.len()..buf.len() * 2)
+        .unwrap()
+        .is_empty());
+    #[allow(clippy::reversed_empty_ranges)]
+    let err = rdr.verify_range(200..100).unwrap_err();
+    assert_eq!(err, Error::InvalidArgument);
+
+    // same content has same hash
+    let mut f2 = repo.create_file("/file2").unwrap();
+    f2.write_once(&buf[..]).unwrap();
+    let hist2 = f2.history().unwrap();
+    assert!(hist2[0].content_hash().is_some());
+    assert_eq!(hist[0].content_hash(), hist2[0].content_hash());
+    assert_ne!(hist[1].content_hash(), hist2[0].content_hash());
+}
+
+#[test]
+fn file_merkle_proof() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    let mut rng = XorShiftRng::from_seed([42u8; 16]);
+    let mut buf = vec![0; 1024 * 1024 + 3];
+    rng.fill_bytes(&mut buf);
+
+    let mut f = repo.create_file("/file").unwrap();
+    f.write_once(&buf[..]).unwrap();
+
+    let ver = f.history().unwrap().pop().unwrap();
+    let root_hash = ver.content_hash().unwrap().to_vec();
+    let len = buf.len();
+    let rdr = f.version_reader(ver.num()).unwrap();
+
+    let mut idx = 0;
+    while let Ok(proof) = rdr.merkle_proof(idx) {
+        let begin = idx * 256 * 1024;
+        let end = (begin + 256 * 1024).min(len);
+        let mut piece = buf[begin..end].to_vec();
+        assert!(verify_merkle_proof(&root_hash, len, idx, &piece, &proof));
+
+        // proof of a piece doesn't verify other pieces
+        if idx > 0 {
+            let prev = &buf[begin - 256 * 1024..begin];
+            assert!(!verify_merkle_proof(&root_hash, len, idx, prev, &proof));
+        }
+
+        piece[1] ^= 0xff;
+        assert!(!verify_merkle_proof(&root_hash, len, idx, &piece, &proof));
+        idx += 1;
+    }
+    assert_eq!(idx, 5);
+    assert_eq!(rdr.merkle_proof(idx).unwrap_err(), Error::InvalidArgument);
+}
+
+#[test]
+fn file_xattr() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(repo, "/file")
+        .unwrap();
+    f.write_once(b"content").unwrap();
+    assert!(f.list_xattrs().unwrap().is_empty());
+    assert!(f.get_xattr("user.a").unwrap().is_none());
+
+    // set, overwrite and list
+    f.set_xattr("user.b", b"bbb").unwrap();
+    f.set_xattr("user.a", b"aaa").unwrap();
+    f.set_xattr("user.a", b"aaa2").unwrap();
+    assert_eq!(f.get_xattr("user.a").unwrap().unwrap(), b"aaa2");
+    assert_eq!(f.list_xattrs().unwrap(), vec!["user.a", "user.b"]);
+
+    // xattr changes should not create new version
+    assert_eq!(f.history().unwrap().len(), 1);
+
+    // invalid name and value
+    assert_eq!(f.set_xattr("", b"x").unwrap_err(), Error::InvalidArgument);
+    let long_name = "n".repeat(256);
+    assert_eq!(
+        f.set_xattr(&long_name, b"x").unwrap_err(),
+        Error::InvalidArgument
+    );
+    let big_value = vec![0u8; 64 * 1024 + 1];
+    assert_eq!(
+        f.set_xattr("user.c", &big_value).unwrap_err(),
+        Error::InvalidArgument
+    );
+
+    // remove, including non-existing one
+    f.remove_xattr("user.b").unwrap();
+    f.remove_xattr("user.b").unwrap();
+    assert_eq!(f.list_xattrs().unwrap(), vec!["user.a"]);
+    drop(f);
+
+    // read-only file handle cannot change xattrs
+    let mut f = repo.open_file("/file").unwrap();
+    assert_eq!(f.get_xattr("user.a").unwrap().unwrap(), b"aaa2");
+    assert_eq!(f.set_xattr("user.d", b"d").unwrap_err(), Error::CannotWrite);
+    assert_eq!(f.remove_xattr("user.a").unwrap_err(), Error::CannotWrite);
+    drop(f);
+
+    // path based xattrs on file and directory
+    repo.create_dir("/dir").unwrap();
+    repo.set_xattr("/dir", "user.x", b"xxx").unwrap();
+    assert_eq!(repo.get_xattr("/dir", "user.x").unwrap().unwrap(), b"xxx");
+    assert_eq!(repo.list_xattrs("/file").unwrap(), vec!["user.a"]);
+    repo.remove_xattr("/dir", "user.x").unwrap();
+    assert!(repo.list_xattrs("/dir").unwrap().is_empty());
+    assert_eq!(
+        repo.get_xattr("/non-exists", "user.x").unwrap_err(),
+        Error::NotFound
+    );
+}
+
+#[test]
+fn file_sparse() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .read(true)
+        .open(repo, "/file")
+        .unwrap();
+    f.write_once(&[1u8; 1000]).unwrap();
+
+    // extend file by set_len should create a hole
+    let len = 3 * 1024 * 1024 + 5;
+    f.set_len(len).unwrap();
+    let md = f.metadata().unwrap();
+    assert_eq!(md.content_len(), len);
+    assert_eq!(md.allocated_len(), 1000);
+
+    let mut buf = Vec::new();
+    f.seek(SeekFrom::Start(0)).unwrap();
+    f.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf.len(), len);
+    assert!(buf[..1000].iter().all(|b| *b == 1));
+    assert!(buf[1000..].iter().all(|b| *b == 0));
+
+    // write after seeking beyond EOF should create a hole
+    f.seek(SeekFrom::Start(len as u64 + 100)).unwrap();
+    f.write_once(&[2u8; 10]).unwrap();
+    let md = f.metadata().unwrap();
+    assert_eq!(md.content_len(), len + 110);
+    assert_eq!(md.allocated_len(), 1010);
+
+    // write into the middle of hole
+    f.seek(SeekFrom::Start(2000)).unwrap();
+    f.write_once(&[3u8; 100]).unwrap();
+    let md = f.metadata().unwrap();
+    assert_eq!(md.content_len(), len + 110);
+    assert_eq!(md.allocated_len(), 1110);
+
+    // punch hole across data and existing holes
+    f.punch_hole(500..2050).unwrap();
+    let md = f.metadata().unwrap();
+    assert_eq!(md.content_len(), len + 110);
+    assert_eq!(md.allocated_len(), 560);
+
+    // punching hole beyond EOF should not change file size
+    f.punch_hole(len + 105..len + 1000).unwrap();
+    let md = f.metadata().unwrap();
+    assert_eq!(md.content_len(), len + 110);
+    assert_eq!(md.allocated_len(), 555);
+
+    let mut expected = vec![0u8; len + 110];
+    expected[..500].copy_from_slice(&[1u8; 500]);
+    expected[2050..2100].copy_from_slice(&[3u8; 50]);
+    expected[len + 100..len + 105].copy_from_slice(&[2u8; 5]);
+    let mut buf = Vec::new();
+    f.seek(SeekFrom::Start(0)).unwrap();
+    f.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, expected);
+
+    // content hash should be same as the file without holes
+    let ver = f.history().unwrap().pop().unwrap();
+    assert_eq!(ver.allocated_len(), 555);
+    assert!(f.verify().unwrap().is_empty());
+    drop(f);
+    let mut f2 = repo.create_file("/file2").unwrap();
+    f2.write_once(&expected).unwrap();
+    let ver2 = f2.history().unwrap().pop().unwrap();
+    assert_eq!(ver.content_hash(), ver2.content_hash());
+    assert_eq!(ver2.allocated_len(), expected.len());
+    drop(f2);
+
+    // read-only file cannot punch hole
+    let mut f = repo.open_file("/file").unwrap();
+    assert_eq!(f.punch_hole(0..10).unwrap_err(), Error::CannotWrite);
+}
+
+#[test]
+fn file_version_tag() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    let read_ver = |f: &File, tag: &str| -> String {
+        let mut buf = String::new();
+        f.version_reader_by_tag(tag)
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        buf
+    };
+
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .version_limit(2)
+        .open(repo, "/file")
+        .unwrap();
+    f.write_once(b"v1").unwrap();
+    f.tag_version(2, "release-1").unwrap();
+    f.tag_version(2, "release-1").unwrap();
+    assert_eq!(
+        f.tag_version(1, "release-1").unwrap_err(),
+        Error::AlreadyExists
+    );
+    assert_eq!(f.tag_version(9, "x").unwrap_err(), Error::NoVersion);
+    assert_eq!(f.tag_version(2, "").unwrap_err(), Error::InvalidArgument);
+
+    // tagged version is not evicted and not counted in the limit
+    for i in 0..5 {
+        f.seek(SeekFrom::Start(0)).unwrap();
+        f.write_once(format!("v{}", i + 2).as_bytes()).unwrap();
+    }
+    let hist = f.history().unwrap();
+    assert_eq!(hist.len(), 3);
+    assert_eq!(hist[0].num(), 2);
+    assert_eq!(hist[0].tag(), Some("release-1"));
+    assert!(hist[1].tag().is_none());
+    assert_eq!(read_ver(&f, "release-1"), "v1");
+    assert_eq!(
+        f.version_reader_by_tag("non-exists").unwrap_err(),
+        Error::NoVersion
+    );
+
+    // retag to another version
+    f.tag_version(7, "release-2").unwrap();
+    f.tag_version(7, "release-3").unwrap();
+    assert_eq!(
+        f.version_reader_by_tag("release-2").unwrap_err(),
+        Error::NoVersion
+    );
+    assert_eq!(read_ver(&f, "release-3"), "v6");
+
+    // untagged version is evicted once limit is exceeded
+    f.seek(SeekFrom::Start(0)).unwrap();
+    f.write_once(b"v7").unwrap();
+    assert_eq!(f.history().unwrap().len(), 4);
+    f.untag_version("release-1").unwrap();
+    let hist = f.history().unwrap();
+    assert_eq!(hist.len(), 3);
+    assert_eq!(hist[0].num(), 6);
+    assert_eq!(f.untag_version("release-1").unwrap_err(), Error::NoVersion);
+    drop(f);
+
+    // read-only file handle cannot change tags
+    let mut f = repo.open_file("/file").unwrap();
+    assert_eq!(read_ver(&f, "release-3"), "v6");
+    assert_eq!(f.tag_version(8, "x").unwrap_err(), Error::CannotWrite);
+    assert_eq!(
+        f.untag_version("release-3").unwrap_err(),
+        Error::CannotWrite
+    );
+}
+
+#[test]
+fn file_retention() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    // keep versions until history exceeds 10 bytes
+    let mut f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .retention_policy(RetentionPolicy::new().max_history_size(10))
+        .open(repo, "/file")
+        .unwrap();
+    for i in 0..5 {
+        f.seek(SeekFrom::Start(0)).unwrap();
+        f.write_once(format!("ver{}", i).as_bytes()).unwrap();
+    }
+    let hist = f.history().unwrap();
+    assert_eq!(hist.len(), 2);
+    assert_eq!(hist[0].num(), 5);
+
+    // tagged version is not counted
+    f.tag_version(6, "keep").unwrap();
+    f.seek(SeekFrom::Start(0)).unwrap();
+    f.write_once(b"ver5").unwrap();
+    let hist = f.history().unwrap();
+    assert_eq!(hist.len(), 3);
+    assert_eq!(hist[1].tag(), Some("keep"));
+
+    // current version is always kept
+    f.seek(SeekFrom::Start(0)).unwrap();
+    f.write_once(&[0u8; 20]).unwrap();
+    let hist = f.history().unwrap();
+    assert_eq!(hist.len(), 2);
+    assert_eq!(hist[1].content_len(), 20);
+}
+
+#[test]
+fn file_restore_version() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    let read_file = |f: &mut File| -> String {
+        let mut buf = String::new();
+        f.seek(SeekFrom::Start(0)).unwrap();
+        f.read_to_string(&mut buf).unwrap();
+        buf
+    };
+
+    let mut f = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .version_limit(5)
+        .open(repo, "/file")
+        .unwrap();
+    f.write_once(b"foo").unwrap();
+    f.seek(SeekFrom::Start(0)).unwrap();
+    f.write_once(b"barbaz").unwrap();
+    assert_eq!(read_file(&mut f), "barbaz");
+
+    // restore through file
+    f.restore_version(2).unwrap();
+    assert_eq!(f.curr_version().unwrap(), 4);
+    assert_eq!(read_file(&mut f), "foo");
+    let hist = f.history().unwrap();
+    assert_eq!(hist.len(), 4);
+    assert_eq!(hist[3].content_len(), 3);
+    assert_eq!(f.restore_version(100).unwrap_err(), Error::NoVersion);
+
+    // restore through repo
+    repo.restore_version("/file", 3).unwrap();
+    assert_eq!(repo.history("/file").unwrap().len(), 5);
+    let mut f = repo.open_file("/file").unwrap();
+    assert_eq!(read_file(&mut f), "barbaz");
+    assert_eq!(
+        repo.restore_version("/file", 100).unwrap_err(),
+        Error::NoVersion
+    );
+    assert_eq!(repo.restore_version("/", 1).unwrap_err(), Error::NotFile);
+
+    // read-only file cannot be restored
+    assert_eq!(f.restore_version(2).unwrap_err(), Error::CannotWrite);
+}
+
+#[test]
+fn file_diff_versions() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    const LEN: usize = 1024 * 1024;
+    const PIECE_SIZE: usize = 256 * 1024;
+    let mut buf = vec![0u8; LEN];
+    let mut rng = XorShiftRng::from_seed([42u8; 16]);
+    rng.fill_bytes(&mut buf);
+
+    let mut f = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .version_limit(10)
+        .open(repo, "/file")
+        .unwrap();
+    f.write_once(&buf).unwrap();
+    assert!(f.diff_versions(2, 2).unwrap().is_empty());
+    assert_eq!(f.diff_versions(1, 2).unwrap(), vec![0..LEN]);
+
+    // overwrite in the middle, changed range is within one piece
+    let pos = PIECE_SIZE + 1000;
+    f.seek(SeekFrom::Start(pos as u64)).unwrap();
+    f.write_once(b"0123456789").unwrap();
+    let diff = f.diff_versions(2, 3).unwrap();
+    assert_eq!(diff.len(), 1);
+    assert!(diff[0].start >= PIECE_SIZE && diff[0].start <= pos);
+    assert!(diff[0].end >= pos + 10 && diff[0].end <= PIECE_SIZE * 2);
+    assert_eq!(f.diff_versions(3, 2).unwrap(), diff);
+
+    // append to the end
+    f.seek(SeekFrom::End(0)).unwrap();
+    f.write_once(&[1u8; 100]).unwrap();
+    assert_eq!(f.diff_versions(3, 4).unwrap(), vec![LEN..LEN + 100]);
+
+    // write the same data again
+    f.seek(SeekFrom::Start(pos as u64)).unwrap();
+    f.write_once(b"0123456789").unwrap();
+    assert!(f.diff_versions(4, 5).unwrap().is_empty());
+
+    assert_eq!(f.diff_versions(1, 100).unwrap_err(), Error::NoVersion);
+}
+
+#[test]
+fn file_read_at() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    const LEN: usize = 1024 * 1024;
+    let mut data = vec![0u8; LEN];
+    let mut rng = XorShiftRng::from_seed([42u8; 16]);
+    rng.fill_bytes(&mut data);
+
+    let mut f = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .open(repo, "/file")
+        .unwrap();
+    f.write_once(&data).unwrap();
+
+    // read at offsets without changing cursor
+    let mut buf = vec![0u8; 1000];
+    assert_eq!(f.read_at(&mut buf, 12_345).unwrap(), 1000);
+    assert_eq!(&buf[..], &data[12_345..13_345]);
+    assert_eq!(f.read_at(&mut buf, LEN as u64 - 10).unwrap(), 10);
+    assert_eq!(&buf[..10], &data[LEN - 10..]);
+    assert_eq!(f.read_at(&mut buf, LEN as u64 + 10).unwrap(), 0);
+    assert_eq!(f.stream_position().unwrap(), LEN as u64);
+
+    // read handle is shared among threads
+    fn is_send_sync<T: Send + Sync>(_: &T) {}
+    let handle = f.read_handle().unwrap();
+    is_send_sync(&handle);
+    assert_eq!(handle.version(), 2);
+    assert_eq!(handle.len(), LEN);
+
+    let data = Arc::new(data);
+    let children: Vec<_> = (0..4)
+        .map(|i| {
+            let handle = handle.clone();
+            let data = data.clone();
+            thread::spawn(move || {
+                let chunk = LEN / 4;
+                let mut buf = vec![0u8; chunk];
+                let offset = i * chunk;
+                let read = handle.read_at(&mut buf, offset as u64).unwrap();
+                assert_eq!(read, chunk);
+                assert_eq!(&buf[..], &data[offset..offset + chunk]);
+            })
+        })
+        .collect();
+    for child in children {
+        child.join().unwrap();
+    }
+
+    // handle keeps reading the pinned version
+    f.set_len(LEN + 100).unwrap();
+    assert_eq!(handle.len(), LEN);
+    assert_eq!(handle.read_at(&mut buf, LEN as u64).unwrap(), 0);
+    let mut buf = vec![1u8; 200];
+    assert_eq!(f.read_at(&mut buf, LEN as u64 - 100).unwrap(), 200);
+    assert_eq!(&buf[..100], &data[LEN - 100..]);
+    assert!(buf[100..].iter().all(|b| *b == 0));
+
+    // write-only file cannot be read
+    let f = OpenOptions::new()
+        .read(false)
+        .write(true)
+        .open(repo, "/file")
+        .unwrap();
+    assert_eq!(f.read_at(&mut buf, 0).unwrap_err(), Error::CannotRead);
+    assert_eq!(f.read_handle().unwrap_err(), Error::CannotRead);
+}
+
+#[test]
+fn file_write_once_failed() {
+    let mut env = common::TestEnv::new();
+    let repo = &mut env.repo;
+
+    repo.set_quota("/", Some(Quota::new().max_logical_bytes(4)))
+        .unwrap();
+    let mut f = repo.create_file("/file").unwrap();
+
+    // failed write_once aborts its transaction and leaves the file ready
+    // for the next write
+    assert_eq!(f.write_once(b"12345").unwrap_err(), Error::QuotaExceeded);
+    f.write_once(b"1234").unwrap();
+    verify_content(&mut f, b"1234");
+    assert_eq!(f.history().unwrap().len(), 1);
+}
@@ -5,15 +5,21 @@
     feature = "storage-redis"
 ))]
 
+#[cfg(feature = "tar-archive")]
+extern crate tar;
 extern crate tempdir;
 
 extern crate zbox;
 
 use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
 use tempdir::TempDir;
 #[allow(unused_imports)]
 use zbox::{
-    init_env, Cipher, Error, MemLimit, OpenOptions, OpsLimit, Repo, RepoOpener,
+    init_env, ChangeKind, CheckMode, Cipher, Error, ExportOptions, FileType,
+    ImportOptions, MemLimit, OpenOptions, OpsLimit, Quota, Repo, RepoOpener,
+    RetentionPolicy,
 };
 
 #[cfg(all(
@@ -178,6 +184,19 @@ fn repo_oper() {
         f2.write_once(&buf3[..]).unwrap();
         let hist = f2.history().unwrap();
         assert_eq!(hist.len(), 2);
+
+        drop(f);
+        drop(f2);
+        drop(repo);
+
+        // version limit can be larger than 255
+        let path = base.clone() + "/repo7_2";
+        let repo = RepoOpener::new()
+            .create_new(true)
+            .version_limit(u32::MAX)
+            .open(&path, pwd)
+            .unwrap();
+        assert_eq!(repo.info().unwrap().version_limit(), u32::MAX);
     }
 
     // case #8: test file read/write after repo is closed
@@ -367,3 +386,1225 @@ fn repo_smoke_test() {
     // to suppress unused variable warning
     drop(tmpdir);
 }
+
+#[cfg(feature = "storage-mem")]
+fn write_versions(repo: &mut Repo) {
+    repo.create_dir_all("/dir/sub").unwrap();
+    for i in 0..5 {
+        let path = format!("/dir/sub/file{}", i);
+        let mut file =
+            OpenOptions::new().create(true).open(repo, &path).unwrap();
+        for ver in 0..5 {
+            file.write_once(format!("{} {}", path, ver).as_bytes())
+                .unwrap();
+        }
+    }
+    repo.remove_file("/dir/sub/file0").unwrap();
+}
+
+#[cfg(feature = "storage-mem")]
+#[test]
+fn repo_check() {
+    init_env();
+
+    for dedup_file in [true, false] {
+        let uri = format!("mem://repo_check_{}", dedup_file);
+        let mut repo = RepoOpener::new()
+            .create_new(true)
+            .version_limit(3)
+            .dedup_file(dedup_file)
+            .open(&uri, "pwd")
+            .unwrap();
+        write_versions(&mut repo);
+
+        let report = repo.check(CheckMode::Check).unwrap();
+        assert!(report.is_ok(), "{:?}", report.problems());
+        assert!(!report.is_repaired());
+        assert_eq!(report.fnode_cnt(), 7);
+        assert_eq!(report.content_cnt(), 12);
+        assert!(report.segment_cnt() > 0);
+
+        // repair on a healthy repo changes nothing
+        let report = repo.check(CheckMode::Repair).unwrap();
+        assert!(report.is_ok());
+        assert!(!report.is_repaired());
+
+        // repair is not allowed on read-only repo
+        drop(repo);
+        let mut repo =
+            RepoOpener::new().read_only(true).open(&uri, "pwd").unwrap();
+        assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+        assert_eq!(repo.check(CheckMode::Repair).unwrap_err(), Error::ReadOnly);
+    }
+}
+
+#[cfg(feature = "storage-mem")]
+#[test]
+fn repo_check_retired() {
+    init_env();
+
+    // retired versions must release their contents without file dedup
+    let uri = "mem://repo_check_retired";
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .version_limit(3)
+        .dedup_file(false)
+        .open(uri, "pwd")
+        .unwrap();
+    write_versions(&mut repo);
+    repo.remove_dir_all("/dir/sub").unwrap();
+    repo.empty_trash().unwrap();
+
+    let report = repo.check(CheckMode::Check).unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems());
+    assert_eq!(report.content_cnt(), 0);
+
+    // repo should stay clean after re-open
+    drop(repo);
+    let mut repo = RepoOpener::new().open(uri, "pwd").unwrap();
+    repo.create_file("/file")
+        .unwrap()
+        .write_once(b"foo")
+        .unwrap();
+    let report = repo.check(CheckMode::Check).unwrap();
+    assert!(report.is_ok(), "{:?}", report.problems());
+    assert_eq!(report.content_cnt(), 2);
+}
+
+#[cfg(feature = "storage-mem")]
+#[test]
+fn repo_attrs_persist() {
+    init_env();
+
+    let uri = "mem://repo_attrs_persist";
+    let mut repo = RepoOpener::new().create_new(true).open(uri, "pwd").unwrap();
+    repo.create_dir("/dir").unwrap();
+    repo.create_file("/dir/file").unwrap();
+    repo.set_xattr("/dir", "user.dir", b"dir value").unwrap();
+    repo.set_xattr("/dir/file", "user.file", &[0, 1, 2])
+        .unwrap();
+    repo.set_xattr("/dir/file", "user.tmp", b"tmp").unwrap();
+    repo.remove_xattr("/dir/file", "user.tmp").unwrap();
+    drop(repo);
+
+    // xattrs should survive re-open
+    let repo = RepoOpener::new().read_only(true).open(uri, "pwd").unwrap();
+    assert_eq!(
+        repo.get_xattr("/dir", "user.dir").unwrap().unwrap(),
+        b"dir value"
+    );
+    assert_eq!(repo.list_xattrs("/dir/file").unwrap(), vec!["user.file"]);
+    assert_eq!(
+        repo.get_xattr("/dir/file", "user.file").unwrap().unwrap(),
+        vec![0, 1, 2]
+    );
+    drop(repo);
+
+    let mut repo = RepoOpener::new().open(uri, "pwd").unwrap();
+    repo.set_permissions("/dir/file", 0o600).unwrap();
+    repo.set_owner("/dir/file", Some(1000), Some(1000)).unwrap();
+    let mtime = UNIX_EPOCH + Duration::from_secs(1_234_567);
+    repo.set_times("/dir", mtime, mtime).unwrap();
+    drop(repo);
+
+    // mode, owner and times should survive re-open
+    let mut repo = RepoOpener::new().read_only(true).open(uri, "pwd").unwrap();
+    let md = repo.metadata("/dir/file").unwrap();
+    assert_eq!(md.mode(), 0o600);
+    assert_eq!((md.uid(), md.gid()), (1000, 1000));
+    let md = repo.metadata("/dir").unwrap();
+    assert_eq!(md.mode(), 0o755);
+    assert_eq!(md.accessed_at(), mtime);
+    assert_eq!(md.modified_at(), mtime);
+    assert_eq!(
+        repo.set_permissions("/dir", 0o700).unwrap_err(),
+        Error::ReadOnly
+    );
+    assert_eq!(
+        repo.set_xattr("/dir", "user.x", b"x").unwrap_err(),
+        Error::ReadOnly
+    );
+    assert_eq!(
+        repo.remove_xattr("/dir", "user.dir").unwrap_err(),
+        Error::ReadOnly
+    );
+}
+
+#[cfg(feature = "storage-mem")]
+#[test]
+fn repo_transaction() {
+    init_env();
+
+    let uri = "mem://repo_transaction";
+    let mut repo = RepoOpener::new().create_new(true).open(uri, "pwd").unwrap();
+    repo.create_dir("/dir").unwrap();
+    repo.create_file("/b").unwrap().write_once(b"bbb").unwrap();
+    repo.create_file("/c").unwrap();
+
+    let read_file = |repo: &mut Repo, path: &str| -> String {
+        let mut buf = String::new();
+        repo.open_file(path)
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        buf
+    };
+
+    // committed transaction
+    let ret = repo
+        .transaction(|tx| {
+            tx.write_file("/dir/a", b"aaa")?;
+            tx.write_file("/dir/a", b"aaa2")?;
+            tx.rename("/b", "/dir/b")?;
+            tx.remove_file("/c")?;
+            tx.create_dir_all("/x/y")?;
+            assert!(tx.path_exists("/dir/b"));
+            assert!(!tx.path_exists("/c"));
+            Ok(tx.read_dir("/dir")?.len())
+        })
+        .unwrap();
+    assert_eq!(ret, 2);
+    assert_eq!(read_file(&mut repo, "/dir/a"), "aaa2");
+    assert_eq!(read_file(&mut repo, "/dir/b"), "bbb");
+    assert!(!repo.path_exists("/b").unwrap());
+    assert!(!repo.path_exists("/c").unwrap());
+    assert!(repo.is_dir("/x/y").unwrap());
+
+    // failed operation rolls back the whole transaction
+    let err = repo
+        .transaction(|tx| {
+            tx.write_file("/dir/a", b"changed")?;
+            tx.write_file("/new", b"new")?;
+            tx.rename("/dir/b", "/b")?;
+            tx.remove_dir_all("/x")?;
+            tx.remove_file("/non-exists")
+        })
+        .unwrap_err();
+    assert_eq!(err, Error::NotFound);
+    assert_eq!(read_file(&mut repo, "/dir/a"), "aaa2");
+    assert_eq!(read_file(&mut repo, "/dir/b"), "bbb");
+    assert!(!repo.path_exists("/new").unwrap());
+    assert!(!repo.path_exists("/b").unwrap());
+    assert!(repo.is_dir("/x/y").unwrap());
+
+    // error returned by the closure rolls back as well
+    let err = repo
+        .transaction(|tx| -> zbox::Result<()> {
+            tx.remove_file("/dir/a")?;
+            Err(Error::InvalidArgument)
+        })
+        .unwrap_err();
+    assert_eq!(err, Error::InvalidArgument);
+    assert!(repo.path_exists("/dir/a").unwrap());
+
+    // ignored error still fails the transaction
+    let err = repo
+        .transaction(|tx| {
+            tx.remove_file("/dir/a")?;
+            assert!(tx.create_dir("/x").is_err());
+            assert_eq!(
+                tx.remove_file("/dir/b").unwrap_err(),
+                Error::Uncompleted
+            );
+            Ok(())
+        })
+        .unwrap_err();
+    assert_eq!(err, Error::Uncompleted);
+    assert!(repo.path_exists("/dir/a").unwrap());
+    assert!(repo.path_exists("/dir/b").unwrap());
+
+    // repo is still usable and committed changes survive re-open
+    repo.create_file("/d").unwrap();
+    drop(repo);
+    let mut repo = RepoOpener::new().open(uri, "pwd").unwrap();
+    assert_eq!(read_file(&mut repo, "/dir/a"), "aaa2");
+    assert_eq!(read_file(&mut repo, "/dir/b"), "bbb");
+    assert!(repo.path_exists("/d").unwrap());
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+    drop(repo);
+
+    let mut repo = RepoOpener::new().read_only(true).open(uri, "pwd").unwrap();
+    assert_eq!(
+        repo.transaction(|tx| tx.remove_file("/d")).unwrap_err(),
+        Error::ReadOnly
+    );
+}
+
+#[test]
+fn repo_snapshot() {
+    init_env();
+
+    let check_repo = |repo: &mut Repo| {
+        let report = repo.check(CheckMode::Check).unwrap();
+        assert!(report.is_ok());
+        report.fnode_cnt()
+    };
+
+    for &dedup_file in [false, true].iter() {
+        let uri = format!("mem://repo_snapshot_{}", dedup_file);
+        let mut repo = RepoOpener::new()
+            .create_new(true)
+            .version_limit(1)
+            .dedup_file(dedup_file)
+            .open(&uri, "pwd")
+            .unwrap();
+        repo.create_dir_all("/dir/sub").unwrap();
+        repo.create_file("/dir/a")
+            .unwrap()
+            .write_once(b"aaa")
+            .unwrap();
+        repo.create_file("/dir/sub/b")
+            .unwrap()
+            .write_once(b"bbb")
+            .unwrap();
+        repo.hard_link("/dir/a", "/a_link").unwrap();
+        repo.symlink("dir/sub", "/sub_link").unwrap();
+        repo.set_xattr("/dir/a", "user.tag", b"x").unwrap();
+        let fnode_cnt = check_repo(&mut repo);
+
+        // snapshot shares the whole tree
+        repo.create_snapshot("s1").unwrap();
+        assert_eq!(check_repo(&mut repo), fnode_cnt);
+        assert_eq!(
+            repo.create_snapshot("s1").unwrap_err(),
+            Error::AlreadyExists
+        );
+        assert_eq!(
+            repo.create_snapshot("").unwrap_err(),
+            Error::InvalidArgument
+        );
+        assert_eq!(repo.open_snapshot("s2").unwrap_err(), Error::NotFound);
+        assert_eq!(repo.delete_snapshot("s2").unwrap_err(), Error::NotFound);
+
+        // change the repo after snapshot is created
+        OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&mut repo, "/dir/a")
+            .unwrap()
+            .write_once(b"changed")
+            .unwrap();
+        repo.remove_dir_all("/dir/sub").unwrap();
+        repo.create_file("/new").unwrap();
+        repo.create_snapshot("s2").unwrap();
+        check_repo(&mut repo);
+
+        // snapshots without changes in between share the frozen fnodes
+        repo.create_snapshot("s3").unwrap();
+        repo.create_file("/new2").unwrap();
+        OpenOptions::new()
+            .write(true)
+            .open(&mut repo, "/new")
+            .unwrap()
+            .write_once(b"new")
+            .unwrap();
+        repo.create_file("/dir/c").unwrap();
+        repo.remove_dir_all("/dir").unwrap();
+        check_repo(&mut repo);
+
+        let names: Vec<String> = repo
+            .list_snapshots()
+            .unwrap()
+            .iter()
+            .map(|s| s.name().to_string())
+            .collect();
+        assert_eq!(names, vec!["s1", "s2", "s3"]);
+
+        // snapshot keeps the tree when it was created
+        let mut snapshot = repo.open_snapshot("s1").unwrap();
+        assert_eq!(snapshot.name(), "s1");
+        let mut buf = String::new();
+        snapshot
+            .open_file("/a_link")
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        assert_eq!(buf, "aaa");
+        buf.clear();
+        snapshot
+            .open_file("/sub_link/b")
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        assert_eq!(buf, "bbb");
+        assert_eq!(snapshot.metadata("/dir/a").unwrap().link_count(), 2);
+        assert_eq!(
+            snapshot.get_xattr("/dir/a", "user.tag").unwrap().unwrap(),
+            b"x"
+        );
+        assert!(!snapshot.path_exists("/new").unwrap());
+        assert_eq!(snapshot.walk_dir("/").unwrap().into_iter().count(), 6);
+        assert!(snapshot
+            .open_file("/dir/a")
+            .unwrap()
+            .write_once(b"x")
+            .is_err());
+        assert_eq!(repo.delete_snapshot("s1").unwrap_err(), Error::InUse);
+        let snapshot2 = repo.open_snapshot("s1").unwrap();
+        drop(snapshot);
+        assert_eq!(repo.delete_snapshot("s1").unwrap_err(), Error::InUse);
+        drop(snapshot2);
+
+        // deleting one snapshot doesn't affect others
+        repo.delete_snapshot("s1").unwrap();
+        assert_eq!(repo.open_snapshot("s1").unwrap_err(), Error::NotFound);
+        check_repo(&mut repo);
+        drop(repo);
+
+        // snapshots survive re-open
+        let mut repo = RepoOpener::new().open(&uri, "pwd").unwrap();
+        assert_eq!(repo.list_snapshots().unwrap().len(), 2);
+        let mut snapshot = repo.open_snapshot("s2").unwrap();
+        buf.clear();
+        snapshot
+            .open_file("/dir/a")
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        assert_eq!(buf, "changed");
+        assert!(!snapshot.path_exists("/dir/sub").unwrap());
+        drop(snapshot);
+        repo.delete_snapshot("s2").unwrap();
+        check_repo(&mut repo);
+
+        let mut snapshot = repo.open_snapshot("s3").unwrap();
+        buf.clear();
+        snapshot
+            .open_file("/new")
+            .unwrap()
+            .read_to_string(&mut buf)
+            .unwrap();
+        assert!(buf.is_empty());
+        assert!(!snapshot.path_exists("/new2").unwrap());
+        assert!(snapshot.path_exists("/dir/a").unwrap());
+        assert!(!snapshot.path_exists("/dir/c").unwrap());
+        drop(snapshot);
+
+        // fnodes kept for snapshots are removed with the last snapshot,
+        // only the removed dir is gone
+        repo.delete_snapshot("s3").unwrap();
+        assert_eq!(check_repo(&mut repo), fnode_cnt - 1);
+    }
+}
+
+#[test]
+fn repo_retention() {
+    init_env();
+
+    let policy = RetentionPolicy::new().keep_within(Duration::from_secs(1));
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .retention_policy(policy)
+        .open("mem://repo_retention", "pwd")
+        .unwrap();
+    assert_eq!(repo.info().unwrap().retention_policy(), Some(policy));
+
+    let write_file = |repo: &mut Repo, path: &str, opts: &mut OpenOptions| {
+        let mut f = opts.create(true).write(true).open(repo, path).unwrap();
+        for i in 0..3 {
+            f.seek(SeekFrom::Start(0)).unwrap();
+            f.write_once(&[i]).unwrap();
+        }
+    };
+    repo.create_dir("/dir").unwrap();
+    write_file(&mut repo, "/a", &mut OpenOptions::new());
+    write_file(&mut repo, "/dir/b", &mut OpenOptions::new());
+    write_file(&mut repo, "/c", OpenOptions::new().version_limit(2));
+    assert_eq!(repo.history("/a").unwrap().len(), 4);
+    assert_eq!(repo.history("/dir/b").unwrap().len(), 4);
+    assert_eq!(repo.history("/c").unwrap().len(), 2);
+    assert_eq!(repo.apply_retention().unwrap(), 0);
+
+    // versions expire over time
+    std::thread::sleep(Duration::from_secs(2));
+    assert_eq!(repo.apply_retention().unwrap(), 6);
+    assert_eq!(repo.history("/a").unwrap().len(), 1);
+    assert_eq!(repo.history("/dir/b").unwrap().len(), 1);
+    assert_eq!(repo.history("/c").unwrap().len(), 2);
+    assert_eq!(repo.apply_retention().unwrap(), 0);
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+}
+
+#[test]
+fn repo_trash() {
+    init_env();
+
+    let write_file = |repo: &mut Repo, path: &str, buf: &[u8]| {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(repo, path)
+            .unwrap();
+        f.write_once(buf).unwrap();
+    };
+    let read_file = |repo: &mut Repo, path: &str| -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut f = repo.open_file(path).unwrap();
+        f.read_to_end(&mut buf).unwrap();
+        buf
+    };
+
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .trash(true)
+        .open("mem://repo_trash", "pwd")
+        .unwrap();
+    assert!(repo.info().unwrap().trash());
+    repo.create_dir_all("/dir/sub").unwrap();
+    write_file(&mut repo, "/a", b"aaa");
+    write_file(&mut repo, "/dir/b", b"bb");
+    write_file(&mut repo, "/dir/sub/c", b"c");
+
+    // remove file and directory tree
+    repo.remove_file("/a").unwrap();
+    repo.remove_dir_all("/dir").unwrap();
+    assert!(!repo.path_exists("/a").unwrap());
+    assert!(!repo.path_exists("/dir").unwrap());
+    let trash = repo.list_trash().unwrap();
+    assert_eq!(trash.len(), 2);
+    assert_eq!(trash[0].path(), Path::new("/a"));
+    assert!(trash[0].metadata().is_file());
+    assert_eq!(trash[0].size(), 3);
+    assert_eq!(trash[1].path(), Path::new("/dir"));
+    assert!(trash[1].metadata().is_dir());
+    assert_eq!(trash[1].size(), 3);
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+
+    // undelete
+    repo.undelete(trash[0].id()).unwrap();
+    repo.undelete(trash[1].id()).unwrap();
+    assert_eq!(read_file(&mut repo, "/a"), b"aaa");
+    assert_eq!(read_file(&mut repo, "/dir/sub/c"), b"c");
+    assert!(repo.list_trash().unwrap().is_empty());
+    assert_eq!(repo.undelete(trash[0].id()).unwrap_err(), Error::NotFound);
+
+    // undelete to another path
+    repo.remove_file("/a").unwrap();
+    write_file(&mut repo, "/a", b"new");
+    let id = repo.list_trash().unwrap()[0].id();
+    assert_eq!(repo.undelete(id).unwrap_err(), Error::AlreadyExists);
+    repo.undelete_to(id, "/dir/a").unwrap();
+    assert_eq!(read_file(&mut repo, "/dir/a"), b"aaa");
+    assert_eq!(read_file(&mut repo, "/a"), b"new");
+
+    // removing hard link doesn't move file to trash
+    repo.hard_link("/a", "/a2").unwrap();
+    repo.remove_file("/a2").unwrap();
+    assert!(repo.list_trash().unwrap().is_empty());
+
+    // file replaced by rename is moved to trash
+    write_file(&mut repo, "/b", b"bb");
+    repo.rename("/b", "/a").unwrap();
+    let trash = repo.list_trash().unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].path(), Path::new("/a"));
+    assert_eq!(trash[0].size(), 3);
+    assert_eq!(read_file(&mut repo, "/a"), b"bb");
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+
+    // trash survives re-open
+    drop(repo);
+    let mut repo = RepoOpener::new().open("mem://repo_trash", "pwd").unwrap();
+    assert_eq!(repo.list_trash().unwrap().len(), 1);
+
+    // removing root moves its children to trash
+    repo.remove_dir_all("/").unwrap();
+    assert_eq!(repo.list_trash().unwrap().len(), 3);
+    assert_eq!(repo.empty_trash().unwrap(), 3);
+    assert!(repo.list_trash().unwrap().is_empty());
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+
+    // purge by size and age
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .trash(true)
+        .trash_max_size(5)
+        .trash_max_age(Duration::from_secs(1))
+        .open("mem://repo_trash2", "pwd")
+        .unwrap();
+    write_file(&mut repo, "/x", b"xxxx");
+    write_file(&mut repo, "/y", b"yyyy");
+    repo.remove_file("/x").unwrap();
+    repo.remove_file("/y").unwrap();
+    let trash = repo.list_trash().unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].path(), Path::new("/y"));
+    assert_eq!(repo.purge_trash().unwrap(), 0);
+    std::thread::sleep(Duration::from_secs(2));
+    assert_eq!(repo.purge_trash().unwrap(), 1);
+    assert!(repo.list_trash().unwrap().is_empty());
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+}
+
+#[test]
+fn repo_import_export() {
+    init_env();
+
+    let tmpdir = TempDir::new("zbox_test").expect("Create temp dir failed");
+    let src = tmpdir.path().join("src");
+    std::fs::create_dir_all(src.join("sub/deep")).unwrap();
+    std::fs::write(src.join("a.txt"), b"aaa").unwrap();
+    std::fs::write(src.join("empty"), b"").unwrap();
+    std::fs::write(src.join("sub/b.txt"), vec![42u8; 100_000]).unwrap();
+    std::fs::write(src.join("sub/deep/c.txt"), b"ccc").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        symlink("/a.txt", src.join("link")).unwrap();
+        let perm = std::fs::Permissions::from_mode(0o600);
+        std::fs::set_permissions(src.join("a.txt"), perm).unwrap();
+    }
+
+    let mut repo = RepoOpener::new()
+        .create(true)
+        .open("mem://repo_import_export", "pwd")
+        .unwrap();
+
+    // first import copies everything
+    let seen = std::rc::Rc::new(std::cell::Cell::new(0));
+    let counter = seen.clone();
+    let report = repo
+        .import_dir(
+            &src,
+            "/dst",
+            ImportOptions::new()
+                .batch_size(2)
+                .progress(move |_| counter.set(counter.get() + 1)),
+        )
+        .unwrap();
+    assert_eq!(report.files(), 4);
+    assert_eq!(report.dirs(), 3);
+    assert_eq!(report.skipped(), 0);
+    assert_eq!(report.bytes(), 100_006);
+    assert_eq!(
+        seen.get(),
+        report.files() + report.dirs() + report.symlinks()
+    );
+
+    let mut content = String::new();
+    repo.open_file("/dst/sub/deep/c.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "ccc");
+    assert_eq!(
+        repo.metadata("/dst/sub/b.txt").unwrap().content_len(),
+        100_000
+    );
+    let host_md = std::fs::metadata(src.join("sub/b.txt")).unwrap();
+    let md = repo.metadata("/dst/sub/b.txt").unwrap();
+    assert_eq!(md.modified_at(), host_md.modified().unwrap());
+    let host_md = std::fs::metadata(src.join("sub")).unwrap();
+    let md = repo.metadata("/dst/sub").unwrap();
+    assert_eq!(md.modified_at(), host_md.modified().unwrap());
+    #[cfg(unix)]
+    {
+        assert_eq!(report.symlinks(), 1);
+        assert_eq!(repo.metadata("/dst/a.txt").unwrap().mode(), 0o600);
+        assert_eq!(repo.read_link("/dst/link").unwrap(), Path::new("/a.txt"));
+    }
+
+    // second import skips unchanged files
+    std::fs::write(src.join("sub/deep/c.txt"), b"changed").unwrap();
+    let report = repo
+        .import_dir(&src, "/dst", &mut ImportOptions::new())
+        .unwrap();
+    assert_eq!(report.files(), 1);
+    assert_eq!(report.bytes(), 7);
+    #[cfg(unix)]
+    assert_eq!(report.skipped(), 4);
+
+    // type mismatch between host and repo entries
+    repo.remove_file("/dst/a.txt").unwrap();
+    repo.create_dir("/dst/a.txt").unwrap();
+    assert_eq!(
+        repo.import_dir(&src, "/dst", &mut ImportOptions::new())
+            .unwrap_err(),
+        Error::AlreadyExists
+    );
+    repo.remove_dir("/dst/a.txt").unwrap();
+    repo.import_dir(&src, "/dst", &mut ImportOptions::new())
+        .unwrap();
+
+    // export to host
+    let dst = tmpdir.path().join("dst");
+    let report = repo
+        .export_dir("/dst", &dst, &mut ExportOptions::new())
+        .unwrap();
+    assert_eq!(report.files(), 4);
+    assert_eq!(report.dirs(), 3);
+    assert_eq!(report.bytes(), 100_010);
+    assert_eq!(
+        std::fs::read(dst.join("sub/deep/c.txt")).unwrap(),
+        b"changed"
+    );
+    assert_eq!(std::fs::read(dst.join("sub/b.txt")).unwrap().len(), 100_000);
+    assert!(std::fs::read(dst.join("empty")).unwrap().is_empty());
+    let md = repo.metadata("/dst/sub/b.txt").unwrap();
+    let host_md = std::fs::metadata(dst.join("sub/b.txt")).unwrap();
+    assert_eq!(host_md.modified().unwrap(), md.modified_at());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let host_md = std::fs::metadata(dst.join("a.txt")).unwrap();
+        assert_eq!(host_md.permissions().mode() & 0o7777, 0o600);
+        assert_eq!(
+            std::fs::read_link(dst.join("link")).unwrap(),
+            Path::new("/a.txt")
+        );
+    }
+
+    // existing host files are not overwritten by default
+    assert_eq!(
+        repo.export_dir("/dst", &dst, &mut ExportOptions::new())
+            .unwrap_err(),
+        Error::AlreadyExists
+    );
+    repo.export_dir("/dst", &dst, ExportOptions::new().overwrite(true))
+        .unwrap();
+}
+
+#[cfg(feature = "tar-archive")]
+#[test]
+fn repo_tar() {
+    init_env();
+
+    let mut repo = RepoOpener::new()
+        .create(true)
+        .open("mem://repo_tar", "pwd")
+        .unwrap();
+
+    // build an archive by hand
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_mode(0o700);
+    header.set_mtime(1_000_000);
+    header.set_size(0);
+    builder
+        .append_data(&mut header, "./dir/", std::io::empty())
+        .unwrap();
+    let data = vec![7u8; 300_000];
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o640);
+    header.set_mtime(2_000_000);
+    header.set_size(data.len() as u64);
+    builder
+        .append_data(&mut header, "dir/a.bin", &data[..])
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o644);
+    header.set_size(data.len() as u64);
+    header.as_old_mut().name[..8].copy_from_slice(b"/b/c.bin");
+    header.set_cksum();
+    builder.append(&header, &data[..]).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "dir/link", "/x/dir/a.bin")
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Link);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "hard", "dir/a.bin")
+        .unwrap();
+    let archive = builder.into_inner().unwrap();
+
+    let report = repo.import_tar(&archive[..], "/x").unwrap();
+    assert_eq!(report.dirs(), 1);
+    assert_eq!(report.files(), 3);
+    assert_eq!(report.symlinks(), 1);
+    assert_eq!(report.bytes(), 600_000);
+
+    let md = repo.metadata("/x/dir").unwrap();
+    assert!(md.is_dir());
+    assert_eq!(md.mode(), 0o700);
+    assert_eq!(
+        md.modified_at(),
+        UNIX_EPOCH + Duration::from_secs(1_000_000)
+    );
+    let md = repo.metadata("/x/dir/a.bin").unwrap();
+    assert_eq!(md.content_len(), 300_000);
+    assert_eq!(md.mode(), 0o640);
+    assert_eq!(
+        md.modified_at(),
+        UNIX_EPOCH + Duration::from_secs(2_000_000)
+    );
+    assert_eq!(md.link_count(), 2);
+    assert!(repo.is_file("/x/b/c.bin").unwrap());
+    assert_eq!(
+        repo.read_link("/x/dir/link").unwrap(),
+        Path::new("/x/dir/a.bin")
+    );
+
+    // importing again overwrites existing files
+    repo.import_tar(&archive[..], "/x").unwrap();
+    assert_eq!(repo.metadata("/x/b/c.bin").unwrap().content_len(), 300_000);
+
+    // parent directory component is rejected
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(3);
+    header.as_old_mut().name[..9].copy_from_slice(b"../escape");
+    header.set_cksum();
+    builder.append(&header, &b"bad"[..]).unwrap();
+    let bad = builder.into_inner().unwrap();
+    assert_eq!(
+        repo.import_tar(&bad[..], "/y").unwrap_err(),
+        Error::InvalidPath
+    );
+
+    // export and import round trip
+    let mut out = Vec::new();
+    let report = repo.export_tar("/x", &mut out).unwrap();
+    assert_eq!(report.dirs(), 2);
+    assert_eq!(report.files(), 3);
+    assert_eq!(report.symlinks(), 1);
+
+    let mut archive = tar::Archive::new(&out[..]);
+    let mut names = Vec::new();
+    let mut hard_links = 0;
+    for ent in archive.entries().unwrap() {
+        let ent = ent.unwrap();
+        if ent.header().entry_type() == tar::EntryType::Link {
+            hard_links += 1;
+        }
+        names.push(ent.path().unwrap().display().to_string());
+    }
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["b", "b/c.bin", "dir", "dir/a.bin", "dir/link", "hard"]
+    );
+    assert_eq!(hard_links, 1);
+
+    repo.import_tar(&out[..], "/z").unwrap();
+    let mut content = Vec::new();
+    repo.open_file("/z/dir/a.bin")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content, data);
+    let md = repo.metadata("/z/dir/a.bin").unwrap();
+    assert_eq!(md.mode(), 0o640);
+    assert_eq!(
+        md.modified_at(),
+        UNIX_EPOCH + Duration::from_secs(2_000_000)
+    );
+    assert_eq!(repo.metadata("/z/dir").unwrap().mode(), 0o700);
+    assert_eq!(repo.metadata("/z/hard").unwrap().link_count(), 2);
+    assert!(repo.check(CheckMode::Check).unwrap().is_ok());
+}
+
+#[test]
+fn repo_journal() {
+    init_env();
+
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .journal(true)
+        .journal_limit(8)
+        .open("mem://repo_journal", "pwd")
+        .unwrap();
+    assert!(repo.info().unwrap().journal());
+    let cursor = repo.changes_since(0).unwrap().cursor();
+    assert_eq!(cursor, 0);
+
+    // file operations
+    repo.create_dir("/dir").unwrap();
+    let mut f = repo.create_file("/dir/file").unwrap();
+    f.write_once(b"foo").unwrap();
+    f.set_len(10).unwrap();
+    repo.rename("/dir/file", "/dir/file2").unwrap();
+    f.write_once(b"bar").unwrap();
+    drop(f);
+    repo.remove_file("/dir/file2").unwrap();
+
+    let changes = repo.changes_since(cursor).unwrap();
+    assert!(!changes.is_truncated());
+    let got: Vec<_> = changes
+        .changes()
+        .iter()
+        .map(|c| (c.kind(), c.path().to_str().unwrap(), c.version()))
+        .collect();
+    assert_eq!(
+        got,
+        vec![
+            (ChangeKind::Create, "/dir", None),
+            (ChangeKind::Create, "/dir/file", Some(1)),
+            (ChangeKind::Write, "/dir/file", Some(2)),
+            (ChangeKind::SetLen, "/dir/file", Some(3)),
+            (ChangeKind::Rename, "/dir/file", Some(3)),
+            (ChangeKind::Write, "/dir/file2", Some(4)),
+            (ChangeKind::Remove, "/dir/file2", Some(4)),
+        ]
+    );
+    assert_eq!(
+        changes.changes()[4].new_path(),
+        Some(Path::new("/dir/file2"))
+    );
+    assert_eq!(changes.changes()[0].file_type(), FileType::Dir);
+    let cursor = changes.cursor();
+    assert_eq!(cursor, 7);
+    assert!(repo.changes_since(cursor).unwrap().changes().is_empty());
+    assert_eq!(
+        repo.changes_since(cursor + 1).unwrap_err(),
+        Error::InvalidArgument
+    );
+
+    // changes in one transaction share the same txid, and aborted
+    // transaction is not recorded
+    repo.transaction(|tx| {
+        tx.write_file("/a", b"a")?;
+        tx.copy("/a", "/b")?;
+        Ok(())
+    })
+    .unwrap();
+    assert!(repo
+        .transaction(|tx| {
+            tx.create_dir("/c")?;
+            Err::<(), _>(Error::InvalidArgument)
+        })
+        .is_err());
+    let changes = repo.changes_since(cursor).unwrap();
+    let changes = changes.changes();
+    assert!(changes.len() >= 3);
+    assert!(changes.iter().all(|c| c.txid() == changes[0].txid()));
+    assert!(changes.iter().all(|c| c.path() != Path::new("/c")));
+    assert_eq!(changes.last().unwrap().path(), Path::new("/b"));
+    assert_eq!(changes.last().unwrap().kind(), ChangeKind::Write);
+
+    // old changes are dropped when limit is exceeded
+    let changes = repo.changes_since(0).unwrap();
+    assert!(changes.is_truncated());
+    assert_eq!(changes.changes().len(), 8);
+
+    // journal is persisted
+    let cursor = changes.cursor();
+    drop(repo);
+    let repo = RepoOpener::new().open("mem://repo_journal", "pwd").unwrap();
+    let changes = repo.changes_since(0).unwrap();
+    assert_eq!(changes.cursor(), cursor);
+    assert_eq!(changes.changes().len(), 8);
+
+    // changes of a file with hard links are recorded at the path which
+    // the file is changed through
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .journal(true)
+        .version_limit(5)
+        .open("mem://repo_journal3", "pwd")
+        .unwrap();
+    repo.create_dir("/a").unwrap();
+    repo.create_dir("/b").unwrap();
+    let mut f = repo.create_file("/a/file").unwrap();
+    f.write_once(b"foo").unwrap();
+    let cursor = repo.changes_since(0).unwrap().cursor();
+    repo.hard_link("/a/file", "/b/link").unwrap();
+    f.write_once(b"bar").unwrap();
+    repo.restore_version("/b/link", 2).unwrap();
+    repo.remove_file("/b/link").unwrap();
+    f.write_once(b"baz").unwrap();
+    let changes = repo.changes_since(cursor).unwrap();
+    let got: Vec<_> = changes
+        .changes()
+        .iter()
+        .map(|c| (c.kind(), c.path().to_str().unwrap()))
+        .collect();
+    assert_eq!(
+        got,
+        vec![
+            (ChangeKind::Create, "/b/link"),
+            (ChangeKind::Write, "/a/file"),
+            (ChangeKind::Write, "/b/link"),
+            (ChangeKind::Remove, "/b/link"),
+            (ChangeKind::SetLen, "/a/file"),
+            (ChangeKind::Write, "/a/file"),
+        ]
+    );
+
+    // journal is not enabled
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .open("mem://repo_journal2", "pwd")
+        .unwrap();
+    repo.create_dir("/dir").unwrap();
+    let changes = repo.changes_since(0).unwrap();
+    assert!(changes.changes().is_empty());
+    assert_eq!(changes.cursor(), 0);
+}
+
+#[test]
+fn repo_watch() {
+    init_env();
+
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .open("mem://repo_watch", "pwd")
+        .unwrap();
+    assert_eq!(repo.watch("dir", false).unwrap_err(), Error::InvalidPath);
+    let all = repo.watch("/", true).unwrap();
+    let dir = repo.watch("/dir/./", false).unwrap();
+
+    // file operations
+    repo.create_dir_all("/dir/sub").unwrap();
+    let mut f = repo.create_file("/dir/sub/file").unwrap();
+    f.write_once(b"foo").unwrap();
+    drop(f);
+    repo.rename("/dir/sub/file", "/dir/file").unwrap();
+    repo.remove_file("/dir/file").unwrap();
+
+    let got: Vec<_> = all
+        .try_iter()
+        .map(|e| (e.kind(), e.path().to_str().unwrap().to_string()))
+        .collect();
+    assert_eq!(
+        got,
+        vec![
+            (ChangeKind::Create, "/dir".to_string()),
+            (ChangeKind::Create, "/dir/sub".to_string()),
+            (ChangeKind::Create, "/dir/sub/file".to_string()),
+            (ChangeKind::Write, "/dir/sub/file".to_string()),
+            (ChangeKind::Rename, "/dir/sub/file".to_string()),
+            (ChangeKind::Remove, "/dir/file".to_string()),
+        ]
+    );
+
+    // non-recursive watch only gets the path and its direct children
+    let got: Vec<_> = dir
+        .try_iter()
+        .map(|e| (e.kind(), e.path().to_str().unwrap().to_string()))
+        .collect();
+    assert_eq!(
+        got,
+        vec![
+            (ChangeKind::Create, "/dir".to_string()),
+            (ChangeKind::Create, "/dir/sub".to_string()),
+            (ChangeKind::Rename, "/dir/sub/file".to_string()),
+            (ChangeKind::Remove, "/dir/file".to_string()),
+        ]
+    );
+
+    // events are sent after transaction is committed
+    let (tx_sent, tx_got) = {
+        let mut sent = 0;
+        repo.transaction(|tx| {
+            tx.write_file("/a", b"a")?;
+            tx.copy("/a", "/b")?;
+            sent = all.try_iter().count();
+            Ok(())
+        })
+        .unwrap();
+        let got: Vec<_> = all.try_iter().collect();
+        (sent, got)
+    };
+    assert_eq!(tx_sent, 0);
+    assert!(tx_got.len() >= 3);
+    assert!(tx_got.iter().all(|e| e.txid() == tx_got[0].txid()));
+    assert_eq!(tx_got.last().unwrap().path(), Path::new("/b"));
+    assert!(tx_got.last().unwrap().version().is_some());
+    assert_eq!(tx_got.last().unwrap().file_type(), FileType::File);
+
+    // aborted transaction sends no events
+    assert!(repo
+        .transaction(|tx| {
+            tx.create_dir("/dir/c")?;
+            Err::<(), _>(Error::InvalidArgument)
+        })
+        .is_err());
+    assert!(all.try_recv().is_err());
+    assert!(dir.try_recv().is_err());
+
+    // dropped receiver cancels the watch
+    drop(dir);
+    repo.create_dir("/dir/d").unwrap();
+    assert_eq!(all.try_recv().unwrap().path(), Path::new("/dir/d"));
+
+    // rename into watched directory
+    let dir = repo.watch("/dir", false).unwrap();
+    repo.rename("/a", "/dir/a").unwrap();
+    let event = dir.try_recv().unwrap();
+    assert_eq!(event.kind(), ChangeKind::Rename);
+    assert_eq!(event.path(), Path::new("/a"));
+    assert_eq!(event.new_path(), Some(Path::new("/dir/a")));
+}
+
+#[test]
+fn repo_quota() {
+    init_env();
+
+    // repository quota
+    let quota = Quota::new().max_files(2);
+    let mut repo = RepoOpener::new()
+        .create_new(true)
+        .version_limit(3)
+        .quota(quota)
+        .open("mem://repo_quota", "pwd")
+        .unwrap();
+    assert_eq!(repo.quota("/").unwrap(), Some(quota));
+    repo.create_file("/file1")
+        .unwrap()
+        .write_once(b"foo")
+        .unwrap();
+    repo.create_dir("/dir").unwrap();
+    repo.create_file("/dir/file2")
+        .unwrap()
+        .write_once(b"bar")
+        .unwrap();
+    assert_eq!(
+        repo.create_file("/dir/file3").unwrap_err(),
+        Error::QuotaExceeded
+    );
+    assert!(!repo.path_exists("/dir/file3").unwrap());
+    // hard link doesn't add a file
+    repo.hard_link("/file1", "/file3").unwrap();
+    let usage = repo.usage("/").unwrap();
+    assert_eq!(usage.files(), 2);
+    repo.remove_file("/file3").unwrap();
+    let usage = repo.usage("/").unwrap();
+    assert_eq!(usage.files(), 2);
+    assert_eq!(usage.logical_bytes(), 6);
+    assert_eq!(usage.allocated_bytes(), 6);
+
+    // quota is persisted, and can be replaced when opening
+    drop(repo);
+    let repo = RepoOpener::new().open("mem://repo_quota", "pwd").unwrap();
+    assert_eq!(repo.quota("/").unwrap(), Some(quota));
+    drop(repo);
+    let mut repo = RepoOpener::new()
+        .quota(Quota::new())
+        .open("mem://repo_quota", "pwd")
+        .unwrap();
+    assert_eq!(repo.quota("/").unwrap(), Some(Quota::new()));
+    repo.set_quota("/", None).unwrap();
+    assert_eq!(repo.quota("/").unwrap(), None);
+    assert_eq!(repo.set_quota("/file1", None).unwrap_err(), Error::NotDir);
+
+    // logical bytes quota on directory
+    let quota = Quota::new().max_logical_bytes(10);
+    repo.set_quota("/dir", Some(quota)).unwrap();
+    assert_eq!(repo.usage("/dir").unwrap().logical_bytes(), 3);
+    let mut f = repo.create_file("/dir/file3").unwrap();
+    f.write_once(b"1234567").unwrap();
+    assert_eq!(f.write_once(b"8").unwrap_err(), Error::QuotaExceeded);
+    assert_eq!(f.set_len(8).unwrap_err(), Error::QuotaExceeded);
+    assert_eq!(f.metadata().unwrap().content_len(), 7);
+    f.set_len(2).unwrap();
+    assert_eq!(repo.usage("/dir").unwrap().logical_bytes(), 5);
+    repo.copy("/file1", "/dir/file4").unwrap();
+    assert_eq!(repo.usage("/dir").unwrap().logical_bytes(), 8);
+    repo.remove_file("/dir/file4").unwrap();
+
+    // write is stopped early when quota is exceeded
+    f.seek(SeekFrom::Start(0)).unwrap();
+    assert!(std::io::Write::write(&mut f, &[0u8; 20]).is_err());
+    assert_eq!(f.metadata().unwrap().content_len(), 2);
+    drop(f);
+
+    // allocated bytes includes history versions, but not holes
+    repo.set_quota("/dir", Some(Quota::new().max_allocated_bytes(16)))
+        .unwrap();
+    let usage = repo.usage("/dir").unwrap();
+    assert_eq!(usage.allocated_bytes(), 3 + 7 + 2);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .open(&mut repo, "/dir/file2")
+        .unwrap();
+    f.set_len(100).unwrap();
+    assert_eq!(f.write_once(b"12345").unwrap_err(), Error::QuotaExceeded);
+    drop(f);
+    let usage = repo.usage("/dir").unwrap();
+    assert_eq!(usage.logical_bytes(), 100 + 2);
+    assert_eq!(usage.allocated_bytes(), 3 + 3 + 7 + 2);
+
+    // nested quota and moving files between directories
+    repo.set_quota("/dir", Some(Quota::new().max_files(2)))
+        .unwrap();
+    repo.create_dir("/dir/sub").unwrap();
+    repo.set_quota("/dir/sub", Some(Quota::new().max_files(1)))
+        .unwrap();
+    repo.rename("/dir/file3", "/dir/sub/file3").unwrap();
+    assert_eq!(repo.usage("/dir/sub").unwrap().files(), 1);
+    assert_eq!(repo.usage("/dir").unwrap().files(), 2);
+    assert_eq!(
+        repo.rename("/file1", "/dir/sub/file1").unwrap_err(),
+        Error::QuotaExceeded
+    );
+    assert!(repo.path_exists("/file1").unwrap());
+    repo.rename("/file1", "/dir/sub/file3").unwrap();
+    assert_eq!(repo.usage("/dir/sub").unwrap().files(), 1);
+    assert_eq!(repo.usage("/dir/sub").unwrap().logical_bytes(), 3);
+    assert_eq!(repo.usage("/dir").unwrap().files(), 2);
+
+    // quota lower than usage only allows reducing usage
+    repo.set_quota("/dir", Some(Quota::new().max_files(1)))
+        .unwrap();
+    assert_eq!(
+        repo.create_file("/dir/file4").unwrap_err(),
+        Error::QuotaExceeded
+    );
+    repo.remove_file("/dir/file2").unwrap();
+    assert_eq!(repo.usage("/dir").unwrap().files(), 1);
+
+    // aborted transaction doesn't change usage
+    assert_eq!(
+        repo.transaction(|tx| {
+            tx.remove_file("/dir/sub/file3")?;
+            tx.write_file("/dir/file4", b"")?;
+            tx.write_file("/dir/file5", b"")
+        })
+        .unwrap_err(),
+        Error::QuotaExceeded
+    );
+    assert_eq!(repo.usage("/dir").unwrap().files(), 1);
+    assert!(repo.path_exists("/dir/sub/file3").unwrap());
+
+    // removing directory with quota
+    repo.create_file("/file5").unwrap();
+    repo.remove_dir_all("/dir").unwrap();
+    assert_eq!(repo.usage("/").unwrap().files(), 1);
+
+    // hard linked file is counted once, and charged to quotas on the path
+    // it is changed through
+    repo.create_dir("/a").unwrap();
+    repo.create_dir("/b").unwrap();
+    repo.set_quota("/a", Some(Quota::new().max_files(1)))
+        .unwrap();
+    repo.set_quota("/b", Some(Quota::new().max_logical_bytes(5)))
+        .unwrap();
+    repo.create_file("/a/file")
+        .unwrap()
+        .write_once(b"123")
+        .unwrap();
+    repo.hard_link("/a/file", "/a/link").unwrap();
+    assert_eq!(repo.usage("/a").unwrap().files(), 1);
+    assert_eq!(repo.usage("/a").unwrap().logical_bytes(), 3);
+    repo.hard_link("/a/file", "/b/link").unwrap();
+    assert_eq!(repo.usage("/b").unwrap().logical_bytes(), 3);
+    let mut f = OpenOptions::new()
+        .write(true)
+        .append(true)
+        .open(&mut repo, "/b/link")
+        .unwrap();
+    assert_eq!(f.write_once(b"456").unwrap_err(), Error::QuotaExceeded);
+    drop(f);
+    OpenOptions::new()
+        .write(true)
+        .open(&mut repo, "/a/link")
+        .unwrap()
+        .write_once(b"123456")
+        .unwrap();
+    assert_eq!(repo.usage("/a").unwrap().logical_bytes(), 6);
+    repo.remove_dir_all("/b").unwrap();
+    repo.remove_file("/a/link").unwrap();
+    assert_eq!(repo.usage("/a").unwrap().files(), 1);
+    assert_eq!(
+        repo.create_file("/a/file2").unwrap_err(),
+        Error::QuotaExceeded
+    );
+    repo.remove_file("/a/file").unwrap();
+    repo.create_file("/a/file2").unwrap();
+}